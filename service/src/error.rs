@@ -25,9 +25,18 @@ quick_error! {
     NotAuthenticated(error: String) {
       display("{}", error)
     }
+    Forbidden(error: String) {
+      display("{}", error)
+    }
     NotFound(error: String) {
       display("{}", error)
     }
+    Conflict(error: String) {
+      display("{}", error)
+    }
+    Unprocessable(error: String, data: JsonValue) {
+      display("{}", error)
+    }
     IOError(error: String) {
       display("{}", error)
     }
@@ -80,7 +89,10 @@ impl Error {
   fn to_code(&self) -> usize {
     match self {
       Error::NotAuthenticated(_) => 401,
+      Error::Forbidden(_) => 403,
       Error::NotFound(_) => 404,
+      Error::Conflict(_) => 409,
+      Error::Unprocessable(..) => 422,
       Error::NotImplemented => 501,
       _ => 500,
     }
@@ -89,7 +101,10 @@ impl Error {
   fn to_class_name(&self) -> &str {
     match self {
       Error::NotAuthenticated(_) => "not-authenticated",
+      Error::Forbidden(_) => "forbidden",
       Error::NotFound(_) => "not-found",
+      Error::Conflict(_) => "conflict",
+      Error::Unprocessable(..) => "unprocessable",
       Error::IOError(_) => "io-errors",
       Error::GeneralError(_) => "general-errors",
       Error::CameraError(_) => "general-errors",
@@ -100,7 +115,10 @@ impl Error {
   fn to_name(&self) -> &str {
     match self {
       Error::NotAuthenticated(_) => "NotAuthenticated",
+      Error::Forbidden(_) => "Forbidden",
       Error::NotFound(_) => "NotFound",
+      Error::Conflict(_) => "Conflict",
+      Error::Unprocessable(..) => "Unprocessable",
       Error::IOError(_) => "IOError",
       Error::GeneralError(_) => "GeneralError",
       Error::CameraError(_) => "GeneralError",
@@ -109,11 +127,28 @@ impl Error {
   }
 
   pub fn to_json(&self) -> JsonValue {
-    json::object! {
+    let mut obj = json::object! {
       className: self.to_class_name(),
       code: self.to_code(),
       message: self.to_string(),
       name: self.to_name(),
+    };
+    if let Error::Unprocessable(_, data) = self {
+      obj["data"] = data.clone();
     }
+    obj
+  }
+}
+
+impl actix_web::ResponseError for Error {
+  fn status_code(&self) -> actix_web::http::StatusCode {
+    actix_web::http::StatusCode::from_u16(self.to_code() as u16)
+      .unwrap_or(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR)
+  }
+
+  fn error_response(&self) -> actix_web::HttpResponse {
+    actix_web::HttpResponse::build(self.status_code())
+      .content_type("application/json")
+      .body(self.to_json().dump())
   }
 }
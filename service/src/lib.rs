@@ -1,4 +1,5 @@
 pub mod error;
+pub mod query;
 pub mod utils;
 
 use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
@@ -26,11 +27,30 @@ pub struct Account {
   // data: Option<JsonValue>,
 }
 
+/// Where request came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+  Local,
+  Rest,
+  Websocket,
+}
+
+impl Origin {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Origin::Local => "local",
+      Origin::Rest => "rest",
+      Origin::Websocket => "websocket",
+    }
+  }
+}
+
 #[derive(Debug, Clone)]
 pub struct Context {
   pub request: Option<actix_web::dev::RequestHead>,
   pub account: Arc<RwLock<Account>>,
   pub timestamp: Duration,
+  pub origin: Origin,
 }
 
 impl Context {
@@ -39,15 +59,30 @@ impl Context {
   }
 
   pub fn local() -> Self {
-    Self { request: None, timestamp: Context::since_the_epoch(), account: Self::guest() }
+    Self {
+      request: None,
+      timestamp: Context::since_the_epoch(),
+      account: Self::guest(),
+      origin: Origin::Local,
+    }
   }
 
   pub fn rest(request: actix_web::dev::RequestHead) -> Self {
-    Self { request: Some(request), timestamp: Context::since_the_epoch(), account: Self::guest() }
+    Self {
+      request: Some(request),
+      timestamp: Context::since_the_epoch(),
+      account: Self::guest(),
+      origin: Origin::Rest,
+    }
   }
 
   pub fn websocket(request: actix_web::dev::RequestHead) -> Self {
-    Self { request: Some(request), timestamp: Context::since_the_epoch(), account: Self::guest() }
+    Self {
+      request: Some(request),
+      timestamp: Context::since_the_epoch(),
+      account: Self::guest(),
+      origin: Origin::Websocket,
+    }
   }
 
   fn since_the_epoch() -> Duration {
@@ -0,0 +1,383 @@
+use std::cmp::Ordering;
+
+use json::JsonValue;
+
+use crate::error::Error;
+use crate::utils::json::JsonParams;
+use values::c;
+
+/// Feathers-style query of `find` params: `filter` with operators, `$sort` and `$select`.
+///
+/// Fields are addressed by path, nested ones by dots like `goods._id`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+  filter: Filter,
+  // path and true if descending
+  sort: Vec<(Vec<String>, bool)>,
+  select: Option<Vec<Vec<String>>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+  And(Vec<Filter>),
+  Or(Vec<Filter>),
+  Field(Vec<String>, Operator),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operator {
+  Eq(JsonValue),
+  Ne(JsonValue),
+  In(Vec<JsonValue>),
+  Nin(Vec<JsonValue>),
+  Gt(JsonValue),
+  Gte(JsonValue),
+  Lt(JsonValue),
+  Lte(JsonValue),
+  StartsWith(String),
+}
+
+fn path(name: &str) -> Vec<String> {
+  name.split('.').map(|s| s.to_string()).collect()
+}
+
+/// Resolve reference to other document, met in the middle of path, into that document.
+pub type Resolver<'a> = &'a dyn Fn(&str) -> JsonValue;
+
+/// Value at path of object, `Null` if there is no such.
+pub fn value_at(data: &JsonValue, path: &[String], resolve: Option<Resolver>) -> JsonValue {
+  let (name, rest) = match path.split_first() {
+    Some(split) => split,
+    None => return data.clone(),
+  };
+
+  let child = |data: &JsonValue| -> JsonValue {
+    let child = if data.is_array() {
+      match name.parse::<usize>() {
+        Ok(i) => &data[i],
+        Err(_) => return JsonValue::Null,
+      }
+    } else {
+      &data[name.as_str()]
+    };
+    value_at(child, rest, resolve)
+  };
+
+  match (data.as_str(), resolve) {
+    (Some(reference), Some(resolve)) => child(&resolve(reference)),
+    _ => child(data),
+  }
+}
+
+/// Numbers (or strings of numbers compared with numbers) by value, strings lexicographically.
+fn compare(left: &JsonValue, right: &JsonValue) -> Option<Ordering> {
+  if left.is_number() || right.is_number() {
+    Some(left.number_or_none()?.cmp(&right.number_or_none()?))
+  } else {
+    Some(left.as_str()?.cmp(right.as_str()?))
+  }
+}
+
+fn list(value: &JsonValue) -> Result<Vec<JsonValue>, Error> {
+  if value.is_array() {
+    Ok(value.members().cloned().collect())
+  } else {
+    Err(Error::GeneralError(format!("list expected, but got {value}")))
+  }
+}
+
+impl Operator {
+  fn parse(name: &str, value: &JsonValue) -> Result<Self, Error> {
+    match name {
+      "$eq" => Ok(Operator::Eq(value.clone())),
+      "$ne" => Ok(Operator::Ne(value.clone())),
+      "$in" => Ok(Operator::In(list(value)?)),
+      "$nin" => Ok(Operator::Nin(list(value)?)),
+      "$gt" => Ok(Operator::Gt(value.clone())),
+      "$gte" => Ok(Operator::Gte(value.clone())),
+      "$lt" => Ok(Operator::Lt(value.clone())),
+      "$lte" => Ok(Operator::Lte(value.clone())),
+      "$starts-with" => Ok(Operator::StartsWith(value.string())),
+      _ => Err(Error::GeneralError(format!("unknown operator {name}"))),
+    }
+  }
+
+  fn is_match(&self, value: &JsonValue) -> bool {
+    match self {
+      Operator::Eq(v) => value == v,
+      Operator::Ne(v) => value != v,
+      Operator::In(vs) => vs.contains(value),
+      Operator::Nin(vs) => !vs.contains(value),
+      Operator::Gt(v) => compare(value, v) == Some(Ordering::Greater),
+      Operator::Gte(v) => matches!(compare(value, v), Some(Ordering::Greater | Ordering::Equal)),
+      Operator::Lt(v) => compare(value, v) == Some(Ordering::Less),
+      Operator::Lte(v) => matches!(compare(value, v), Some(Ordering::Less | Ordering::Equal)),
+      Operator::StartsWith(v) => value.as_str().map(|s| s.starts_with(v.as_str())).unwrap_or(false),
+    }
+  }
+}
+
+impl Filter {
+  fn parse(filter: &JsonValue) -> Result<Self, Error> {
+    if !filter.is_object() {
+      return Err(Error::GeneralError(format!("filter must be object, but got {filter}")));
+    }
+
+    let mut filters = Vec::new();
+    for (name, value) in filter.entries() {
+      match name {
+        "$or" | "$and" => {
+          let list = list(value)?.iter().map(Filter::parse).collect::<Result<Vec<_>, _>>()?;
+          filters.push(if name == "$or" { Filter::Or(list) } else { Filter::And(list) });
+        },
+        "$starts-with" => {
+          for (name, value) in value.entries() {
+            filters.push(Filter::Field(path(name), Operator::StartsWith(value.string())));
+          }
+        },
+        _ => {
+          let is_operators = value.is_object()
+            && !value.is_empty()
+            && value.entries().all(|(n, _)| n.starts_with('$'));
+          if is_operators {
+            for (op, value) in value.entries() {
+              filters.push(Filter::Field(path(name), Operator::parse(op, value)?));
+            }
+          } else {
+            filters.push(Filter::Field(path(name), Operator::Eq(value.clone())));
+          }
+        },
+      }
+    }
+    Ok(Filter::And(filters))
+  }
+
+  fn is_match(&self, data: &JsonValue, resolve: Option<Resolver>) -> bool {
+    match self {
+      Filter::And(filters) => filters.iter().all(|f| f.is_match(data, resolve)),
+      Filter::Or(filters) => filters.iter().any(|f| f.is_match(data, resolve)),
+      Filter::Field(path, op) => op.is_match(&value_at(data, path, resolve)),
+    }
+  }
+}
+
+impl Query {
+  /// Query of `find` params (first one if it is list of them).
+  pub fn new(params: &JsonValue) -> Result<Self, Error> {
+    let params = if params.is_array() { &params[0] } else { params };
+
+    let filter = if params["filter"].is_object() {
+      Filter::parse(&params["filter"])?
+    } else {
+      Filter::And(vec![])
+    };
+
+    let mut sort = Vec::new();
+    for (name, order) in params["$sort"].entries() {
+      let order = order.as_i32().or_else(|| order.as_str().and_then(|s| s.parse().ok()));
+      let descending = match order {
+        Some(1) => false,
+        Some(-1) => true,
+        _ => return Err(Error::GeneralError(format!("sort order of {name} must be 1 or -1"))),
+      };
+      sort.push((path(name), descending));
+    }
+
+    let select = if params["$select"].is_array() {
+      Some(params["$select"].members().map(|name| path(&name.string())).collect())
+    } else {
+      None
+    };
+
+    Ok(Query { filter, sort, select })
+  }
+
+  pub fn is_match(&self, data: &JsonValue) -> bool {
+    self.filter.is_match(data, None)
+  }
+
+  /// Match with references on nested paths resolved, so `goods.name` is name of goods
+  /// which `_id` or `_uuid` is at `goods`.
+  pub fn is_match_with(&self, data: &JsonValue, resolve: Resolver) -> bool {
+    self.filter.is_match(data, Some(resolve))
+  }
+
+  pub fn is_sorted(&self) -> bool {
+    !self.sort.is_empty()
+  }
+
+  /// Sort by `$sort` fields, missing values go last.
+  pub fn sort(&self, list: &mut Vec<JsonValue>) {
+    self.sort_by_values(list, None)
+  }
+
+  /// Sort with references on nested paths resolved, see `is_match_with`.
+  pub fn sort_with(&self, list: &mut Vec<JsonValue>, resolve: Resolver) {
+    self.sort_by_values(list, Some(resolve))
+  }
+
+  fn sort_by_values(&self, list: &mut Vec<JsonValue>, resolve: Option<Resolver>) {
+    if self.sort.is_empty() {
+      return;
+    }
+
+    let mut keyed: Vec<(Vec<JsonValue>, JsonValue)> = std::mem::take(list)
+      .into_iter()
+      .map(|data| {
+        let keys = self.sort.iter().map(|(path, _)| value_at(&data, path, resolve)).collect();
+        (keys, data)
+      })
+      .collect();
+
+    keyed.sort_by(|(a, _), (b, _)| {
+      for ((a, b), (_, descending)) in a.iter().zip(b.iter()).zip(self.sort.iter()) {
+        let ordering = match (a.is_null(), b.is_null()) {
+          (true, true) => Ordering::Equal,
+          (true, false) => Ordering::Greater,
+          (false, true) => Ordering::Less,
+          (false, false) => {
+            let ordering = compare(a, b).unwrap_or(Ordering::Equal);
+            if *descending {
+              ordering.reverse()
+            } else {
+              ordering
+            }
+          },
+        };
+        if ordering != Ordering::Equal {
+          return ordering;
+        }
+      }
+      Ordering::Equal
+    });
+
+    list.extend(keyed.into_iter().map(|(_, data)| data));
+  }
+
+  /// Only `$select` fields and `_id` of document, all of them if there is no `$select`.
+  pub fn project(&self, data: JsonValue) -> JsonValue {
+    let select = match &self.select {
+      Some(select) => select,
+      None => return data,
+    };
+
+    let mut result = JsonValue::new_object();
+    result[c::ID] = data[c::ID].clone();
+    for path in select {
+      let value = value_at(&data, path, None);
+      if value.is_null() {
+        continue;
+      }
+      let mut node = &mut result;
+      for name in &path[..path.len() - 1] {
+        if !node[name.as_str()].is_object() {
+          node[name.as_str()] = JsonValue::new_object();
+        }
+        node = &mut node[name.as_str()];
+      }
+      node[path[path.len() - 1].as_str()] = value;
+    }
+    result
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use json::object;
+
+  fn query(params: JsonValue) -> Query {
+    Query::new(&params).unwrap()
+  }
+
+  #[test]
+  fn test_in_nin() {
+    let q = query(object! { filter: { status: { "$in": ["draft", "posted"] } } });
+    assert!(q.is_match(&object! { status: "draft" }));
+    assert!(!q.is_match(&object! { status: "deleted" }));
+    assert!(!q.is_match(&object! {}));
+
+    let q = query(object! { filter: { status: { "$nin": ["deleted"] } } });
+    assert!(q.is_match(&object! { status: "draft" }));
+    assert!(q.is_match(&object! {}));
+    assert!(!q.is_match(&object! { status: "deleted" }));
+
+    assert!(Query::new(&object! { filter: { status: { "$in": "draft" } } }).is_err());
+  }
+
+  #[test]
+  fn test_range_of_not_numbers() {
+    let q = query(object! { filter: { date: { "$gte": "2023-01-05", "$lt": "2023-02-01" } } });
+    assert!(q.is_match(&object! { date: "2023-01-05" }));
+    assert!(q.is_match(&object! { date: "2023-01-31" }));
+    assert!(!q.is_match(&object! { date: "2023-02-01" }));
+    assert!(!q.is_match(&object! { date: "2023-01-04" }));
+
+    // values that can't be compared never match
+    assert!(!q.is_match(&object! {}));
+    assert!(!q.is_match(&object! { date: { year: 2023 } }));
+    assert!(!q.is_match(&object! { date: true }));
+
+    // string of number is compared with number by value
+    let q = query(object! { filter: { qty: { "$gt": 9 } } });
+    assert!(q.is_match(&object! { qty: "10" }));
+    assert!(!q.is_match(&object! { qty: "9" }));
+    assert!(!q.is_match(&object! { qty: "ten" }));
+  }
+
+  #[test]
+  fn test_or() {
+    let q = query(object! {
+      filter: {
+        storage: "s1",
+        "$or": [{ status: "draft" }, { qty: { "$lte": 0 } }],
+      }
+    });
+    assert!(q.is_match(&object! { storage: "s1", status: "draft", qty: 5 }));
+    assert!(q.is_match(&object! { storage: "s1", status: "posted", qty: 0 }));
+    assert!(!q.is_match(&object! { storage: "s1", status: "posted", qty: 5 }));
+    assert!(!q.is_match(&object! { storage: "s2", status: "draft", qty: 0 }));
+
+    assert!(Query::new(&object! { filter: { "$or": { status: "draft" } } }).is_err());
+  }
+
+  #[test]
+  fn test_sort_ties() {
+    let q = query(object! { "$sort": { date: 1, number: -1 } });
+    assert!(q.is_sorted());
+
+    let mut list = vec![
+      object! { _id: "a", date: "2023-01-02", number: 1 },
+      object! { _id: "b", date: "2023-01-01", number: 1 },
+      object! { _id: "c", date: "2023-01-02", number: 3 },
+      object! { _id: "d" },
+      object! { _id: "e", date: "2023-01-02", number: 3 },
+    ];
+    q.sort(&mut list);
+
+    // ties keep their order, missing values go last
+    let ids: Vec<String> = list.iter().map(|d| d[c::ID].string()).collect();
+    assert_eq!(ids, vec!["b", "c", "e", "a", "d"]);
+
+    assert!(Query::new(&object! { "$sort": { date: 2 } }).is_err());
+  }
+
+  #[test]
+  fn test_select_nested() {
+    let q = query(object! { "$select": ["goods.name", "qty.number", "missing.field"] });
+    let data = object! {
+      _id: "a",
+      goods: { name: "milk", uom: "l" },
+      qty: { number: 2, uom: "l" },
+      cost: 10,
+    };
+    assert_eq!(q.project(data), object! { _id: "a", goods: { name: "milk" }, qty: { number: 2 } });
+
+    let q = query(object! { "$select": ["lines.1.goods"] });
+    let data = object! { _id: "a", lines: [{ goods: "g1" }, { goods: "g2" }] };
+    assert_eq!(q.project(data), object! { _id: "a", lines: { "1": { goods: "g2" } } });
+
+    let data = object! { _id: "a", cost: 10 };
+    assert_eq!(query(object! {}).project(data.clone()), data);
+  }
+}
@@ -1,7 +1,7 @@
 use std::str::FromStr;
 
 use chrono::{DateTime, Utc};
-use json::JsonValue;
+use json::{object, JsonValue};
 use rust_decimal::Decimal;
 use uuid::Uuid;
 
@@ -145,3 +145,39 @@ impl JsonMerge for JsonValue {
     obj
   }
 }
+
+pub trait JsonDiff {
+  /// Changed values as `{ path, before, after }`, nested names of path joined by dots.
+  fn diff(&self, after: &JsonValue) -> Vec<JsonValue>;
+}
+
+impl JsonDiff for JsonValue {
+  fn diff(&self, after: &JsonValue) -> Vec<JsonValue> {
+    let mut changes = Vec::new();
+    diff_at("", self, after, &mut changes);
+    changes
+  }
+}
+
+fn diff_at(path: &str, before: &JsonValue, after: &JsonValue, changes: &mut Vec<JsonValue>) {
+  if before == after {
+    return;
+  }
+
+  let child = |name: &str| if path.is_empty() { name.to_string() } else { format!("{path}.{name}") };
+
+  if before.is_object() && after.is_object() {
+    for (name, value) in before.entries() {
+      diff_at(&child(name), value, &after[name], changes);
+    }
+    for (name, value) in after.entries().filter(|(name, _)| !before.has_key(name)) {
+      diff_at(&child(name), &JsonValue::Null, value, changes);
+    }
+  } else if before.is_array() && after.is_array() {
+    for i in 0..before.len().max(after.len()) {
+      diff_at(&child(&i.to_string()), &before[i], &after[i], changes);
+    }
+  } else {
+    changes.push(object! { path: path, before: before.clone(), after: after.clone() });
+  }
+}
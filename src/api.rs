@@ -8,7 +8,14 @@ use crate::animo::db::AnimoDB;
 use crate::animo::memory::Memory;
 use crate::animo::memory::{ChangeTransformation, TransformationKey};
 use crate::commutator::Application;
+use crate::inventory::export::export;
+use service::utils::json::JsonParams;
 use service::{Context, Services};
+use store::barcodes::ean13_svg;
+use store::elements::dt;
+use store::import::Format;
+use uuid::Uuid;
+use values::{c, ID};
 
 pub async fn not_implemented() -> impl Responder {
   HttpResponse::NotImplemented().json("")
@@ -84,17 +91,34 @@ pub async fn docs_update(
   let ctx: Vec<String> = params["ctx"].split(',').map(|s| s.to_string()).collect();
   let oid = params["oid"].clone();
 
-  let params: JsonValue = object! {"ctx": ctx, "oid": oid};
+  let mut params: JsonValue = object! {"ctx": ctx, "oid": oid};
+
+  // expected revision, stale one is rejected with 409
+  if let Some(revision) = req.headers().get(header::IF_MATCH).and_then(|v| v.to_str().ok()) {
+    params["revision"] = revision.trim_matches('"').into();
+  }
 
   let ctx = Context::rest(req.head().clone());
 
-  let result = web::block(move || app.service("docs").update(ctx, id, data, params))
-    .await?
-    .map_err(actix_web::error::ErrorInternalServerError)?;
+  let (result, revision) = web::block(move || -> Result<_, service::error::Error> {
+    let ws = app.wss.get(&crate::services::oid(&params)?);
+
+    let result = app.service("docs").update(ctx, id, data, params)?;
+
+    // new revision for `If-Match` of next write
+    let revision = ws.resolve_id(&result[c::ID].string()).and_then(|doc| doc.revision());
+
+    Ok((result, revision))
+  })
+  .await??;
 
   let result: serde_json::Value = serde_json::from_str(&result.dump()).unwrap();
 
-  Ok(HttpResponse::Ok().json(result))
+  let mut response = HttpResponse::Ok();
+  if let Some(revision) = revision {
+    response.insert_header((header::ETAG, format!("\"{revision}\"")));
+  }
+  Ok(response.json(result))
 }
 
 #[get("/api/docs/find")]
@@ -151,3 +175,65 @@ pub async fn inventory_find(
 
   Ok(res)
 }
+
+#[get("/api/inventory/export")]
+pub async fn inventory_export(
+  app: web::Data<Application>,
+  params: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, Error> {
+  let param = |name: &str| {
+    params
+      .get(name)
+      .cloned()
+      .ok_or_else(|| actix_web::error::ErrorBadRequest(format!("`{name}` is missing")))
+  };
+
+  let oid = ID::from_base64(param("oid")?.as_bytes()).map_err(actix_web::error::ErrorBadRequest)?;
+
+  let storages = param("storage")?
+    .split(',')
+    .map(Uuid::parse_str)
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(actix_web::error::ErrorBadRequest)?;
+
+  let (from_date, till_date) = (param("from_date")?, param("till_date")?);
+  let from = dt(&from_date).map_err(|e| actix_web::error::ErrorBadRequest(e.message()))?;
+  let till = dt(&till_date).map_err(|e| actix_web::error::ErrorBadRequest(e.message()))?;
+
+  let format = params.get("format").map(|f| f.as_str()).unwrap_or("csv");
+  let format = Format::try_from(format).map_err(actix_web::error::ErrorBadRequest)?;
+
+  let content = web::block(move || export(&app, &oid, &storages, from, till, format))
+    .await?
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+  let (content_type, extension) = match format {
+    Format::Csv => ("text/csv; charset=utf-8", "csv"),
+    Format::Xlsx => ("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet", "xlsx"),
+  };
+  let file_name = format!("inventory_{from_date}_{till_date}.{extension}");
+
+  let res = HttpResponse::Ok()
+    .append_header((header::CONTENT_TYPE, content_type))
+    .append_header((header::CONTENT_DISPOSITION, format!("attachment; filename=\"{file_name}\"")))
+    .body(content);
+
+  Ok(res)
+}
+
+#[get("/api/inventory/label")]
+pub async fn inventory_label(
+  params: web::Query<HashMap<String, String>>,
+) -> Result<HttpResponse, Error> {
+  let code = params
+    .get("code")
+    .ok_or_else(|| actix_web::error::ErrorBadRequest("`code` is missing"))?;
+
+  let svg = ean13_svg(code).map_err(|e| actix_web::error::ErrorBadRequest(e.message()))?;
+
+  let res = HttpResponse::Ok()
+    .append_header((header::CONTENT_TYPE, "image/svg+xml"))
+    .body(svg);
+
+  Ok(res)
+}
@@ -0,0 +1,267 @@
+use crate::storage::organizations::Workspace;
+use db::PrefixIterator;
+use json::{object, JsonValue};
+use rocksdb::{
+  BoundColumnFamily, ColumnFamilyDescriptor, Direction, IteratorMode, Options, WriteBatch, DB,
+};
+use service::error::Error;
+use service::utils::json::JsonParams;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use values::c;
+
+const CF_WS_TIME_ID: &str = "ws_time_id";
+const CF_WS_DOCUMENT_TIME: &str = "ws_document_time";
+
+// set once entries recorded before the document index are indexed, empty key is before any
+// entry, so scans of entries never meet it
+const INDEXED: &[u8] = &[];
+
+const SEPARATOR: u8 = 0;
+
+/// Who, when and from where made revision of document, kept by audit log only.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stamp {
+  pub time: String,
+  pub account: String,
+  pub origin: String,
+}
+
+/// Log of memories mutations per workspace in order of time.
+#[derive(Clone)]
+pub struct AuditLog {
+  pub database: Arc<DB>,
+  // order of mutations made at the same millisecond
+  seq: Arc<AtomicU64>,
+}
+
+impl AuditLog {
+  pub fn cf_name() -> &'static str {
+    CF_WS_TIME_ID
+  }
+
+  fn cf(&self) -> Result<Arc<BoundColumnFamily<'_>>, Error> {
+    if let Some(cf) = self.database.cf_handle(AuditLog::cf_name()) {
+      Ok(cf)
+    } else {
+      Err(Error::NotFound("column family not found".into()))
+    }
+  }
+
+  fn documents_cf(&self) -> Result<Arc<BoundColumnFamily<'_>>, Error> {
+    if let Some(cf) = self.database.cf_handle(CF_WS_DOCUMENT_TIME) {
+      Ok(cf)
+    } else {
+      Err(Error::NotFound("column family not found".into()))
+    }
+  }
+
+  pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+    std::fs::create_dir_all(&path).map_err(|e| Error::GeneralError(e.to_string()))?;
+
+    let mut opts = Options::default();
+
+    let mut cfs = Vec::new();
+    let cf = ColumnFamilyDescriptor::new(CF_WS_TIME_ID, opts.clone());
+    cfs.push(cf);
+    let cf = ColumnFamilyDescriptor::new(CF_WS_DOCUMENT_TIME, opts.clone());
+    cfs.push(cf);
+
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+
+    let tmp_db =
+      DB::open_cf_descriptors(&opts, &path, cfs).map_err(|e| Error::GeneralError(e.to_string()))?;
+    let inner_db = Arc::new(tmp_db);
+
+    let log = AuditLog { database: inner_db, seq: Arc::new(AtomicU64::new(0)) };
+    log.index()?;
+
+    Ok(log)
+  }
+
+  // entries recorded before the document index, once
+  fn index(&self) -> Result<(), Error> {
+    let (cf, documents) = (self.cf()?, self.documents_cf()?);
+    if self
+      .database
+      .get_cf(&documents, INDEXED)
+      .map_err(|e| Error::GeneralError(e.to_string()))?
+      .is_some()
+    {
+      return Ok(());
+    }
+
+    let mut batch = WriteBatch::default();
+    for item in self.database.iterator_cf(&cf, IteratorMode::Start) {
+      let (k, v) = item.map_err(|e| Error::GeneralError(e.to_string()))?;
+      let entry = AuditLog::from_bytes(&v)?;
+
+      // | ws | time | seq | id |
+      let (time, document) = (entry["time"].string(), entry["document"].string());
+      let ws = &k[..k.len() - document.len() - 8 - time.len()];
+      let seq = &k[ws.len() + time.len()..ws.len() + time.len() + 8];
+
+      let key: Vec<u8> = ws
+        .iter()
+        .chain(document.as_bytes())
+        .chain([SEPARATOR].iter())
+        .chain(time.as_bytes())
+        .chain(seq)
+        .copied()
+        .collect();
+      batch.put_cf(&documents, key, k);
+    }
+    batch.put_cf(&documents, INDEXED, "");
+
+    self.database.write(batch).map_err(|e| Error::GeneralError(e.to_string()))
+  }
+
+  /// Record mutation of document from `before` to `after` at revision of `stamp`.
+  pub fn record(
+    &self,
+    ws: &Workspace,
+    ctx: &[String],
+    stamp: &Stamp,
+    before: &JsonValue,
+    after: &JsonValue,
+  ) -> Result<(), Error> {
+    let document = after[c::ID].string();
+
+    let action = if before.is_null() { "create" } else { "update" };
+
+    let entry = object! {
+      time: stamp.time.clone(),
+      ctx: ctx.to_vec(),
+      document: document.clone(),
+      action: action,
+      account: stamp.account.clone(),
+      origin: stamp.origin.clone(),
+    };
+
+    let seq = self.seq.fetch_add(1, Ordering::SeqCst);
+    let key = AuditLog::to_bytes(ws, &stamp.time, seq, &document);
+
+    let mut batch = WriteBatch::default();
+    batch.put_cf(
+      &self.documents_cf()?,
+      AuditLog::document_key(ws, &document, &stamp.time, seq),
+      &key,
+    );
+    batch.put_cf(&self.cf()?, key, entry.dump());
+
+    self.database.write(batch).map_err(|e| Error::GeneralError(e.to_string()))
+  }
+
+  /// Entry of document revision made at `time`.
+  pub fn entry(&self, ws: &Workspace, time: &str, id: &str) -> Result<Option<JsonValue>, Error> {
+    let cf = self.cf()?;
+    let prefix = AuditLog::prefix(ws, time);
+    for (k, v) in self
+      .database
+      .prefix(&cf, prefix.clone())
+      .map_err(|e| Error::GeneralError(e.to_string()))?
+    {
+      // sequence number is followed by id
+      if &k[prefix.len() + 8..] == id.as_bytes() {
+        return Ok(Some(AuditLog::from_bytes(&v)?));
+      }
+    }
+    Ok(None)
+  }
+
+  // | ws | time |
+  fn prefix(ws: &Workspace, time: &str) -> Vec<u8> {
+    ws.id.as_slice().iter().chain(time.as_bytes()).copied().collect()
+  }
+
+  // | ws | time | seq | id |
+  fn to_bytes(ws: &Workspace, time: &str, seq: u64, id: &str) -> Vec<u8> {
+    AuditLog::prefix(ws, time)
+      .into_iter()
+      .chain(seq.to_be_bytes())
+      .chain(id.as_bytes().iter().copied())
+      .collect()
+  }
+
+  // | ws | document | 0 | time | seq |, value is key of entry
+  fn document_key(ws: &Workspace, document: &str, time: &str, seq: u64) -> Vec<u8> {
+    ws.id
+      .as_slice()
+      .iter()
+      .chain(document.as_bytes())
+      .chain([SEPARATOR].iter())
+      .chain(time.as_bytes())
+      .copied()
+      .chain(seq.to_be_bytes())
+      .collect()
+  }
+
+  fn from_bytes(bytes: &[u8]) -> Result<JsonValue, Error> {
+    let data = String::from_utf8_lossy(bytes);
+    json::parse(&data).map_err(|e| Error::GeneralError(e.to_string()))
+  }
+
+  /// Mutations of workspace accepted by `filter`, newest first.
+  pub fn find<F>(&self, ws: &Workspace, filter: F) -> Result<Vec<JsonValue>, Error>
+  where
+    F: Fn(&JsonValue) -> bool,
+  {
+    let cf = self.cf()?;
+    let prefix = ws.id.as_slice().to_vec();
+    // time is ascii, so keys of workspace are before this one
+    let last: Vec<u8> = prefix.iter().copied().chain([u8::MAX]).collect();
+
+    let mut result = Vec::new();
+    for item in self.database.iterator_cf(&cf, IteratorMode::From(&last, Direction::Reverse)) {
+      let (k, v) = item.map_err(|e| Error::GeneralError(e.to_string()))?;
+      if !k.starts_with(&prefix) {
+        break;
+      }
+      let entry = AuditLog::from_bytes(&v)?;
+      if filter(&entry) {
+        result.push(entry);
+      }
+    }
+    Ok(result)
+  }
+
+  /// Mutations of documents which `_id` starts with `documents`, accepted by `filter`, newest
+  /// first. Only entries of those documents are read.
+  pub fn find_documents<F>(
+    &self,
+    ws: &Workspace,
+    documents: &str,
+    filter: F,
+  ) -> Result<Vec<JsonValue>, Error>
+  where
+    F: Fn(&JsonValue) -> bool,
+  {
+    let (cf, index) = (self.cf()?, self.documents_cf()?);
+    let prefix: Vec<u8> = ws.id.as_slice().iter().chain(documents.as_bytes()).copied().collect();
+
+    // keys of entries are in order of log
+    let mut keys: Vec<Box<[u8]>> = self
+      .database
+      .prefix(&index, prefix)
+      .map_err(|e| Error::GeneralError(e.to_string()))?
+      .into_iter()
+      .map(|(_, key)| key)
+      .collect();
+    keys.sort();
+
+    let mut result = Vec::new();
+    for key in keys.iter().rev() {
+      if let Some(v) =
+        self.database.get_cf(&cf, key).map_err(|e| Error::GeneralError(e.to_string()))?
+      {
+        let entry = AuditLog::from_bytes(&v)?;
+        if filter(&entry) {
+          result.push(entry);
+        }
+      }
+    }
+    Ok(result)
+  }
+}
@@ -0,0 +1,10 @@
+use crate::audit::audit_log::AuditLog;
+
+pub mod audit_log;
+mod service;
+
+pub use service::Audit;
+
+pub trait GetAudit {
+  fn audit(&self) -> AuditLog;
+}
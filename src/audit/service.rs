@@ -0,0 +1,103 @@
+use crate::audit::GetAudit;
+use crate::commutator::Application;
+use crate::services::{Data, Params};
+use json::{object, JsonValue};
+use service::error::Error;
+use service::query::Query;
+use service::utils::json::JsonParams;
+use service::{Context, Service};
+use std::sync::Arc;
+
+/// Mutations of memories, filtered by `ctx`, `document`, `account`, `origin`, `action` or `time`.
+pub struct Audit {
+  app: Application,
+  path: Arc<String>,
+}
+
+impl Audit {
+  pub fn new(app: Application) -> Arc<dyn Service> {
+    Arc::new(Audit { app, path: Arc::new("audit".to_string()) })
+  }
+}
+
+impl Service for Audit {
+  fn path(&self) -> &str {
+    &self.path
+  }
+
+  fn find(&self, _ctx: Context, params: Params) -> crate::services::Result {
+    let oid = crate::services::oid(&params)?;
+
+    let limit = self.limit(&params);
+    let skip = self.skip(&params);
+
+    let query = Query::new(self.params(&params))?;
+
+    let ws = self.app.wss.get(&oid);
+
+    // document or context narrow log to entries of its documents, `_id` starts with context
+    let filter = &self.params(&params)["filter"];
+    let documents = if let Some(document) = filter["document"].as_str() {
+      Some(document.to_string())
+    } else if filter["ctx"].is_array() {
+      let ctx: Vec<String> = filter["ctx"].members().map(|c| c.string()).collect();
+      Some(format!("{}/", ctx.join("/")))
+    } else {
+      None
+    };
+
+    // latest first
+    let audit = self.app.audit();
+    let mut list = match documents {
+      Some(documents) => audit.find_documents(&ws, &documents, |entry| query.is_match(entry))?,
+      None => audit.find(&ws, |entry| query.is_match(entry))?,
+    };
+    query.sort(&mut list);
+
+    let total = list.len();
+    let list: Vec<JsonValue> = list
+      .into_iter()
+      .skip(skip)
+      .take(limit)
+      .map(|entry| query.project(entry))
+      .collect();
+
+    Ok(object! {
+      data: JsonValue::Array(list),
+      total: total,
+      "$skip": skip,
+    })
+  }
+
+  fn get(&self, _ctx: Context, _id: String, _params: Params) -> crate::services::Result {
+    Err(Error::NotImplemented)
+  }
+
+  fn create(&self, _ctx: Context, _data: Data, _params: Params) -> crate::services::Result {
+    Err(Error::NotImplemented)
+  }
+
+  fn update(
+    &self,
+    _ctx: Context,
+    _id: String,
+    _data: Data,
+    _params: Params,
+  ) -> crate::services::Result {
+    Err(Error::NotImplemented)
+  }
+
+  fn patch(
+    &self,
+    _ctx: Context,
+    _id: String,
+    _data: Data,
+    _params: Params,
+  ) -> crate::services::Result {
+    Err(Error::NotImplemented)
+  }
+
+  fn remove(&self, _ctx: Context, _id: String, _params: Params) -> crate::services::Result {
+    Err(Error::NotImplemented)
+  }
+}
@@ -8,6 +8,10 @@ use crossbeam::channel::{Receiver, Sender};
 use json::{array, JsonValue};
 use uuid::Uuid;
 
+use crate::audit::audit_log::AuditLog;
+use crate::audit::GetAudit;
+use crate::fields::fields_index::FieldsIndex;
+use crate::fields::GetFields;
 use crate::links::links_index::LinksIndex;
 use crate::links::GetLinks;
 use crate::services::{Event, Mutation};
@@ -17,6 +21,7 @@ use crate::{animo::db::AnimoDB, settings::Settings};
 use crate::{storage::Workspaces, ws};
 use service::error::Error;
 use service::{Service, Services};
+use store::elements::{receive_data, ToJson};
 use store::wh_storage::WHStorage;
 use store::GetWarehouse;
 
@@ -32,6 +37,8 @@ pub struct Application {
   pub wss: Workspaces,
   pub(crate) warehouse: WHStorage,
   links: LinksIndex,
+  fields: FieldsIndex,
+  audit: AuditLog,
 
   // background dispatcher
   stop: Arc<AtomicBool>,
@@ -53,6 +60,18 @@ impl GetLinks for Application {
   }
 }
 
+impl GetFields for Application {
+  fn fields(&self) -> FieldsIndex {
+    self.fields.clone()
+  }
+}
+
+impl GetAudit for Application {
+  fn audit(&self) -> AuditLog {
+    self.audit.clone()
+  }
+}
+
 impl Application {
   pub async fn new(
     settings: Arc<Settings>,
@@ -79,6 +98,8 @@ impl Application {
       warehouse: WHStorage::open(&settings.database.inventory)
         .map_err(|e| Error::GeneralError(e.message()))?,
       links: LinksIndex::open(&settings.database.links)?,
+      fields: FieldsIndex::open(&settings.database.fields)?,
+      audit: AuditLog::open(&settings.database.audit)?,
       // channels: Arc::new(HashMap::new()),
       stop: stop.clone(),
       events: events_sender,
@@ -86,6 +107,17 @@ impl Application {
       search: Arc::new(RwLock::new(SearchEngine::new(&settings.database.ftsearch))),
     };
 
+    // balances dropped below minimum are broadcast as `inventory reorder`
+    app.warehouse.on_reorder({
+      let events = app.events.clone();
+      move |reorder| {
+        let event = Event::Custom("inventory".into(), "reorder".into(), reorder.to_json());
+        if let Err(e) = events.send(event) {
+          println!("reorder event is not sent because of {e}");
+        }
+      }
+    });
+
     thread::spawn({
       let should_stop = stop;
       let r = receiver;
@@ -114,6 +146,24 @@ impl Application {
     Ok((app, events_receiver))
   }
 
+  /// Index min/max levels of goods kept by `warehouse/level` memories, needs `memories` service.
+  pub fn index_levels(&self) -> Result<(), Error> {
+    let ctx = vec!["warehouse".to_string(), "level".to_string()];
+    for ws in self.wss.list()? {
+      let wid = ws.id.to_string();
+      for doc in ws.memories(ctx.clone()).list(None)? {
+        receive_data(self, &wid, JsonValue::Null, doc.json()?, &ctx, &HashMap::new())
+          .map_err(|e| Error::GeneralError(e.message()))?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Index fields of memories that `find` answers from index, once for older databases.
+  pub fn index_fields(&self) -> Result<usize, Error> {
+    crate::storage::memories::index_fields(self)
+  }
+
   pub(crate) fn handle(&self, mutation: Mutation) -> crate::services::Result {
     match mutation {
       Mutation::Create(ctx, name, data, params) => {
@@ -152,6 +202,7 @@ impl Application {
       Event::Updated(name, _) => name,
       Event::Patched(name, _) => name,
       Event::Removed(name, _) => name,
+      Event::Custom(name, _, _) => name,
     };
     if service_name == "authentication" || service_name == "users" {
       // TODO || service_name == "actions" {
@@ -218,6 +269,7 @@ impl Commutator {
                 Event::Updated(name, data) => (format!("{name} updated"), data),
                 Event::Patched(name, data) => (format!("{name} patched"), data),
                 Event::Removed(name, data) => (format!("{name} removed"), data),
+                Event::Custom(name, event, data) => (format!("{name} {event}"), data),
               };
               let data = array![JsonValue::String(name.clone()), data];
               c.event_to_all(data.dump());
@@ -370,3 +422,108 @@ impl Handler<Disconnect> for Commutator {
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::memories::MemoriesInFiles;
+  use crate::warehouse::test_util::init;
+  use json::object;
+  use service::utils::json::JsonParams;
+  use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+  use values::c;
+
+  const WID: &str = "yjmgJUmDo_kn9uxVi8s9Mj9mgGRJISxRt63wT46NyTQ";
+
+  // client side of socket, acknowledgements only
+  struct Client(UnboundedSender<JsonValue>);
+
+  impl Actor for Client {
+    type Context = Context<Self>;
+  }
+
+  impl Handler<WsMessage> for Client {
+    type Result = ();
+
+    fn handle(&mut self, msg: WsMessage, _ctx: &mut Self::Context) -> Self::Result {
+      if msg.socket_code.as_deref() == Some(socket_io::ACK) {
+        let data = msg.data.trim_start_matches(|c: char| c.is_ascii_digit());
+        self.0.send(json::parse(data).unwrap()).unwrap();
+      }
+    }
+  }
+
+  async fn patch(
+    com: &Addr<Commutator>,
+    acks: &mut UnboundedReceiver<JsonValue>,
+    sid: Uuid,
+    id: &str,
+    data: JsonValue,
+    revision: &str,
+  ) -> JsonValue {
+    com
+      .send(ws::Event {
+        ctx: service::Context::local(),
+        sid,
+        event_id: "1".into(),
+        path: "memories".into(),
+        command: "patch".into(),
+        data: array![
+          id,
+          data,
+          object! { oid: WID, ctx: ["warehouse", "storage"], revision: revision }
+        ],
+      })
+      .await
+      .unwrap();
+    acks.recv().await.unwrap()
+  }
+
+  #[actix_web::test]
+  async fn check_socket_io_stale_revision() {
+    let (tmp_dir, settings, db) = init();
+
+    let wss = Workspaces::new(tmp_dir.path().join("companies"));
+
+    let (mut app, events) = Application::new(Arc::new(settings), Arc::new(db), wss).await.unwrap();
+    app.register(MemoriesInFiles::new(app.clone(), "memories"));
+
+    let com = Commutator::new(app.clone(), events).start();
+
+    let (sender, mut acks) = unbounded_channel();
+    let sid = Uuid::new_v4();
+    com
+      .send(Connect { sid, socket: Client(sender).start().recipient() })
+      .await
+      .unwrap();
+
+    let store = app
+      .service("memories")
+      .create(
+        service::Context::local(),
+        object! { name: "склад" },
+        object! { oid: WID, ctx: ["warehouse", "storage"] },
+      )
+      .unwrap();
+    let id = store[c::ID].string();
+    let loaded = store[c::REVISION].string();
+    assert!(!loaded.is_empty());
+
+    // revision of saved document is given back
+    let response = patch(&com, &mut acks, sid, &id, object! { name: "склад 1" }, &loaded).await;
+    assert!(response[0].is_null());
+    let saved = response[1][c::REVISION].string();
+    assert_ne!(saved, loaded);
+
+    // write based on stale revision is rejected
+    let response = patch(&com, &mut acks, sid, &id, object! { name: "склад 2" }, &loaded).await;
+    assert_eq!(response[0]["code"], 409);
+
+    let params = object! { oid: WID, ctx: ["warehouse", "storage"] };
+    let current = app.service("memories").get(service::Context::local(), id, params).unwrap();
+    assert_eq!(current["name"], "склад 1");
+    assert_eq!(current[c::REVISION], saved.as_str());
+
+    tmp_dir.close().unwrap();
+  }
+}
@@ -0,0 +1,321 @@
+use crate::storage::memories::Memories;
+use crate::storage::organizations::Workspace;
+use crate::storage::Workspaces;
+use blake2::digest::Output;
+use blake2::{digest::consts::U16, Blake2b, Digest};
+use db::PrefixIterator;
+use json::JsonValue;
+use rocksdb::{
+  BoundColumnFamily, ColumnFamilyDescriptor, Direction, IteratorMode, Options, WriteBatch, DB,
+};
+use service::error::Error;
+use service::utils::json::JsonParams;
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::Arc;
+use values::c;
+
+const CF_CTX_FIELD_VALUE_ID: &str = "ctx_field_value_id";
+
+/// Fields of memories that `find` can answer from index.
+pub const INDEXED_FIELDS: [&str; 4] = ["date", "document", "goods", "storage"];
+
+const SEPARATOR: u8 = 0;
+
+// set once documents of all workspaces are indexed, empty key is before any entry, so scans
+// of entries never meet it
+const INDEXED: &[u8] = &[];
+
+type Blake2b80 = Blake2b<U16>;
+
+/// Filter on indexed field which index can answer.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+  Equal(String),
+  StartsWith(String),
+  Range { gt: Option<String>, gte: Option<String>, lt: Option<String>, lte: Option<String> },
+}
+
+impl Condition {
+  /// Range condition of object with `$gt`, `$gte`, `$lt` or `$lte` only.
+  pub fn range(value: &JsonValue) -> Option<Condition> {
+    if !value.is_object() || value.is_empty() {
+      return None;
+    }
+
+    let (mut gt, mut gte, mut lt, mut lte) = (None, None, None, None);
+    for (n, v) in value.entries() {
+      let v = v.as_str()?.to_string();
+      match n {
+        "$gt" => gt = Some(v),
+        "$gte" => gte = Some(v),
+        "$lt" => lt = Some(v),
+        "$lte" => lte = Some(v),
+        _ => return None,
+      }
+    }
+    Some(Condition::Range { gt, gte, lt, lte })
+  }
+
+  pub fn is_match(&self, value: &str) -> bool {
+    match self {
+      Condition::Equal(v) => value == v,
+      Condition::StartsWith(v) => value.starts_with(v.as_str()),
+      Condition::Range { gt, gte, lt, lte } => {
+        gt.as_ref().map(|b| value > b.as_str()).unwrap_or(true)
+          && gte.as_ref().map(|b| value >= b.as_str()).unwrap_or(true)
+          && lt.as_ref().map(|b| value < b.as_str()).unwrap_or(true)
+          && lte.as_ref().map(|b| value <= b.as_str()).unwrap_or(true)
+      },
+    }
+  }
+
+  /// Conditions of `find` filter over indexed fields.
+  pub fn from_filter(filter: &JsonValue) -> Vec<(String, Condition)> {
+    let mut result = Vec::new();
+    for (n, v) in filter.entries() {
+      if n == "$starts-with" {
+        for (n, v) in v.entries() {
+          if let (true, Some(v)) = (INDEXED_FIELDS.contains(&n), v.as_str()) {
+            result.push((n.to_string(), Condition::StartsWith(v.to_string())));
+          }
+        }
+      } else if INDEXED_FIELDS.contains(&n) {
+        if let Some(v) = v.as_str() {
+          result.push((n.to_string(), Condition::Equal(v.to_string())));
+        } else if let Some(condition) = Condition::range(v) {
+          result.push((n.to_string(), condition));
+        }
+      }
+    }
+    result
+  }
+}
+
+/// Secondary index of memories: context, field and value to `_id`.
+#[derive(Clone)]
+pub struct FieldsIndex {
+  pub database: Arc<DB>,
+}
+
+impl FieldsIndex {
+  pub fn cf_name() -> &'static str {
+    CF_CTX_FIELD_VALUE_ID
+  }
+
+  fn cf(&self) -> Result<Arc<BoundColumnFamily<'_>>, Error> {
+    if let Some(cf) = self.database.cf_handle(FieldsIndex::cf_name()) {
+      Ok(cf)
+    } else {
+      Err(Error::NotFound("column family not found".into()))
+    }
+  }
+
+  pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+    std::fs::create_dir_all(&path).map_err(|e| Error::GeneralError(e.to_string()))?;
+
+    let mut opts = Options::default();
+
+    let mut cfs = Vec::new();
+    let cf = ColumnFamilyDescriptor::new(CF_CTX_FIELD_VALUE_ID, opts.clone());
+    cfs.push(cf);
+
+    opts.create_if_missing(true);
+    opts.create_missing_column_families(true);
+
+    let tmp_db =
+      DB::open_cf_descriptors(&opts, &path, cfs).map_err(|e| Error::GeneralError(e.to_string()))?;
+    let inner_db = Arc::new(tmp_db);
+
+    Ok(FieldsIndex { database: inner_db })
+  }
+
+  /// Replace index entries of `before` by entries of `after`.
+  pub fn save_fields(
+    &self,
+    ws: &Workspace,
+    ctx: &Vec<String>,
+    after: &JsonValue,
+    before: &JsonValue,
+  ) -> Result<(), Error> {
+    let ctx = FieldsIndex::ctx_to_hash(ws, ctx);
+
+    let before = self.entries(&ctx, before);
+    let after = self.entries(&ctx, after);
+
+    let cf = self.cf()?;
+    let mut batch = WriteBatch::default();
+    for key in before.difference(&after) {
+      batch.delete_cf(&cf, key);
+    }
+    for key in after.difference(&before) {
+      batch.put_cf(&cf, key, "");
+    }
+
+    self.database.write(batch).map_err(|e| Error::GeneralError(e.to_string()))
+  }
+
+  /// Index documents of all workspaces, once for database recorded before the index. Documents
+  /// saved later keep it by `save_fields`, so memories must not be saved meanwhile.
+  pub fn index(&self, wss: &Workspaces) -> Result<usize, Error> {
+    let cf = self.cf()?;
+    if self
+      .database
+      .get_cf(&cf, INDEXED)
+      .map_err(|e| Error::GeneralError(e.to_string()))?
+      .is_some()
+    {
+      return Ok(0);
+    }
+
+    // entries of interrupted indexing are replaced
+    let mut batch = WriteBatch::default();
+    for item in self.database.iterator_cf(&cf, IteratorMode::Start) {
+      let (k, _) = item.map_err(|e| Error::GeneralError(e.to_string()))?;
+      batch.delete_cf(&cf, k);
+    }
+
+    let mut count = 0;
+    for ws in wss.list()? {
+      for doc in ws.into_iter() {
+        if let Ok(data) = doc.json() {
+          let ctx = FieldsIndex::ctx_to_hash(&doc.mem.ws, &doc.mem.ctx);
+          for key in self.entries(&ctx, &data) {
+            batch.put_cf(&cf, key, "");
+          }
+          count += 1;
+        }
+      }
+    }
+    batch.put_cf(&cf, INDEXED, "");
+
+    self.database.write(batch).map_err(|e| Error::GeneralError(e.to_string()))?;
+
+    Ok(count)
+  }
+
+  fn entries(&self, ctx: &Output<Blake2b80>, data: &JsonValue) -> BTreeSet<Vec<u8>> {
+    let mut result = BTreeSet::new();
+    if let (true, Some(id)) = (data.is_object(), data[c::ID].string_or_none()) {
+      for field in INDEXED_FIELDS {
+        if let Some(value) = data[field].as_str() {
+          result.insert(self.to_bytes(ctx, field, value, &id));
+        }
+      }
+    }
+    result
+  }
+
+  // | ctx | field | value | 0 | id |
+  fn to_bytes(&self, ctx: &Output<Blake2b80>, field: &str, value: &str, id: &str) -> Vec<u8> {
+    self
+      .prefix_to_bytes(ctx, field)
+      .into_iter()
+      .chain(value.as_bytes().iter().copied())
+      .chain([SEPARATOR])
+      .chain(id.as_bytes().iter().copied())
+      .collect()
+  }
+
+  fn prefix_to_bytes(&self, ctx: &Output<Blake2b80>, field: &str) -> Vec<u8> {
+    let mut hasher = Blake2b80::new();
+    hasher.update(field);
+    let field = hasher.finalize();
+
+    ctx.iter().chain(field.as_slice()).copied().collect()
+  }
+
+  fn ctx_to_hash(ws: &Workspace, ctx: &[String]) -> Output<Blake2b80> {
+    let mut hasher = Blake2b80::new();
+    hasher.update(ws.id.to_string());
+    hasher.update("/");
+    hasher.update(ctx.join("/"));
+    hasher.finalize()
+  }
+
+  /// `_id` of documents which value of field meet condition.
+  pub fn lookup(
+    &self,
+    memories: &Memories,
+    field: &str,
+    condition: &Condition,
+  ) -> Result<BTreeSet<String>, Error> {
+    let ctx = FieldsIndex::ctx_to_hash(&memories.ws, &memories.ctx);
+    let prefix = self.prefix_to_bytes(&ctx, field);
+
+    let cf = self.cf()?;
+    let records = match condition {
+      Condition::Equal(value) => {
+        let mut prefix = prefix.clone();
+        prefix.extend(value.as_bytes());
+        prefix.push(SEPARATOR);
+        self
+          .database
+          .prefix(&cf, prefix)
+          .map_err(|e| Error::GeneralError(e.to_string()))?
+      },
+      Condition::StartsWith(value) => {
+        let mut prefix = prefix.clone();
+        prefix.extend(value.as_bytes());
+        self
+          .database
+          .prefix(&cf, prefix)
+          .map_err(|e| Error::GeneralError(e.to_string()))?
+      },
+      Condition::Range { gt, gte, lt, lte } => {
+        let mut from = prefix.clone();
+        if let Some(bound) = gte.as_ref().or(gt.as_ref()) {
+          from.extend(bound.as_bytes());
+        }
+
+        // keys are ordered by value, so stop after upper bound
+        let mut records = Vec::new();
+        for item in self.database.iterator_cf(&cf, IteratorMode::From(&from, Direction::Forward)) {
+          let (k, v) = item.map_err(|e| Error::GeneralError(e.to_string()))?;
+          if !k.starts_with(&prefix) {
+            break;
+          }
+          let entry = &k[prefix.len()..];
+          let value = &entry[..entry.iter().position(|b| *b == SEPARATOR).unwrap_or(entry.len())];
+          if lt.as_ref().map(|b| value >= b.as_bytes()).unwrap_or(false)
+            || lte.as_ref().map(|b| value > b.as_bytes()).unwrap_or(false)
+          {
+            break;
+          }
+          records.push((k, v));
+        }
+        records
+      },
+    };
+
+    let mut result = BTreeSet::new();
+    for (k, _) in records {
+      let entry = &k[prefix.len()..];
+      if let Some(pos) = entry.iter().position(|b| *b == SEPARATOR) {
+        let value = String::from_utf8_lossy(&entry[..pos]);
+        if condition.is_match(&value) {
+          result.insert(String::from_utf8_lossy(&entry[pos + 1..]).to_string());
+        }
+      }
+    }
+    Ok(result)
+  }
+
+  /// `_id` of documents which meet all conditions of filter over indexed fields,
+  /// `None` if there is no such conditions.
+  pub fn find(
+    &self,
+    memories: &Memories,
+    filter: &JsonValue,
+  ) -> Result<Option<BTreeSet<String>>, Error> {
+    let mut result: Option<BTreeSet<String>> = None;
+    for (field, condition) in Condition::from_filter(filter) {
+      let ids = self.lookup(memories, &field, &condition)?;
+      result = Some(match result {
+        Some(found) => found.intersection(&ids).cloned().collect(),
+        None => ids,
+      });
+    }
+    Ok(result)
+  }
+}
@@ -0,0 +1,7 @@
+use crate::fields::fields_index::FieldsIndex;
+
+pub mod fields_index;
+
+pub trait GetFields {
+  fn fields(&self) -> FieldsIndex;
+}
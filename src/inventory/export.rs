@@ -0,0 +1,219 @@
+use crate::commutator::Application;
+use crate::memories::Resolve;
+use crate::storage::organizations::Workspace;
+use chrono::{DateTime, Utc};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use rust_xlsxwriter::{Format as XlsxFormat, Workbook, XlsxError};
+use service::error::Error;
+use service::utils::json::JsonParams;
+use store::aggregations::AggregationStoreGoods;
+use store::balance::Cost;
+use store::elements::Store;
+use store::import::Format;
+use store::qty::Qty;
+use store::GetWarehouse;
+use values::ID;
+
+const COLUMNS: [&str; 11] = [
+  "storage",
+  "goods",
+  "uom",
+  "open qty",
+  "open cost",
+  "receive qty",
+  "receive cost",
+  "issue qty",
+  "issue cost",
+  "close qty",
+  "close cost",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+  Empty,
+  Text(String),
+  Number(Decimal),
+}
+
+/// Row of movement report, subtotal of store or of all stores is `total`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+  pub cells: Vec<Cell>,
+  pub total: bool,
+}
+
+/// Movement report of stores between dates as file of given format.
+pub fn export(
+  app: &Application,
+  oid: &ID,
+  storages: &[Store],
+  from: DateTime<Utc>,
+  till: DateTime<Utc>,
+  format: Format,
+) -> Result<Vec<u8>, Error> {
+  let ws = app.wss.get(oid);
+  let rows = movement_report(app, &ws, storages, from, till)?;
+
+  match format {
+    Format::Csv => to_csv(&rows),
+    Format::Xlsx => to_xlsx(&rows),
+  }
+}
+
+/// Open, receive, issue & close balances of goods with subtotal per store, and total of all
+/// stores if there are many of them.
+pub fn movement_report(
+  app: &Application,
+  ws: &Workspace,
+  storages: &[Store],
+  from: DateTime<Utc>,
+  till: DateTime<Utc>,
+) -> Result<Vec<Row>, Error> {
+  let mut rows = Vec::new();
+  let mut totals = [Cost::ZERO; 4];
+
+  for store in storages {
+    let report = app
+      .warehouse()
+      .database
+      .report_for_store(*store, from, till)
+      .map_err(|e| Error::GeneralError(e.message()))?;
+
+    let store_name = store.resolve_to_json_object(ws)["name"].string();
+
+    let mut goods: Vec<(String, &AggregationStoreGoods)> = report
+      .items
+      .1
+      .iter()
+      .filter_map(|item| item.goods.map(|g| (g.resolve_to_json_object(ws)["name"].string(), item)))
+      .collect();
+    goods.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    // issue is stored as negative, but report shows outgoing amounts
+    for (name, item) in goods {
+      let issue = -item.issue.qty.clone();
+      let qty = [&item.open_balance.qty, &item.receive.qty, &issue, &item.close_balance.qty];
+      let (uom, qty) = qty_cells(ws, qty);
+      let cost =
+        [item.open_balance.cost, item.receive.cost, -item.issue.cost, item.close_balance.cost];
+
+      let mut cells = vec![Cell::Text(store_name.clone()), Cell::Text(name), uom];
+      for (qty, cost) in qty.into_iter().zip(cost) {
+        cells.push(qty);
+        cells.push(Cell::Number(cost.number()));
+      }
+      rows.push(Row { cells, total: false });
+    }
+
+    let store = &report.items.0;
+    let cost = [store.open_balance, store.receive, -store.issue, store.close_balance];
+    for (total, cost) in totals.iter_mut().zip(cost) {
+      *total += cost;
+    }
+    rows.push(total_row(Cell::Text(store_name), cost));
+  }
+
+  if storages.len() > 1 {
+    rows.push(total_row(Cell::Empty, totals));
+  }
+
+  Ok(rows)
+}
+
+fn total_row(storage: Cell, cost: [Cost; 4]) -> Row {
+  let mut cells = vec![storage, Cell::Text("total".into()), Cell::Empty];
+  for cost in cost {
+    cells.push(Cell::Empty);
+    cells.push(Cell::Number(cost.number()));
+  }
+  Row { cells, total: true }
+}
+
+// quantities in the same base unit are numbers, otherwise they are text with units
+fn qty_cells(ws: &Workspace, qty: [&Qty; 4]) -> (Cell, Vec<Cell>) {
+  let lower: Vec<Qty> = qty.iter().map(|q| q.lower()).collect();
+
+  let mut units = lower.iter().flat_map(|q| q.inner.iter().map(|n| n.name.uuid()));
+  let unit = units.next();
+  let single = unit.is_some() && units.all(|u| Some(u) == unit);
+
+  let name = |uuid: uuid::Uuid| uuid.resolve_to_json_object(ws)["name"].string();
+
+  if single && lower.iter().all(|q| q.inner.len() <= 1) {
+    let uom = unit.map(|u| Cell::Text(name(u))).unwrap_or(Cell::Empty);
+    let cells = lower
+      .iter()
+      .map(|q| Cell::Number(q.inner.first().map(|n| n.number).unwrap_or_default()))
+      .collect();
+    (uom, cells)
+  } else {
+    let cells = lower
+      .iter()
+      .map(|q| {
+        let text: Vec<String> = q
+          .inner
+          .iter()
+          .map(|n| format!("{} {}", n.number, name(n.name.uuid())))
+          .collect();
+        Cell::Text(text.join(", "))
+      })
+      .collect();
+    (Cell::Empty, cells)
+  }
+}
+
+pub fn to_csv(rows: &[Row]) -> Result<Vec<u8>, Error> {
+  let mut writer = csv::Writer::from_writer(Vec::new());
+
+  let error = |e: csv::Error| Error::IOError(e.to_string());
+
+  writer.write_record(COLUMNS).map_err(error)?;
+  for row in rows {
+    let record: Vec<String> = row
+      .cells
+      .iter()
+      .map(|cell| match cell {
+        Cell::Empty => String::new(),
+        Cell::Text(text) => text.clone(),
+        Cell::Number(number) => number.normalize().to_string(),
+      })
+      .collect();
+    writer.write_record(record).map_err(error)?;
+  }
+
+  writer.into_inner().map_err(|e| Error::IOError(e.to_string()))
+}
+
+pub fn to_xlsx(rows: &[Row]) -> Result<Vec<u8>, Error> {
+  let error = |e: XlsxError| Error::IOError(e.to_string());
+
+  let mut workbook = Workbook::new();
+  let sheet = workbook.add_worksheet();
+
+  let bold = XlsxFormat::new().set_bold();
+  let plain = XlsxFormat::new();
+
+  for (col, name) in COLUMNS.iter().enumerate() {
+    sheet.write_string_with_format(0, col as u16, *name, &bold).map_err(error)?;
+  }
+
+  for (i, row) in rows.iter().enumerate() {
+    let format = if row.total { &bold } else { &plain };
+    for (col, cell) in row.cells.iter().enumerate() {
+      let (r, c) = (i as u32 + 1, col as u16);
+      match cell {
+        Cell::Empty => {},
+        Cell::Text(text) => {
+          sheet.write_string_with_format(r, c, text, format).map_err(error)?;
+        },
+        Cell::Number(number) => {
+          let number = number.to_f64().unwrap_or_default();
+          sheet.write_number_with_format(r, c, number, format).map_err(error)?;
+        },
+      }
+    }
+  }
+
+  workbook.save_to_buffer().map_err(error)
+}
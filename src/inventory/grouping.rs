@@ -0,0 +1,98 @@
+use crate::storage::organizations::Workspace;
+use json::JsonValue;
+use service::error::Error;
+use service::utils::json::JsonParams;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use store::elements::{Goods, Store};
+use store::grouping::{Attributes, Dimension, Key, Measure};
+use store::operations::Op;
+use uuid::Uuid;
+
+/// Categories of goods, documents and counterparties of operations from workspace memories.
+pub struct WorkspaceAttributes<'a> {
+  ws: &'a Workspace,
+  records: RwLock<HashMap<String, JsonValue>>,
+}
+
+impl<'a> WorkspaceAttributes<'a> {
+  pub fn new(ws: &'a Workspace) -> Self {
+    WorkspaceAttributes { ws, records: RwLock::new(HashMap::new()) }
+  }
+
+  // record as it is stored, without enrichment
+  fn record(&self, id: &str) -> JsonValue {
+    let mut records = self.records.write().unwrap();
+
+    records
+      .entry(id.to_string())
+      .or_insert_with(|| {
+        let document = match Uuid::parse_str(id) {
+          Ok(uuid) => self.ws.resolve_uuid(&uuid),
+          Err(_) => self.ws.resolve_id(id),
+        };
+        document.and_then(|d| d.json().ok()).unwrap_or(JsonValue::Null)
+      })
+      .clone()
+  }
+}
+
+fn key(id: &JsonValue) -> Key {
+  match id.as_str() {
+    Some(id) if !id.is_empty() => Key::Id(id.to_string()),
+    _ => Key::None,
+  }
+}
+
+impl Attributes for WorkspaceAttributes<'_> {
+  fn category(&self, goods: Goods) -> Key {
+    key(&self.record(&goods.to_string())["category"])
+  }
+
+  fn document(&self, op: &Op) -> Key {
+    key(&self.record(&op.id.to_string())["document"])
+  }
+
+  fn counterparty(&self, op: &Op) -> Key {
+    match self.document(op) {
+      Key::Id(document) => key(&self.record(&document)["counterparty"]),
+      _ => Key::None,
+    }
+  }
+}
+
+/// Dimensions, measures and storages of grouped report from filter of `inventory` find:
+/// `group_by` is list of dimensions, `measures` are all if omitted and report is of all
+/// storages if `storage` is omitted.
+pub(crate) fn grouping_params(
+  filter: &JsonValue,
+) -> Result<(Vec<Dimension>, Vec<Measure>, Vec<Store>), Error> {
+  let error = |e: store::error::WHError| Error::GeneralError(e.message());
+
+  let dimensions = filter["group_by"]
+    .members()
+    .map(|d| Dimension::try_from(d.as_str().unwrap_or_default()).map_err(error))
+    .collect::<Result<Vec<_>, _>>()?;
+
+  let measures = if filter["measures"].is_array() {
+    filter["measures"]
+      .members()
+      .map(|m| Measure::try_from(m.as_str().unwrap_or_default()).map_err(error))
+      .collect::<Result<Vec<_>, _>>()?
+  } else {
+    Measure::ALL.to_vec()
+  };
+
+  Ok((dimensions, measures, storages_param(filter)?))
+}
+
+/// Storages of `storage` filter, one or list of them, none if it is omitted.
+pub(crate) fn storages_param(filter: &JsonValue) -> Result<Vec<Store>, Error> {
+  if filter["storage"].is_array() {
+    filter["storage"].members().map(|s| s.uuid()).collect()
+  } else if filter["storage"].is_null() {
+    Ok(vec![])
+  } else {
+    Ok(vec![filter["storage"].uuid()?])
+  }
+}
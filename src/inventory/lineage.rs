@@ -0,0 +1,291 @@
+use crate::commutator::Application;
+use crate::links::GetLinks;
+use crate::storage::organizations::Workspace;
+use json::{object, JsonValue};
+use service::error::Error;
+use service::utils::json::JsonParams;
+use std::collections::{BTreeMap, BTreeSet};
+use store::batch::Batch;
+use store::elements::{record_op, Goods, ToJson};
+use store::operations::{InternalOperation, Op};
+use store::qty::Qty;
+use store::wh_storage::WHStorage;
+use uuid::Uuid;
+use values::c;
+use values::c::IntoDomain;
+use values::ID;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+  // from raw-material batch to produced batches and dispatches
+  Forward,
+  // from finished batch to consumed raw-material batches and receives
+  Backward,
+}
+
+impl Direction {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Direction::Forward => "forward",
+      Direction::Backward => "backward",
+    }
+  }
+}
+
+impl TryFrom<&str> for Direction {
+  type Error = Error;
+
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    match value {
+      "forward" => Ok(Direction::Forward),
+      "backward" => Ok(Direction::Backward),
+      _ => Err(Error::GeneralError(format!("unknown lineage direction {value}"))),
+    }
+  }
+}
+
+/// Batch of goods or memories document (production order, receive or dispatch).
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Node {
+  Batch(Goods, Batch),
+  Document(String),
+}
+
+impl Node {
+  fn id(&self) -> String {
+    match self {
+      Node::Batch(goods, batch) => format!("{goods}/{}", batch.id),
+      Node::Document(id) => id.clone(),
+    }
+  }
+
+  fn to_json(&self) -> JsonValue {
+    match self {
+      Node::Batch(goods, batch) => object! {
+        id: self.id(),
+        type: "batch",
+        goods: goods.to_json(),
+        batch: batch.to_json(),
+      },
+      Node::Document(id) => object! {
+        id: id.clone(),
+        type: "document",
+        ctx: ctx(id),
+      },
+    }
+  }
+}
+
+// how record of operation connects batch with document
+enum Link {
+  Receive,
+  Produce,
+  Use,
+  Dispatch,
+}
+
+impl Link {
+  fn of(ctx: &[String]) -> Option<Link> {
+    let ctx: Vec<&str> = ctx.iter().map(|s| s.as_str()).collect();
+    match ctx[..] {
+      ["warehouse", "receive"] => Some(Link::Receive),
+      ["production", "produce"] => Some(Link::Produce),
+      ["production", "material", "produced"] => Some(Link::Produce),
+      ["production", "material", "used"] => Some(Link::Use),
+      ["warehouse", "dispatch"] => Some(Link::Dispatch),
+      _ => None,
+    }
+  }
+}
+
+// context of memories record from its id, `warehouse/receive/2023-01-06T12:43:15Z`
+fn ctx(id: &str) -> Vec<String> {
+  let mut ctx: Vec<String> = id.split('/').map(|s| s.to_string()).collect();
+  ctx.pop();
+  ctx
+}
+
+/// Graph of batches and documents they went through, edges carry quantities.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lineage {
+  pub direction: Direction,
+  pub nodes: BTreeSet<Node>,
+  pub edges: BTreeMap<(Node, Node), Qty>,
+}
+
+impl Lineage {
+  pub fn to_json(&self) -> JsonValue {
+    let nodes: Vec<JsonValue> = self.nodes.iter().map(|node| node.to_json()).collect();
+    let edges: Vec<JsonValue> = self
+      .edges
+      .iter()
+      .map(|((from, into), qty)| {
+        object! {
+          from: from.id(),
+          into: into.id(),
+          qty: qty,
+        }
+      })
+      .collect();
+
+    object! {
+      direction: self.direction.as_str(),
+      nodes: nodes,
+      edges: edges,
+    }
+  }
+
+  fn edge(&mut self, from: Node, into: Node, qty: &Qty) {
+    self.nodes.insert(from.clone());
+    self.nodes.insert(into.clone());
+    *self.edges.entry((from, into)).or_default() += qty;
+  }
+}
+
+/// Forward lineage of raw-material batch: production orders it was used in, batches they
+/// produced and dispatches of them, recursively. Backward lineage of finished batch: orders
+/// produced it, raw-material batches they consumed and receives of those, recursively.
+pub fn lineage(
+  app: &Application,
+  oid: &ID,
+  goods: Goods,
+  batch: Batch,
+  direction: Direction,
+) -> Result<Lineage, Error> {
+  let ws = app.wss.get(oid);
+  let mut tracer = Tracer {
+    app,
+    ws: &ws,
+    warehouse: &app.warehouse,
+    visited: BTreeSet::new(),
+    lineage: Lineage { direction, nodes: BTreeSet::new(), edges: BTreeMap::new() },
+  };
+
+  let start = Node::Batch(goods, batch);
+  tracer.lineage.nodes.insert(start.clone());
+  tracer.batch(start)?;
+
+  Ok(tracer.lineage)
+}
+
+struct Tracer<'a> {
+  app: &'a Application,
+  ws: &'a Workspace,
+  warehouse: &'a WHStorage,
+  visited: BTreeSet<Node>,
+  lineage: Lineage,
+}
+
+impl Tracer<'_> {
+  fn batch(&mut self, node: Node) -> Result<(), Error> {
+    if !self.visited.insert(node.clone()) {
+      return Ok(());
+    }
+    let (goods, batch) = match &node {
+      Node::Batch(goods, batch) => (*goods, batch.clone()),
+      Node::Document(..) => return Ok(()),
+    };
+
+    let ops = self
+      .warehouse
+      .database
+      .ops_for_batch(goods, &batch)
+      .map_err(|e| Error::GeneralError(e.message()))?;
+    for op in ops.iter().filter(|op| op.store_into.is_none()) {
+      let (link, document) = match self.document(op) {
+        Some(found) => found,
+        None => continue,
+      };
+
+      match (self.lineage.direction, link, &op.op) {
+        (Direction::Forward, Link::Use, InternalOperation::Issue(qty, ..)) => {
+          self.lineage.edge(node.clone(), document.clone(), qty);
+          self.order(document)?;
+        },
+        (Direction::Forward, Link::Dispatch, InternalOperation::Issue(qty, ..)) => {
+          self.lineage.edge(node.clone(), document, qty);
+        },
+        (Direction::Backward, Link::Produce, InternalOperation::Receive(qty, _)) => {
+          self.lineage.edge(document.clone(), node.clone(), qty);
+          self.order(document)?;
+        },
+        (Direction::Backward, Link::Receive, InternalOperation::Receive(qty, _)) => {
+          self.lineage.edge(document, node.clone(), qty);
+        },
+        _ => {},
+      }
+    }
+
+    Ok(())
+  }
+
+  // batches produced by order for forward lineage or consumed by it for backward one
+  fn order(&mut self, node: Node) -> Result<(), Error> {
+    if !self.visited.insert(node.clone()) {
+      return Ok(());
+    }
+    let order = match self.ws.resolve_id(&node.id()).map(|doc| doc.json()) {
+      Some(order) => order?,
+      None => return Ok(()),
+    };
+    let uuid = order[c::UUID].uuid()?;
+    let wid = self.ws.id.to_string();
+
+    let contexts = match self.lineage.direction {
+      Direction::Forward => vec![c::P_PRODUCE.domain(), c::PM_PRODUCED.domain()],
+      Direction::Backward => vec![c::PM_USED.domain()],
+    };
+
+    for ctx in contexts {
+      for record in self.app.links().get_source_links_for_ctx(uuid, &ctx)? {
+        let record = match self.ws.resolve_uuid(&record) {
+          Some(record) => record.json()?,
+          None => continue,
+        };
+        // store and goods of record narrow the lookup
+        let op = match record_op(self.app, &wid, &record, &ctx)
+          .map_err(|e| Error::GeneralError(e.message()))?
+        {
+          Some(op) => op,
+          None => continue,
+        };
+        let ops = self
+          .warehouse
+          .database
+          .ops_for_record(&op)
+          .map_err(|e| Error::GeneralError(e.message()))?;
+
+        for op in ops.iter().filter(|op| !op.batch.is_empty()) {
+          let batch = Node::Batch(op.goods, op.batch.clone());
+          match (self.lineage.direction, &op.op) {
+            (Direction::Forward, InternalOperation::Receive(qty, _)) => {
+              self.lineage.edge(node.clone(), batch.clone(), qty);
+            },
+            (Direction::Backward, InternalOperation::Issue(qty, ..)) => {
+              self.lineage.edge(batch.clone(), node.clone(), qty);
+            },
+            _ => continue,
+          }
+          self.batch(batch)?;
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  // document of operation's record and how the record links them
+  fn document(&self, op: &Op) -> Option<(Link, Node)> {
+    let record = self.ws.resolve_uuid(&op.id)?.json().ok()?;
+    let link = Link::of(&ctx(&record[c::ID].string()))?;
+
+    let id = record[c::DOCUMENT].string();
+    let document = if let Ok(uuid) = Uuid::parse_str(&id) {
+      self.ws.resolve_uuid(&uuid)?
+    } else {
+      self.ws.resolve_id(&id)?
+    };
+
+    Some((link, Node::Document(document.json().ok()?[c::ID].string())))
+  }
+}
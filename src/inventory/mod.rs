@@ -1 +1,26 @@
+pub mod export;
+pub mod grouping;
+pub mod lineage;
+pub mod periods;
 pub mod service;
+pub mod stocktake;
+
+use crate::storage::organizations::Workspace;
+use json::JsonValue;
+use service::error::Error;
+use service::{Context, Origin};
+use values::ID_MIN;
+
+/// Local calls are trusted, remote account have to be listed at `accountants` of company.
+pub(crate) fn check_accountant(ctx: &Context, ws: &Workspace, action: &str) -> Result<(), Error> {
+  if ctx.origin == Origin::Local {
+    return Ok(());
+  }
+  let account = ctx.account.read().unwrap().id;
+  let company = ws.load().unwrap_or(JsonValue::Null);
+  if account != ID_MIN && company["accountants"].members().any(|a| *a == account.to_base64()) {
+    Ok(())
+  } else {
+    Err(Error::Forbidden(format!("{action} is not allowed for account")))
+  }
+}
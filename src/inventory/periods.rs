@@ -0,0 +1,113 @@
+use crate::commutator::Application;
+use crate::inventory::check_accountant;
+use crate::services::{Data, Params};
+use json::{object, JsonValue};
+use service::error::Error;
+use service::utils::json::JsonParams;
+use service::{Context, Service};
+use std::sync::Arc;
+use store::elements::ToJson;
+
+/// Closing and reopening of accounting periods of workspace, `create` with `action` ("close"
+/// or "reopen") and `date` of month. Only accounts listed at `accountants` of company may do it.
+pub struct Periods {
+  app: Application,
+  path: Arc<String>,
+}
+
+impl Periods {
+  pub fn new(app: Application) -> Arc<dyn Service> {
+    Arc::new(Periods { app, path: Arc::new("periods".to_string()) })
+  }
+}
+
+impl Service for Periods {
+  fn path(&self) -> &str {
+    &self.path
+  }
+
+  fn find(&self, _ctx: Context, params: Params) -> crate::services::Result {
+    let wid = crate::services::oid(&params)?.to_string();
+    let limit = self.limit(&params);
+    let skip = self.skip(&params);
+
+    let closed_till = self
+      .app
+      .warehouse
+      .closed_till(&wid)
+      .map_err(|e| Error::GeneralError(e.message()))?;
+
+    // latest first
+    let records = self
+      .app
+      .warehouse
+      .periods_audit(&wid)
+      .map_err(|e| Error::GeneralError(e.message()))?;
+
+    let total = records.len();
+    let list: Vec<JsonValue> = records
+      .iter()
+      .rev()
+      .skip(skip)
+      .take(limit)
+      .map(|record| record.to_json())
+      .collect();
+
+    Ok(object! {
+      data: JsonValue::Array(list),
+      total: total,
+      "$skip": skip,
+      closed_till: closed_till.map(|d| d.to_json()).unwrap_or(JsonValue::Null),
+    })
+  }
+
+  fn get(&self, _ctx: Context, _id: String, _params: Params) -> crate::services::Result {
+    Err(Error::NotImplemented)
+  }
+
+  fn create(&self, ctx: Context, data: Data, params: Params) -> crate::services::Result {
+    let oid = crate::services::oid(&params)?;
+    check_accountant(&ctx, &self.app.wss.get(&oid), "closing of periods")?;
+
+    let wid = oid.to_string();
+    let date = data["date"].date()?;
+    let by = ctx.account.read().unwrap().id.to_base64();
+
+    let record = match data["action"].as_str() {
+      Some("close") => self.app.warehouse.close_period(&wid, date, &by),
+      Some("reopen") => self.app.warehouse.reopen_period(&wid, date, &by),
+      _ => return Err(Error::GeneralError("action must be close or reopen".to_string())),
+    }
+    .map_err(|e| match e.closed_till() {
+      // period is already closed
+      Some(_) => Error::Conflict(e.message()),
+      None => Error::GeneralError(e.message()),
+    })?;
+
+    Ok(record.to_json())
+  }
+
+  fn update(
+    &self,
+    _ctx: Context,
+    _id: String,
+    _data: Data,
+    _params: Params,
+  ) -> crate::services::Result {
+    Err(Error::NotImplemented)
+  }
+
+  fn patch(
+    &self,
+    _ctx: Context,
+    _id: String,
+    _data: Data,
+    _params: Params,
+  ) -> crate::services::Result {
+    Err(Error::NotImplemented)
+  }
+
+  fn remove(&self, _ctx: Context, _id: String, _params: Params) -> crate::services::Result {
+    Err(Error::NotImplemented)
+  }
+}
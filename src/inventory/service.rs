@@ -1,22 +1,227 @@
 use crate::commutator::Application;
+use crate::inventory::grouping::{grouping_params, storages_param, WorkspaceAttributes};
+use crate::inventory::lineage::{lineage, Direction};
+use crate::memories::Resolve;
 use crate::services::{Data, Params};
+use crate::storage::organizations::Workspace;
 use chrono::{DateTime, Utc};
+use json::JsonValue;
 use service::error::Error;
 use service::utils::json::JsonParams;
+use service::utils::time::DateRange;
 use service::{Context, Service};
 use std::sync::Arc;
 use store::batch::Batch;
+use store::checkpoints::granularity::Granularity;
 use store::elements::ToJson;
+use values::ID;
 
 pub struct Inventory {
   app: Application,
   path: Arc<String>,
 }
 
+/// Report asked by filter. Keys are checked in the order of variants and the first present one
+/// is served, filter without any of them reports movements of store.
+enum Query {
+  Barcode(String),
+  Reorder,
+  Expiring(i64),
+  Lineage(Direction),
+  Grouped,
+  Turnover(Granularity),
+  Movements,
+}
+
+impl Query {
+  fn of(filter: &JsonValue) -> Result<Self, Error> {
+    let query = if let Some(code) = filter["barcode"].as_str() {
+      Query::Barcode(code.to_string())
+    } else if filter["reorder"].as_bool().unwrap_or_default() {
+      Query::Reorder
+    } else if let Some(days) = filter["expiring"].as_i64() {
+      Query::Expiring(days)
+    } else if let Some(direction) = filter["lineage"].as_str() {
+      Query::Lineage(Direction::try_from(direction)?)
+    } else if filter["group_by"].is_array() {
+      Query::Grouped
+    } else if let Some(granularity) = filter["turnover"].as_str() {
+      Query::Turnover(
+        Granularity::try_from(granularity).map_err(|e| Error::GeneralError(e.message()))?,
+      )
+    } else {
+      Query::Movements
+    };
+    Ok(query)
+  }
+}
+
+fn response(data: JsonValue, total: usize) -> JsonValue {
+  json::object! {
+    data: data,
+    total: total,
+    "$skip": 0,
+  }
+}
+
+fn list(data: Vec<JsonValue>) -> JsonValue {
+  let total = data.len();
+  response(JsonValue::Array(data), total)
+}
+
 impl Inventory {
   pub fn new(app: Application) -> Arc<dyn Service> {
     Arc::new(Inventory { app, path: Arc::new("inventory".to_string()) })
   }
+
+  fn dates(&self, filter: &JsonValue) -> Result<DateRange, Error> {
+    match self.date_range(filter)? {
+      Some(dates) => Ok(dates),
+      None => Err(Error::GeneralError("dates not defined".into())),
+    }
+  }
+
+  fn scan(&self, ws: &Workspace, code: &str) -> crate::services::Result {
+    let scan = self.app.warehouse.scan(code).map_err(|e| Error::GeneralError(e.message()))?;
+
+    Ok(list(
+      scan
+        .iter()
+        .map(|scan| {
+          let mut data = scan.to_json();
+          data["goods"] = scan.goods.resolve_to_json_object(ws);
+          for (i, (store, _)) in scan.balances.iter().enumerate() {
+            data["balances"][i]["store"] = store.resolve_to_json_object(ws);
+          }
+          data
+        })
+        .collect(),
+    ))
+  }
+
+  fn reorders(&self, ws: &Workspace, filter: &JsonValue) -> crate::services::Result {
+    let reorders = self
+      .app
+      .warehouse
+      .reorders(&storages_param(filter)?)
+      .map_err(|e| Error::GeneralError(e.message()))?;
+
+    Ok(list(
+      reorders
+        .iter()
+        .map(|reorder| {
+          let mut data = reorder.to_json();
+          data["store"] = reorder.store.resolve_to_json_object(ws);
+          data["goods"] = reorder.goods.resolve_to_json_object(ws);
+          data
+        })
+        .collect(),
+    ))
+  }
+
+  fn expiring(&self, ws: &Workspace, filter: &JsonValue, days: i64) -> crate::services::Result {
+    let expiring = self
+      .app
+      .warehouse
+      .expiring(&storages_param(filter)?, days)
+      .map_err(|e| Error::GeneralError(e.message()))?;
+
+    Ok(list(
+      expiring
+        .iter()
+        .map(|expiring| {
+          let mut data = expiring.to_json();
+          data["store"] = expiring.store.resolve_to_json_object(ws);
+          data["goods"] = expiring.goods.resolve_to_json_object(ws);
+          data
+        })
+        .collect(),
+    ))
+  }
+
+  fn lineage(&self, oid: &ID, filter: &JsonValue, direction: Direction) -> crate::services::Result {
+    let goods = filter["goods"].uuid()?;
+    let batch =
+      Batch { id: filter["batch_id"].uuid()?, date: filter["batch_date"].date_with_check()? };
+
+    let lineage = lineage(&self.app, oid, goods, batch, direction)?;
+
+    Ok(list(vec![lineage.to_json()]))
+  }
+
+  fn grouped(&self, ws: &Workspace, filter: &JsonValue) -> crate::services::Result {
+    let dates = self.dates(filter)?;
+    let (dimensions, measures, storages) = grouping_params(filter)?;
+
+    let report = self
+      .app
+      .warehouse
+      .database
+      .grouped_report(
+        &storages,
+        dimensions,
+        measures,
+        dates.0,
+        dates.1,
+        &WorkspaceAttributes::new(ws),
+      )
+      .map_err(|e| Error::GeneralError(e.message()))?;
+
+    Ok(list(vec![report.to_json()]))
+  }
+
+  fn turnover(&self, filter: &JsonValue, granularity: Granularity) -> crate::services::Result {
+    let dates = self.dates(filter)?;
+    let goods = filter["goods"].uuid()?;
+    let storage = filter["storage"].uuid_or_none();
+
+    let series = self
+      .app
+      .warehouse
+      .database
+      .turnover(goods, storage, granularity, dates.0, dates.1)
+      .map_err(|e| Error::GeneralError(e.message()))?;
+
+    Ok(list(series.iter().map(|turnover| turnover.to_json()).collect()))
+  }
+
+  // of batch, of goods or of the whole store
+  fn movements(&self, filter: &JsonValue) -> crate::services::Result {
+    let dates = self.dates(filter)?;
+    let storage = filter["storage"].uuid()?;
+    let database = &self.app.warehouse.database;
+
+    let goods = match filter["goods"].uuid() {
+      Ok(goods) => goods,
+      Err(_) => {
+        let report = database
+          .report_for_store(storage, dates.0, dates.1)
+          .map_err(|e| Error::GeneralError(e.message()))?
+          .to_json();
+        let total = report.len();
+        return Ok(response(report, total));
+      },
+    };
+
+    if let Ok(batch_id) = filter["batch_id"].uuid() {
+      let batch_date: DateTime<Utc> = filter["batch_date"].date_with_check()?;
+      let batch = Batch { id: batch_id, date: batch_date };
+
+      let report = database
+        .get_report_for_goods(storage, goods, &batch, dates.0, dates.1)
+        .map_err(|e| Error::GeneralError(e.message()))?;
+
+      Ok(response(report, 1))
+    } else {
+      let report = database
+        .get_report_for_store_goods(storage, goods, dates.0, dates.1)
+        .map_err(|e| Error::GeneralError(e.message()))?
+        .to_json();
+      let total = report.len();
+
+      Ok(response(report, total))
+    }
+  }
 }
 
 impl Service for Inventory {
@@ -25,7 +230,7 @@ impl Service for Inventory {
   }
 
   fn find(&self, _ctx: Context, params: Params) -> crate::services::Result {
-    let _oid = crate::services::oid(&params)?;
+    let oid = crate::services::oid(&params)?;
 
     // let limit = self.limit(&params);
     let skip = self.skip(&params);
@@ -38,77 +243,17 @@ impl Service for Inventory {
       });
     }
 
-    // println!("FN_FIND_PARAMS: {:#?}", params);
-
-    let params = self.params(&params);
-
-    let filter = params["filter"].clone();
-
-    let dates = if let Some(dates) = self.date_range(&filter)? {
-      dates
-    } else {
-      return Err(Error::GeneralError("dates not defined".into()));
-    };
-
-    let storage = filter["storage"].uuid()?;
-
-    if let Ok(goods) = filter["goods"].uuid() {
-      if let Ok(batch_id) = filter["batch_id"].uuid() {
-        let batch_date: DateTime<Utc> = filter["batch_date"].date_with_check()?;
-
-        let batch = Batch { id: batch_id, date: batch_date };
-
-        // println!("get_report_for_goods {batch:?}");
-
-        let report = match self
-          .app
-          .warehouse
-          .database
-          .get_report_for_goods(storage, goods, &batch, dates.0, dates.1)
-        {
-          Ok(report) => report,
-          Err(error) => return Err(Error::GeneralError(error.message())),
-        };
+    let filter = &self.params(&params)["filter"];
+    let ws = self.app.wss.get(&oid);
 
-        // println!("REPORT = {report:?}");
-
-        Ok(json::object! {
-          data: report,
-          total: 1,
-          "$skip": 0,
-        })
-      } else {
-        let report = match self
-          .app
-          .warehouse
-          .database
-          .get_report_for_store_goods(storage, goods, dates.0, dates.1)
-        {
-          Ok(report) => report.to_json(),
-          Err(error) => return Err(Error::GeneralError(error.message())),
-        };
-
-        // println!("REPORT = {report:?}");
-
-        Ok(json::object! {
-          total: report.len(),
-          data: report,
-          "$skip": 0,
-        })
-      }
-    } else {
-      let report = match self.app.warehouse.database.report_for_store(storage, dates.0, dates.1) {
-        Ok(report) => report.to_json(),
-        Err(error) => return Err(Error::GeneralError(error.message())),
-      };
-
-      // println!("REPORT = {report:?}");
-
-      Ok(json::object! {
-      total: report.len(),
-      data: report,
-      "$skip": 0,
-      })
+    match Query::of(filter)? {
+      Query::Barcode(code) => self.scan(&ws, &code),
+      Query::Reorder => self.reorders(&ws, filter),
+      Query::Expiring(days) => self.expiring(&ws, filter, days),
+      Query::Lineage(direction) => self.lineage(&oid, filter, direction),
+      Query::Grouped => self.grouped(&ws, filter),
+      Query::Turnover(granularity) => self.turnover(filter, granularity),
+      Query::Movements => self.movements(filter),
     }
   }
 
@@ -0,0 +1,190 @@
+use crate::commutator::Application;
+use crate::inventory::check_accountant;
+use crate::services::{Data, Params};
+use json::{object, JsonValue};
+use service::error::Error;
+use service::utils::json::JsonParams;
+use service::{Context, Service, Services};
+use std::sync::{Arc, Mutex};
+use store::balance::Cost;
+use store::batch::Batch;
+use store::elements::{Mode, ToJson};
+use store::operations::InternalOperation;
+use store::qty::Qty;
+use store::stocktake::StockTake;
+use uuid::Uuid;
+use values::c;
+
+/// Stock-takes of stores of workspace. `create` with `storage` and `date` takes snapshot of
+/// expected balances, `patch` with `goods`, `batch` and `qty` records counted quantity and with
+/// `action` "post" posts differences by `warehouse/inventory` document. Only accounts listed
+/// at `accountants` of company may do it.
+pub struct StockTakes {
+  app: Application,
+  path: Arc<String>,
+  // one posting at a time, so stock-take is never posted by two documents
+  posting: Mutex<()>,
+}
+
+impl StockTakes {
+  pub fn new(app: Application) -> Arc<dyn Service> {
+    Arc::new(StockTakes { app, path: Arc::new("stocktake".to_string()), posting: Mutex::new(()) })
+  }
+
+  fn to_json(&self, stocktake: &StockTake) -> JsonValue {
+    stocktake.to_json(&self.app.warehouse.database.conversions)
+  }
+
+  // workspace of request with checked rights of account
+  fn wid(&self, ctx: &Context, params: &Params) -> Result<String, Error> {
+    let oid = crate::services::oid(params)?;
+    check_accountant(ctx, &self.app.wss.get(&oid), "stock-take")?;
+    Ok(oid.to_string())
+  }
+
+  fn stocktake(&self, wid: &str, id: Uuid) -> Result<StockTake, Error> {
+    match self
+      .app
+      .warehouse
+      .database
+      .stocktake(wid, id)
+      .map_err(|e| Error::GeneralError(e.message()))?
+    {
+      Some(stocktake) => Ok(stocktake),
+      None => Err(Error::NotFound(format!("stock-take {id}"))),
+    }
+  }
+
+  /// Differences are recorded by inventory document, so they are seen by history and audit
+  /// and replayed with other documents.
+  fn post(&self, ctx: Context, wid: &str, id: Uuid) -> Result<StockTake, Error> {
+    let _posting = self.posting.lock().unwrap();
+
+    let stocktake = self.stocktake(wid, id)?;
+    if stocktake.posted {
+      return Err(Error::Conflict(format!("stock-take {id} is already posted")));
+    }
+
+    let memories = self.app.service("memories");
+
+    let document = memories.create(
+      ctx.clone(),
+      object! {
+        date: stocktake.date.to_json(),
+        storage: stocktake.store.to_json(),
+        stocktake: id.to_json(),
+      },
+      object! { oid: wid, ctx: ["warehouse", "inventory", "document"] },
+    )?;
+    let document = document[c::ID].string();
+
+    // posted before records are written, failed one is corrected at the document
+    let stocktake = self
+      .app
+      .warehouse
+      .stocktake_post(wid, id, &document)
+      .map_err(|e| Error::GeneralError(e.message()))?;
+
+    for op in stocktake.adjustments() {
+      let balance = match op.after {
+        Some((InternalOperation::Inventory(balance, _, _), _)) => balance,
+        _ => continue,
+      };
+      let qty: JsonValue = (&balance.qty).into();
+      memories.create(
+        ctx.clone(),
+        object! {
+          document: document.clone(),
+          goods: op.goods.to_json(),
+          batch: { _uuid: op.batch.id.to_json(), date: op.batch.date.to_json() },
+          qty: qty,
+          cost: { number: balance.cost.to_json() },
+          mode: Mode::Counted.to_json(),
+        },
+        object! { oid: wid, ctx: ["warehouse", "inventory"] },
+      )?;
+    }
+
+    Ok(stocktake)
+  }
+}
+
+fn id(id: &str) -> Result<Uuid, Error> {
+  Uuid::parse_str(id).map_err(|e| Error::GeneralError(e.to_string()))
+}
+
+impl Service for StockTakes {
+  fn path(&self) -> &str {
+    &self.path
+  }
+
+  fn find(&self, _ctx: Context, _params: Params) -> crate::services::Result {
+    Err(Error::NotImplemented)
+  }
+
+  fn get(&self, ctx: Context, id: String, params: Params) -> crate::services::Result {
+    let wid = self.wid(&ctx, &params)?;
+    let id = self::id(&id)?;
+
+    Ok(self.to_json(&self.stocktake(&wid, id)?))
+  }
+
+  fn create(&self, ctx: Context, data: Data, params: Params) -> crate::services::Result {
+    let wid = self.wid(&ctx, &params)?;
+    let id = data["id"].uuid_or_none().unwrap_or_else(Uuid::new_v4);
+    let store = data["storage"].uuid()?;
+    let date = data["date"].date()?;
+
+    let stocktake = self
+      .app
+      .warehouse
+      .stocktake_start(&wid, id, store, date)
+      .map_err(|e| Error::GeneralError(e.message()))?;
+
+    Ok(self.to_json(&stocktake))
+  }
+
+  fn update(
+    &self,
+    _ctx: Context,
+    _id: String,
+    _data: Data,
+    _params: Params,
+  ) -> crate::services::Result {
+    Err(Error::NotImplemented)
+  }
+
+  fn patch(&self, ctx: Context, id: String, data: Data, params: Params) -> crate::services::Result {
+    let wid = self.wid(&ctx, &params)?;
+    let id = self::id(&id)?;
+
+    let stocktake = if data["action"].as_str() == Some("post") {
+      self.post(ctx, &wid, id)?
+    } else {
+      let goods = data["goods"].uuid()?;
+      // surplus of goods without batch is received into the batch of stock-take
+      let batch = if data["batch"].is_null() {
+        Batch::no()
+      } else {
+        Batch { id: data["batch"]["id"].uuid()?, date: data["batch"]["date"].date_with_check()? }
+      };
+      let qty: Qty = data["qty"]
+        .clone()
+        .try_into()
+        .map_err(|e: store::error::WHError| Error::GeneralError(e.message()))?;
+      let cost = data["cost"]["number"].number_or_none().map(Cost::from);
+
+      self
+        .app
+        .warehouse
+        .stocktake_count(&wid, id, goods, batch, qty, cost)
+        .map_err(|e| Error::GeneralError(e.message()))?
+    };
+
+    Ok(self.to_json(&stocktake))
+  }
+
+  fn remove(&self, _ctx: Context, _id: String, _params: Params) -> crate::services::Result {
+    Err(Error::NotImplemented)
+  }
+}
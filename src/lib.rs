@@ -1,5 +1,7 @@
+pub mod audit;
 mod auth;
 pub mod commutator;
+pub mod fields;
 pub mod inventory;
 pub mod services;
 pub mod settings;
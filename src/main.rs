@@ -1,3 +1,4 @@
+use crate::audit::Audit;
 use crate::commutator::{Application, Commutator};
 use actix::{Actor, Addr};
 use actix_cors::Cors;
@@ -9,13 +10,15 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io;
 use std::io::{Error, ErrorKind, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use structopt::StructOpt;
 use uuid::Uuid;
 
+mod audit;
 mod auth;
 mod commutator;
+mod fields;
 mod inventory;
 mod services;
 mod settings;
@@ -35,20 +38,25 @@ pub mod warehouse;
 
 use crate::hr::services::companies::Companies;
 use crate::links::GetLinks;
-use crate::memories::MemoriesInFiles;
+use crate::memories::{MemoriesHistory, MemoriesImport, MemoriesInFiles};
 use crate::settings::Settings;
 use crate::storage::organizations::Workspace;
 use crate::storage::Workspaces;
 use crate::warehouse::primitive_types::Decimal;
 use animo::db::AnimoDB;
 use animo::memory::Memory;
+use inventory::periods::Periods;
 use inventory::service::Inventory;
+use inventory::stocktake::StockTakes;
 use service::utils::json::JsonParams;
 use service::Services;
 use store::balance::BalanceForGoods;
+use store::elements::ToJson;
 use store::error::WHError;
+use store::journal::Recovery;
 use store::operations::OpMutation;
 use store::qty::Qty;
+use store::wh_storage::WHStorage;
 use values::c;
 
 #[derive(StructOpt, Debug)]
@@ -68,6 +76,98 @@ struct Opt {
   /// port
   #[structopt(short, long, default_value = "3030")]
   port: u16,
+
+  /// Rebuild broken topologies at verify and migrate modes
+  #[structopt(long)]
+  repair: bool,
+}
+
+fn journal(app: &Application, recovery: Recovery) -> io::Result<()> {
+  let recovered = app
+    .warehouse
+    .recover(recovery)
+    .map_err(|e| Error::new(ErrorKind::Other, e.message()))?;
+
+  if !recovered.done.is_empty() {
+    println!("journal: {recovery:?} of {} pending mutations", recovered.done.len());
+  }
+
+  if recovered.failed.is_empty() {
+    Ok(())
+  } else {
+    for (seq, e) in recovered.failed.iter() {
+      println!("journal: {recovery:?} of #{seq} failed: {}", e.message());
+    }
+    Err(Error::new(
+      ErrorKind::Other,
+      format!(
+        "{} pending mutations failed, roll them back by `--mode journal -c rollback`",
+        recovered.failed.len()
+      ),
+    ))
+  }
+}
+
+fn verify(app: &Application, repair: bool) -> io::Result<()> {
+  let storage = &app.warehouse;
+  let err = |e: WHError| Error::new(ErrorKind::Other, e.message());
+
+  let verification = storage.verify().map_err(err)?;
+
+  let mut report = verification.to_json();
+  if repair && !verification.is_consistent() {
+    storage.repair(&verification).map_err(err)?;
+    report["repaired"] = verification.broken().into();
+  }
+
+  println!("{}", report.pretty(2));
+
+  Ok(())
+}
+
+// rebuilds interrupted by shutdown, like of checkpoints of store after change of its granularity,
+// are continued in background
+fn resume_rebuilds(app: &Application) {
+  let warehouse = app.warehouse.clone();
+  std::thread::spawn(move || {
+    let result = warehouse.resume_rebuilds(10_000, &mut |progress| {
+      log::info!("rebuild {}: {} operations", progress.topology, progress.operations);
+      Ok(())
+    });
+    if let Err(e) = result {
+      log::error!("can't continue rebuild: {}", e.message());
+    }
+  });
+}
+
+fn rebuild(app: &Application, name: &str) -> io::Result<()> {
+  let progress = app
+    .warehouse
+    .rebuild(name, 10_000, &mut |progress| {
+      println!("rebuild {}: {} operations", progress.topology, progress.operations);
+      Ok(())
+    })
+    .map_err(|e| Error::new(ErrorKind::Other, e.message()))?;
+
+  println!("rebuild {}: done", progress.topology);
+
+  Ok(())
+}
+
+fn migrate(path: &Path, repair: bool) -> io::Result<()> {
+  let verification = WHStorage::migrate_checkpoints(path, repair, &mut |progress| {
+    println!("migrate {}: {} operations", progress.topology, progress.operations);
+    Ok(())
+  })
+  .map_err(|e| Error::new(ErrorKind::Other, e.message()))?;
+
+  println!("{}", verification.to_json().pretty(2));
+
+  if verification.is_consistent() {
+    Ok(())
+  } else {
+    Err(Error::new(ErrorKind::Other, "verification failed, older checkpoints are kept"))
+  }
 }
 
 async fn fix_topologies(app: Application) -> io::Result<()> {
@@ -150,7 +250,7 @@ async fn fix_topologies(app: Application) -> io::Result<()> {
     // println!("}},");
     // ================================================================
 
-    cur_balance.apply(&cur.op);
+    cur_balance.apply(&cur.op, &app.warehouse.database.conversions.of(cur.goods));
     // println!("balance after: {:?} {cur_balance:?}", cur.store);
     // println!("====================================================================================");
 
@@ -491,6 +591,8 @@ async fn server(
       .wrap(middleware::Logger::default())
       // .wrap(auth)
       .service(web::scope("/socket.io").service(ws::start::start_connection))
+      .service(api::inventory_export)
+      .service(api::inventory_label)
       .service(web::scope("/"))
       .service(
         web::scope("/v1")
@@ -513,6 +615,11 @@ async fn startup() -> io::Result<()> {
   let opt = Opt::from_args();
 
   let settings = Arc::new(Settings::new().unwrap());
+  // warehouse storage with older checkpoints can't be opened till they are migrated by this mode
+  if opt.mode == "migrate" {
+    return migrate(&settings.database.inventory, opt.repair);
+  }
+
   println!("db starting up");
   let db: AnimoDB = Memory::init(settings.database.memory.clone()).unwrap();
   println!("db started up");
@@ -523,6 +630,13 @@ async fn startup() -> io::Result<()> {
       .await
       .map_err(|e| io::Error::new(io::ErrorKind::Unsupported, e))?;
 
+  // finish pending mutations before anything else touch the storage, diagnostic modes inspect
+  // it as it is
+  if !["verify", "rebuild", "fix", "journal"].contains(&opt.mode.as_str()) {
+    journal(&app, Recovery::Replay)?;
+    resume_rebuilds(&app);
+  }
+
   {
     let mut engine = app.search.write().unwrap();
     engine.load(app.wss.clone()).unwrap();
@@ -534,7 +648,17 @@ async fn startup() -> io::Result<()> {
   app.register(Companies::new(app.clone()));
 
   app.register(MemoriesInFiles::new(app.clone(), "memories"));
+  app.register(MemoriesHistory::new(app.clone(), "memories-history"));
+  app.register(MemoriesImport::new(app.clone(), "memories-import"));
   app.register(Inventory::new(app.clone()));
+  app.register(Audit::new(app.clone()));
+  app.register(Periods::new(app.clone()));
+  app.register(StockTakes::new(app.clone()));
+
+  // min/max levels of goods are kept by memories
+  app.index_levels()?;
+  // before any document is saved by requests
+  app.index_fields()?;
 
   println!("app started up");
 
@@ -611,6 +735,13 @@ async fn startup() -> io::Result<()> {
       _ => unreachable!(),
     },
     "fix" => fix_topologies(app).await,
+    "verify" => verify(&app, opt.repair),
+    "rebuild" => rebuild(&app, &opt.case),
+    "journal" => match opt.case.as_str() {
+      "replay" => journal(&app, Recovery::Replay),
+      "rollback" => journal(&app, Recovery::Rollback),
+      case => Err(Error::new(ErrorKind::InvalidInput, format!("unknown journal action {case}"))),
+    },
     _ => unreachable!(),
   }
 }
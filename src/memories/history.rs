@@ -0,0 +1,136 @@
+use json::{object, JsonValue};
+use service::error::Error;
+use service::utils::json::{JsonDiff, JsonParams};
+use service::utils::time::{string_to_time, time_to_string};
+use service::{Context, Service};
+use std::sync::Arc;
+
+use crate::audit::GetAudit;
+use crate::commutator::Application;
+use crate::services::{Data, Params};
+
+/// Revisions of memories documents and changes between them.
+pub struct MemoriesHistory {
+  app: Application,
+  name: Arc<String>,
+}
+
+impl MemoriesHistory {
+  pub fn new(app: Application, name: &str) -> Arc<dyn Service> {
+    Arc::new(MemoriesHistory { app, name: Arc::new(name.to_string()) })
+  }
+}
+
+impl Service for MemoriesHistory {
+  fn path(&self) -> &str {
+    &self.name
+  }
+
+  fn find(&self, _ctx: Context, params: Params) -> crate::services::Result {
+    let oid = crate::services::oid(&params)?;
+    let ctx = self.ctx(&params);
+
+    let id = self.params(&params)["id"].string();
+
+    let ws = self.app.wss.get(&oid);
+    let doc = ws
+      .memories(ctx.clone())
+      .get(&id)
+      .ok_or_else(|| Error::NotFound(format!("id `{id}` not found at {ctx:?}")))?;
+
+    // who made revision is known by audit log
+    let audit = self.app.audit();
+    let mut list = Vec::new();
+    for revision in doc.revisions()? {
+      let time = time_to_string(revision.time);
+      let entry = audit.entry(&ws, &time, &id)?.unwrap_or(JsonValue::Null);
+      list.push(object! {
+        revision: time,
+        account: entry["account"].clone(),
+        origin: entry["origin"].clone(),
+      });
+    }
+
+    let total = list.len();
+
+    Ok(object! {
+      data: JsonValue::Array(list),
+      total: total,
+      "$skip": 0,
+    })
+  }
+
+  fn get(&self, _ctx: Context, id: String, params: Params) -> crate::services::Result {
+    let oid = crate::services::oid(&params)?;
+    let ctx = self.ctx(&params);
+
+    let ws = self.app.wss.get(&oid);
+    let doc = ws
+      .memories(ctx.clone())
+      .get(&id)
+      .ok_or_else(|| Error::NotFound(format!("id `{id}` not found at {ctx:?}")))?;
+
+    let revisions = doc.revisions()?;
+
+    // `to` is latest revision and `from` is one before it by default
+    let position = |name: &str| -> Result<usize, Error> {
+      let time = string_to_time(name)?;
+      revisions
+        .iter()
+        .position(|r| r.time == time)
+        .ok_or_else(|| Error::NotFound(format!("revision `{name}` not found")))
+    };
+
+    let to = match self.params(&params)["to"].as_str() {
+      Some(name) => position(name)?,
+      None => revisions
+        .len()
+        .checked_sub(1)
+        .ok_or_else(|| Error::NotFound(format!("id `{id}` has no revisions")))?,
+    };
+    let from = match self.params(&params)["from"].as_str() {
+      Some(name) => Some(position(name)?),
+      None => to.checked_sub(1),
+    };
+
+    let before = match from {
+      Some(from) => revisions[from].json()?,
+      None => JsonValue::Null,
+    };
+    let after = revisions[to].json()?;
+
+    Ok(object! {
+      from: from.map(|from| time_to_string(revisions[from].time)),
+      to: time_to_string(revisions[to].time),
+      changes: before.diff(&after),
+    })
+  }
+
+  fn create(&self, _ctx: Context, _data: Data, _params: Params) -> crate::services::Result {
+    Err(Error::NotImplemented)
+  }
+
+  fn update(
+    &self,
+    _ctx: Context,
+    _id: String,
+    _data: Data,
+    _params: Params,
+  ) -> crate::services::Result {
+    Err(Error::NotImplemented)
+  }
+
+  fn patch(
+    &self,
+    _ctx: Context,
+    _id: String,
+    _data: Data,
+    _params: Params,
+  ) -> crate::services::Result {
+    Err(Error::NotImplemented)
+  }
+
+  fn remove(&self, _ctx: Context, _id: String, _params: Params) -> crate::services::Result {
+    Err(Error::NotImplemented)
+  }
+}
@@ -0,0 +1,80 @@
+use base64::Engine;
+use service::error::Error;
+use service::{Context, Service};
+use std::sync::Arc;
+use store::elements::ToJson;
+use store::import::{import, Format, Mapping};
+
+use crate::commutator::Application;
+use crate::services::{Data, Params};
+
+/// Import of warehouse documents from file by `{mapping, format, content, dry_run}`, content
+/// of xlsx file is base64 encoded.
+pub struct MemoriesImport {
+  app: Application,
+  name: Arc<String>,
+}
+
+impl MemoriesImport {
+  pub fn new(app: Application, name: &str) -> Arc<dyn Service> {
+    Arc::new(MemoriesImport { app, name: Arc::new(name.to_string()) })
+  }
+}
+
+impl Service for MemoriesImport {
+  fn path(&self) -> &str {
+    &self.name
+  }
+
+  fn find(&self, _ctx: Context, _params: Params) -> crate::services::Result {
+    Err(Error::NotImplemented)
+  }
+
+  fn get(&self, _ctx: Context, _id: String, _params: Params) -> crate::services::Result {
+    Err(Error::NotImplemented)
+  }
+
+  fn create(&self, _ctx: Context, data: Data, params: Params) -> crate::services::Result {
+    let oid = crate::services::oid(&params)?;
+
+    let mapping = Mapping::try_from(&data["mapping"])?;
+    let format = Format::try_from(data["format"].as_str().unwrap_or("csv"))?;
+    let dry_run = data["dry_run"].as_bool().unwrap_or(false);
+
+    let content = data["content"].as_str().unwrap_or_default();
+    let content = match format {
+      Format::Csv => content.as_bytes().to_vec(),
+      Format::Xlsx => base64::engine::general_purpose::STANDARD
+        .decode(content)
+        .map_err(|e| Error::GeneralError(format!("content is not valid base64: {e}")))?,
+    };
+
+    let report = import(&self.app, &oid.to_base64(), &mapping, format, &content, dry_run)?;
+
+    Ok(report.to_json())
+  }
+
+  fn update(
+    &self,
+    _ctx: Context,
+    _id: String,
+    _data: Data,
+    _params: Params,
+  ) -> crate::services::Result {
+    Err(Error::NotImplemented)
+  }
+
+  fn patch(
+    &self,
+    _ctx: Context,
+    _id: String,
+    _data: Data,
+    _params: Params,
+  ) -> crate::services::Result {
+    Err(Error::NotImplemented)
+  }
+
+  fn remove(&self, _ctx: Context, _id: String, _params: Params) -> crate::services::Result {
+    Err(Error::NotImplemented)
+  }
+}
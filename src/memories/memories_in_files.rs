@@ -3,7 +3,9 @@ use super::*;
 use chrono::Utc;
 use json::{object, JsonValue};
 use service::error::Error;
-use service::utils::json::{JsonMerge, JsonParams};
+use service::query::Query;
+use service::utils::json::JsonParams;
+use service::utils::time::string_to_time;
 use service::{Context, Service};
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -16,6 +18,7 @@ use crate::services::{Data, Params};
 
 use crate::commutator::Application;
 
+use crate::fields::GetFields;
 use crate::links::GetLinks;
 use stock::find_items;
 use store::qty::Qty;
@@ -101,12 +104,29 @@ impl Service for MemoriesInFiles {
         .map_err(|e| Error::GeneralError(e.message()))?;
       // log::debug!("balances: {balances:?}");
 
-      return find_items(&ws, &balances, &filter, skip);
+      let reserved = warehouse
+        .reservations
+        .reserved(Utc::now(), &warehouse.conversions)
+        .map_err(|e| Error::GeneralError(e.message()))?;
+
+      return find_items(&ws, &balances, &reserved, &warehouse.conversions, &filter, skip);
     }
 
+    let search = &self.params(&params)["search"];
+    let filters = &self.params(&params)["filter"];
+
+    let query = Query::new(self.params(&params))?;
+
     let ws = self.app.wss.get(&wsid);
+    let resolve = |id: &str| id.resolve_to_json_object(&ws);
     let memories = ws.memories(ctx.clone());
-    let list = memories.list(Some(reverse))?;
+    let list = match (search.is_string(), filters.is_object()) {
+      (false, true) => match self.app.fields().find(&memories, filters)? {
+        Some(ids) => memories.documents(ids, Some(reverse)),
+        None => memories.list(Some(reverse))?,
+      },
+      _ => memories.list(Some(reverse))?,
+    };
 
     fn show_deleted(ctx: &Vec<String>) -> bool {
       let ctx: Vec<&str> = ctx.iter().map(|s| s.as_str()).collect();
@@ -122,8 +142,6 @@ impl Service for MemoriesInFiles {
       }
     }
 
-    let search = &self.params(&params)["search"];
-    let filters = &self.params(&params)["filter"];
     let (total, mut list): (isize, Vec<JsonValue>) = if let Some(search) = search.as_str() {
       let search = search.to_lowercase();
       let mut total = 0;
@@ -156,23 +174,24 @@ impl Service for MemoriesInFiles {
         (-1, list)
       }
     } else if filters.is_object() {
-      let mut total = 0;
-      let list: Vec<JsonValue> = list
+      let matched = list
         .into_iter()
         .map(|o| o.json().unwrap_or_else(|_| JsonValue::Null))
         .filter(|o| o.is_object())
         .filter(|o| show_deleted(&ctx) || o[c::STATUS].string().as_str() != c::DELETED)
-        .filter(|o| {
-          filters.entries().all(|(n, v)| {
-            if n == "$starts-with" {
-              v.entries().all(|(n, v)| {
-                o[n].as_str().map(|s| s.starts_with(v.as_str().unwrap_or(""))).unwrap_or(false)
-              })
-            } else {
-              &o[n] == v
-            }
-          })
-        })
+        .filter(|o| query.is_match_with(o, &resolve));
+
+      // sorting require all of them
+      let matched: Box<dyn Iterator<Item = JsonValue>> = if query.is_sorted() {
+        let mut matched: Vec<JsonValue> = matched.collect();
+        query.sort_with(&mut matched, &resolve);
+        Box::new(matched.into_iter())
+      } else {
+        Box::new(matched)
+      };
+
+      let mut total = 0;
+      let list: Vec<JsonValue> = matched
         .map(|o| {
           total += 1;
           o
@@ -187,6 +206,11 @@ impl Service for MemoriesInFiles {
       } else {
         (-1, list)
       }
+    } else if query.is_sorted() {
+      let mut list: Vec<JsonValue> = list.into_iter().map(|o| o.json()).collect::<Result<_, _>>()?;
+      query.sort_with(&mut list, &resolve);
+
+      (list.len() as isize, list.into_iter().skip(skip).take(limit).collect())
     } else {
       (
         list.len() as isize,
@@ -383,6 +407,8 @@ impl Service for MemoriesInFiles {
       }
     }
 
+    let list = list.into_iter().map(|o| query.project(o)).collect();
+
     Ok(object! {
       data: JsonValue::Array(list),
       total: total,
@@ -402,30 +428,44 @@ impl Service for MemoriesInFiles {
     let ws = self.app.wss.get(&oid);
 
     if let Some(memories) = ws.memories(ctx.clone()).get(&id) {
+      // document as it was at given time
+      let data = if let Some(at) = self.params(&params)["at"].as_str() {
+        match memories.revision_at(string_to_time(at)?)? {
+          Some(revision) => revision.json()?,
+          None => return Err(Error::NotFound(format!("id `{id}` did not exist at {at}"))),
+        }
+      } else {
+        let mut data = memories.json()?;
+        if let Some(revision) = memories.revision() {
+          data[c::REVISION] = revision.into();
+        }
+        data
+      };
+
       if do_enrich {
-        Ok(memories.json()?.enrich(&ws))
+        Ok(data.enrich(&ws))
       } else {
-        memories.json()
+        Ok(data)
       }
     } else {
       Err(Error::GeneralError(format!("id `{id}` not found at {ctx:?}")))
     }
   }
 
-  fn create(&self, _ctx: Context, data: Data, params: Params) -> crate::services::Result {
+  fn create(&self, context: Context, data: Data, params: Params) -> crate::services::Result {
     let oid = crate::services::oid(&params)?;
     let ctx = self.ctx(&params);
 
     let ws = self.app.wss.get(&oid);
 
-    let data = ws.memories(ctx).create(&self.app, data)?;
+    let data = ws.memories(ctx).create(&self.app, &context, data)?;
 
     Ok(data.enrich(&ws))
   }
 
   fn update(
     &self,
-    _ctx: Context,
+    context: Context,
     id: String,
     data: Data,
     params: Params,
@@ -443,13 +483,21 @@ impl Service for MemoriesInFiles {
       let ws = self.app.wss.get(&oid);
       let memories = ws.memories(ctx);
 
-      let data = memories.update(&self.app, id, data)?;
+      let revision = self.params(&params)["revision"].as_str();
+
+      let data = memories.update(&self.app, &context, id, revision, data)?;
 
       Ok(data.enrich(&ws))
     }
   }
 
-  fn patch(&self, _ctx: Context, id: String, data: Data, params: Params) -> crate::services::Result {
+  fn patch(
+    &self,
+    context: Context,
+    id: String,
+    data: Data,
+    params: Params,
+  ) -> crate::services::Result {
     let oid = crate::services::oid(&params)?;
     let ctx = self.ctx(&params);
 
@@ -463,21 +511,12 @@ impl Service for MemoriesInFiles {
     if !data.is_object() {
       Err(Error::GeneralError("only object allowed".into()))
     } else {
-      let doc = memories.get(&id).ok_or(Error::GeneralError(format!("id '{id}' not found")))?;
-      let obj = doc.json()?;
-
       let mut patch = data;
       patch.remove(c::ID); // TODO check id?
 
-      let obj = obj.merge(&patch);
-
-      // for (n, v) in data.entries() {
-      //   if n != _ID {
-      //     obj[n] = v.clone();
-      //   }
-      // }
+      let revision = self.params(&params)["revision"].as_str();
 
-      let data = memories.update(&self.app, id, obj.clone())?;
+      let data = memories.patch(&self.app, &context, id, revision, patch)?;
 
       Ok(data.enrich(&ws))
     }
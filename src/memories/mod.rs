@@ -1,8 +1,12 @@
+mod history;
+mod import;
 mod memories_in_files;
 pub(crate) mod stock;
 
 use crate::storage::organizations::Workspace;
 use json::JsonValue;
+pub use history::MemoriesHistory;
+pub use import::MemoriesImport;
 pub use memories_in_files::MemoriesInFiles;
 use store::qty::Qty;
 use uuid::Uuid;
@@ -1,5 +1,7 @@
+use crate::inventory::grouping::WorkspaceAttributes;
 use crate::memories::{Enrich, Resolve};
 use crate::storage::organizations::Workspace;
+use chrono::Utc;
 use json::JsonValue;
 use service::utils::json::JsonParams;
 use std::collections::{HashMap, HashSet};
@@ -7,19 +9,25 @@ use std::hash::Hash;
 use std::sync::RwLock;
 use store::balance::{BalanceForGoods, Cost};
 use store::batch::Batch;
+use store::conversions::{Conversions, Factors};
 use store::elements::{Goods, Store, ToJson};
+use store::grouping::{Attributes, Dimension, Group, Grouping, Key};
+use store::qty::Qty;
+use store::reservations::Reserved;
 use uuid::Uuid;
 use values::c;
 
 pub(crate) fn find_items(
   ws: &Workspace,
   balances: &HashMap<Store, HashMap<Goods, HashMap<Batch, BalanceForGoods>>>,
+  reserved: &Reserved,
+  conversions: &Conversions,
   filters: &JsonValue,
   skip: usize,
 ) -> crate::services::Result {
   println!("find_items filter: {filters:?}");
 
-  let items = process(balances, filters, ws);
+  let items = process(balances, reserved, conversions, filters, ws);
   let total = items.len();
 
   log::debug!("fn_find_items: {items:?}");
@@ -33,13 +41,14 @@ pub(crate) fn find_items(
 
 fn process(
   balances: &HashMap<Store, HashMap<Goods, HashMap<Batch, BalanceForGoods>>>,
+  reserved: &Reserved,
+  conversions: &Conversions,
   filters: &JsonValue,
   ws: &Workspace,
 ) -> Vec<JsonValue> {
-  let mut storages_aggregation = HashMap::new();
-  let mut categories_aggregation = HashMap::new();
   let mut goods_aggregation = HashMap::new();
   let mut batches_aggregation = HashMap::new();
+  let mut reserved_counted = HashSet::new();
 
   let cache = Cache::new(ws);
 
@@ -47,6 +56,12 @@ fn process(
   let cat_filter = filters["category"].uuid_or_none();
   let goods_filter = filters["goods"].uuid_or_none();
 
+  let attributes =
+    StockAttributes { cache: &cache, filter: store_filter, goods: WorkspaceAttributes::new(ws) };
+  let now = Utc::now();
+  let mut storages = Grouping::new(vec![Dimension::Store], now, now, &attributes, conversions);
+  let mut categories = Grouping::new(vec![Dimension::Category], now, now, &attributes, conversions);
+
   for (store, sb) in balances {
     for (goods, gb) in sb {
       for (batch, bb) in gb {
@@ -58,12 +73,10 @@ fn process(
         // filtering
 
         // storage
-        let (store_top, before, store_found) = top_and_before(&cache, *store, store_filter);
-        // println!("store {store:?} store_top {store_top:?} before {before:?} found {store_found}");
+        let (_, _, store_found) = top_and_before(&cache, *store, store_filter);
         if store_filter.is_some() && !store_found {
           continue;
         }
-        let store_uuid = before.unwrap_or(store_top);
 
         // category
         let goods_obj = goods.resolve_to_json_object(ws);
@@ -91,27 +104,43 @@ fn process(
         }
 
         // aggregate
-        let cost = storages_aggregation.entry(store_uuid).or_insert(Cost::ZERO);
-        *cost += bb.cost;
-
-        let cost = categories_aggregation.entry(category_id).or_insert(Cost::ZERO);
-        *cost += bb.cost;
-
-        let balance = goods_aggregation.entry(*goods).or_insert(BalanceForGoods::default());
-        balance.qty += &bb.qty;
-        balance.cost += bb.cost;
+        storages.balance(*store, *goods, batch, bb);
+        categories.balance(*store, *goods, batch, bb);
+
+        let reservations = reserved.get(store).and_then(|sr| sr.get(goods));
+
+        let stock = goods_aggregation
+          .entry(*goods)
+          .or_insert_with(|| Stock::of(*goods, conversions));
+        stock.add_balance(bb);
+        // reservations of goods are counted once per store
+        if reserved_counted.insert((*store, *goods)) {
+          for qty in reservations.iter().flat_map(|r| r.values()) {
+            stock.add_reserved(qty);
+          }
+        }
 
         if goods_filter.is_some() {
-          let balance = batches_aggregation
+          let stock = batches_aggregation
             .entry((*store, *goods, batch.clone()))
-            .or_insert(BalanceForGoods::default());
-          balance.qty += &bb.qty;
-          balance.cost += bb.cost;
+            .or_insert_with(|| Stock::of(*goods, conversions));
+          stock.add_balance(bb);
+          if let Some(qty) = reservations.and_then(|r| r.get(batch)) {
+            stock.add_reserved(qty);
+          }
         }
       }
     }
   }
 
+  let mut storages_aggregation: HashMap<Uuid, Cost> = costs(storages.build())
+    .filter_map(|(key, cost)| if let Key::Uuid(uuid) = key { Some((uuid, cost)) } else { None })
+    .collect();
+
+  let categories_aggregation: HashMap<String, Cost> = costs(categories.build())
+    .map(|(key, cost)| if let Key::Id(id) = key { (id, cost) } else { (String::new(), cost) })
+    .collect();
+
   // workaround: remove filtering storage at aggregation
   if let Some(store) = store_filter.as_ref() {
     storages_aggregation.remove(store);
@@ -147,6 +176,63 @@ fn process(
   }
 }
 
+/// Cost of balances of every group.
+fn costs(total: Group) -> impl Iterator<Item = (Key, Cost)> {
+  total
+    .children
+    .into_iter()
+    .map(|group| (group.key, group.movement.close_balance.cost))
+}
+
+/// Balances are counted at the top storage, or at the one right under the filtering storage.
+struct StockAttributes<'a> {
+  cache: &'a Cache<'a>,
+  filter: Option<Uuid>,
+  goods: WorkspaceAttributes<'a>,
+}
+
+impl Attributes for StockAttributes<'_> {
+  fn store(&self, store: Store) -> Key {
+    let (top, before, _) = top_and_before(self.cache, store, self.filter);
+    Key::Uuid(before.unwrap_or(top))
+  }
+
+  fn category(&self, goods: Goods) -> Key {
+    self.goods.category(goods)
+  }
+}
+
+/// Balance with quantity reserved of it, `factors` of goods convert units of quantities.
+#[derive(Default)]
+struct Stock {
+  balance: BalanceForGoods,
+  reserved: Qty,
+  factors: Factors,
+}
+
+impl Stock {
+  fn of(goods: Goods, conversions: &Conversions) -> Self {
+    Stock { factors: conversions.of(goods), ..Stock::default() }
+  }
+
+  fn add_balance(&mut self, balance: &BalanceForGoods) {
+    self.balance = self.balance.add_balance(balance, &self.factors);
+  }
+
+  fn add_reserved(&mut self, qty: &Qty) {
+    self.reserved = self.reserved.add_with(qty, &self.factors);
+  }
+}
+
+impl ToJson for Stock {
+  fn to_json(&self) -> JsonValue {
+    let mut data = self.balance.to_json();
+    data["reserved"] = (&self.reserved).into();
+    data["available"] = (&self.balance.qty.sub_with(&self.reserved, &self.factors)).into();
+    data
+  }
+}
+
 fn top_and_before(cache: &Cache, store: Store, filter: Option<Uuid>) -> (Uuid, Option<Uuid>, bool) {
   if filter.is_some() && Some(store) == filter {
     return (store, None, true);
@@ -28,6 +28,8 @@ pub enum Event {
   Updated(String, Data),
   Patched(String, Data),
   Removed(String, Data),
+  // service name, event name, data
+  Custom(String, String, Data),
 }
 
 pub fn id(name: &str, params: &Params) -> std::result::Result<ID, Error> {
@@ -8,9 +8,21 @@ pub struct Database {
   pub memory: PathBuf,
   pub inventory: PathBuf,
   pub links: PathBuf,
+  #[serde(default = "default_fields")]
+  pub fields: PathBuf,
+  #[serde(default = "default_audit")]
+  pub audit: PathBuf,
   pub ftsearch: PathBuf,
 }
 
+fn default_fields() -> PathBuf {
+  "./data/fields".into()
+}
+
+fn default_audit() -> PathBuf {
+  "./data/audit".into()
+}
+
 #[derive(Debug, Deserialize)]
 pub struct JWTConfig {
   pub audience: String,
@@ -59,6 +71,8 @@ impl Settings {
         memory: folder.join("memory"),
         inventory: folder.join("inventory"),
         links: folder.join("links"),
+        fields: folder.join("fields"),
+        audit: folder.join("audit"),
         ftsearch: folder.join("tantivy"),
       },
     }
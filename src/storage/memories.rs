@@ -6,17 +6,22 @@ use chrono::{DateTime, Utc};
 use json::JsonValue;
 
 use service::error::Error;
-use service::utils::time::time_to_string;
+use service::utils::time::{string_to_time, time_to_string};
+use service::Context;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use crate::audit::audit_log::Stamp;
+use crate::audit::GetAudit;
+use crate::fields::GetFields;
 use crate::links::GetLinks;
 use crate::memories::{Enrich, Resolve};
 use crate::utils::substring::StringUtils;
-use service::utils::json::JsonParams;
+use service::utils::json::{JsonMerge, JsonParams};
 use std::collections::HashMap;
 use std::sync::Mutex;
-use store::elements::receive_data;
+use store::elements::{receive_data, ToJson};
+use store::error::WHError;
 use uuid::Uuid;
 use values::c;
 
@@ -43,24 +48,41 @@ fn save_data(
   folder: &PathBuf,
   ctx: &Vec<String>,
   _id: &String,
-  _uuid: Option<Uuid>,
-  time: DateTime<Utc>,
+  mut time: DateTime<Utc>,
+  context: &Context,
+  revision: Option<&str>,
+  patch: bool,
   mut data: JsonValue,
 ) -> Result<JsonValue, Error> {
   let mut stack: HashMap<String, (JsonValue, JsonValue)> = HashMap::new();
 
-  let (before, after) = {
+  // name of revision is given back, not saved
+  data.remove(c::REVISION);
+
+  let (before, after, time_str) = {
     let _lock = LOCK.lock().unwrap();
 
     // if data[_ID] != id {
     //   return Err(Error::IOError(format!("incorrect id {id} vs {}", data[_ID])));
     // }
 
-    let time_str = time_to_string(time);
+    // revision is named by time in milliseconds, write at the same millisecond as previous
+    // one takes next free name, so revisions never overwrite each other
+    let (time_str, file_name, path_current) = loop {
+      let time_str = time_to_string(time);
+
+      let file_name = format!("{time_str}.json");
+      let mut path_current = folder.clone();
+      path_current.push(&file_name);
+
+      // empty file is reserved by `create` for this write
+      if path_current.metadata().map(|m| m.len() > 0).unwrap_or(false) {
+        time += chrono::Duration::milliseconds(1);
+        continue;
+      }
 
-    let file_name = format!("{time_str}.json");
-    let mut path_current = folder.clone();
-    path_current.push(&file_name);
+      break (time_str, file_name, path_current);
+    };
 
     // 2023/01/2023-01-06T12:43:15Z/latest.json
     let mut path_latest = folder.clone();
@@ -73,20 +95,41 @@ fn save_data(
     // data = { _id: "", date: "2023-01-11", storage: "uuid", goods: [{goods: "", uom: "", qty: 0, price: 0, cost: 0, _tid: ""}, ...]}
     // cost = qty * price
 
+    // write based on stale revision would overwrite changes made since
+    if let Some(expected) = revision {
+      let current = latest_revision(&path_latest).unwrap_or_default();
+      if current != expected {
+        return Err(Error::Conflict(format!(
+          "document `{_id}` is at revision `{current}`, but `{expected}` expected"
+        )));
+      }
+    }
+
     // println!("loading before {path_latest:?}");
 
-    let before = match load(&path_latest) {
-      Ok(b) => {
-        //WORKAROUND: make sure that id & uuid stay same
-        if !b[c::ID].is_null() {
-          data[c::ID] = b[c::ID].clone();
-        }
-        if !b[c::UUID].is_null() {
-          data[c::UUID] = b[c::UUID].clone();
-        }
-        b
-      },
-      Err(_) => JsonValue::Null,
+    let before = load(&path_latest).unwrap_or(JsonValue::Null);
+
+    // patch is merged with the latest revision under the lock, so concurrent changes are kept
+    if patch {
+      if before.is_null() {
+        return Err(Error::GeneralError(format!("id '{_id}' not found")));
+      }
+      data = before.merge(&data);
+    }
+
+    //WORKAROUND: make sure that id & uuid stay same
+    if !before[c::ID].is_null() {
+      data[c::ID] = before[c::ID].clone();
+    }
+    if !before[c::UUID].is_null() {
+      data[c::UUID] = before[c::UUID].clone();
+    }
+
+    // who, when and from where made this revision
+    let stamp = Stamp {
+      time: time_str.clone(),
+      account: context.account.read().unwrap().id.to_base64(),
+      origin: context.origin.as_str().to_string(),
     };
 
     // println!("loaded before {before:?}");
@@ -95,7 +138,7 @@ fn save_data(
     // TODO .map_err(|e| IOError(e.to_string()))?;
 
     receive_data(app, ws.id.to_string().as_str(), before.clone(), data.clone(), ctx, &stack)
-      .map_err(|e| Error::GeneralError(e.message()))?;
+      .map_err(to_error)?;
 
     app.links().save_links(ws, ctx, &data, &before)?;
 
@@ -111,7 +154,13 @@ fn save_data(
       index_uuid(top_folder, folder, uuid)?;
     }
 
-    (before, data.clone())
+    // one batch after `latest.json` is updated, so index never lists unsaved revision
+    app.fields().save_fields(ws, ctx, &data, &before)?;
+
+    // after `latest.json` is updated, so failed audit leave revisions consistent
+    app.audit().record(ws, ctx, &stamp, &before, &data)?;
+
+    (before, data.clone(), time_str)
   };
 
   log::debug!("_Before {before:?}\n_After {after:?}");
@@ -136,9 +185,26 @@ fn save_data(
     })
     .collect();
 
+  let mut after = after;
+  after[c::REVISION] = time_str.into();
+
   Ok(after)
 }
 
+/// Index fields of memories saved before the index, under the lock so no document is saved
+/// meanwhile.
+pub(crate) fn index_fields(app: &Application) -> Result<usize, Error> {
+  let _lock = LOCK.lock().unwrap();
+  app.fields().index(&app.wss)
+}
+
+// name of revision `latest.json` links to
+fn latest_revision(path_latest: &Path) -> Option<String> {
+  std::fs::read_link(path_latest)
+    .ok()
+    .and_then(|p| p.file_stem().map(|s| s.to_string_lossy().to_string()))
+}
+
 pub(crate) fn index_uuid(top_folder: &PathBuf, folder: &PathBuf, uuid: &str) -> Result<(), Error> {
   // let str = uuid.to_string();
   let mut path_folder = top_folder.clone();
@@ -163,6 +229,17 @@ pub(crate) fn index_uuid(top_folder: &PathBuf, folder: &PathBuf, uuid: &str) ->
   Ok(())
 }
 
+// shortage of negative stock reach client as data of error
+fn to_error(e: WHError) -> Error {
+  if let Some(shortage) = e.shortage() {
+    Error::Unprocessable(e.message(), shortage.to_json())
+  } else if e.closed_till().is_some() {
+    Error::Forbidden(e.message())
+  } else {
+    Error::GeneralError(e.message())
+  }
+}
+
 // remove context details
 fn remove_prefix(id: &str) -> &str {
   if let Some(pos) = &id.rfind('/') {
@@ -195,7 +272,12 @@ pub(crate) fn build_folder_path(id: &String, folder: &PathBuf) -> Option<PathBuf
 }
 
 impl Memories {
-  pub(crate) fn create(&self, app: &Application, mut data: JsonValue) -> Result<JsonValue, Error> {
+  pub(crate) fn create(
+    &self,
+    app: &Application,
+    context: &Context,
+    mut data: JsonValue,
+  ) -> Result<JsonValue, Error> {
     let (id, time, folder) = {
       let _lock = LOCK.lock().unwrap();
 
@@ -244,16 +326,54 @@ impl Memories {
     data[c::ID] = id.clone().into();
     data[c::UUID] = uuid.to_string().into();
 
-    let data =
-      save_data(app, &self.ws, &self.top_folder, &folder, &self.ctx, &id, Some(uuid), time, data)?;
+    let data = save_data(
+      app,
+      &self.ws,
+      &self.top_folder,
+      &folder,
+      &self.ctx,
+      &id,
+      time,
+      context,
+      None,
+      false,
+      data,
+    )?;
 
     Ok(data.enrich(&self.ws))
   }
 
+  /// Save new revision of document, if `revision` given it must be the latest one.
   pub(crate) fn update(
     &self,
     app: &Application,
+    context: &Context,
+    id: String,
+    revision: Option<&str>,
+    data: Data,
+  ) -> Result<JsonValue, Error> {
+    self.save(app, context, id, revision, false, data)
+  }
+
+  /// Like `update`, but `data` is merged into the latest revision.
+  pub(crate) fn patch(
+    &self,
+    app: &Application,
+    context: &Context,
+    id: String,
+    revision: Option<&str>,
+    data: Data,
+  ) -> Result<JsonValue, Error> {
+    self.save(app, context, id, revision, true, data)
+  }
+
+  fn save(
+    &self,
+    app: &Application,
+    context: &Context,
     id: String,
+    revision: Option<&str>,
+    patch: bool,
     data: Data,
   ) -> Result<JsonValue, Error> {
     let time = Utc::now();
@@ -263,8 +383,19 @@ impl Memories {
       None => return Err(Error::IOError(format!("fail on folder path for id: {}", id))),
     };
 
-    let data =
-      save_data(app, &self.ws, &self.top_folder, &folder, &self.ctx, &id, None, time, data)?;
+    let data = save_data(
+      app,
+      &self.ws,
+      &self.top_folder,
+      &folder,
+      &self.ctx,
+      &id,
+      time,
+      context,
+      revision,
+      patch,
+      data,
+    )?;
 
     Ok(data.enrich(&self.ws))
   }
@@ -325,16 +456,40 @@ impl Memories {
       }
     }
 
-    if let Some(reverse) = reverse {
-      if reverse {
-        result.sort_by(|a, b| a.id.cmp(&b.id));
-      } else {
-        result.sort_by(|a, b| b.id.cmp(&a.id));
-      }
-    }
+    sort(&mut result, reverse);
 
     Ok(result)
   }
+
+  /// Documents of this context by `_id`, ordered like `list`.
+  pub(crate) fn documents<I>(&self, ids: I, reverse: Option<bool>) -> Vec<Document>
+  where
+    I: IntoIterator<Item = String>,
+  {
+    let mut result: Vec<Document> = ids
+      .into_iter()
+      .filter_map(|id| {
+        let mut path = build_folder_path(&id, &self.folder)?;
+        path.push("latest.json");
+
+        Some(Document { mem: self.clone(), id: remove_prefix(&id).to_string(), path })
+      })
+      .collect();
+
+    sort(&mut result, reverse);
+
+    result
+  }
+}
+
+fn sort(documents: &mut [Document], reverse: Option<bool>) {
+  if let Some(reverse) = reverse {
+    if reverse {
+      documents.sort_by(|a, b| a.id.cmp(&b.id));
+    } else {
+      documents.sort_by(|a, b| b.id.cmp(&a.id));
+    }
+  }
 }
 
 pub struct Document {
@@ -348,6 +503,58 @@ impl Document {
   pub fn json(&self) -> Result<JsonValue, Error> {
     load(&self.path)
   }
+
+  /// Name of the latest revision, expected by writes with revision check.
+  pub fn revision(&self) -> Option<String> {
+    latest_revision(&self.path)
+  }
+
+  /// Saved revisions of document, oldest first.
+  pub fn revisions(&self) -> Result<Vec<Revision>, Error> {
+    let folder = match self.path.parent() {
+      Some(folder) => folder,
+      None => return Err(Error::IOError(format!("no folder of {}", self.path.to_string_lossy()))),
+    };
+
+    let mut result = Vec::new();
+    for entry in std::fs::read_dir(folder)? {
+      let path = entry?.path();
+
+      let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+      let time = match name.strip_suffix(".json").map(string_to_time) {
+        Some(Ok(time)) => time,
+        _ => continue, // latest.json and others
+      };
+
+      // skip file reserved by `create` but not written yet
+      if path.metadata()?.len() == 0 {
+        continue;
+      }
+
+      result.push(Revision { time, path });
+    }
+
+    result.sort_by_key(|r| r.time);
+
+    Ok(result)
+  }
+
+  /// Revision which was the latest one at given time.
+  pub fn revision_at(&self, time: DateTime<Utc>) -> Result<Option<Revision>, Error> {
+    Ok(self.revisions()?.into_iter().take_while(|r| r.time <= time).last())
+  }
+}
+
+pub struct Revision {
+  pub time: DateTime<Utc>,
+
+  pub(crate) path: PathBuf,
+}
+
+impl Revision {
+  pub fn json(&self) -> Result<JsonValue, Error> {
+    load(&self.path)
+  }
 }
 
 #[cfg(test)]
@@ -1,5 +1,7 @@
 use crate::balance::{Balance, BalanceDelta, BalanceForGoods, Cost};
 use crate::batch::Batch;
+use crate::conversions::{Conversions, Factors};
+use crate::costing::CostingMethod;
 use crate::elements::{
   time_to_naive_string, Goods, KeyValueStore, Mode, ReturnType, Store, ToJson, WHError,
 };
@@ -7,12 +9,11 @@ use crate::operations::{InternalOperation, Op, OpMutation};
 use chrono::{DateTime, Utc};
 use json::{object, JsonValue};
 use std::collections::BTreeMap;
-use std::ops::AddAssign;
 use uuid::Uuid;
 
 trait Aggregation {
   fn check(&mut self, op: &OpMutation) -> ReturnType; // если операция валидна, вернет None, если нет - вернет свое значение и обнулит себя и выставит новые ключи
-  fn apply_operation(&mut self, op: &Op);
+  fn apply_operation(&mut self, op: &Op, factors: &Factors);
   fn apply_aggregation_batch(&mut self, agr: Option<&AggregationStoreGoodsBatch>);
   fn apply_aggregation_goods(&mut self, agr: Option<&AggregationStoreGoods>);
   fn balance(&mut self, balance: Option<&Balance>) -> ReturnType; // имплементировать для трех возможных ситуаций
@@ -43,15 +44,15 @@ impl AggregationStoreGoodsBatch {
     self.close_balance = BalanceForGoods::default();
   }
 
-  fn add_to_open_balance(&mut self, op: &Op) {
+  fn add_to_open_balance(&mut self, op: &Op, factors: &Factors) {
     self.store = Some(op.store);
     self.goods = Some(op.goods);
     self.batch = Some(op.batch.clone());
 
     let delta = op.to_delta();
 
-    self.open_balance += delta.clone();
-    self.close_balance += delta;
+    self.open_balance = self.open_balance.add_with(&delta, factors);
+    self.close_balance = self.close_balance.add_with(&delta, factors);
   }
 
   fn is_zero(&self) -> bool {
@@ -81,16 +82,12 @@ impl ToJson for AggregationStoreGoodsBatch {
   }
 }
 
-impl AddAssign<&Op> for AggregationStoreGoodsBatch {
-  fn add_assign(&mut self, rhs: &Op) {
-    // if let Some(batch) = rhs.batch.as_ref() {
-    self.store = Some(rhs.store);
-    self.goods = Some(rhs.goods);
-    self.batch = Some(rhs.batch.clone());
-    self.apply_operation(rhs);
-    // } else {
-    //   todo!();
-    // }
+impl AggregationStoreGoodsBatch {
+  fn add_operation(&mut self, op: &Op, factors: &Factors) {
+    self.store = Some(op.store);
+    self.goods = Some(op.goods);
+    self.batch = Some(op.batch.clone());
+    self.apply_operation(op, factors);
   }
 }
 
@@ -104,6 +101,8 @@ pub struct AggregationStoreGoods {
   pub receive: BalanceDelta,
   pub issue: BalanceDelta,
   pub close_balance: BalanceForGoods,
+  // метод оценки себестоимости
+  pub costing: Option<CostingMethod>,
 }
 
 impl AggregationStoreGoods {
@@ -117,14 +116,14 @@ impl AggregationStoreGoods {
     self.close_balance = BalanceForGoods::default();
   }
 
-  fn add_to_open_balance(&mut self, op: &Op) {
+  fn add_to_open_balance(&mut self, op: &Op, factors: &Factors) {
     self.store = Some(op.store);
     self.goods = Some(op.goods);
 
     let delta = op.to_delta();
 
-    self.open_balance += delta.clone();
-    self.close_balance += delta;
+    self.open_balance = self.open_balance.add_with(&delta, factors);
+    self.close_balance = self.close_balance.add_with(&delta, factors);
   }
 
   fn is_zero(&self) -> bool {
@@ -146,6 +145,7 @@ impl ToJson for AggregationStoreGoods {
         receive: self.receive.to_json(),
         issue: self.issue.reverse().to_json(),
         close_balance: self.close_balance.to_json(),
+        costing: self.costing.map(|c| c.to_json()),
       }
     } else {
       JsonValue::Null
@@ -153,11 +153,11 @@ impl ToJson for AggregationStoreGoods {
   }
 }
 
-impl AddAssign<&Op> for AggregationStoreGoods {
-  fn add_assign(&mut self, rhs: &Op) {
-    self.store = Some(rhs.store);
-    self.goods = Some(rhs.goods);
-    self.apply_operation(rhs);
+impl AggregationStoreGoods {
+  fn add_operation(&mut self, op: &Op, factors: &Factors) {
+    self.store = Some(op.store);
+    self.goods = Some(op.goods);
+    self.apply_operation(op, factors);
   }
 }
 
@@ -233,7 +233,7 @@ impl Aggregation for AggregationStore {
     }
   }
 
-  fn apply_operation(&mut self, _op: &Op) {
+  fn apply_operation(&mut self, _op: &Op, _factors: &Factors) {
     // match &op.op {
     //   InternalOperation::Inventory(_, d, _) => {
     //     self.receive += d.cost;
@@ -296,38 +296,42 @@ impl Aggregation for AggregationStoreGoodsBatch {
     }
   }
 
-  fn apply_operation(&mut self, op: &Op) {
+  fn apply_operation(&mut self, op: &Op, factors: &Factors) {
     match &op.op {
       InternalOperation::Inventory(_b, d, mode) => {
-        self.issue.qty += &d.qty;
+        self.issue.qty = self.issue.qty.add_with(&d.qty, factors);
         if mode == &Mode::Auto {
-          let balance = self.open_balance.clone() + self.receive.clone();
-          let cost = d.qty.cost(&balance);
+          let balance = self.open_balance.add_with(&self.receive, factors);
+          let cost = d.qty.cost(&balance, factors);
           self.issue.cost += cost;
         } else {
+          // counted difference is valued at evaluation of operation
           self.issue.cost += d.cost;
         }
       },
       InternalOperation::Receive(qty, cost) => {
-        self.receive.qty += qty;
+        self.receive.qty = self.receive.qty.add_with(qty, factors);
         self.receive.cost += cost;
       },
       InternalOperation::Issue(qty, cost, mode) => {
-        self.issue.qty -= qty;
+        self.issue.qty = self.issue.qty.sub_with(qty, factors);
         if mode == &Mode::Auto {
           let balance = if !self.close_balance.is_zero() {
             self.close_balance.clone()
           } else {
-            self.open_balance.clone() + self.receive.clone()
+            self.open_balance.add_with(&self.receive, factors)
           };
-          let cost = if qty == &balance.qty { balance.cost } else { qty.cost(&balance) };
+          let cost = if qty == &balance.qty { balance.cost } else { qty.cost(&balance, factors) };
           self.issue.cost -= cost;
         } else {
           self.issue.cost -= cost;
         }
       },
     }
-    self.close_balance = self.open_balance.clone() + self.receive.clone() + self.issue.clone();
+    self.close_balance = self
+      .open_balance
+      .add_with(&self.receive, factors)
+      .add_with(&self.issue, factors);
   }
 
   fn apply_aggregation_batch(&mut self, _agr: Option<&AggregationStoreGoodsBatch>) {
@@ -410,38 +414,42 @@ impl Aggregation for AggregationStoreGoods {
     }
   }
 
-  fn apply_operation(&mut self, op: &Op) {
+  fn apply_operation(&mut self, op: &Op, factors: &Factors) {
     match &op.op {
       InternalOperation::Inventory(_b, d, mode) => {
-        self.issue.qty += &d.qty;
+        self.issue.qty = self.issue.qty.add_with(&d.qty, factors);
         if mode == &Mode::Auto {
-          let balance = self.open_balance.clone() + self.receive.clone();
-          let cost = d.qty.cost(&balance);
+          let balance = self.open_balance.add_with(&self.receive, factors);
+          let cost = d.qty.cost(&balance, factors);
           self.issue.cost += cost;
         } else {
+          // counted difference is valued at evaluation of operation
           self.issue.cost += d.cost;
         }
       },
       InternalOperation::Receive(qty, cost) => {
-        self.receive.qty += qty;
+        self.receive.qty = self.receive.qty.add_with(qty, factors);
         self.receive.cost += cost;
       },
       InternalOperation::Issue(qty, cost, mode) => {
-        self.issue.qty -= qty;
+        self.issue.qty = self.issue.qty.sub_with(qty, factors);
         if mode == &Mode::Auto {
           let balance = if !self.close_balance.is_zero() {
             self.close_balance.clone()
           } else {
-            self.open_balance.clone() + self.receive.clone()
+            self.open_balance.add_with(&self.receive, factors)
           };
-          let cost = if qty == &balance.qty { balance.cost } else { qty.cost(&balance) };
+          let cost = if qty == &balance.qty { balance.cost } else { qty.cost(&balance, factors) };
           self.issue.cost -= cost;
         } else {
           self.issue.cost -= cost;
         }
       },
     }
-    self.close_balance = self.open_balance.clone() + self.receive.clone() + self.issue.clone();
+    self.close_balance = self
+      .open_balance
+      .add_with(&self.receive, factors)
+      .add_with(&self.issue, factors);
   }
 
   fn apply_aggregation_batch(&mut self, _agr: Option<&AggregationStoreGoodsBatch>) {
@@ -463,6 +471,7 @@ impl Aggregation for AggregationStoreGoods {
           receive: BalanceDelta::default(),
           issue: BalanceDelta::default(),
           close_balance: b.number.clone(),
+          costing: None,
         })
       } else if b.goods > self.goods.expect("option in fn balance") {
         // None
@@ -493,6 +502,7 @@ pub(crate) fn get_aggregations_for_one_goods(
   operations: Vec<Op>,
   start_date: DateTime<Utc>,
   end_date: DateTime<Utc>,
+  factors: &Factors,
 ) -> Result<JsonValue, WHError> {
   let mut result: Vec<JsonValue> = vec![];
 
@@ -519,10 +529,10 @@ pub(crate) fn get_aggregations_for_one_goods(
     // only "none-virtual" operations
     if op.dependant.is_empty() {
       if op.date < start_date {
-        open_balance += op.to_delta();
-        close_balance += op.to_delta();
+        open_balance = open_balance.add_with(&op.to_delta(), factors);
+        close_balance = close_balance.add_with(&op.to_delta(), factors);
       } else {
-        close_balance += op.to_delta();
+        close_balance = close_balance.add_with(&op.to_delta(), factors);
         result.push(op.to_json());
       }
     }
@@ -546,6 +556,7 @@ pub(crate) fn aggregations_store_goods(
   balances: Vec<Balance>,
   operations: Vec<Op>,
   start_date: DateTime<Utc>,
+  conversions: &Conversions,
 ) -> (AggregationStore, Vec<AggregationStoreGoods>, Vec<AggregationStoreGoodsBatch>) {
   // log::debug!("aggregations_store_goods: balances {balances:?}\noperations {operations:?}");
   let key = |store: &Store, goods: &Goods| -> Vec<u8> {
@@ -571,22 +582,24 @@ pub(crate) fn aggregations_store_goods(
         receive: BalanceDelta::default(),
         issue: BalanceDelta::default(),
         close_balance: BalanceForGoods::default(),
+        costing: None,
       }
     });
 
-    agr.open_balance += balance.number.clone();
-    agr.close_balance += balance.number;
+    let factors = conversions.of(balance.goods);
+    agr.open_balance.qty = agr.open_balance.qty.add_with(&balance.number.qty, &factors);
+    agr.open_balance.cost += balance.number.cost;
+    agr.close_balance.qty = agr.close_balance.qty.add_with(&balance.number.qty, &factors);
+    agr.close_balance.cost += balance.number.cost;
   }
 
   for op in operations {
+    let factors = conversions.of(op.goods);
+    let agr = aggregations.entry(key_for(&op)).or_insert(AggregationStoreGoods::default());
     if op.date < start_date {
-      aggregations
-        .entry(key_for(&op))
-        .or_insert(AggregationStoreGoods::default())
-        .add_to_open_balance(&op);
+      agr.add_to_open_balance(&op, &factors);
     } else {
-      let agr = aggregations.entry(key_for(&op)).or_insert(AggregationStoreGoods::default());
-      *agr += &op;
+      agr.add_operation(&op, &factors);
     }
   }
 
@@ -606,6 +619,7 @@ pub(crate) fn aggregations_for_store_goods_batch(
   balances: Vec<Balance>,
   operations: Vec<Op>,
   start_date: DateTime<Utc>,
+  conversions: &Conversions,
 ) -> (AggregationStore, Vec<AggregationStoreGoods>, Vec<AggregationStoreGoodsBatch>) {
   log::debug!("fn_get_aggregations: balances {balances:?}\noperations {operations:?}");
   let key = |store: &Store, goods: &Goods, batch: &Batch| -> Vec<u8> {
@@ -640,15 +654,14 @@ pub(crate) fn aggregations_for_store_goods_batch(
   }
 
   for op in operations {
+    let factors = conversions.of(op.goods);
+    let agr = aggregations
+      .entry(key_for(&op))
+      .or_insert(AggregationStoreGoodsBatch::default());
     if op.date < start_date {
-      aggregations
-        .entry(key_for(&op))
-        .or_insert(AggregationStoreGoodsBatch::default())
-        .add_to_open_balance(&op);
+      agr.add_to_open_balance(&op, &factors);
     } else {
-      *aggregations
-        .entry(key_for(&op))
-        .or_insert(AggregationStoreGoodsBatch::default()) += &op;
+      agr.add_operation(&op, &factors);
     }
   }
 
@@ -8,6 +8,7 @@ use std::ops::{Add, AddAssign, Neg, Sub, SubAssign};
 use std::str::FromStr;
 
 use crate::batch::Batch;
+use crate::conversions::Factors;
 use crate::elements::{Goods, Mode, Store, UUID_NIL};
 use crate::operations::{InternalOperation, OpMutation};
 use crate::qty::{Qty, Uom};
@@ -63,11 +64,11 @@ impl Cost {
     }
   }
 
-  pub fn price(&self, qty: &Qty, name: &Uom) -> Price {
+  pub fn price(&self, qty: &Qty, name: &Uom, factors: &Factors) -> Price {
     if qty.is_zero() {
       Price::ZERO
     } else {
-      if let Some(lower) = qty.lowering(name) {
+      if let Some(lower) = qty.lowering(name, factors) {
         log::debug!("_qty {qty:?}\n_lower {lower:?}");
         Price((self.0 / lower.number).round_dp(5).into(), name.clone())
       } else {
@@ -172,8 +173,8 @@ pub struct BalanceForGoods {
 }
 
 impl BalanceForGoods {
-  pub fn price(&self, name: &Uom) -> Price {
-    self.cost.price(&self.qty, name)
+  pub fn price(&self, name: &Uom, factors: &Factors) -> Price {
+    self.cost.price(&self.qty, name, factors)
   }
 
   pub fn is_zero(&self) -> bool {
@@ -184,24 +185,34 @@ impl BalanceForGoods {
     BalanceDelta { qty: &other.qty - &self.qty, cost: other.cost - self.cost }
   }
 
+  /// Sum of balances, `factors` of the goods convert units.
+  pub fn add_balance(&self, rhs: &BalanceForGoods, factors: &Factors) -> BalanceForGoods {
+    BalanceForGoods { qty: self.qty.add_with(&rhs.qty, factors), cost: self.cost + rhs.cost }
+  }
+
+  /// Balance after `delta`, `factors` of the goods convert units of delta.
+  pub fn add_with(&self, delta: &BalanceDelta, factors: &Factors) -> BalanceForGoods {
+    BalanceForGoods { qty: self.qty.add_with(&delta.qty, factors), cost: self.cost + delta.cost }
+  }
+
   // pub(crate) fn from_json(data: JsonValue) -> Result<Self, WHError> {
   //   let qty = data["qty"].clone().try_into()?;
   //   Ok(BalanceForGoods { qty, cost: data["cost"].number().into() })
   // }
 
-  pub fn apply(&mut self, op: &InternalOperation) {
+  /// Apply operation of goods, `factors` of the goods convert units of operation.
+  pub fn apply(&mut self, op: &InternalOperation, factors: &Factors) {
     match op {
-      InternalOperation::Inventory(b, d, ..) => {
-        self.qty += &d.qty;
+      InternalOperation::Inventory(_, d, ..) => {
+        self.qty = self.qty.add_with(&d.qty, factors);
         self.cost += d.cost;
-        assert_eq!(b, self);
       },
       InternalOperation::Receive(qty, cost) => {
-        self.qty += qty;
+        self.qty = self.qty.add_with(qty, factors);
         self.cost += cost;
       },
       InternalOperation::Issue(qty, cost, _mode) => {
-        self.qty -= qty;
+        self.qty = self.qty.sub_with(qty, factors);
         self.cost -= cost;
       },
     }
@@ -247,7 +258,8 @@ impl Add<InternalOperation> for BalanceForGoods {
     match rhs {
       InternalOperation::Inventory(_, d, mode) => {
         self.qty += &d.qty;
-        self.cost += if mode == Mode::Manual { d.cost } else { d.qty.cost(&self) }
+        self.cost +=
+          if mode == Mode::Manual { d.cost } else { d.qty.cost(&self, &Factors::default()) }
       },
       InternalOperation::Receive(qty, cost) => {
         self.qty += &qty;
@@ -255,7 +267,7 @@ impl Add<InternalOperation> for BalanceForGoods {
       },
       InternalOperation::Issue(qty, cost, mode) => {
         self.qty -= &qty;
-        self.cost -= if mode == Mode::Manual { cost } else { qty.cost(&self) }
+        self.cost -= if mode == Mode::Manual { cost } else { qty.cost(&self, &Factors::default()) }
       },
     }
     self
@@ -280,6 +292,11 @@ impl BalanceDelta {
   pub(crate) fn reverse(&self) -> Self {
     BalanceDelta { qty: self.qty.reverse(), cost: self.cost.reverse() }
   }
+
+  /// Sum of deltas, `factors` of the goods convert units.
+  pub(crate) fn add_with(&self, rhs: &BalanceDelta, factors: &Factors) -> BalanceDelta {
+    BalanceDelta { qty: self.qty.add_with(&rhs.qty, factors), cost: self.cost + rhs.cost }
+  }
 }
 
 impl ToJson for BalanceDelta {
@@ -323,7 +340,7 @@ impl AddAssign<&OpMutation> for Balance {
     self.goods = rhs.goods;
     self.store = rhs.store;
     if let Some((o, _)) = &rhs.after {
-      self.number.apply(o);
+      self.number.apply(o, &Factors::default());
     }
   }
 }
@@ -0,0 +1,257 @@
+use crate::balance::BalanceForGoods;
+use crate::batch::Batch;
+use crate::db::Db;
+use crate::elements::{Goods, Store, ToJson};
+use crate::error::WHError;
+use crate::staging::StagedDB;
+use chrono::{DateTime, Datelike, Utc};
+use json::{object, JsonValue};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const CF_NAME: &str = "cf_barcodes";
+
+// | CODE | code | -> goods & batch, | BATCH | goods | batch | -> code
+const CODE: u8 = 0;
+const BATCH: u8 = 1;
+
+const SERIALS: u32 = 100_000;
+
+/// Check digit of EAN-13 for its first twelve digits.
+pub fn ean13_check_digit(digits: &str) -> Result<char, WHError> {
+  if digits.len() != 12 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+    return Err(WHError::new("EAN-13 require twelve digits before check digit"));
+  }
+
+  let sum: u32 = digits
+    .bytes()
+    .enumerate()
+    .map(|(i, b)| {
+      let digit = (b - b'0') as u32;
+      if i % 2 == 0 {
+        digit
+      } else {
+        digit * 3
+      }
+    })
+    .sum();
+
+  Ok(char::from(b'0' + ((10 - sum % 10) % 10) as u8))
+}
+
+pub fn is_valid_ean13(code: &str) -> bool {
+  code.len() == 13
+    && code.is_ascii()
+    && ean13_check_digit(&code[..12])
+      .map(|check| code.ends_with(check))
+      .unwrap_or(false)
+}
+
+/// EAN-13 of in-store range: `2`, date as `yymmdd`, five digits of serial and check digit.
+pub(crate) fn ean13(date: DateTime<Utc>, serial: u32) -> String {
+  let digits =
+    format!("2{:02}{:02}{:02}{:05}", date.year() % 100, date.month(), date.day(), serial % SERIALS);
+  let check = ean13_check_digit(&digits).expect("twelve digits");
+  format!("{digits}{check}")
+}
+
+/// Persistent index of barcodes assigned to batches of goods.
+#[derive(Clone)]
+pub struct Barcodes {
+  pub db: Arc<StagedDB>,
+}
+
+impl Barcodes {
+  pub fn cf_name() -> &'static str {
+    CF_NAME
+  }
+
+  fn code_key(&self, code: &str) -> Vec<u8> {
+    [CODE].iter().chain(code.as_bytes().iter()).copied().collect()
+  }
+
+  fn batch_key(&self, goods: Goods, batch: &Batch) -> Vec<u8> {
+    [BATCH].iter().chain(batch.to_bytes(&goods).iter()).copied().collect()
+  }
+
+  /// Barcode assigned to batch of goods.
+  pub fn get(&self, goods: Goods, batch: &Batch) -> Result<Option<String>, WHError> {
+    match self.db.get_cf(CF_NAME, self.batch_key(goods, batch))? {
+      Some(bs) => Ok(Some(String::from_utf8(bs)?)),
+      None => Ok(None),
+    }
+  }
+
+  /// Goods and batch of scanned barcode, fails if check digit is wrong.
+  pub fn resolve(&self, code: &str) -> Result<Option<(Goods, Batch)>, WHError> {
+    if !is_valid_ean13(code) {
+      return Err(WHError::new(&format!("invalid barcode {code}")));
+    }
+
+    match self.db.get_cf(CF_NAME, self.code_key(code))? {
+      Some(bs) => Ok(Some(ciborium::de::from_reader(&bs[..])?)),
+      None => Ok(None),
+    }
+  }
+
+  /// Assign barcode to batch of goods if it has none. Code is made of batch date and digits
+  /// of its id, if it is taken already next free serial of the date is used.
+  pub fn assign(&self, goods: Goods, batch: &Batch) -> Result<String, WHError> {
+    if let Some(code) = self.get(goods, batch)? {
+      return Ok(code);
+    }
+
+    let first = batch.serial();
+    for n in 0..SERIALS {
+      let code = ean13(batch.date, first + n);
+      if self.db.get_cf(CF_NAME, self.code_key(&code))?.is_some() {
+        continue;
+      }
+
+      let mut bs = Vec::new();
+      ciborium::ser::into_writer(&(goods, batch), &mut bs)?;
+      self.db.put_cf(CF_NAME, self.code_key(&code), bs)?;
+      self.db.put_cf(CF_NAME, self.batch_key(goods, batch), code.as_bytes())?;
+
+      return Ok(code);
+    }
+
+    Err(WHError::new(&format!("no free barcode for batches of {}", batch.date)))
+  }
+}
+
+/// Goods and batch of scanned barcode with current balance of it at every store.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scan {
+  pub barcode: String,
+  pub goods: Goods,
+  pub batch: Batch,
+  pub balances: Vec<(Store, BalanceForGoods)>,
+}
+
+impl ToJson for Scan {
+  fn to_json(&self) -> JsonValue {
+    let balances: Vec<JsonValue> = self
+      .balances
+      .iter()
+      .map(|(store, balance)| {
+        object! {
+          store: store.to_json(),
+          qty: &balance.qty,
+          cost: balance.cost.to_json(),
+        }
+      })
+      .collect();
+
+    object! {
+      barcode: self.barcode.clone(),
+      goods: self.goods.to_json(),
+      batch: self.batch.to_json(),
+      balances: balances,
+    }
+  }
+}
+
+impl Db {
+  /// Resolve scanned barcode to goods, batch and its balances at stores.
+  pub fn scan(&self, code: &str, date: DateTime<Utc>) -> Result<Option<Scan>, WHError> {
+    let (goods, batch) = match self.barcodes.resolve(code)? {
+      Some(found) => found,
+      None => return Ok(None),
+    };
+
+    // batch has no operations before its date, so they make its balances without checkpoints
+    let factors = self.conversions.of(goods);
+    let mut by_store: HashMap<Store, BalanceForGoods> = HashMap::new();
+    for op in self.ops_for_goods_batch(goods, &batch, batch.date, date)? {
+      by_store.entry(op.store).or_default().apply(&op.op, &factors);
+    }
+
+    let mut balances: Vec<(Store, BalanceForGoods)> =
+      by_store.into_iter().filter(|(_, balance)| !balance.is_zero()).collect();
+    balances.sort_by_key(|(store, _)| *store);
+
+    Ok(Some(Scan { barcode: code.to_string(), goods, batch, balances }))
+  }
+
+  /// Assign barcodes to all received batches, for databases recorded before the index.
+  pub fn index_barcodes(&self) -> Result<usize, WHError> {
+    let ops = self.ops(None, Batch::no().date, DateTime::<Utc>::MAX_UTC)?;
+
+    let mut count = 0;
+    for op in ops.iter().filter(|op| op.is_receive() && !op.batch.is_empty()) {
+      self.barcodes.assign(op.goods, &op.batch)?;
+      count += 1;
+    }
+    Ok(count)
+  }
+}
+
+const L: [&str; 10] = [
+  "0001101", "0011001", "0010011", "0111101", "0100011", "0110001", "0101111", "0111011", "0110111",
+  "0001011",
+];
+const G: [&str; 10] = [
+  "0100111", "0110011", "0011011", "0100001", "0011101", "0111001", "0000101", "0010001", "0001001",
+  "0010111",
+];
+const R: [&str; 10] = [
+  "1110010", "1100110", "1101100", "1000010", "1011100", "1001110", "1010000", "1000100", "1001000",
+  "1110100",
+];
+// encoding of left half digits by the first digit
+const PARITY: [&str; 10] = [
+  "LLLLLL", "LLGLGG", "LLGGLG", "LLGGGL", "LGLLGG", "LGGLLG", "LGGGLL", "LGLGLG", "LGLGGL", "LGGLGL",
+];
+
+/// Modules of EAN-13 symbol from left to right, `true` is bar.
+pub fn ean13_modules(code: &str) -> Result<Vec<bool>, WHError> {
+  if !is_valid_ean13(code) {
+    return Err(WHError::new(&format!("invalid barcode {code}")));
+  }
+  let digits: Vec<usize> = code.bytes().map(|b| (b - b'0') as usize).collect();
+
+  let mut pattern = String::from("101");
+  for (digit, parity) in digits[1..7].iter().zip(PARITY[digits[0]].chars()) {
+    pattern.push_str(if parity == 'L' { L[*digit] } else { G[*digit] });
+  }
+  pattern.push_str("01010");
+  for digit in digits[7..].iter() {
+    pattern.push_str(R[*digit]);
+  }
+  pattern.push_str("101");
+
+  Ok(pattern.chars().map(|c| c == '1').collect())
+}
+
+/// Label of EAN-13 barcode as SVG image with digits under bars.
+pub fn ean13_svg(code: &str) -> Result<String, WHError> {
+  let modules = ean13_modules(code)?;
+  let (module, quiet, height) = (2, 11, 60);
+  let width = (modules.len() + 2 * quiet) * module;
+
+  let mut bars = String::new();
+  let mut x = 0;
+  while x < modules.len() {
+    if modules[x] {
+      let start = x;
+      while x < modules.len() && modules[x] {
+        x += 1;
+      }
+      bars.push_str(&format!(
+        r#"<rect x="{}" y="0" width="{}" height="{height}"/>"#,
+        (quiet + start) * module,
+        (x - start) * module
+      ));
+    } else {
+      x += 1;
+    }
+  }
+
+  Ok(format!(
+    r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{total}" viewBox="0 0 {width} {total}"><rect width="100%" height="100%" fill="white"/><g fill="black">{bars}</g><text x="{center}" y="{text}" font-family="monospace" font-size="14" text-anchor="middle">{code}</text></svg>"#,
+    total = height + 20,
+    center = width / 2,
+    text = height + 16,
+  ))
+}
@@ -1,3 +1,4 @@
+use crate::barcodes::ean13;
 use crate::elements::{dt, Goods, ToJson, UUID_MAX, UUID_NIL};
 use chrono::{DateTime, Utc};
 use json::{object, JsonValue};
@@ -40,13 +41,19 @@ impl Batch {
   //   }
   // }
 
-  pub(crate) fn to_barcode(&self) -> String {
-    let date = self.date.to_string();
-    let mut id: String = self.id.to_string().chars().filter(|c| *c >= '0' && *c <= '9').collect();
+  /// EAN-13 from batch date and digits of its id, the index of `Barcodes` may assign other
+  /// serial to the batch if this code is taken.
+  pub fn to_barcode(&self) -> String {
+    ean13(self.date, self.serial())
+  }
+
+  // first five digits of id
+  pub(crate) fn serial(&self) -> u32 {
+    let mut id: String = self.id.to_string().chars().filter(|c| c.is_ascii_digit()).collect();
     while id.len() < 5 {
       id.push('0');
     }
-    format!("2{}{}{}{}", &date[2..4], &date[5..7], &date[8..10], &id[0..5])
+    id[0..5].parse().unwrap_or_default()
   }
 
   pub fn to_bytes(&self, goods: &Goods) -> Vec<u8> {
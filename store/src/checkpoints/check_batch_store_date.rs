@@ -2,20 +2,22 @@ use std::sync::Arc;
 
 use crate::balance::Balance;
 use crate::batch::Batch;
+use crate::checkpoints::granularity::{Granularities, Granularity};
 use crate::checkpoints::CheckpointTopology;
+use crate::staging::StagedDB;
 use crate::{
   balance::BalanceForGoods,
   elements::{dt, Goods, Store, UUID_NIL},
   error::WHError,
 };
 use chrono::{DateTime, Utc};
-use rocksdb::{BoundColumnFamily, DB};
+use rocksdb::{BoundColumnFamily, IteratorMode, DB};
 use std::collections::HashMap;
 use uuid::Uuid;
 
 const CF_NAME: &str = "cf_checkpoint_batch_store_date";
 pub struct CheckBatchStoreDate {
-  pub db: Arc<DB>,
+  pub db: Arc<StagedDB>,
 }
 
 impl CheckBatchStoreDate {
@@ -39,7 +41,7 @@ impl CheckpointTopology for CheckBatchStoreDate {
   }
 
   fn get_balance(&self, key: &Vec<u8>) -> Result<BalanceForGoods, WHError> {
-    match self.db.get_cf(&self.cf()?, key)? {
+    match self.db.get_cf(CF_NAME, key)? {
       Some(v) => Ok(serde_json::from_slice(&v)?),
       None => Ok(BalanceForGoods::default()),
     }
@@ -48,15 +50,34 @@ impl CheckpointTopology for CheckBatchStoreDate {
   fn set_balance(&self, key: &Vec<u8>, balance: &BalanceForGoods) -> Result<(), WHError> {
     self
       .db
-      .put_cf(&self.cf()?, key, serde_json::to_string(balance)?)
+      .put_cf(CF_NAME, key, serde_json::to_string(balance)?)
       .map_err(|_| WHError::new("Can't put to database"))
   }
 
   fn del_balance(&self, key: &Vec<u8>) -> Result<(), WHError> {
-    self.db.delete_cf(&self.cf()?, key)?;
+    self.db.delete_cf(CF_NAME, key)?;
     Ok(())
   }
 
+  fn balance_before(
+    &self,
+    store: Store,
+    goods: Goods,
+    batch: &Batch,
+    date: DateTime<Utc>,
+  ) -> Result<BalanceForGoods, WHError> {
+    let from = self.key(store, goods, batch.clone(), dt("1970-01-01")?);
+    let till = self.key(store, goods, batch.clone(), date);
+
+    match self.db.iterator_range(CF_NAME, from..till, IteratorMode::End)?.next() {
+      Some(item) => {
+        let (_, v) = item?;
+        Ok(serde_json::from_slice(&v)?)
+      },
+      None => Ok(BalanceForGoods::default()),
+    }
+  }
+
   fn key_latest_checkpoint_date(&self) -> Vec<u8> {
     [].iter()
       .chain(UUID_NIL.as_bytes().iter())
@@ -71,7 +92,7 @@ impl CheckpointTopology for CheckBatchStoreDate {
   fn get_latest_checkpoint_date(&self) -> Result<DateTime<Utc>, WHError> {
     if let Some(bytes) = self
       .db
-      .get_cf(&self.cf().map_err(|_| WHError::new("get cf()"))?, self.key_latest_checkpoint_date())
+      .get_cf(CF_NAME, self.key_latest_checkpoint_date())
       .map_err(|_| WHError::new("key_latest_checkpoint_date()"))?
     {
       let date =
@@ -84,11 +105,19 @@ impl CheckpointTopology for CheckBatchStoreDate {
   }
 
   fn set_latest_checkpoint_date(&self, date: DateTime<Utc>) -> Result<(), WHError> {
-    Ok(self.db.put_cf(
-      &self.cf().map_err(|_| WHError::new("set cf()"))?,
+    self.db.put_cf(
+      CF_NAME,
       self.key_latest_checkpoint_date(),
       serde_json::to_string(&date).map_err(|_| WHError::new("set serde_json::from_slice"))?,
-    )?)
+    )
+  }
+
+  fn granularity(&self, store: Store) -> Result<Granularity, WHError> {
+    Granularities { db: self.db.clone() }.get(store)
+  }
+
+  fn granularities(&self) -> Result<HashMap<Store, Granularity>, WHError> {
+    Granularities { db: self.db.clone() }.all()
   }
 
   fn balances_for_store_goods(
@@ -161,15 +190,19 @@ impl CheckpointTopology for CheckBatchStoreDate {
     unimplemented!()
   }
 
+  fn get_latest_checkpoints(&self) -> Result<Vec<Balance>, WHError> {
+    unimplemented!()
+  }
+
   fn db(&self) -> Arc<DB> {
-    self.db.clone()
+    self.db.inner()
+  }
+
+  fn name(&self) -> &'static str {
+    CF_NAME
   }
 
   fn cf(&self) -> Result<Arc<BoundColumnFamily>, WHError> {
-    if let Some(cf) = self.db.cf_handle(CheckBatchStoreDate::cf_name()) {
-      Ok(cf)
-    } else {
-      Err(WHError::new("can't get CF"))
-    }
+    self.db.cf_handle(CheckBatchStoreDate::cf_name())
   }
 }
@@ -2,15 +2,16 @@ use std::sync::Arc;
 
 use crate::balance::Balance;
 use crate::batch::{max_batch, min_batch, Batch};
+use crate::checkpoints::granularity::{Granularities, Granularity};
 use crate::checkpoints::CheckpointTopology;
+use crate::staging::StagedDB;
 use crate::{
   balance::BalanceForGoods,
-  elements::{dt, first_day_current_month, Goods, Store, UUID_MAX, UUID_NIL},
+  elements::{dt, Goods, Store, UUID_MAX, UUID_NIL},
   error::WHError,
 };
 use chrono::{DateTime, Utc};
-use db::PrefixIterator;
-use rocksdb::{BoundColumnFamily, IteratorMode, ReadOptions, DB};
+use rocksdb::{BoundColumnFamily, IteratorMode, DB};
 use service::utils::time::timestamp_to_time;
 use std::collections::HashMap;
 use std::convert::TryFrom;
@@ -19,7 +20,7 @@ use uuid::Uuid;
 const CF_NAME: &str = "cf_checkpoint_date_store_batch";
 
 pub struct CheckDateStoreBatch {
-  pub db: Arc<DB>,
+  pub db: Arc<StagedDB>,
 }
 
 impl CheckDateStoreBatch {
@@ -57,7 +58,7 @@ impl CheckpointTopology for CheckDateStoreBatch {
   }
 
   fn get_balance(&self, key: &Vec<u8>) -> Result<BalanceForGoods, WHError> {
-    match self.db.get_cf(&self.cf()?, key)? {
+    match self.db.get_cf(CF_NAME, key)? {
       Some(v) => {
         let b = self.from_bytes(&v)?;
         log::debug!("checkpoint_get_balance {b:?}");
@@ -70,15 +71,25 @@ impl CheckpointTopology for CheckDateStoreBatch {
   fn set_balance(&self, key: &Vec<u8>, balance: &BalanceForGoods) -> Result<(), WHError> {
     self
       .db
-      .put_cf(&self.cf()?, key, self.to_bytes(balance)?)
+      .put_cf(CF_NAME, key, self.to_bytes(balance)?)
       .map_err(|_| WHError::new("Can't put to database"))
   }
 
   fn del_balance(&self, key: &Vec<u8>) -> Result<(), WHError> {
-    self.db.delete_cf(&self.cf()?, key)?;
+    self.db.delete_cf(CF_NAME, key)?;
     Ok(())
   }
 
+  fn balance_before(
+    &self,
+    _store: Store,
+    _goods: Goods,
+    _batch: &Batch,
+    _date: DateTime<Utc>,
+  ) -> Result<BalanceForGoods, WHError> {
+    unimplemented!()
+  }
+
   fn key_latest_checkpoint_date(&self) -> Vec<u8> {
     [].iter()
       .chain(u64::MIN.to_be_bytes().iter())
@@ -91,7 +102,7 @@ impl CheckpointTopology for CheckDateStoreBatch {
   }
 
   fn get_latest_checkpoint_date(&self) -> Result<DateTime<Utc>, WHError> {
-    if let Some(bytes) = self.db.get_cf(&self.cf()?, self.key_latest_checkpoint_date())? {
+    if let Some(bytes) = self.db.get_cf(CF_NAME, self.key_latest_checkpoint_date())? {
       let date = serde_json::from_slice(&bytes)?;
       Ok(DateTime::parse_from_rfc3339(date)?.into()) // TODO store/read timestamp in binary format
     } else {
@@ -100,11 +111,44 @@ impl CheckpointTopology for CheckDateStoreBatch {
   }
 
   fn set_latest_checkpoint_date(&self, date: DateTime<Utc>) -> Result<(), WHError> {
-    Ok(self.db.put_cf(
-      &self.cf()?,
-      self.key_latest_checkpoint_date(),
-      serde_json::to_string(&date)?,
-    )?)
+    self
+      .db
+      .put_cf(CF_NAME, self.key_latest_checkpoint_date(), serde_json::to_string(&date)?)
+  }
+
+  fn granularity(&self, store: Store) -> Result<Granularity, WHError> {
+    Granularities { db: self.db.clone() }.get(store)
+  }
+
+  fn granularities(&self) -> Result<HashMap<Store, Granularity>, WHError> {
+    Granularities { db: self.db.clone() }.all()
+  }
+
+  fn get_latest_checkpoints(&self) -> Result<Vec<Balance>, WHError> {
+    let latest = self.get_latest_checkpoint_date()?;
+    if latest.timestamp() == 0 {
+      return Ok(Vec::new());
+    }
+
+    let granularities = self.granularities()?;
+    let mut intervals: Vec<Granularity> =
+      granularities.values().copied().chain([Granularity::default()]).collect();
+    intervals.sort();
+    intervals.dedup();
+
+    let mut balances = Vec::new();
+    for interval in intervals {
+      let ts = u64::try_from(interval.ceil(latest).timestamp()).unwrap_or_default();
+      for (k, v) in self.db.prefix(CF_NAME, ts.to_be_bytes().to_vec())? {
+        let (date, store, goods, batch) = self.key_to_data(k.to_vec())?;
+        if granularities.get(&store).copied().unwrap_or_default() == interval {
+          let b: BalanceForGoods = self.from_bytes(&v)?;
+          balances.push(Balance { date, store, goods, batch, number: b });
+        }
+      }
+    }
+
+    Ok(balances)
   }
 
   fn get_checkpoints_for_one_goods(
@@ -115,12 +159,7 @@ impl CheckpointTopology for CheckDateStoreBatch {
   ) -> Result<Vec<Balance>, WHError> {
     let mut balances = Vec::new();
 
-    let current_date = first_day_current_month(date);
-
-    let latest_checkpoint_date = self.get_latest_checkpoint_date()?;
-
-    let actual_date =
-      if current_date > latest_checkpoint_date { latest_checkpoint_date } else { current_date };
+    let actual_date = self.checkpoint_date(store, date)?;
 
     let ts = u64::try_from(actual_date.timestamp()).unwrap_or_default();
 
@@ -143,10 +182,7 @@ impl CheckpointTopology for CheckDateStoreBatch {
       .copied()
       .collect();
 
-    let mut opts = ReadOptions::default();
-    opts.set_iterate_range(from..till);
-
-    let iter = self.db.iterator_cf_opt(&self.cf()?, opts, IteratorMode::Start);
+    let iter = self.db.iterator_range(CF_NAME, from..till, IteratorMode::Start)?;
 
     for res in iter {
       let (k, v) = res?;
@@ -167,15 +203,7 @@ impl CheckpointTopology for CheckDateStoreBatch {
     batch: &Batch,
     date: DateTime<Utc>,
   ) -> Result<Option<Balance>, WHError> {
-    let current_date = first_day_current_month(date);
-
-    let latest_checkpoint_date = self.get_latest_checkpoint_date()?;
-
-    let ts = if current_date > latest_checkpoint_date {
-      u64::try_from(latest_checkpoint_date.timestamp()).unwrap_or_default()
-    } else {
-      u64::try_from(current_date.timestamp()).unwrap_or_default()
-    };
+    let ts = u64::try_from(self.checkpoint_date(store, date)?.timestamp()).unwrap_or_default();
 
     let ts_batch = u64::try_from(batch.date.timestamp()).unwrap_or_default();
 
@@ -189,7 +217,7 @@ impl CheckpointTopology for CheckDateStoreBatch {
       .copied()
       .collect();
 
-    if let Some(v) = self.db.get(key)? {
+    if let Some(v) = self.db.get_cf(CF_NAME, key)? {
       let b: BalanceForGoods = self.from_bytes(&v)?;
 
       Ok(Some(Balance { date, store, goods, batch: batch.clone(), number: b }))
@@ -207,12 +235,7 @@ impl CheckpointTopology for CheckDateStoreBatch {
     let mut balances: HashMap<Uuid, BalanceForGoods> = HashMap::new();
     balances.insert(goods, BalanceForGoods::default());
 
-    let current_date = first_day_current_month(date);
-
-    let latest_checkpoint_date = self.get_latest_checkpoint_date()?;
-
-    let actual_date =
-      if current_date > latest_checkpoint_date { latest_checkpoint_date } else { current_date };
+    let actual_date = self.checkpoint_date(store, date)?;
 
     let ts = u64::try_from(actual_date.timestamp()).unwrap_or_default();
 
@@ -236,10 +259,7 @@ impl CheckpointTopology for CheckDateStoreBatch {
       .copied()
       .collect();
 
-    let mut opts = ReadOptions::default();
-    opts.set_iterate_range(from..till);
-
-    let iter = self.db.iterator_cf_opt(&self.cf()?, opts, IteratorMode::Start);
+    let iter = self.db.iterator_range(CF_NAME, from..till, IteratorMode::Start)?;
 
     for res in iter {
       let (k, v) = res?;
@@ -259,18 +279,13 @@ impl CheckpointTopology for CheckDateStoreBatch {
     store: Store,
     goods: Goods,
   ) -> Result<(DateTime<Utc>, HashMap<Batch, BalanceForGoods>), WHError> {
-    let current_date = first_day_current_month(date);
-
-    let latest_checkpoint_date = self.get_latest_checkpoint_date()?;
-
-    let actual_date =
-      if current_date > latest_checkpoint_date { latest_checkpoint_date } else { current_date };
+    let actual_date = self.checkpoint_date(store, date)?;
 
     let ts = u64::try_from(actual_date.timestamp()).unwrap_or_default();
 
     let prefix: Vec<u8> = ts.to_be_bytes().iter().chain(store.as_bytes().iter()).copied().collect();
 
-    let iter = self.db.prefix(&self.cf()?, prefix)?;
+    let iter = self.db.prefix(CF_NAME, prefix)?;
 
     let mut balances: HashMap<Batch, BalanceForGoods> = HashMap::new();
     for (k, v) in iter {
@@ -295,12 +310,7 @@ impl CheckpointTopology for CheckDateStoreBatch {
     let mut balances: HashMap<Uuid, BalanceForGoods> =
       goods.iter().map(|key| (*key, BalanceForGoods::default())).collect();
 
-    let current_date = first_day_current_month(date);
-
-    let latest_checkpoint_date = self.get_latest_checkpoint_date()?;
-
-    let actual_date =
-      if current_date > latest_checkpoint_date { latest_checkpoint_date } else { current_date };
+    let actual_date = self.common_checkpoint_date(date)?;
 
     let ts = u64::try_from(actual_date.timestamp()).unwrap_or_default();
 
@@ -323,10 +333,7 @@ impl CheckpointTopology for CheckDateStoreBatch {
       .copied()
       .collect();
 
-    let mut opts = ReadOptions::default();
-    opts.set_iterate_range(from..till);
-
-    let iter = self.db.iterator_cf_opt(&self.cf()?, opts, IteratorMode::Start);
+    let iter = self.db.iterator_range(CF_NAME, from..till, IteratorMode::Start)?;
 
     for res in iter {
       let (k, v) = res?;
@@ -347,15 +354,7 @@ impl CheckpointTopology for CheckDateStoreBatch {
     (DateTime<Utc>, HashMap<Store, HashMap<Goods, HashMap<Batch, BalanceForGoods>>>),
     WHError,
   > {
-    let start_of_current_month_date = first_day_current_month(date);
-
-    let latest_checkpoint_date = self.get_latest_checkpoint_date()?;
-
-    let checkpoint_date = if start_of_current_month_date > latest_checkpoint_date {
-      latest_checkpoint_date
-    } else {
-      start_of_current_month_date
-    };
+    let checkpoint_date = self.common_checkpoint_date(date)?;
 
     let ts = u64::try_from(checkpoint_date.timestamp()).unwrap_or_default();
 
@@ -363,7 +362,7 @@ impl CheckpointTopology for CheckDateStoreBatch {
 
     let mut result = HashMap::with_capacity(10_000);
 
-    let iter = self.db.prefix(&self.cf()?, prefix)?;
+    let iter = self.db.prefix(CF_NAME, prefix)?;
     for (k, v) in iter {
       let stock: BalanceForGoods = self.from_bytes(&v)?;
 
@@ -387,15 +386,7 @@ impl CheckpointTopology for CheckDateStoreBatch {
   ) -> Result<Vec<Balance>, WHError> {
     let mut balances = Vec::new();
 
-    let current_date = first_day_current_month(date);
-
-    let latest_checkpoint_date = self.get_latest_checkpoint_date()?;
-
-    let ts = if current_date > latest_checkpoint_date {
-      u64::try_from(latest_checkpoint_date.timestamp()).unwrap_or_default()
-    } else {
-      u64::try_from(current_date.timestamp()).unwrap_or_default()
-    };
+    let ts = u64::try_from(self.checkpoint_date(store, date)?.timestamp()).unwrap_or_default();
 
     let from: Vec<u8> = ts
       .to_be_bytes()
@@ -412,10 +403,7 @@ impl CheckpointTopology for CheckDateStoreBatch {
       .copied()
       .collect();
 
-    let mut opts = ReadOptions::default();
-    opts.set_iterate_range(from..till);
-
-    let iter = self.db.iterator_cf_opt(&self.cf()?, opts, IteratorMode::Start);
+    let iter = self.db.iterator_range(CF_NAME, from..till, IteratorMode::Start)?;
 
     for res in iter {
       let (k, v) = res?;
@@ -435,18 +423,10 @@ impl CheckpointTopology for CheckDateStoreBatch {
   ) -> Result<Vec<Balance>, WHError> {
     let mut balances = Vec::new();
 
-    let current_date = first_day_current_month(date);
-
-    let latest_checkpoint_date = self.get_latest_checkpoint_date()?;
-
-    let ts = if current_date > latest_checkpoint_date {
-      u64::try_from(latest_checkpoint_date.timestamp()).unwrap_or_default()
-    } else {
-      u64::try_from(current_date.timestamp()).unwrap_or_default()
-    };
+    let ts = u64::try_from(self.common_checkpoint_date(date)?.timestamp()).unwrap_or_default();
 
     let prefix: Vec<u8> = ts.to_be_bytes().iter().copied().collect();
-    let iter = self.db.prefix(&self.cf()?, prefix)?;
+    let iter = self.db.prefix(CF_NAME, prefix)?;
 
     for (k, v) in iter {
       let b: BalanceForGoods = self.from_bytes(&v)?;
@@ -460,14 +440,14 @@ impl CheckpointTopology for CheckDateStoreBatch {
   }
 
   fn db(&self) -> Arc<DB> {
-    self.db.clone()
+    self.db.inner()
+  }
+
+  fn name(&self) -> &'static str {
+    CF_NAME
   }
 
   fn cf(&self) -> Result<Arc<BoundColumnFamily>, WHError> {
-    if let Some(cf) = self.db.cf_handle(CheckDateStoreBatch::cf_name()) {
-      Ok(cf)
-    } else {
-      Err(WHError::new("can't get CF"))
-    }
+    self.db.cf_handle(CheckDateStoreBatch::cf_name())
   }
 }
@@ -0,0 +1,390 @@
+use std::sync::Arc;
+
+use crate::balance::Balance;
+use crate::batch::Batch;
+use crate::checkpoints::granularity::{Granularities, Granularity};
+use crate::checkpoints::CheckpointTopology;
+use crate::operations::OpMutation;
+use crate::staging::StagedDB;
+use crate::{
+  balance::BalanceForGoods,
+  elements::{dt, Goods, Store, UUID_NIL},
+  error::WHError,
+};
+use chrono::{DateTime, Duration, Utc};
+use rocksdb::{BoundColumnFamily, Direction, IteratorMode, DB};
+use service::utils::time::timestamp_to_time;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use uuid::Uuid;
+
+const CF_NAME: &str = "cf_checkpoint_store_batch_date";
+
+// | store | goods | batch date | batch id |
+const BATCH_LEN: usize = 56;
+
+/// Sparse checkpoints: balance of store, goods & batch is kept only at the end of interval
+/// where it changed, lookup take the latest checkpoint at or before the date.
+pub struct CheckStoreBatchDate {
+  pub db: Arc<StagedDB>,
+}
+
+impl CheckStoreBatchDate {
+  pub fn cf_name() -> &'static str {
+    CF_NAME
+  }
+
+  fn ts(date: DateTime<Utc>) -> u64 {
+    u64::try_from(date.timestamp()).unwrap_or_default()
+  }
+
+  /// Balances at `date` of all batches with given key prefix, zero ones are skipped.
+  /// Every batch is a seek to its first key and a seek backwards from `date`, so
+  /// checkpoints of other dates are not read.
+  fn balances_at(&self, prefix: Vec<u8>, date: DateTime<Utc>) -> Result<Vec<Balance>, WHError> {
+    let latest_key = self.key_latest_checkpoint_date();
+    let ts = Self::ts(date);
+
+    let mut balances = Vec::new();
+    let mut from = prefix.clone();
+    loop {
+      let batch: Vec<u8> =
+        match self.db.iterator(CF_NAME, IteratorMode::From(&from, Direction::Forward))?.next() {
+          Some(item) => {
+            let (k, _) = item?;
+            if !k.starts_with(&prefix) {
+              break;
+            }
+            k[..BATCH_LEN].to_vec()
+          },
+          None => break,
+        };
+
+      // the first key after all checkpoints of batch
+      from = batch.iter().chain([u8::MAX; 9].iter()).copied().collect();
+
+      if batch[..] == latest_key[..BATCH_LEN] {
+        continue;
+      }
+
+      // the latest checkpoint at or before date
+      let till = batch.iter().chain((ts + 1).to_be_bytes().iter()).copied().collect();
+      if let Some(item) =
+        self.db.iterator_range(CF_NAME, batch.clone()..till, IteratorMode::End)?.next()
+      {
+        let (k, v) = item?;
+        let number = self.from_bytes(&v)?;
+        if !number.is_zero() {
+          let (_, store, goods, batch) = self.key_to_data(k.to_vec())?;
+          balances.push(Balance { date, store, goods, batch, number });
+        }
+      }
+    }
+    Ok(balances)
+  }
+}
+
+impl CheckpointTopology for CheckStoreBatchDate {
+  fn key(&self, store: Store, goods: Goods, batch: Batch, date: DateTime<Utc>) -> Vec<u8> {
+    store
+      .as_bytes()
+      .iter()
+      .chain(batch.to_bytes(&goods).iter())
+      .chain(Self::ts(date).to_be_bytes().iter())
+      .copied()
+      .collect()
+  }
+
+  fn key_to_data(&self, k: Vec<u8>) -> Result<(DateTime<Utc>, Store, Goods, Batch), WHError> {
+    let store = Uuid::from_slice(&k[0..=15])?;
+    let goods = Uuid::from_slice(&k[16..=31])?;
+
+    let ts = u64::from_be_bytes(k[32..=39].try_into().unwrap());
+    let batch_id = Uuid::from_slice(&k[40..=55])?;
+    let batch = Batch { id: batch_id, date: timestamp_to_time(ts)? };
+
+    let ts = u64::from_be_bytes(k[56..=63].try_into().unwrap());
+    let date = timestamp_to_time(ts)?;
+
+    Ok((date, store, goods, batch))
+  }
+
+  fn get_balance(&self, key: &Vec<u8>) -> Result<BalanceForGoods, WHError> {
+    match self.db.get_cf(CF_NAME, key)? {
+      Some(v) => self.from_bytes(&v),
+      None => Ok(BalanceForGoods::default()),
+    }
+  }
+
+  fn set_balance(&self, key: &Vec<u8>, balance: &BalanceForGoods) -> Result<(), WHError> {
+    self.db.put_cf(CF_NAME, key, self.to_bytes(balance)?)
+  }
+
+  fn del_balance(&self, key: &Vec<u8>) -> Result<(), WHError> {
+    self.db.delete_cf(CF_NAME, key)
+  }
+
+  fn balance_before(
+    &self,
+    store: Store,
+    goods: Goods,
+    batch: &Batch,
+    date: DateTime<Utc>,
+  ) -> Result<BalanceForGoods, WHError> {
+    let from = self.key(store, goods, batch.clone(), dt("1970-01-01")?);
+    let till = self.key(store, goods, batch.clone(), date);
+
+    match self.db.iterator_range(CF_NAME, from..till, IteratorMode::End)?.next() {
+      Some(item) => {
+        let (_, v) = item?;
+        self.from_bytes(&v)
+      },
+      None => Ok(BalanceForGoods::default()),
+    }
+  }
+
+  fn key_latest_checkpoint_date(&self) -> Vec<u8> {
+    [].iter()
+      .chain(UUID_NIL.as_bytes().iter())
+      .chain(UUID_NIL.as_bytes().iter())
+      .chain(u64::MIN.to_be_bytes().iter())
+      .chain(UUID_NIL.as_bytes().iter())
+      .chain(u64::MIN.to_be_bytes().iter())
+      .copied()
+      .collect()
+  }
+
+  fn get_latest_checkpoint_date(&self) -> Result<DateTime<Utc>, WHError> {
+    if let Some(bytes) = self.db.get_cf(CF_NAME, self.key_latest_checkpoint_date())? {
+      Ok(ciborium::de::from_reader(bytes.as_slice())?)
+    } else {
+      dt("1970-01-01")
+    }
+  }
+
+  fn set_latest_checkpoint_date(&self, date: DateTime<Utc>) -> Result<(), WHError> {
+    let mut bs = Vec::new();
+    ciborium::ser::into_writer(&date, &mut bs)?;
+    self.db.put_cf(CF_NAME, self.key_latest_checkpoint_date(), bs)
+  }
+
+  fn granularity(&self, store: Store) -> Result<Granularity, WHError> {
+    Granularities { db: self.db.clone() }.get(store)
+  }
+
+  fn granularities(&self) -> Result<HashMap<Store, Granularity>, WHError> {
+    Granularities { db: self.db.clone() }.all()
+  }
+
+  fn get_latest_checkpoints(&self) -> Result<Vec<Balance>, WHError> {
+    let latest = self.get_latest_checkpoint_date()?;
+    self.balances_at(Vec::new(), latest)
+  }
+
+  /// Write balance at the end of interval of operation, unless it is the same as before.
+  fn checkpoint_update(
+    &self,
+    op: &OpMutation,
+    next_op_date: Option<DateTime<Utc>>,
+    balance: &BalanceForGoods,
+  ) -> Result<(), WHError> {
+    let date = self.granularity(op.store)?.next(op.date);
+
+    // the last operation of interval is in charge of it
+    if let Some(next_op_date) = next_op_date {
+      if next_op_date < date {
+        return Ok(());
+      }
+    }
+
+    let key = self.key(op.store, op.goods, op.batch.clone(), date);
+    if is_unchanged(&self.balance_before(op.store, op.goods, &op.batch, date)?, balance) {
+      self.del_balance(&key)?;
+    } else {
+      self.set_balance(&key, balance)?;
+    }
+
+    // stores have checkpoints till the first one of its granularity at or after this date
+    if self.get_latest_checkpoint_date()? < date {
+      self.set_latest_checkpoint_date(date)?;
+    }
+
+    Ok(())
+  }
+
+  fn balances_for_store_goods(
+    &self,
+    date: DateTime<Utc>,
+    store: Store,
+    goods: Goods,
+  ) -> Result<(DateTime<Utc>, HashMap<Batch, BalanceForGoods>), WHError> {
+    let date = self.checkpoint_date(store, date)?;
+    let prefix = store.as_bytes().iter().chain(goods.as_bytes().iter()).copied().collect();
+
+    let balances = self
+      .balances_at(prefix, date)?
+      .into_iter()
+      .map(|b| (b.batch, b.number))
+      .collect();
+
+    Ok((date, balances))
+  }
+
+  fn get_checkpoints_for_one_goods(
+    &self,
+    store: Store,
+    goods: Goods,
+    date: DateTime<Utc>,
+  ) -> Result<Vec<Balance>, WHError> {
+    let date = self.checkpoint_date(store, date)?;
+    let prefix = store.as_bytes().iter().chain(goods.as_bytes().iter()).copied().collect();
+
+    self.balances_at(prefix, date)
+  }
+
+  fn get_checkpoints_for_one_goods_with_date(
+    &self,
+    store: Store,
+    goods: Goods,
+    date: DateTime<Utc>,
+  ) -> Result<(DateTime<Utc>, HashMap<Uuid, BalanceForGoods>), WHError> {
+    let date = self.checkpoint_date(store, date)?;
+    let prefix = store.as_bytes().iter().chain(goods.as_bytes().iter()).copied().collect();
+
+    let mut balance = BalanceForGoods::default();
+    for b in self.balances_at(prefix, date)? {
+      balance += b.number;
+    }
+
+    Ok((date, HashMap::from([(goods, balance)])))
+  }
+
+  fn get_checkpoint_for_goods_and_batch(
+    &self,
+    store: Store,
+    goods: Goods,
+    batch: &Batch,
+    date: DateTime<Utc>,
+  ) -> Result<Option<Balance>, WHError> {
+    let checkpoint_date = self.checkpoint_date(store, date)?;
+
+    // at or before checkpoint date
+    let number = self.balance_before(store, goods, batch, checkpoint_date + Duration::seconds(1))?;
+    if number.is_zero() {
+      Ok(None)
+    } else {
+      Ok(Some(Balance { date, store, goods, batch: batch.clone(), number }))
+    }
+  }
+
+  fn get_checkpoints_for_all(
+    &self,
+    date: DateTime<Utc>,
+  ) -> Result<
+    (DateTime<Utc>, HashMap<Store, HashMap<Goods, HashMap<Batch, BalanceForGoods>>>),
+    WHError,
+  > {
+    let date = self.common_checkpoint_date(date)?;
+
+    let mut result = HashMap::new();
+    for balance in self.balances_at(Vec::new(), date)? {
+      result
+        .entry(balance.store)
+        .or_insert_with(HashMap::new)
+        .entry(balance.goods)
+        .or_insert_with(HashMap::new)
+        .insert(balance.batch, balance.number);
+    }
+
+    Ok((date, result))
+  }
+
+  fn get_checkpoints_for_many_goods(
+    &self,
+    date: DateTime<Utc>,
+    goods: &Vec<Goods>,
+  ) -> Result<(DateTime<Utc>, HashMap<Uuid, BalanceForGoods>), WHError> {
+    let date = self.common_checkpoint_date(date)?;
+
+    let mut balances: HashMap<Uuid, BalanceForGoods> =
+      goods.iter().map(|key| (*key, BalanceForGoods::default())).collect();
+
+    for balance in self.balances_at(Vec::new(), date)? {
+      balances.entry(balance.goods).and_modify(|bal| *bal += balance.number);
+    }
+
+    Ok((date, balances))
+  }
+
+  fn checkpoints_after(
+    &self,
+    store: Store,
+    goods: Goods,
+    date: DateTime<Utc>,
+  ) -> Result<Vec<Balance>, WHError> {
+    let date = self.granularity(store)?.next(date);
+    let prefix: Vec<u8> = store.as_bytes().iter().chain(goods.as_bytes().iter()).copied().collect();
+
+    // carried to the end of interval even if checkpoint of it was not written
+    let mut balances = self.balances_at(prefix.clone(), date)?;
+
+    let mut from = prefix.clone();
+    loop {
+      let batch: Vec<u8> =
+        match self.db.iterator(CF_NAME, IteratorMode::From(&from, Direction::Forward))?.next() {
+          Some(item) => {
+            let (k, _) = item?;
+            if !k.starts_with(&prefix) {
+              break;
+            }
+            k[..BATCH_LEN].to_vec()
+          },
+          None => break,
+        };
+
+      from = batch.iter().chain([u8::MAX; 9].iter()).copied().collect();
+
+      let after = batch.iter().chain((Self::ts(date) + 1).to_be_bytes().iter()).copied().collect();
+      for item in self.db.iterator_range(CF_NAME, after..from.clone(), IteratorMode::Start)? {
+        let (k, v) = item?;
+        let (date, store, goods, batch) = self.key_to_data(k.to_vec())?;
+        balances.push(Balance { date, store, goods, batch, number: self.from_bytes(&v)? });
+      }
+    }
+    Ok(balances)
+  }
+
+  fn get_checkpoints_for_one_storage_before_date(
+    &self,
+    store: Store,
+    date: DateTime<Utc>,
+  ) -> Result<Vec<Balance>, WHError> {
+    let date = self.checkpoint_date(store, date)?;
+    self.balances_at(store.as_bytes().to_vec(), date)
+  }
+
+  fn get_checkpoints_for_all_storages_before_date(
+    &self,
+    date: DateTime<Utc>,
+  ) -> Result<Vec<Balance>, WHError> {
+    let date = self.common_checkpoint_date(date)?;
+    self.balances_at(Vec::new(), date)
+  }
+
+  fn db(&self) -> Arc<DB> {
+    self.db.inner()
+  }
+
+  fn name(&self) -> &'static str {
+    CF_NAME
+  }
+
+  fn cf(&self) -> Result<Arc<BoundColumnFamily<'_>>, WHError> {
+    self.db.cf_handle(CheckStoreBatchDate::cf_name())
+  }
+}
+
+/// `true` if balance at the end of interval need no checkpoint after the `before` one.
+pub(crate) fn is_unchanged(before: &BalanceForGoods, after: &BalanceForGoods) -> bool {
+  before == after || (before.is_zero() && after.is_zero())
+}
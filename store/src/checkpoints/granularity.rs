@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::db::Db;
+use crate::elements::{first_day_current_month, first_day_next_month, Store};
+use crate::error::WHError;
+use crate::rebuild::Progress;
+use crate::staging::StagedDB;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, Utc};
+use rocksdb::IteratorMode;
+use serde::{Deserialize, Serialize};
+
+const CF_NAME: &str = "cf_checkpoint_granularity";
+
+// operations per month that switch adaptive granularity to finer one
+const ADAPTIVE_DAILY: usize = 10_000;
+const ADAPTIVE_WEEKLY: usize = 1_000;
+const ADAPTIVE_MONTHLY: usize = 50;
+
+/// Interval between checkpoints of store, from finer to coarser.
+#[derive(
+  Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, Default,
+)]
+pub enum Granularity {
+  Daily,
+  /// weeks from monday, split at first day of month
+  Weekly,
+  #[default]
+  Monthly,
+  Quarterly,
+  /// one of above, chosen by number of operations of store per month
+  Adaptive,
+}
+
+impl Granularity {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Granularity::Daily => "daily",
+      Granularity::Weekly => "weekly",
+      Granularity::Monthly => "monthly",
+      Granularity::Quarterly => "quarterly",
+      Granularity::Adaptive => "adaptive",
+    }
+  }
+
+  /// Granularity for store with given number of operations per month.
+  pub fn for_activity(operations: usize) -> Self {
+    if operations >= ADAPTIVE_DAILY {
+      Granularity::Daily
+    } else if operations >= ADAPTIVE_WEEKLY {
+      Granularity::Weekly
+    } else if operations >= ADAPTIVE_MONTHLY {
+      Granularity::Monthly
+    } else {
+      Granularity::Quarterly
+    }
+  }
+
+  /// Start of interval that contain `date`.
+  pub fn current(&self, date: DateTime<Utc>) -> DateTime<Utc> {
+    let day = date.date_naive().and_time(NaiveTime::MIN).and_utc();
+    match self {
+      Granularity::Daily => day,
+      Granularity::Weekly => {
+        let monday = day - Duration::days(day.weekday().num_days_from_monday() as i64);
+        monday.max(first_day_current_month(date))
+      },
+      Granularity::Monthly | Granularity::Adaptive => first_day_current_month(date),
+      Granularity::Quarterly => {
+        let month = date.month0() / 3 * 3 + 1;
+        NaiveDate::from_ymd_opt(date.year(), month, 1)
+          .unwrap()
+          .and_time(NaiveTime::MIN)
+          .and_utc()
+      },
+    }
+  }
+
+  /// Start of interval after the one that contain `date`.
+  pub fn next(&self, date: DateTime<Utc>) -> DateTime<Utc> {
+    match self {
+      Granularity::Daily => self.current(date) + Duration::days(1),
+      Granularity::Weekly => {
+        (self.current(date) + Duration::days(7)).min(first_day_next_month(date))
+      },
+      Granularity::Monthly | Granularity::Adaptive => first_day_next_month(date),
+      Granularity::Quarterly => {
+        let start = self.current(date);
+        (1..=3).fold(start, |date, _| first_day_next_month(date))
+      },
+    }
+  }
+
+  /// `date` if it is start of interval, otherwise start of the next one.
+  pub fn ceil(&self, date: DateTime<Utc>) -> DateTime<Utc> {
+    let current = self.current(date);
+    if current == date {
+      current
+    } else {
+      self.next(date)
+    }
+  }
+}
+
+impl TryFrom<&str> for Granularity {
+  type Error = WHError;
+
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    match value.to_lowercase().as_str() {
+      "daily" => Ok(Granularity::Daily),
+      "weekly" => Ok(Granularity::Weekly),
+      "monthly" => Ok(Granularity::Monthly),
+      "quarterly" => Ok(Granularity::Quarterly),
+      "adaptive" => Ok(Granularity::Adaptive),
+      _ => Err(WHError::new(&format!("unknown checkpoint granularity {value}"))),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Setting {
+  chosen: Granularity,
+  actual: Granularity,
+}
+
+/// Checkpoint granularity recorded per store, monthly if nothing is recorded.
+#[derive(Clone)]
+pub struct Granularities {
+  pub db: Arc<StagedDB>,
+}
+
+impl Granularities {
+  pub fn cf_name() -> &'static str {
+    CF_NAME
+  }
+
+  fn setting(&self, store: Store) -> Result<Option<Setting>, WHError> {
+    match self.db.get_cf(CF_NAME, store.as_bytes())? {
+      Some(bytes) => Ok(Some(ciborium::de::from_reader(bytes.as_slice())?)),
+      None => Ok(None),
+    }
+  }
+
+  pub(crate) fn put(
+    &self,
+    store: Store,
+    chosen: Granularity,
+    actual: Granularity,
+  ) -> Result<(), WHError> {
+    let mut bs = Vec::new();
+    ciborium::ser::into_writer(&Setting { chosen, actual }, &mut bs)?;
+    self.db.put_cf(CF_NAME, store.as_bytes(), bs)
+  }
+
+  /// Granularity of checkpoints of store, never adaptive.
+  pub fn get(&self, store: Store) -> Result<Granularity, WHError> {
+    Ok(self.setting(store)?.map(|s| s.actual).unwrap_or_default())
+  }
+
+  /// Granularity as it was set for store, may be adaptive.
+  pub fn chosen(&self, store: Store) -> Result<Granularity, WHError> {
+    Ok(self.setting(store)?.map(|s| s.chosen).unwrap_or_default())
+  }
+
+  /// Granularity of checkpoints of stores that have it recorded.
+  pub fn all(&self) -> Result<HashMap<Store, Granularity>, WHError> {
+    let mut res = HashMap::new();
+    for item in self.db.iterator(CF_NAME, IteratorMode::Start)? {
+      let (k, v) = item?;
+      let setting: Setting = ciborium::de::from_reader(&v[..])?;
+      res.insert(Store::from_slice(&k)?, setting.actual);
+    }
+    Ok(res)
+  }
+
+  /// The coarsest granularity of stores, its checkpoints are present at all of them.
+  pub fn coarsest(&self) -> Result<Granularity, WHError> {
+    Ok(self.all()?.into_values().fold(Granularity::default(), Granularity::max))
+  }
+}
+
+impl Db {
+  /// Record checkpoint granularity of store and rebuild its checkpoints if interval changed.
+  /// Adaptive one is evaluated by current number of operations, set it again to re-evaluate.
+  ///
+  /// Interval can't change at store with closed period, its checkpoints there are kept.
+  pub fn set_checkpoint_granularity(
+    &self,
+    store: Store,
+    granularity: Granularity,
+  ) -> Result<Granularity, WHError> {
+    self.change_checkpoint_granularity(store, granularity, usize::MAX, &mut |_| Ok(()))
+  }
+
+  /// Like `set_checkpoint_granularity`, checkpoints of store are rebuilt by `step` operations
+  /// with `report` called after each. Interrupted rebuild is continued by `resume_rebuilds`.
+  pub fn change_checkpoint_granularity(
+    &self,
+    store: Store,
+    granularity: Granularity,
+    step: usize,
+    report: &mut dyn FnMut(&Progress) -> Result<(), WHError>,
+  ) -> Result<Granularity, WHError> {
+    // no mutation is committed while checkpoints of store are rebuilt
+    self.db.exclusive(|| {
+      let actual = match granularity {
+        Granularity::Adaptive => Granularity::for_activity(self.operations_per_month(store)?),
+        granularity => granularity,
+      };
+      let before = self.granularities.get(store)?;
+
+      if actual != before {
+        self.check_granularity_change(store)?;
+      }
+
+      // granularity is recorded together with progress of rebuild
+      self.db.stage(|| {
+        self.granularities.put(store, granularity, actual)?;
+        if actual != before {
+          self.start_store_rebuild(store)?;
+        }
+        Ok(())
+      })?;
+
+      if actual != before {
+        for topology in self.checkpoint_topologies.iter() {
+          self.rebuild(topology.name(), step, report)?;
+        }
+      }
+
+      Ok(actual)
+    })
+  }
+
+  /// Reject change of checkpoint interval of store with closed period.
+  pub fn check_granularity_change(&self, store: Store) -> Result<(), WHError> {
+    match self.periods.closed_till_of(store)? {
+      Some(closed) => Err(WHError::closed_period(
+        &format!(
+          "checkpoint granularity of store {store} can't change, period till {} is closed",
+          closed.to_rfc3339()
+        ),
+        closed,
+      )),
+      None => Ok(()),
+    }
+  }
+
+  /// Average number of operations of store per month between the first and the last of them.
+  fn operations_per_month(&self, store: Store) -> Result<usize, WHError> {
+    let primary = &self.ordered_topologies[0];
+
+    let mut count = 0;
+    let mut dates: Option<(DateTime<Utc>, DateTime<Utc>)> = None;
+    for (_, v) in self.db.prefix(primary.name(), store.as_bytes().to_vec())? {
+      let (op, _) = primary.from_bytes(&v)?;
+      if op.dependant.is_empty() {
+        count += 1;
+        dates = Some(match dates {
+          Some((first, last)) => (first.min(op.date), last.max(op.date)),
+          None => (op.date, op.date),
+        });
+      }
+    }
+
+    let months = match dates {
+      Some((first, last)) => {
+        (last.year() - first.year()) * 12 + last.month() as i32 - first.month() as i32 + 1
+      },
+      None => 1,
+    };
+    Ok(count / months as usize)
+  }
+}
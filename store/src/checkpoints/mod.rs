@@ -1,11 +1,14 @@
 pub mod check_batch_store_date;
 pub mod check_date_store_batch;
+pub mod check_store_batch_date;
+pub mod granularity;
 
 use crate::balance::{Balance, BalanceForGoods};
 use crate::batch::Batch;
-use crate::elements::{first_day_next_month, Goods, Store, WHError};
+use crate::elements::{Goods, Store, WHError};
 use crate::operations::OpMutation;
 use chrono::{DateTime, Utc};
+use granularity::Granularity;
 use rocksdb::{BoundColumnFamily, IteratorMode, DB};
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -18,10 +21,39 @@ pub trait CheckpointTopology {
   fn get_balance(&self, key: &Vec<u8>) -> Result<BalanceForGoods, WHError>;
   fn set_balance(&self, key: &Vec<u8>, balance: &BalanceForGoods) -> Result<(), WHError>;
   fn del_balance(&self, key: &Vec<u8>) -> Result<(), WHError>;
+  /// The latest checkpoint of batch before `date`.
+  fn balance_before(
+    &self,
+    store: Store,
+    goods: Goods,
+    batch: &Batch,
+    date: DateTime<Utc>,
+  ) -> Result<BalanceForGoods, WHError>;
   fn key_latest_checkpoint_date(&self) -> Vec<u8>;
   fn get_latest_checkpoint_date(&self) -> Result<DateTime<Utc>, WHError>;
   fn set_latest_checkpoint_date(&self, date: DateTime<Utc>) -> Result<(), WHError>;
 
+  fn granularity(&self, store: Store) -> Result<Granularity, WHError>;
+  /// granularity of stores that have it set, others are monthly
+  fn granularities(&self) -> Result<HashMap<Store, Granularity>, WHError>;
+
+  /// Date of the nearest checkpoint of store at or before `date`.
+  fn checkpoint_date(&self, store: Store, date: DateTime<Utc>) -> Result<DateTime<Utc>, WHError> {
+    let granularity = self.granularity(store)?;
+    let latest = self.get_latest_checkpoint_date()?;
+    Ok(granularity.current(date).min(granularity.ceil(latest)))
+  }
+
+  /// Date of the nearest checkpoint at or before `date` that all stores have.
+  fn common_checkpoint_date(&self, date: DateTime<Utc>) -> Result<DateTime<Utc>, WHError> {
+    let coarsest = self
+      .granularities()?
+      .into_values()
+      .fold(Granularity::default(), Granularity::max);
+    let latest = self.get_latest_checkpoint_date()?;
+    Ok(coarsest.current(date.min(latest)))
+  }
+
   fn balances_for_store_goods(
     &self,
     date: DateTime<Utc>,
@@ -84,9 +116,11 @@ pub trait CheckpointTopology {
     // This assert do not pass
     // assert!(op.before.is_some() || op.after.is_some());
 
-    // check if we reached the last op for current month
+    let granularity = self.granularity(op.store)?;
+
+    // check if we reached the last op for current interval
     if let Some(next_op_date) = next_op_date {
-      if next_op_date < first_day_next_month(op.date) {
+      if next_op_date < granularity.next(op.date) {
         log::debug!("exit from checkpoint_update");
         return Ok(());
       }
@@ -95,18 +129,20 @@ pub trait CheckpointTopology {
     let mut tmp_date = op.date;
     let mut check_point_date = op.date;
 
-    let mut last_checkpoint_date = self.get_latest_checkpoint_date()?;
+    // each store have checkpoints till the first one of its granularity at or after this date
+    let mut latest = self.get_latest_checkpoint_date()?;
 
     // copy previous checkpoint to next one
-    if last_checkpoint_date <= op.date {
-      let old_checkpoints =
-        self.get_checkpoints_for_all_storages_before_date(last_checkpoint_date)?;
+    if latest <= op.date {
+      let old_checkpoints = self.get_latest_checkpoints()?;
+      let granularities = self.granularities()?;
 
-      last_checkpoint_date = first_day_next_month(op.date);
+      latest = granularity.next(op.date);
 
       for old_checkpoint in old_checkpoints.iter() {
-        let mut new_checkpoint_date = first_day_next_month(old_checkpoint.date);
-        while new_checkpoint_date <= last_checkpoint_date {
+        let interval = granularities.get(&old_checkpoint.store).copied().unwrap_or_default();
+        let mut new_checkpoint_date = interval.next(old_checkpoint.date);
+        while new_checkpoint_date <= interval.ceil(latest) {
           let key = self.key(
             old_checkpoint.store,
             old_checkpoint.goods,
@@ -114,13 +150,14 @@ pub trait CheckpointTopology {
             new_checkpoint_date,
           );
           self.set_balance(&key, &old_checkpoint.number)?;
-          new_checkpoint_date = first_day_next_month(new_checkpoint_date);
+          new_checkpoint_date = interval.next(new_checkpoint_date);
         }
       }
     }
 
+    let last_checkpoint_date = granularity.ceil(latest);
     while check_point_date <= last_checkpoint_date {
-      check_point_date = first_day_next_month(tmp_date);
+      check_point_date = granularity.next(tmp_date);
 
       let key = self.key(op.store, op.goods, op.batch.clone(), check_point_date);
 
@@ -138,7 +175,7 @@ pub trait CheckpointTopology {
       }
     }
 
-    self.set_latest_checkpoint_date(check_point_date)?;
+    self.set_latest_checkpoint_date(latest)?;
     // }
 
     Ok(())
@@ -155,6 +192,22 @@ pub trait CheckpointTopology {
     date: DateTime<Utc>,
   ) -> Result<Vec<Balance>, WHError>;
 
+  /// The latest checkpoints of each store by its granularity.
+  fn get_latest_checkpoints(&self) -> Result<Vec<Balance>, WHError>;
+
+  /// Balances of store & goods at the end of interval of `date` and at all later checkpoints.
+  fn checkpoints_after(
+    &self,
+    _store: Store,
+    _goods: Goods,
+    _date: DateTime<Utc>,
+  ) -> Result<Vec<Balance>, WHError> {
+    Err(WHError::new("Not supported"))
+  }
+
+  /// name of column family
+  fn name(&self) -> &'static str;
+
   fn db(&self) -> Arc<DB>;
 
   fn cf(&self) -> Result<Arc<BoundColumnFamily>, WHError>;
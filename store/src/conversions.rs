@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use crate::balance::BalanceForGoods;
+use crate::db::Db;
+use crate::elements::{dt, Goods, ToJson, UUID_NIL};
+use crate::error::WHError;
+use crate::operations::InternalOperation;
+use crate::qty::{Number, Qty, Uom};
+use crate::staging::StagedDB;
+use chrono::{DateTime, Utc};
+use json::{object, JsonValue};
+use rocksdb::IteratorMode;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const CF_NAME: &str = "cf_uom_conversions";
+
+/// One `from` unit is `factor` of `into` units, for given goods or for any.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Conversion {
+  pub goods: Option<Goods>,
+  pub from: Uuid,
+  pub into: Uuid,
+  pub factor: Decimal,
+}
+
+impl ToJson for Conversion {
+  fn to_json(&self) -> JsonValue {
+    object! {
+      goods: self.goods.map(|g| g.to_json()).unwrap_or(JsonValue::Null),
+      from: self.from.to_json(),
+      into: self.into.to_json(),
+      factor: self.factor.to_json(),
+    }
+  }
+}
+
+// | goods | from | into | -> factor, goods is UUID_NIL for common conversions
+type Registry = HashMap<(Goods, Uuid, Uuid), Decimal>;
+
+/// Conversion factors between units without common nested unit, like kg and g.
+#[derive(Clone)]
+pub struct Conversions {
+  pub db: Arc<StagedDB>,
+  factors: Arc<RwLock<Registry>>,
+}
+
+impl Conversions {
+  pub fn cf_name() -> &'static str {
+    CF_NAME
+  }
+
+  /// Load recorded conversions into registry.
+  pub fn new(db: Arc<StagedDB>) -> Result<Self, WHError> {
+    let conversions = Conversions { db, factors: Arc::new(RwLock::new(HashMap::new())) };
+    {
+      let mut factors = conversions.factors.write().unwrap();
+      for conversion in conversions.list()? {
+        factors.insert(
+          (conversion.goods.unwrap_or(UUID_NIL), conversion.from, conversion.into),
+          conversion.factor,
+        );
+      }
+    }
+    Ok(conversions)
+  }
+
+  fn key(&self, goods: Option<Goods>, from: Uuid, into: Uuid) -> Vec<u8> {
+    goods
+      .unwrap_or(UUID_NIL)
+      .as_bytes()
+      .iter()
+      .chain(from.as_bytes().iter())
+      .chain(into.as_bytes().iter())
+      .copied()
+      .collect()
+  }
+
+  pub fn validate(conversion: &Conversion) -> Result<(), WHError> {
+    if conversion.factor <= Decimal::ZERO {
+      return Err(WHError::new("conversion factor must be positive"));
+    }
+    if conversion.from == conversion.into {
+      return Err(WHError::new("conversion of unit into itself"));
+    }
+    Ok(())
+  }
+
+  /// Recorded factor of conversion.
+  pub fn get(
+    &self,
+    goods: Option<Goods>,
+    from: Uuid,
+    into: Uuid,
+  ) -> Result<Option<Decimal>, WHError> {
+    match self.db.get_cf(CF_NAME, self.key(goods, from, into))? {
+      Some(bytes) => {
+        let conversion: Conversion = ciborium::de::from_reader(&bytes[..])?;
+        Ok(Some(conversion.factor))
+      },
+      None => Ok(None),
+    }
+  }
+
+  /// Registry is updated once the stage is committed, see `StagedDB::after_commit`.
+  pub fn set(&self, conversion: &Conversion) -> Result<(), WHError> {
+    Conversions::validate(conversion)?;
+
+    let mut bs = Vec::new();
+    ciborium::ser::into_writer(conversion, &mut bs)?;
+    self
+      .db
+      .put_cf(CF_NAME, self.key(conversion.goods, conversion.from, conversion.into), bs)?;
+
+    let key = (conversion.goods.unwrap_or(UUID_NIL), conversion.from, conversion.into);
+    let (factors, factor) = (self.factors.clone(), conversion.factor);
+    self.db.after_commit(move || {
+      factors.write().unwrap().insert(key, factor);
+    })
+  }
+
+  pub fn remove(&self, goods: Option<Goods>, from: Uuid, into: Uuid) -> Result<(), WHError> {
+    self.db.delete_cf(CF_NAME, self.key(goods, from, into))?;
+
+    let factors = self.factors.clone();
+    self.db.after_commit(move || {
+      factors.write().unwrap().remove(&(goods.unwrap_or(UUID_NIL), from, into));
+    })
+  }
+
+  pub fn list(&self) -> Result<Vec<Conversion>, WHError> {
+    let mut res = Vec::new();
+    for item in self.db.iterator(CF_NAME, IteratorMode::Start)? {
+      let (_, v) = item?;
+      res.push(ciborium::de::from_reader(&v[..])?);
+    }
+    Ok(res)
+  }
+
+  /// Factors for quantities of goods, its own conversions go before common ones.
+  pub fn of(&self, goods: Goods) -> Factors {
+    let registry = self.factors.read().unwrap();
+
+    let mut factors = HashMap::new();
+    for ((g, from, into), factor) in registry.iter() {
+      if *g == UUID_NIL {
+        factors.entry((*from, *into)).or_insert(*factor);
+      }
+    }
+    for ((g, from, into), factor) in registry.iter() {
+      if *g == goods && goods != UUID_NIL {
+        factors.remove(&(*into, *from));
+        factors.insert((*from, *into), *factor);
+      }
+    }
+    Factors(factors)
+  }
+}
+
+impl Db {
+  /// Balances and checkpoints are summed by the factor in use, so it can't be changed or removed
+  /// while goods have operations in both units (any goods for common conversion).
+  pub(crate) fn check_conversion_change(
+    &self,
+    goods: Option<Goods>,
+    from: Uuid,
+    into: Uuid,
+  ) -> Result<(), WHError> {
+    let (from_date, till_date) = (dt("1970-01-01")?, DateTime::<Utc>::MAX_UTC);
+
+    let mut ops = None;
+    for topology in self.ordered_topologies.iter() {
+      let res = match goods {
+        Some(goods) => topology.ops_for_goods(&vec![goods], from_date, till_date),
+        None => topology.ops(from_date, till_date),
+      };
+      if let Ok(res) = res {
+        ops = Some(res);
+        break;
+      }
+    }
+    let ops = ops.ok_or_else(|| WHError::new("fn ops_for_goods not implemented"))?;
+
+    // | goods | -> (in `from`, in `into`)
+    let mut used: HashMap<Goods, (bool, bool)> = HashMap::new();
+    for op in ops {
+      let mut units = Vec::new();
+      match &op.op {
+        InternalOperation::Inventory(BalanceForGoods { qty, .. }, delta, _) => {
+          collect_units(qty, &mut units);
+          collect_units(&delta.qty, &mut units);
+        },
+        InternalOperation::Receive(qty, _) | InternalOperation::Issue(qty, _, _) => {
+          collect_units(qty, &mut units)
+        },
+      }
+
+      let (in_from, in_into) = used.entry(op.goods).or_default();
+      *in_from |= units.contains(&from);
+      *in_into |= units.contains(&into);
+      if *in_from && *in_into {
+        return Err(WHError::new(&format!(
+          "goods {} have operations in both units, conversion can't be changed",
+          op.goods
+        )));
+      }
+    }
+    Ok(())
+  }
+}
+
+// units of quantity with nested ones
+fn collect_units(qty: &Qty, units: &mut Vec<Uuid>) {
+  fn collect(number: &Number<Uom>, units: &mut Vec<Uuid>) {
+    let Uom::In(unit, nested) = &number.name;
+    units.push(*unit);
+    if let Some(nested) = nested {
+      collect(nested, units);
+    }
+  }
+  for number in qty.inner() {
+    collect(number, units);
+  }
+}
+
+/// Conversion factors of one goods used by `Qty` arithmetic, default one convert nothing.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Factors(HashMap<(Uuid, Uuid), Decimal>);
+
+impl Factors {
+  /// `number` of `from` units in `into` units, factor of opposite direction divides.
+  pub(crate) fn convert(&self, number: Decimal, from: Uuid, into: Uuid) -> Option<Decimal> {
+    if from == into {
+      Some(number)
+    } else if let Some(factor) = self.0.get(&(from, into)) {
+      Some(number * factor)
+    } else {
+      self.0.get(&(into, from)).map(|factor| number / factor)
+    }
+  }
+
+  pub(crate) fn is_convertible(&self, from: Uuid, into: Uuid) -> bool {
+    self.0.contains_key(&(from, into)) || self.0.contains_key(&(into, from))
+  }
+}
@@ -0,0 +1,111 @@
+use std::sync::Arc;
+
+use crate::elements::{Goods, Store, ToJson, UUID_NIL};
+use crate::error::WHError;
+use crate::staging::StagedDB;
+use json::JsonValue;
+use serde::{Deserialize, Serialize};
+
+const CF_NAME: &str = "cf_costing_store_goods";
+
+/// How the cost of an `Issue` without an explicit batch is calculated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+pub enum CostingMethod {
+  /// oldest batches are issued first
+  #[default]
+  Fifo,
+  /// newest batches are issued first
+  Lifo,
+  /// batches are issued in FIFO order, but at the average cost of the stock
+  WeightedAverage,
+  /// batch must be given explicitly on every issue
+  Specific,
+  /// batches that expire first are issued first, ones without expiry date after them by FIFO
+  Fefo,
+}
+
+impl ToJson for CostingMethod {
+  fn to_json(&self) -> JsonValue {
+    JsonValue::String(self.as_str().to_string())
+  }
+}
+
+impl CostingMethod {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      CostingMethod::Fifo => "fifo",
+      CostingMethod::Lifo => "lifo",
+      CostingMethod::WeightedAverage => "average",
+      CostingMethod::Specific => "specific",
+      CostingMethod::Fefo => "fefo",
+    }
+  }
+}
+
+impl TryFrom<&str> for CostingMethod {
+  type Error = WHError;
+
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    match value.to_lowercase().as_str() {
+      "fifo" => Ok(CostingMethod::Fifo),
+      "lifo" => Ok(CostingMethod::Lifo),
+      "average" | "weighted_average" => Ok(CostingMethod::WeightedAverage),
+      "specific" => Ok(CostingMethod::Specific),
+      "fefo" => Ok(CostingMethod::Fefo),
+      _ => Err(WHError::new(&format!("unknown costing method {value}"))),
+    }
+  }
+}
+
+/// Costing methods recorded per store and optionally per goods.
+#[derive(Clone)]
+pub struct CostingPolicies {
+  pub db: Arc<StagedDB>,
+}
+
+impl CostingPolicies {
+  pub fn cf_name() -> &'static str {
+    CF_NAME
+  }
+
+  // | store | goods | (goods is UUID_NIL for store default)
+  fn key(&self, store: Store, goods: Option<Goods>) -> Vec<u8> {
+    store
+      .as_bytes()
+      .iter()
+      .chain(goods.unwrap_or(UUID_NIL).as_bytes().iter())
+      .copied()
+      .collect()
+  }
+
+  pub fn set(
+    &self,
+    store: Store,
+    goods: Option<Goods>,
+    method: CostingMethod,
+  ) -> Result<(), WHError> {
+    let mut bs = Vec::new();
+    ciborium::ser::into_writer(&method, &mut bs)?;
+
+    self.db.put_cf(CF_NAME, self.key(store, goods), bs)
+  }
+
+  pub fn remove(&self, store: Store, goods: Option<Goods>) -> Result<(), WHError> {
+    self.db.delete_cf(CF_NAME, self.key(store, goods))
+  }
+
+  fn get_exact(&self, store: Store, goods: Option<Goods>) -> Result<Option<CostingMethod>, WHError> {
+    match self.db.get_cf(CF_NAME, self.key(store, goods))? {
+      Some(bytes) => Ok(Some(ciborium::de::from_reader(bytes.as_slice())?)),
+      None => Ok(None),
+    }
+  }
+
+  /// goods policy overrides the store one, FIFO if nothing is recorded
+  pub fn get(&self, store: Store, goods: Goods) -> Result<CostingMethod, WHError> {
+    if let Some(method) = self.get_exact(store, Some(goods))? {
+      return Ok(method);
+    }
+    Ok(self.get_exact(store, None)?.unwrap_or_default())
+  }
+}
@@ -1,7 +1,6 @@
 use std::sync::Arc;
 
 use chrono::{DateTime, NaiveDateTime, Utc};
-use rocksdb::DB;
 
 use super::{
   balance::BalanceForGoods,
@@ -9,11 +8,23 @@ use super::{
   error::WHError,
 };
 use crate::balance::Balance;
+use crate::barcodes::Barcodes;
 use crate::batch::Batch;
+use crate::checkpoints::granularity::Granularities;
 use crate::checkpoints::CheckpointTopology;
+use crate::conversions::Conversions;
+use crate::costing::CostingPolicies;
 use crate::elements::Goods;
+use crate::expiry::{BatchExpiry, Expiries};
+use crate::grouping::{Attributes, Dimension, GroupedReport, Grouping, Measure};
+use crate::journal::{Journal, JournalState, Recovered, Recovery};
+use crate::levels::StockLevels;
+use crate::negative_stock::NegativeStockPolicies;
 use crate::operations::{Op, OpMutation};
 use crate::ordered_topology::OrderedTopology;
+use crate::periods::Periods;
+use crate::reservations::Reservations;
+use crate::staging::StagedDB;
 use json::JsonValue;
 use log::debug;
 use std::collections::HashMap;
@@ -21,9 +32,19 @@ use uuid::Uuid;
 
 #[derive(Clone)]
 pub struct Db {
-  pub db: Arc<DB>,
+  pub db: Arc<StagedDB>,
   pub checkpoint_topologies: Arc<Vec<Box<dyn CheckpointTopology + Sync + Send>>>,
   pub ordered_topologies: Arc<Vec<Box<dyn OrderedTopology + Sync + Send>>>,
+  pub costing: CostingPolicies,
+  pub journal: Journal,
+  pub reservations: Reservations,
+  pub negative_stock: NegativeStockPolicies,
+  pub periods: Periods,
+  pub conversions: Conversions,
+  pub granularities: Granularities,
+  pub barcodes: Barcodes,
+  pub expiries: Expiries,
+  pub levels: StockLevels,
 }
 
 impl Db {
@@ -63,11 +84,105 @@ impl Db {
     Ok(())
   }
 
+  /// Mutations with all topologies they touch are committed atomically, all or none of them.
   pub fn record_ops(&self, ops: &Vec<OpMutation>) -> Result<(), WHError> {
-    for op in ops {
+    self.record_ops_with_expiries(ops, &[])
+  }
+
+  /// Record mutations with expiry dates of batches, both are written or none of them.
+  pub fn record_ops_with_expiries(
+    &self,
+    ops: &Vec<OpMutation>,
+    expiries: &[BatchExpiry],
+  ) -> Result<(), WHError> {
+    self.record_ops_in_workspace(None, ops, expiries)
+  }
+
+  /// Record mutations of workspace `wid`, their stores get closed periods of it in the same
+  /// batch, see `Periods::assign`.
+  pub fn record_ops_in_workspace(
+    &self,
+    wid: Option<&str>,
+    ops: &Vec<OpMutation>,
+    expiries: &[BatchExpiry],
+  ) -> Result<(), WHError> {
+    self.db.stage(|| {
+      if let Some(wid) = wid {
+        let mut stores: Vec<Store> = Vec::new();
+        for op in ops.iter() {
+          for store in [Some(op.store), op.transfer].into_iter().flatten() {
+            if !stores.contains(&store) {
+              stores.push(store);
+            }
+          }
+        }
+        self.periods.assign(wid, &stores)?;
+      }
+
+      self.apply_ops(ops, expiries)?;
+
+      // committed in the same batch as topologies
+      self.journal.append(ops)?;
+      Ok(())
+    })
+  }
+
+  // checked under writer lock, so balances can't change before mutations are applied
+  fn apply_ops(&self, ops: &[OpMutation], expiries: &[BatchExpiry]) -> Result<(), WHError> {
+    self.periods.check(ops)?;
+    self.check_issues(ops)?;
+
+    // before mutations, so they are distributed by new expiry dates
+    self.update_expiries(expiries)?;
+
+    for op in ops.iter() {
+      let negatives = self.negatives_before(op)?;
       self.ordered_topologies[0].data_update(self, op)?;
+      if let Some(negatives) = negatives {
+        self.check_negatives(op, negatives)?;
+      }
+      self.assign_barcode(op)?;
     }
+    Ok(())
+  }
 
+  /// Replay or roll back pending mutations of the journal, failed ones are left pending.
+  pub fn recover(&self, recovery: Recovery) -> Result<Recovered, WHError> {
+    let mut recovered = Recovered::default();
+
+    for record in self.journal.pending()? {
+      log::info!("journal {recovery:?} #{}: {:?}", record.seq, record.mutation);
+      let res = self.db.stage(|| {
+        let state = match recovery {
+          Recovery::Replay => {
+            self.apply_ops(std::slice::from_ref(&record.mutation), &[])?;
+            JournalState::Applied
+          },
+          // pending record was never applied
+          Recovery::Rollback => JournalState::RolledBack,
+        };
+        // topologies are committed with the mark
+        self.journal.mark(&record, state)
+      });
+      match res {
+        Ok(()) => recovered.done.push(record.seq),
+        Err(e) => {
+          log::error!("journal {recovery:?} #{} failed: {}", record.seq, e.message());
+          recovered.failed.push((record.seq, e));
+        },
+      }
+    }
+
+    Ok(recovered)
+  }
+
+  // received batch get barcode at first receive of it
+  fn assign_barcode(&self, mutation: &OpMutation) -> Result<(), WHError> {
+    if let Some(op) = mutation.to_op_after() {
+      if op.is_receive() && !op.batch.is_empty() {
+        self.barcodes.assign(op.goods, &op.batch)?;
+      }
+    }
     Ok(())
   }
 
@@ -84,7 +199,7 @@ impl Db {
     // apply operation between from and till
     for op in self.operations_for_store_goods(from, operation)? {
       let bal = balances.entry(op.batch.clone()).or_default();
-      bal.apply(&op.op);
+      bal.apply(&op.op, &self.conversions.of(op.goods));
     }
 
     // remove zero balances
@@ -184,6 +299,41 @@ impl Db {
     Err(WHError::new("can't get checkpoint before date"))
   }
 
+  /// Operations of batch at all stores, batch can't have operations before its date.
+  pub fn ops_for_batch(&self, goods: Goods, batch: &Batch) -> Result<Vec<Op>, WHError> {
+    self.ops_for_goods_batch(goods, batch, batch.date, DateTime::<Utc>::MAX_UTC)
+  }
+
+  /// Operations of batch at all stores between dates.
+  pub fn ops_for_goods_batch(
+    &self,
+    goods: Goods,
+    batch: &Batch,
+    from_date: DateTime<Utc>,
+    till_date: DateTime<Utc>,
+  ) -> Result<Vec<Op>, WHError> {
+    for ordered_topology in self.ordered_topologies.iter() {
+      match ordered_topology.ops_for_goods_batch(goods, batch, from_date, till_date) {
+        Ok(ops) => return Ok(ops),
+        Err(e) => {
+          if e.message() == *"Not supported" {
+            continue;
+          } else {
+            return Err(e);
+          }
+        },
+      }
+    }
+    Err(WHError::new("fn ops_for_goods_batch not implemented"))
+  }
+
+  /// Recorded operations of memories record, including dependent ones with batches the
+  /// operation was distributed to.
+  pub fn ops_for_record(&self, record: &Op) -> Result<Vec<Op>, WHError> {
+    let ops = self.ops_for_store_goods(record.store, record.goods, record.date, record.date)?;
+    Ok(ops.into_iter().filter(|op| op.id == record.id).collect())
+  }
+
   pub fn get_checkpoint_for_goods_and_batch(
     &self,
     store: Store,
@@ -206,6 +356,49 @@ impl Db {
     Err(WHError::new("can't get checkpoint before date"))
   }
 
+  /// Date of the nearest checkpoint of store at or before `date`.
+  pub fn checkpoint_date(
+    &self,
+    store: Store,
+    date: DateTime<Utc>,
+  ) -> Result<DateTime<Utc>, WHError> {
+    for checkpoint_topology in self.checkpoint_topologies.iter() {
+      match checkpoint_topology.checkpoint_date(store, date) {
+        Ok(result) => return Ok(result),
+        Err(e) => {
+          if e.message() == *"Not supported" {
+            continue;
+          } else {
+            return Err(e);
+          }
+        },
+      }
+    }
+    Err(WHError::new("can't get checkpoint before date"))
+  }
+
+  /// Checkpoint balances of store & goods from the end of interval of `date` on.
+  pub fn checkpoints_after(
+    &self,
+    store: Store,
+    goods: Goods,
+    date: DateTime<Utc>,
+  ) -> Result<Vec<Balance>, WHError> {
+    for checkpoint_topology in self.checkpoint_topologies.iter() {
+      match checkpoint_topology.checkpoints_after(store, goods, date) {
+        Ok(result) => return Ok(result),
+        Err(e) => {
+          if e.message() == *"Not supported" {
+            continue;
+          } else {
+            return Err(e);
+          }
+        },
+      }
+    }
+    Err(WHError::new("can't get checkpoints after date"))
+  }
+
   pub fn checkpoints_for_store_before_date(
     &self,
     store: Store,
@@ -255,7 +448,7 @@ impl Db {
   ) -> Result<Report, WHError> {
     for ordered_topology in self.ordered_topologies.iter() {
       match ordered_topology.report_for_store_goods(self, storage, goods, from_date, till_date) {
-        Ok(report) => return Ok(report),
+        Ok(report) => return self.with_costing(report),
         Err(_) => {}, // ignore
       }
     }
@@ -271,7 +464,7 @@ impl Db {
   ) -> Result<Report, WHError> {
     for ordered_topology in self.ordered_topologies.iter() {
       match ordered_topology.report_for_store(self, store, from_date, till_date) {
-        Ok(report) => return Ok(report),
+        Ok(report) => return self.with_costing(report),
         Err(_) => {}, // ignore
       }
     }
@@ -279,6 +472,110 @@ impl Db {
     Err(WHError::new("fn get_report not implemented"))
   }
 
+  /// Report of stores, or of all stores if none given, grouped by dimensions.
+  pub fn grouped_report(
+    &self,
+    stores: &[Store],
+    dimensions: Vec<Dimension>,
+    measures: Vec<Measure>,
+    from_date: DateTime<Utc>,
+    till_date: DateTime<Utc>,
+    attributes: &dyn Attributes,
+  ) -> Result<GroupedReport, WHError> {
+    let mut grouping =
+      Grouping::new(dimensions.clone(), from_date, till_date, attributes, &self.conversions);
+
+    let mut sources = Vec::new();
+    if stores.is_empty() {
+      let date = self.common_checkpoint_date(from_date)?;
+      sources.push((self.checkpoints_for_all_storages_before_date(from_date)?, None, date));
+    } else {
+      for store in stores {
+        let date = self.checkpoint_date(*store, from_date)?;
+        sources.push((
+          self.checkpoints_for_store_before_date(*store, from_date)?,
+          Some(*store),
+          date,
+        ));
+      }
+    }
+
+    for (balances, store, date) in sources {
+      for balance in balances {
+        grouping.balance(balance.store, balance.goods, &balance.batch, &balance.number);
+      }
+      for op in self.ops(store, date, till_date)? {
+        grouping.operation(&op);
+      }
+    }
+
+    Ok(GroupedReport { from_date, till_date, dimensions, measures, total: grouping.build() })
+  }
+
+  fn common_checkpoint_date(&self, date: DateTime<Utc>) -> Result<DateTime<Utc>, WHError> {
+    for checkpoint_topology in self.checkpoint_topologies.iter() {
+      match checkpoint_topology.common_checkpoint_date(date) {
+        Ok(result) => return Ok(result),
+        Err(e) => {
+          if e.message() == *"Not supported" {
+            continue;
+          } else {
+            return Err(e);
+          }
+        },
+      }
+    }
+    Err(WHError::new("can't get checkpoint before date"))
+  }
+
+  fn checkpoints_for_all_storages_before_date(
+    &self,
+    date: DateTime<Utc>,
+  ) -> Result<Vec<Balance>, WHError> {
+    for checkpoint_topology in self.checkpoint_topologies.iter() {
+      match checkpoint_topology.get_checkpoints_for_all_storages_before_date(date) {
+        Ok(result) => return Ok(result),
+        Err(e) => {
+          if e.message() == *"Not supported" {
+            continue;
+          } else {
+            return Err(e);
+          }
+        },
+      }
+    }
+    Err(WHError::new("can't get checkpoint before date"))
+  }
+
+  /// Operations of store, or of all stores if none given, between dates.
+  pub(crate) fn ops(
+    &self,
+    store: Option<Store>,
+    from_date: DateTime<Utc>,
+    till_date: DateTime<Utc>,
+  ) -> Result<Vec<Op>, WHError> {
+    for ordered_topology in self.ordered_topologies.iter() {
+      let ops = match store {
+        Some(store) => ordered_topology.ops_for_store(store, from_date, till_date),
+        None => ordered_topology.ops(from_date, till_date),
+      };
+      if let Ok(ops) = ops {
+        return Ok(ops);
+      }
+    }
+
+    Err(WHError::new("fn ops not implemented"))
+  }
+
+  fn with_costing(&self, mut report: Report) -> Result<Report, WHError> {
+    for agr in report.items.1.iter_mut() {
+      if let (Some(store), Some(goods)) = (agr.store, agr.goods) {
+        agr.costing = Some(self.costing.get(store, goods)?);
+      }
+    }
+    Ok(report)
+  }
+
   pub fn get_balance(
     &self,
     date: DateTime<Utc>,
@@ -305,7 +602,13 @@ impl Db {
     };
 
     for ordered_topology in self.ordered_topologies.iter() {
-      match ordered_topology.get_balances(from_date, date, goods, checkpoints.clone()) {
+      match ordered_topology.get_balances(
+        from_date,
+        date,
+        goods,
+        checkpoints.clone(),
+        &self.conversions,
+      ) {
         Ok(res) => return Ok(res),
         Err(_) => {}, // ignore
       }
@@ -347,6 +650,7 @@ impl Db {
         storage,
         goods,
         checkpoints.clone(),
+        &self.conversions,
       ) {
         Ok(res) => return Ok(res),
         Err(_) => {}, // ignore
@@ -383,7 +687,12 @@ impl Db {
     debug!("CHECKPOINTS: {checkpoints:?}");
 
     for ordered_topology in self.ordered_topologies.iter() {
-      match ordered_topology.get_balances_for_all(from_date, date, checkpoints.clone()) {
+      match ordered_topology.get_balances_for_all(
+        from_date,
+        date,
+        checkpoints.clone(),
+        &self.conversions,
+      ) {
         Ok(res) => return Ok(res),
         Err(_) => {}, // ignore
       }
@@ -17,7 +17,14 @@ use service::{Context, Services};
 use crate::aggregations::{AggregationStore, AggregationStoreGoods, AggregationStoreGoodsBatch};
 use crate::balance::{BalanceDelta, BalanceForGoods, Cost};
 use crate::batch::Batch;
+use crate::checkpoints::granularity::Granularity;
+use crate::conversions::{Conversion, Conversions};
+use crate::costing::CostingMethod;
+use crate::expiry::BatchExpiry;
+use crate::levels::StockLevel;
+use crate::negative_stock::NegativeStockPolicy;
 use crate::operations::{InternalOperation, Op, OpMutation};
+use crate::process_records::{GOODS, STORAGE, UOM};
 use crate::qty::Qty;
 use service::utils::json::JsonParams;
 use values::c;
@@ -93,6 +100,9 @@ pub struct NumberForGoods {
 pub enum Mode {
   Auto,
   Manual,
+  /// inventory of stock-take, difference to counted balance is evaluated against balance at
+  /// its date
+  Counted,
 }
 
 impl ToJson for Mode {
@@ -100,6 +110,7 @@ impl ToJson for Mode {
     match self {
       Mode::Auto => JsonValue::String("auto".to_string()),
       Mode::Manual => JsonValue::String("manual".to_string()),
+      Mode::Counted => JsonValue::String("counted".to_string()),
     }
   }
 }
@@ -154,6 +165,20 @@ pub(crate) fn time_to_naive_string(time: DateTime<Utc>) -> String {
   res
 }
 
+/// Operation memories record makes, to look it up at topologies by its store and goods.
+pub fn record_op(
+  app: &(impl GetWarehouse + Services),
+  wid: &str,
+  record: &JsonValue,
+  ctx: &Vec<String>,
+) -> Result<Option<Op>, WHError> {
+  let ops = json_to_ops(app, wid, record, ctx, |id| {
+    let params = object! {oid: wid, ctx: [], enrich: false };
+    app.service("memories").get(Context::local(), id, params).ok()
+  })?;
+  Ok(ops.into_values().next())
+}
+
 pub fn receive_data(
   app: &(impl GetWarehouse + Services),
   wid: &str,
@@ -177,6 +202,45 @@ pub fn receive_data(
   log::debug!("BEFOR: {:?}", before.dump());
   log::debug!("AFTER: {:?}", after.dump());
 
+  if ctx[..] == ["warehouse", "level"] {
+    return level_data(app, wid, &before, &after);
+  }
+
+  if ctx[..] == ["warehouse", "costing"] {
+    return costing_data(app, wid, &before, &after);
+  }
+
+  if ctx[..] == ["warehouse", "conversion"] {
+    return conversion_data(app, wid, &before, &after);
+  }
+
+  if ctx[..] == ["warehouse", "storage"] {
+    return storage_data(app, &before, &after);
+  }
+
+  // issues of document take goods reserved for it
+  let document = {
+    let data = if after.is_object() { &after } else { &before };
+    let id = data[c::DOCUMENT].string();
+    match stack.get(&id) {
+      Some((b, a)) => {
+        let document = if a.is_object() { a } else { b };
+        document[c::UUID].uuid_or_none()
+      },
+      None => {
+        let params = object! {oid: wid, ctx: [], enrich: false };
+        match app.service("memories").get(Context::local(), id, params) {
+          Ok(d) => d[c::UUID].uuid_or_none(),
+          Err(_) => None,
+        }
+      },
+    }
+  };
+
+  // expiry date of received batch is attribute of receive record
+  let expiry = after["expiry"].date_with_check().ok();
+  let had_expiry = !before["expiry"].is_null();
+
   let before = match json_to_ops(app, wid, &before, ctx, |id| {
     if let Some((b, _)) = stack.get(&id) {
       Some(b.clone())
@@ -248,10 +312,192 @@ pub fn receive_data(
     ops.push(OpMutation::new_from_ops(None, Some(a.1.clone())));
   }
 
+  for op in ops.iter_mut() {
+    op.document = document;
+  }
+
   log::debug!("OPS: {:#?}", ops);
 
+  // written in the same batch as operations
+  let expiries: Vec<BatchExpiry> = if expiry.is_some() || had_expiry {
+    ops
+      .iter()
+      .filter_map(|op| op.to_op_after())
+      .filter(|op| op.is_receive())
+      .map(|op| BatchExpiry { goods: op.goods, batch: op.batch, expiry })
+      .collect()
+  } else {
+    Vec::new()
+  };
+
   if !ops.is_empty() {
-    app.warehouse().mutate(&ops)?;
+    // closed periods of workspace apply to its stores
+    app.warehouse().mutate_in_workspace(Some(wid), &ops, &expiries)?;
+  }
+
+  Ok(())
+}
+
+// uuid of reference, references may be saved enriched or as id of memories at `ctx`
+fn uuid_of(app: &impl Services, wid: &str, reference: &JsonValue, ctx: &[&str]) -> Option<Uuid> {
+  if let Some(uuid) = reference[c::UUID].uuid_or_none() {
+    return Some(uuid);
+  }
+  let params = object! {oid: wid, ctx: ctx.to_vec() };
+  app.service("memories").get(Context::local(), reference.string(), params).ok()?[c::UUID]
+    .uuid_or_none()
+}
+
+/// Min/max levels of goods at store are kept by `warehouse/level` memories.
+fn level_data(
+  app: &(impl GetWarehouse + Services),
+  wid: &str,
+  before: &JsonValue,
+  after: &JsonValue,
+) -> Result<(), WHError> {
+  let store_goods = |data: &JsonValue| -> Option<(Store, Goods)> {
+    if !data.is_object() || data[c::STATUS].string() == c::DELETED {
+      return None;
+    }
+    Some((
+      uuid_of(app, wid, &data["storage"], &STORAGE)?,
+      uuid_of(app, wid, &data["goods"], &GOODS)?,
+    ))
+  };
+
+  if let Some((store, goods)) = store_goods(before) {
+    app.warehouse().set_level(store, goods, None)?;
+  }
+
+  if let Some((store, goods)) = store_goods(after) {
+    let level = StockLevel {
+      min: after["min"].clone().try_into().unwrap_or_default(),
+      max: after["max"].clone().try_into().unwrap_or_default(),
+    };
+    app.warehouse().set_level(store, goods, Some(level))?;
+  }
+
+  Ok(())
+}
+
+// costing method of store, or of goods at store if `goods` is given
+fn costing_data(
+  app: &(impl GetWarehouse + Services),
+  wid: &str,
+  before: &JsonValue,
+  after: &JsonValue,
+) -> Result<(), WHError> {
+  let store_goods = |data: &JsonValue| -> Option<(Store, Option<Goods>)> {
+    if !data.is_object() || data[c::STATUS].string() == c::DELETED {
+      return None;
+    }
+    let store = uuid_of(app, wid, &data["storage"], &STORAGE)?;
+    let goods =
+      if data["goods"].is_null() { None } else { Some(uuid_of(app, wid, &data["goods"], &GOODS)?) };
+    Some((store, goods))
+  };
+
+  // unknown method reject saving before old one is removed
+  let method = match store_goods(after) {
+    Some((store, goods)) => {
+      Some((store, goods, CostingMethod::try_from(after["method"].as_str().unwrap_or_default())?))
+    },
+    None => None,
+  };
+
+  if let Some((store, goods)) = store_goods(before) {
+    app.warehouse().database.costing.remove(store, goods)?;
+  }
+
+  if let Some((store, goods, method)) = method {
+    app.warehouse().set_costing(store, goods, method)?;
+  }
+
+  Ok(())
+}
+
+// factor between units `from` and `into`, of `goods` if it is given
+fn conversion_data(
+  app: &(impl GetWarehouse + Services),
+  wid: &str,
+  before: &JsonValue,
+  after: &JsonValue,
+) -> Result<(), WHError> {
+  let conversion = |data: &JsonValue| -> Option<Conversion> {
+    if !data.is_object() || data[c::STATUS].string() == c::DELETED {
+      return None;
+    }
+    let goods =
+      if data["goods"].is_null() { None } else { Some(uuid_of(app, wid, &data["goods"], &GOODS)?) };
+    Some(Conversion {
+      goods,
+      from: uuid_of(app, wid, &data["from"], &UOM)?,
+      into: uuid_of(app, wid, &data["into"], &UOM)?,
+      factor: data["factor"].number_or_none().unwrap_or_default(),
+    })
+  };
+
+  // invalid conversion reject saving before old one is removed
+  let after = conversion(after);
+  if let Some(conversion) = after.as_ref() {
+    Conversions::validate(conversion)?;
+  }
+
+  let before = conversion(before);
+  if before == after {
+    return Ok(());
+  }
+
+  // both or none of changes are applied
+  let warehouse = app.warehouse();
+  warehouse.database.db.stage(|| {
+    if let Some(before) = before {
+      warehouse.remove_conversion(before.goods, before.from, before.into)?;
+    }
+    if let Some(after) = after.as_ref() {
+      warehouse.set_conversion(after)?;
+    }
+    Ok(())
+  })
+}
+
+// policies of store are kept by `warehouse/storage` memories
+fn storage_data(
+  app: &(impl GetWarehouse + Services),
+  before: &JsonValue,
+  after: &JsonValue,
+) -> Result<(), WHError> {
+  let store = match after[c::UUID].uuid_or_none() {
+    Some(store) => store,
+    None => return Ok(()),
+  };
+
+  // allow if setting is removed
+  let negative_stock = |data: &JsonValue| -> Result<NegativeStockPolicy, WHError> {
+    match data["negative_stock"].as_str() {
+      Some(policy) if data[c::STATUS].string() != c::DELETED => {
+        NegativeStockPolicy::try_from(policy)
+      },
+      _ => Ok(NegativeStockPolicy::default()),
+    }
+  };
+
+  // monthly if setting is removed
+  let granularity = match after["checkpoint_granularity"].as_str() {
+    Some(granularity) if after[c::STATUS].string() != c::DELETED => {
+      Granularity::try_from(granularity)?
+    },
+    _ => Granularity::default(),
+  };
+
+  let policy = negative_stock(after)?;
+  if before.is_null() || policy != negative_stock(before).unwrap_or_default() {
+    app.warehouse().set_negative_stock(store, policy)?;
+  }
+
+  // checkpoints of store are rebuilt only if it is changed, not while document is saved
+  if granularity != app.warehouse().database.granularities.chosen(store)? {
+    app.warehouse().schedule_checkpoint_granularity(store, granularity)?;
   }
 
   Ok(())
@@ -359,6 +605,9 @@ where
         let (cost, mode) =
           if let Some(cost) = cost { (cost.into(), Mode::Manual) } else { (0.into(), Mode::Auto) };
 
+        // records of stock-take document carry counted balance
+        let mode = if data["mode"].as_str() == Some("counted") { Mode::Counted } else { mode };
+
         // let qty = qty.unwrap_or_default();
 
         InternalOperation::Inventory(BalanceForGoods { qty, cost }, BalanceDelta::default(), mode)
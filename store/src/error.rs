@@ -1,84 +1,105 @@
-use chrono::ParseError;
+use crate::negative_stock::Shortage;
+use chrono::{DateTime, ParseError, Utc};
 use json::JsonError;
 use std::string::FromUtf8Error;
 
 #[derive(Debug)]
 pub struct WHError {
   message: String,
+  shortage: Option<Box<Shortage>>,
+  closed_till: Option<DateTime<Utc>>,
 }
 
 impl WHError {
   pub fn new(e: &str) -> Self {
-    WHError { message: e.to_string() }
+    WHError { message: e.to_string(), shortage: None, closed_till: None }
+  }
+
+  /// Operations rejected by negative stock policy of store.
+  pub fn negative_stock(shortage: Shortage) -> Self {
+    WHError { message: shortage.to_string(), shortage: Some(Box::new(shortage)), closed_till: None }
+  }
+
+  /// Operations rejected because they are dated inside of closed period.
+  pub fn closed_period(message: &str, closed_till: DateTime<Utc>) -> Self {
+    WHError { message: message.to_string(), shortage: None, closed_till: Some(closed_till) }
   }
 
   pub fn message(&self) -> String {
     self.message.clone()
   }
+
+  pub fn shortage(&self) -> Option<&Shortage> {
+    self.shortage.as_deref()
+  }
+
+  pub fn closed_till(&self) -> Option<DateTime<Utc>> {
+    self.closed_till
+  }
 }
 
 impl From<service::error::Error> for WHError {
   fn from(e: service::error::Error) -> Self {
-    WHError { message: e.to_string() }
+    WHError::new(&e.to_string())
   }
 }
 
 impl From<rocksdb::Error> for WHError {
   fn from(e: rocksdb::Error) -> Self {
-    WHError { message: e.to_string() }
+    WHError::new(e.as_ref())
   }
 }
 
 impl From<serde_json::Error> for WHError {
   fn from(e: serde_json::Error) -> Self {
-    WHError { message: e.to_string() }
+    WHError::new(&e.to_string())
   }
 }
 
 impl From<ciborium::ser::Error<std::io::Error>> for WHError {
   fn from(e: ciborium::ser::Error<std::io::Error>) -> Self {
-    WHError { message: e.to_string() }
+    WHError::new(&e.to_string())
   }
 }
 
 impl From<ciborium::de::Error<std::io::Error>> for WHError {
   fn from(e: ciborium::de::Error<std::io::Error>) -> Self {
-    WHError { message: e.to_string() }
+    WHError::new(&e.to_string())
   }
 }
 
 impl From<ParseError> for WHError {
   fn from(e: ParseError) -> Self {
-    WHError { message: e.to_string() }
+    WHError::new(&e.to_string())
   }
 }
 
 impl From<FromUtf8Error> for WHError {
   fn from(e: FromUtf8Error) -> Self {
-    WHError { message: e.to_string() }
+    WHError::new(&e.to_string())
   }
 }
 
 impl From<rust_decimal::Error> for WHError {
   fn from(e: rust_decimal::Error) -> Self {
-    WHError { message: e.to_string() }
+    WHError::new(&e.to_string())
   }
 }
 
 impl From<uuid::Error> for WHError {
   fn from(e: uuid::Error) -> Self {
-    WHError { message: e.to_string() }
+    WHError::new(&e.to_string())
   }
 }
 
 // impl From<service::error::Error> for WHError {
 //   fn from(e: service::error::Error) -> Self {
-//     WHError { message: e.to_string() }
+//     WHError::new(&e.to_string())
 //   }
 // }
 
 impl From<JsonError> for WHError {
   fn from(e: JsonError) -> Self {
-    WHError { message: e.to_string() }
+    WHError::new(&e.to_string())
   }
 }
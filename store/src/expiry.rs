@@ -0,0 +1,155 @@
+use crate::balance::BalanceForGoods;
+use crate::batch::Batch;
+use crate::costing::CostingMethod;
+use crate::db::Db;
+use crate::elements::{time_to_naive_string, Goods, Store, ToJson};
+use crate::error::WHError;
+use crate::operations::{InternalOperation, OpMutation};
+use crate::staging::StagedDB;
+use chrono::{DateTime, Duration, Utc};
+use json::{object, JsonValue};
+use std::sync::Arc;
+
+const CF_NAME: &str = "cf_batch_expiry";
+
+/// Expiry dates of batches of goods, batches without it never expire.
+#[derive(Clone)]
+pub struct Expiries {
+  pub db: Arc<StagedDB>,
+}
+
+impl Expiries {
+  pub fn cf_name() -> &'static str {
+    CF_NAME
+  }
+
+  /// Record expiry date of batch of goods, remove it if `None`.
+  pub fn set(
+    &self,
+    goods: Goods,
+    batch: &Batch,
+    expiry: Option<DateTime<Utc>>,
+  ) -> Result<(), WHError> {
+    match expiry {
+      Some(date) => {
+        let mut bs = Vec::new();
+        ciborium::ser::into_writer(&date, &mut bs)?;
+        self.db.put_cf(CF_NAME, batch.to_bytes(&goods), bs)
+      },
+      None => self.db.delete_cf(CF_NAME, batch.to_bytes(&goods)),
+    }
+  }
+
+  pub fn get(&self, goods: Goods, batch: &Batch) -> Result<Option<DateTime<Utc>>, WHError> {
+    match self.db.get_cf(CF_NAME, batch.to_bytes(&goods))? {
+      Some(bs) => Ok(Some(ciborium::de::from_reader(bs.as_slice())?)),
+      None => Ok(None),
+    }
+  }
+}
+
+/// Expiry date of batch of goods recorded together with mutations, `None` remove it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchExpiry {
+  pub goods: Goods,
+  pub batch: Batch,
+  pub expiry: Option<DateTime<Utc>>,
+}
+
+/// Balance of batch at store which expire at date.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expiring {
+  pub store: Store,
+  pub goods: Goods,
+  pub batch: Batch,
+  pub expiry: DateTime<Utc>,
+  pub balance: BalanceForGoods,
+}
+
+impl ToJson for Expiring {
+  fn to_json(&self) -> JsonValue {
+    object! {
+      store: self.store.to_json(),
+      goods: self.goods.to_json(),
+      batch: self.batch.to_json(),
+      expiry: time_to_naive_string(self.expiry),
+      qty: &self.balance.qty,
+      cost: self.balance.cost.to_json(),
+    }
+  }
+}
+
+impl Db {
+  /// Record changed expiry dates and distribute again issues of goods at FEFO stores
+  /// which follow receive of batch, should be called at staging.
+  pub(crate) fn update_expiries(&self, expiries: &[BatchExpiry]) -> Result<(), WHError> {
+    if expiries.is_empty() {
+      return Ok(());
+    }
+    // operations of closed periods stay as they are
+    let closed = self.periods.closed_stores()?;
+
+    for e in expiries {
+      if self.expiries.get(e.goods, &e.batch)? == e.expiry {
+        continue;
+      }
+      self.expiries.set(e.goods, &e.batch, e.expiry)?;
+
+      let mut stores = Vec::new();
+      for op in self.ops_for_batch(e.goods, &e.batch)? {
+        if !stores.contains(&op.store) {
+          stores.push(op.store);
+        }
+      }
+
+      // issues without batch are distributed to batches by costing method of store
+      for op in self.ops_for_batch(e.goods, &Batch::no())? {
+        if op.is_dependent
+          || op.date < e.batch.date
+          || closed.get(&op.store).is_some_and(|till| op.date < *till)
+          || !stores.contains(&op.store)
+          || !matches!(op.op, InternalOperation::Issue(..))
+          || self.costing.get(op.store, e.goods)? != CostingMethod::Fefo
+        {
+          continue;
+        }
+        let mutation = OpMutation::new_from_ops(Some(op.clone()), Some(op));
+        self.ordered_topologies[0].data_update(self, &mutation)?;
+      }
+    }
+    Ok(())
+  }
+
+  /// Batches with positive balance at `date` which expire within `days` after it, expired
+  /// ones included, at given stores or at all of them if none given. Soonest first.
+  pub fn expiring(
+    &self,
+    stores: &[Store],
+    date: DateTime<Utc>,
+    days: i64,
+  ) -> Result<Vec<Expiring>, WHError> {
+    let till = date + Duration::days(days);
+
+    let mut result = Vec::new();
+    for (store, goods_balances) in self.get_balance_for_all(date)? {
+      if !stores.is_empty() && !stores.contains(&store) {
+        continue;
+      }
+      for (goods, batches) in goods_balances {
+        for (batch, balance) in batches {
+          if balance.is_zero() || !balance.qty.is_positive() {
+            continue;
+          }
+          if let Some(expiry) = self.expiries.get(goods, &batch)? {
+            if expiry <= till {
+              result.push(Expiring { store, goods, batch, expiry, balance });
+            }
+          }
+        }
+      }
+    }
+    result.sort_by_key(|e| (e.expiry, e.store, e.goods));
+
+    Ok(result)
+  }
+}
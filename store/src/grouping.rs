@@ -0,0 +1,423 @@
+use crate::balance::{BalanceDelta, BalanceForGoods};
+use crate::batch::Batch;
+use crate::checkpoints::granularity::Granularity;
+use crate::conversions::{Conversions, Factors};
+use crate::elements::{time_to_naive_string, Goods, Store, ToJson};
+use crate::error::WHError;
+use crate::operations::{InternalOperation, Op};
+use chrono::{DateTime, Utc};
+use json::{object, JsonValue};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// What report rows are grouped by, date groups rows by interval of given granularity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dimension {
+  Store,
+  Category,
+  Goods,
+  Batch,
+  Document,
+  Counterparty,
+  Date(Granularity),
+}
+
+impl Dimension {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Dimension::Store => "store",
+      Dimension::Category => "category",
+      Dimension::Goods => "goods",
+      Dimension::Batch => "batch",
+      Dimension::Document => "document",
+      Dimension::Counterparty => "counterparty",
+      Dimension::Date(granularity) => granularity.as_str(),
+    }
+  }
+}
+
+impl TryFrom<&str> for Dimension {
+  type Error = WHError;
+
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    match value {
+      "store" | "storage" => Ok(Dimension::Store),
+      "category" => Ok(Dimension::Category),
+      "goods" => Ok(Dimension::Goods),
+      "batch" => Ok(Dimension::Batch),
+      "document" => Ok(Dimension::Document),
+      "counterparty" => Ok(Dimension::Counterparty),
+      _ => match Granularity::try_from(value) {
+        Ok(Granularity::Adaptive) | Err(_) => {
+          Err(WHError::new(&format!("unknown report dimension {value}")))
+        },
+        Ok(granularity) => Ok(Dimension::Date(granularity)),
+      },
+    }
+  }
+}
+
+/// Value reported for every group.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Measure {
+  OpenQty,
+  OpenCost,
+  ReceiveQty,
+  ReceiveCost,
+  IssueQty,
+  IssueCost,
+  CloseQty,
+  CloseCost,
+}
+
+impl Measure {
+  pub const ALL: [Measure; 8] = [
+    Measure::OpenQty,
+    Measure::OpenCost,
+    Measure::ReceiveQty,
+    Measure::ReceiveCost,
+    Measure::IssueQty,
+    Measure::IssueCost,
+    Measure::CloseQty,
+    Measure::CloseCost,
+  ];
+
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Measure::OpenQty => "open_qty",
+      Measure::OpenCost => "open_cost",
+      Measure::ReceiveQty => "receive_qty",
+      Measure::ReceiveCost => "receive_cost",
+      Measure::IssueQty => "issue_qty",
+      Measure::IssueCost => "issue_cost",
+      Measure::CloseQty => "close_qty",
+      Measure::CloseCost => "close_cost",
+    }
+  }
+}
+
+impl TryFrom<&str> for Measure {
+  type Error = WHError;
+
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    Measure::ALL
+      .into_iter()
+      .find(|measure| measure.as_str() == value)
+      .ok_or_else(|| WHError::new(&format!("unknown report measure {value}")))
+  }
+}
+
+/// Value of dimension that rows of group share.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Key {
+  None,
+  Uuid(Uuid),
+  Id(String),
+  Batch(Batch),
+  Date(DateTime<Utc>),
+}
+
+impl ToJson for Key {
+  fn to_json(&self) -> JsonValue {
+    match self {
+      Key::None => JsonValue::Null,
+      Key::Uuid(uuid) => uuid.to_json(),
+      Key::Id(id) => id.as_str().into(),
+      Key::Batch(batch) => batch.to_json(),
+      Key::Date(date) => time_to_naive_string(*date).into(),
+    }
+  }
+}
+
+/// Attributes of stores, goods and operations that warehouse doesn't keep, provided by caller.
+pub trait Attributes {
+  /// Store that balances of `store` are counted at, e.g. top one of nested storages.
+  fn store(&self, store: Store) -> Key {
+    Key::Uuid(store)
+  }
+
+  fn category(&self, _goods: Goods) -> Key {
+    Key::None
+  }
+
+  fn document(&self, _op: &Op) -> Key {
+    Key::None
+  }
+
+  fn counterparty(&self, _op: &Op) -> Key {
+    Key::None
+  }
+}
+
+impl Attributes for () {}
+
+/// Open balance, receive, issue and close balance of group.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Movement {
+  /// goods of movement, `None` if it is of several goods
+  pub goods: Option<Goods>,
+  pub open_balance: BalanceForGoods,
+  pub receive: BalanceDelta,
+  pub issue: BalanceDelta,
+  pub close_balance: BalanceForGoods,
+}
+
+impl Movement {
+  fn of(goods: Goods) -> Self {
+    Movement { goods: Some(goods), ..Movement::default() }
+  }
+
+  // quantities of several goods are summed without conversions
+  fn factors(&self, conversions: &Conversions) -> Factors {
+    self.goods.map(|goods| conversions.of(goods)).unwrap_or_default()
+  }
+
+  fn close(&mut self, conversions: &Conversions) {
+    let factors = self.factors(conversions);
+    self.close_balance = self
+      .open_balance
+      .add_with(&self.receive, &factors)
+      .add_with(&self.issue, &factors);
+  }
+
+  fn add(&mut self, rhs: &Movement, conversions: &Conversions) {
+    if self.goods != rhs.goods {
+      self.goods = None;
+    }
+    let factors = self.factors(conversions);
+    self.open_balance = self.open_balance.add_balance(&rhs.open_balance, &factors);
+    self.receive = self.receive.add_with(&rhs.receive, &factors);
+    self.issue = self.issue.add_with(&rhs.issue, &factors);
+    self.close_balance = self.close_balance.add_balance(&rhs.close_balance, &factors);
+  }
+
+  pub fn is_zero(&self) -> bool {
+    self.open_balance.is_zero()
+      && self.receive.is_zero()
+      && self.issue.is_zero()
+      && self.close_balance.is_zero()
+  }
+
+  /// Issue is reported as positive like in `AggregationStoreGoods`.
+  pub fn measure(&self, measure: Measure) -> JsonValue {
+    match measure {
+      Measure::OpenQty => (&self.open_balance.qty).into(),
+      Measure::OpenCost => self.open_balance.cost.to_json(),
+      Measure::ReceiveQty => (&self.receive.qty).into(),
+      Measure::ReceiveCost => self.receive.cost.to_json(),
+      Measure::IssueQty => (&self.issue.reverse().qty).into(),
+      Measure::IssueCost => self.issue.reverse().cost.to_json(),
+      Measure::CloseQty => (&self.close_balance.qty).into(),
+      Measure::CloseCost => self.close_balance.cost.to_json(),
+    }
+  }
+}
+
+/// Group of report rows with subtotal of them, children are grouped by next dimension.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Group {
+  pub key: Key,
+  pub movement: Movement,
+  pub children: Vec<Group>,
+}
+
+impl Group {
+  fn build(
+    key: Key,
+    rows: &[(Vec<Key>, Movement)],
+    level: usize,
+    conversions: &Conversions,
+  ) -> Group {
+    let mut movement =
+      Movement { goods: rows.first().and_then(|(_, row)| row.goods), ..Movement::default() };
+    for (_, row) in rows {
+      movement.add(row, conversions);
+    }
+
+    let mut children = Vec::new();
+    let mut rest = rows;
+    while let Some((keys, _)) = rest.first().filter(|(keys, _)| keys.len() > level) {
+      let len = rest.iter().take_while(|(k, _)| k[level] == keys[level]).count();
+      children.push(Group::build(keys[level].clone(), &rest[..len], level + 1, conversions));
+      rest = &rest[len..];
+    }
+
+    Group { key, movement, children }
+  }
+
+  fn to_json(&self, dimensions: &[Dimension], measures: &[Measure]) -> JsonValue {
+    let mut data = object! { key: self.key.to_json() };
+    for measure in measures {
+      data[measure.as_str()] = self.movement.measure(*measure);
+    }
+    if let Some((dimension, rest)) = dimensions.split_first() {
+      data["dimension"] = dimension.as_str().into();
+      let children: Vec<JsonValue> =
+        self.children.iter().map(|child| child.to_json(rest, measures)).collect();
+      data["children"] = children.into();
+    }
+    data
+  }
+}
+
+/// Report grouped by dimensions, `total` is the root group.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupedReport {
+  pub from_date: DateTime<Utc>,
+  pub till_date: DateTime<Utc>,
+  pub dimensions: Vec<Dimension>,
+  pub measures: Vec<Measure>,
+  pub total: Group,
+}
+
+impl ToJson for GroupedReport {
+  fn to_json(&self) -> JsonValue {
+    object! {
+      from_date: time_to_naive_string(self.from_date),
+      till_date: time_to_naive_string(self.till_date),
+      dimensions: self.dimensions.iter().map(|d| d.as_str()).collect::<Vec<_>>(),
+      measures: self.measures.iter().map(|m| m.as_str()).collect::<Vec<_>>(),
+      total: self.total.to_json(&self.dimensions, &self.measures),
+    }
+  }
+}
+
+/// Collects balances at start of period and operations into groups by dimensions.
+pub struct Grouping<'a> {
+  dimensions: Vec<Dimension>,
+  from_date: DateTime<Utc>,
+  till_date: DateTime<Utc>,
+  attributes: &'a dyn Attributes,
+  conversions: &'a Conversions,
+  movements: BTreeMap<Vec<Key>, Movement>,
+}
+
+impl<'a> Grouping<'a> {
+  pub fn new(
+    dimensions: Vec<Dimension>,
+    from_date: DateTime<Utc>,
+    till_date: DateTime<Utc>,
+    attributes: &'a dyn Attributes,
+    conversions: &'a Conversions,
+  ) -> Self {
+    Grouping {
+      dimensions,
+      from_date,
+      till_date,
+      attributes,
+      conversions,
+      movements: BTreeMap::new(),
+    }
+  }
+
+  fn movement(&mut self, keys: Vec<Key>, goods: Goods) -> &mut Movement {
+    let movement = self.movements.entry(keys).or_insert_with(|| Movement::of(goods));
+    if movement.goods != Some(goods) {
+      movement.goods = None;
+    }
+    movement
+  }
+
+  fn keys(&self, store: Store, goods: Goods, batch: &Batch, op: Option<&Op>) -> Vec<Key> {
+    let date = op.map(|op| op.date).unwrap_or(self.from_date);
+    self
+      .dimensions
+      .iter()
+      .map(|dimension| match dimension {
+        Dimension::Store => self.attributes.store(store),
+        Dimension::Category => self.attributes.category(goods),
+        Dimension::Goods => Key::Uuid(goods),
+        Dimension::Batch => Key::Batch(batch.clone()),
+        Dimension::Document => op.map(|op| self.attributes.document(op)).unwrap_or(Key::None),
+        Dimension::Counterparty => {
+          op.map(|op| self.attributes.counterparty(op)).unwrap_or(Key::None)
+        },
+        Dimension::Date(granularity) => Key::Date(granularity.current(date)),
+      })
+      .collect()
+  }
+
+  /// Balance at start of period.
+  pub fn balance(&mut self, store: Store, goods: Goods, batch: &Batch, balance: &BalanceForGoods) {
+    let keys = self.keys(store, goods, batch, None);
+    let factors = self.conversions.of(goods);
+    let movement = self.movement(keys, goods);
+    movement.open_balance = movement.open_balance.add_balance(balance, &factors);
+  }
+
+  /// Operation of period, the ones before it are part of open balance.
+  pub fn operation(&mut self, op: &Op) {
+    if op.date > self.till_date {
+      return;
+    }
+
+    let factors = self.conversions.of(op.goods);
+    if op.date < self.from_date {
+      let keys = self.keys(op.store, op.goods, &op.batch, None);
+      let movement = self.movement(keys, op.goods);
+      movement.open_balance = movement.open_balance.add_with(&op.to_delta(), &factors);
+      return;
+    }
+
+    let keys = self.keys(op.store, op.goods, &op.batch, Some(op));
+    let movement = self.movement(keys, op.goods);
+    match op.op {
+      InternalOperation::Receive(..) => {
+        movement.receive = movement.receive.add_with(&op.to_delta(), &factors)
+      },
+      InternalOperation::Issue(..) | InternalOperation::Inventory(..) => {
+        movement.issue = movement.issue.add_with(&op.to_delta(), &factors)
+      },
+    }
+  }
+
+  /// Groups with subtotals, rows with nothing to report are skipped.
+  pub fn build(self) -> Group {
+    let date = self.dimensions.iter().enumerate().find_map(|(i, dimension)| match dimension {
+      Dimension::Date(granularity) => Some((i, *granularity)),
+      _ => None,
+    });
+
+    let mut rows: BTreeMap<Vec<Key>, Movement> = BTreeMap::new();
+    if let Some((position, granularity)) = date {
+      // balance of every interval continues from previous one
+      let mut series: BTreeMap<Vec<Key>, BTreeMap<Key, Movement>> = BTreeMap::new();
+      for (mut keys, movement) in self.movements {
+        let date = std::mem::replace(&mut keys[position], Key::None);
+        series.entry(keys).or_default().insert(date, movement);
+      }
+
+      for (keys, mut intervals) in series {
+        let goods = intervals.values().next().and_then(|movement| movement.goods);
+        let mut balance = BalanceForGoods::default();
+        let mut date = granularity.current(self.from_date);
+        while date <= self.till_date {
+          let mut movement = intervals
+            .remove(&Key::Date(date))
+            .unwrap_or_else(|| Movement { goods, ..Movement::default() });
+          let factors = movement.factors(self.conversions);
+          movement.open_balance = movement.open_balance.add_balance(&balance, &factors);
+          movement.close(self.conversions);
+          balance = movement.close_balance.clone();
+
+          if !movement.is_zero() {
+            let mut keys = keys.clone();
+            keys[position] = Key::Date(date);
+            rows.insert(keys, movement);
+          }
+          date = granularity.next(date);
+        }
+      }
+    } else {
+      for (keys, mut movement) in self.movements {
+        movement.close(self.conversions);
+        if !movement.is_zero() {
+          rows.insert(keys, movement);
+        }
+      }
+    }
+
+    let rows: Vec<(Vec<Key>, Movement)> = rows.into_iter().collect();
+    Group::build(Key::None, &rows, 0, self.conversions)
+  }
+}
@@ -0,0 +1,669 @@
+use crate::elements::ToJson;
+use crate::process_records::{
+  CATEGORY, COUNTERPARTY, CURRENCY, DISPATCH_DOCUMENT, GOODS, RECEIVE_DOCUMENT, STORAGE,
+  TRANSFER_DOCUMENT, UOM,
+};
+use calamine::{open_workbook_from_rs, Data, Reader, Xlsx, XlsxError};
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use csv::{ReaderBuilder, Trim};
+use json::{object, JsonValue};
+use rust_decimal::Decimal;
+use service::error::Error;
+use service::utils::json::JsonParams;
+use service::{Context, Services};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::str::FromStr;
+use values::c;
+
+const DATE_FORMAT: &str = "%d.%m.%Y";
+// datetime cells of xlsx with time of day
+const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+const CURRENCY_NAME: &str = "uzd";
+// document number of rows without it, same as at csv import
+const NO_NUMBER: &str = "-1";
+
+/// Kind of warehouse document rows of file are imported into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocumentKind {
+  Receive,
+  Dispatch,
+  Transfer,
+}
+
+impl DocumentKind {
+  fn ctx(&self) -> Vec<&'static str> {
+    match self {
+      DocumentKind::Receive => vec!["warehouse", "receive"],
+      DocumentKind::Dispatch => vec!["warehouse", "dispatch"],
+      DocumentKind::Transfer => vec!["warehouse", "transfer"],
+    }
+  }
+
+  fn document_ctx(&self) -> Vec<&'static str> {
+    match self {
+      DocumentKind::Receive => RECEIVE_DOCUMENT.to_vec(),
+      DocumentKind::Dispatch => DISPATCH_DOCUMENT.to_vec(),
+      DocumentKind::Transfer => TRANSFER_DOCUMENT.to_vec(),
+    }
+  }
+
+  // context and name of document field for `from` and `into` columns
+  fn source(&self) -> (Vec<&'static str>, &'static str) {
+    match self {
+      DocumentKind::Receive => (COUNTERPARTY.to_vec(), "counterparty"),
+      DocumentKind::Dispatch => (STORAGE.to_vec(), "storage"),
+      DocumentKind::Transfer => (STORAGE.to_vec(), "from"),
+    }
+  }
+
+  fn target(&self) -> (Vec<&'static str>, &'static str) {
+    match self {
+      DocumentKind::Receive => (STORAGE.to_vec(), "storage"),
+      DocumentKind::Dispatch => (COUNTERPARTY.to_vec(), "counterparty"),
+      DocumentKind::Transfer => (STORAGE.to_vec(), "into"),
+    }
+  }
+
+  fn required(&self) -> Vec<Field> {
+    let mut fields = vec![Field::Date, Field::Goods, Field::Qty];
+    match self {
+      DocumentKind::Receive => fields.push(Field::Into),
+      DocumentKind::Dispatch => fields.push(Field::From),
+      DocumentKind::Transfer => fields.extend([Field::From, Field::Into]),
+    }
+    fields
+  }
+}
+
+impl TryFrom<&str> for DocumentKind {
+  type Error = Error;
+
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    match value {
+      "receive" => Ok(DocumentKind::Receive),
+      "dispatch" => Ok(DocumentKind::Dispatch),
+      "transfer" => Ok(DocumentKind::Transfer),
+      _ => Err(Error::GeneralError(format!("unknown document `{value}`"))),
+    }
+  }
+}
+
+/// Field of document or its operation that column of file is mapped to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Field {
+  Number,
+  Date,
+  Goods,
+  VendorCode,
+  Category,
+  Uom,
+  Qty,
+  Cost,
+  From,
+  Into,
+}
+
+const FIELDS: [(Field, &str); 10] = [
+  (Field::Number, "number"),
+  (Field::Date, "date"),
+  (Field::Goods, "goods"),
+  (Field::VendorCode, "vendor_code"),
+  (Field::Category, "category"),
+  (Field::Uom, "uom"),
+  (Field::Qty, "qty"),
+  (Field::Cost, "cost"),
+  (Field::From, "from"),
+  (Field::Into, "into"),
+];
+
+impl Field {
+  fn as_str(&self) -> &'static str {
+    FIELDS
+      .iter()
+      .find(|(f, _)| f == self)
+      .map(|(_, name)| *name)
+      .unwrap_or_default()
+  }
+}
+
+/// Column of file by its position from zero or by name at header row.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Column {
+  Index(usize),
+  Name(String),
+}
+
+/// Format of imported file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+  Csv,
+  Xlsx,
+}
+
+impl TryFrom<&str> for Format {
+  type Error = Error;
+
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    match value.to_lowercase().as_str() {
+      "csv" => Ok(Format::Csv),
+      "xlsx" => Ok(Format::Xlsx),
+      _ => Err(Error::GeneralError(format!("unknown file format `{value}`"))),
+    }
+  }
+}
+
+/// Declarative mapping of file columns to document fields, e.g.
+/// `{"document": "receive", "header": true, "columns": {"date": "Date", "goods": 2, "qty": 5,
+/// "into": "Storage"}, "units": {"pcs.": "pcs"}}`.
+#[derive(Debug, Clone)]
+pub struct Mapping {
+  pub document: DocumentKind,
+  /// first row contain names of columns
+  pub header: bool,
+  pub delimiter: u8,
+  pub date_format: String,
+  pub columns: HashMap<Field, Column>,
+  /// aliases of units of measure at file
+  pub units: HashMap<String, String>,
+  pub currency: String,
+  /// create goods, storages, counterparties & units that are not found by name
+  pub create_missing: bool,
+}
+
+impl TryFrom<&JsonValue> for Mapping {
+  type Error = Error;
+
+  fn try_from(data: &JsonValue) -> Result<Self, Self::Error> {
+    let document = DocumentKind::try_from(data["document"].as_str().unwrap_or_default())?;
+
+    let mut columns = HashMap::new();
+    for (name, column) in data["columns"].entries() {
+      let field = FIELDS
+        .iter()
+        .find(|(_, n)| *n == name)
+        .map(|(f, _)| *f)
+        .ok_or_else(|| Error::GeneralError(format!("unknown field `{name}` at mapping")))?;
+
+      let column = if let Some(index) = column.as_usize() {
+        Column::Index(index)
+      } else if let Some(name) = column.as_str() {
+        Column::Name(name.to_string())
+      } else {
+        return Err(Error::GeneralError(format!("column of field `{name}` is not valid")));
+      };
+      columns.insert(field, column);
+    }
+
+    for field in document.required() {
+      if !columns.contains_key(&field) {
+        return Err(Error::GeneralError(format!("column of field `{}` is missing", field.as_str())));
+      }
+    }
+
+    let header = data["header"].as_bool().unwrap_or(false);
+    if !header && columns.values().any(|c| matches!(c, Column::Name(_))) {
+      return Err(Error::GeneralError("columns by name require header".into()));
+    }
+
+    let delimiter = match data["delimiter"].as_str() {
+      Some(d) if d.len() == 1 => d.as_bytes()[0],
+      Some(d) => return Err(Error::GeneralError(format!("delimiter `{d}` is not valid"))),
+      None => b',',
+    };
+
+    let units = data["units"]
+      .entries()
+      .filter_map(|(n, v)| v.as_str().map(|v| (n.to_string(), v.to_string())))
+      .collect();
+
+    Ok(Mapping {
+      document,
+      header,
+      delimiter,
+      date_format: data["date_format"].as_str().unwrap_or(DATE_FORMAT).to_string(),
+      columns,
+      units,
+      currency: data["currency"].as_str().unwrap_or(CURRENCY_NAME).to_string(),
+      create_missing: data["create_missing"].as_bool().unwrap_or(false),
+    })
+  }
+}
+
+/// Error of file row, rows are numbered from one including header.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowError {
+  pub row: usize,
+  pub error: String,
+}
+
+/// Outcome of import, at dry run or when any row has error nothing is imported.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportReport {
+  pub rows: usize,
+  pub imported: usize,
+  pub documents: usize,
+  pub errors: Vec<RowError>,
+}
+
+impl ToJson for ImportReport {
+  fn to_json(&self) -> JsonValue {
+    let errors: Vec<JsonValue> = self
+      .errors
+      .iter()
+      .map(|e| object! { row: e.row, error: e.error.clone() })
+      .collect();
+    object! {
+      rows: self.rows,
+      imported: self.imported,
+      documents: self.documents,
+      errors: errors,
+    }
+  }
+}
+
+// values of one row
+#[derive(Debug, Clone, Default)]
+struct Row {
+  number: String,
+  date: String,
+  goods: String,
+  vendor_code: String,
+  category: String,
+  uom: String,
+  qty: Decimal,
+  cost: Option<Decimal>,
+  from: String,
+  into: String,
+}
+
+/// Import rows of file as operations of documents described by mapping.
+///
+/// All rows are parsed and checked before anything is created. If creating of memories fail,
+/// created ones are marked deleted, that rollback is best-effort: memories it can't delete are
+/// listed at `rollback` of `Error::Unprocessable` data together with the original `error`.
+pub fn import(
+  app: &impl Services,
+  oid: &str,
+  mapping: &Mapping,
+  format: Format,
+  content: &[u8],
+  dry_run: bool,
+) -> Result<ImportReport, Error> {
+  let mut rows = read_rows(format, content, mapping.delimiter)?.into_iter().enumerate();
+
+  let header = if mapping.header {
+    rows.next().map(|(_, names)| names).unwrap_or_default()
+  } else {
+    Vec::new()
+  };
+
+  let mut columns = HashMap::new();
+  for (field, column) in mapping.columns.iter() {
+    let index = match column {
+      Column::Index(index) => *index,
+      Column::Name(name) => header
+        .iter()
+        .position(|n| n == name)
+        .ok_or_else(|| Error::GeneralError(format!("column `{name}` not found at header")))?,
+    };
+    columns.insert(*field, index);
+  }
+
+  let mut lookup = Lookup { app, oid, cache: HashMap::new(), created: Vec::new() };
+  let mut report = ImportReport::default();
+
+  let mut parsed = Vec::new();
+  for (i, cells) in rows {
+    if cells.iter().all(|c| c.is_empty()) {
+      continue;
+    }
+    report.rows += 1;
+
+    let row = parse_row(mapping, &columns, &cells).and_then(|row| {
+      lookup.check(mapping, &row)?;
+      Ok(row)
+    });
+    match row {
+      Ok(row) => parsed.push(row),
+      Err(error) => report.errors.push(RowError { row: i + 1, error }),
+    }
+  }
+
+  if dry_run || !report.errors.is_empty() {
+    return Ok(report);
+  }
+
+  let mut documents: HashMap<(String, String, String, String), JsonValue> = HashMap::new();
+  let store = || -> Result<(), Error> {
+    for row in parsed {
+      let key = (row.number.clone(), row.date.clone(), row.from.clone(), row.into.clone());
+      let document = match documents.get(&key) {
+        Some(document) => document.clone(),
+        None => {
+          let document = lookup.document(mapping.document, &row)?;
+          documents.insert(key, document.clone());
+          document
+        },
+      };
+
+      lookup.operation(mapping, &document, &row)?;
+      report.imported += 1;
+    }
+    Ok(())
+  };
+
+  // nothing of file stay imported if any row fail, as far as created memories can be deleted
+  if let Err(error) = store() {
+    let failed = lookup.rollback();
+    if failed.is_empty() {
+      return Err(error);
+    }
+    let left: Vec<JsonValue> = failed
+      .iter()
+      .map(|(id, e)| object! { id: id.clone(), error: e.to_string() })
+      .collect();
+    return Err(Error::Unprocessable(
+      format!("{error}; rollback is best-effort and failed for {} of created memories", left.len()),
+      object! { error: error.to_string(), rollback: left },
+    ));
+  }
+  report.documents = documents.len();
+
+  Ok(report)
+}
+
+/// Cells of first sheet or of csv records as text.
+pub fn read_rows(format: Format, content: &[u8], delimiter: u8) -> Result<Vec<Vec<String>>, Error> {
+  match format {
+    Format::Csv => {
+      let mut reader = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .trim(Trim::All)
+        .from_reader(content);
+
+      let mut rows = Vec::new();
+      for record in reader.records() {
+        let record = record.map_err(|e| Error::IOError(e.to_string()))?;
+        rows.push(record.iter().map(|c| c.to_string()).collect());
+      }
+      Ok(rows)
+    },
+    Format::Xlsx => {
+      let mut workbook: Xlsx<_> = open_workbook_from_rs(Cursor::new(content))
+        .map_err(|e: XlsxError| Error::IOError(e.to_string()))?;
+
+      let range = match workbook.worksheet_range_at(0) {
+        Some(range) => range.map_err(|e| Error::IOError(e.to_string()))?,
+        None => return Ok(Vec::new()),
+      };
+
+      Ok(range.rows().map(|row| row.iter().map(cell_to_string).collect()).collect())
+    },
+  }
+}
+
+fn cell_to_string(cell: &Data) -> String {
+  match cell {
+    // days since 1899-12-30, time is fraction of day
+    Data::DateTime(date) => {
+      let seconds = (date.as_f64() * 86_400.0).round() as i64;
+      match NaiveDate::from_ymd_opt(1899, 12, 30).and_then(|d| d.and_hms_opt(0, 0, 0)) {
+        Some(epoch) if seconds % 86_400 == 0 => {
+          (epoch + Duration::seconds(seconds)).date().to_string()
+        },
+        Some(epoch) => (epoch + Duration::seconds(seconds)).format(DATETIME_FORMAT).to_string(),
+        None => String::new(),
+      }
+    },
+    cell => cell.to_string().trim().to_string(),
+  }
+}
+
+fn parse_row(
+  mapping: &Mapping,
+  columns: &HashMap<Field, usize>,
+  cells: &[String],
+) -> Result<Row, String> {
+  let cell = |field: Field| -> String {
+    columns
+      .get(&field)
+      .and_then(|i| cells.get(*i))
+      .map(|c| c.replace(['\\', '\"'], ""))
+      .unwrap_or_default()
+  };
+
+  let required = |field: Field| -> Result<String, String> {
+    match cell(field) {
+      value if value.is_empty() && mapping.document.required().contains(&field) => {
+        Err(format!("{} is missing", field.as_str()))
+      },
+      value => Ok(value),
+    }
+  };
+
+  let date = required(Field::Date)?;
+  let date = NaiveDate::parse_from_str(&date, &mapping.date_format)
+    .or_else(|_| NaiveDate::parse_from_str(&date, "%Y-%m-%d"))
+    .or_else(|_| NaiveDateTime::parse_from_str(&date, DATETIME_FORMAT).map(|d| d.date()))
+    .map_err(|_| format!("date `{date}` is not valid"))?;
+
+  let (qty, uom) = parse_qty(&required(Field::Qty)?, &cell(Field::Uom))?;
+  let uom = mapping.units.get(&uom).cloned().unwrap_or(uom);
+
+  let cost = match cell(Field::Cost) {
+    cost if cost.is_empty() => None,
+    cost => Some(parse_number(&cost).ok_or_else(|| format!("cost `{cost}` is not valid"))?),
+  };
+
+  let number = match cell(Field::Number) {
+    number if number.is_empty() => NO_NUMBER.to_string(),
+    number => number,
+  };
+
+  Ok(Row {
+    number,
+    date: date.to_string(),
+    goods: required(Field::Goods)?,
+    vendor_code: cell(Field::VendorCode),
+    category: cell(Field::Category),
+    uom,
+    qty,
+    cost,
+    from: required(Field::From)?,
+    into: required(Field::Into)?,
+  })
+}
+
+/// Quantity with unit of measure from its own column or after number at the same cell,
+/// like `1 500,5 kg`.
+fn parse_qty(cell: &str, uom: &str) -> Result<(Decimal, String), String> {
+  let split = cell
+    .find(|c: char| !(c.is_ascii_digit() || c.is_whitespace() || ['.', ',', '-'].contains(&c)))
+    .unwrap_or(cell.len());
+  let (number, unit) = cell.split_at(split);
+
+  let qty = parse_number(number).ok_or_else(|| format!("qty `{cell}` is not valid"))?;
+
+  let uom = if uom.is_empty() { unit.trim() } else { uom };
+  if uom.is_empty() {
+    return Err("uom is missing".into());
+  }
+
+  Ok((qty, uom.to_string()))
+}
+
+fn parse_number(number: &str) -> Option<Decimal> {
+  let number: String = number.chars().filter(|c| !c.is_whitespace()).collect();
+  Decimal::from_str(&number.replace(',', ".")).ok()
+}
+
+// memories found by name, cached for rows of file
+struct Lookup<'a, A: Services> {
+  app: &'a A,
+  oid: &'a str,
+  cache: HashMap<(Vec<&'static str>, String), JsonValue>,
+  // ids of memories created by import
+  created: Vec<(Vec<&'static str>, String)>,
+}
+
+impl<'a, A: Services> Lookup<'a, A> {
+  fn find_all(&self, ctx: &[&str], filter: JsonValue) -> Result<Vec<JsonValue>, Error> {
+    let params = object! {oid: self.oid, ctx: ctx.to_vec(), filter: filter, "$limit": 100};
+    let result = self.app.service("memories").find(Context::local(), params)?;
+
+    Ok(result["data"].members().cloned().collect())
+  }
+
+  fn create(&mut self, ctx: &[&'static str], data: JsonValue) -> Result<JsonValue, Error> {
+    let params = object! {oid: self.oid, ctx: ctx.to_vec()};
+    let item = self.app.service("memories").create(Context::local(), data, params)?;
+    self.created.push((ctx.to_vec(), item[c::ID].string()));
+    Ok(item)
+  }
+
+  /// Mark created memories as deleted, latest first, so operations are reverted before
+  /// documents and references of them. Return ones that stay with errors of deleting them.
+  fn rollback(&mut self) -> Vec<(String, Error)> {
+    let mut failed = Vec::new();
+    while let Some((ctx, id)) = self.created.pop() {
+      let params = object! {oid: self.oid, ctx: ctx};
+      let mut data = JsonValue::new_object();
+      data[c::STATUS] = c::DELETED.into();
+      if let Err(e) = self.app.service("memories").patch(Context::local(), id.clone(), data, params)
+      {
+        log::error!("import rollback of {id} failed: {e}");
+        failed.push((id, e));
+      }
+    }
+    failed
+  }
+
+  fn find(&mut self, ctx: &[&'static str], name: &str) -> Result<Option<JsonValue>, Error> {
+    let key = (ctx.to_vec(), name.to_string());
+    if let Some(item) = self.cache.get(&key) {
+      return Ok(Some(item.clone()));
+    }
+
+    let item = self.find_all(ctx, object! { name: name })?.into_iter().next();
+    if let Some(item) = &item {
+      self.cache.insert(key, item.clone());
+    }
+    Ok(item)
+  }
+
+  fn obtain(
+    &mut self,
+    ctx: &[&'static str],
+    name: &str,
+    data: impl Fn() -> JsonValue,
+  ) -> Result<JsonValue, Error> {
+    match self.find(ctx, name)? {
+      Some(item) => Ok(item),
+      None => {
+        let item = self.create(ctx, data())?;
+        self.cache.insert((ctx.to_vec(), name.to_string()), item.clone());
+        Ok(item)
+      },
+    }
+  }
+
+  // references of row exist or may be created
+  fn check(&mut self, mapping: &Mapping, row: &Row) -> Result<(), String> {
+    if mapping.create_missing {
+      return Ok(());
+    }
+
+    let (from_ctx, _) = mapping.document.source();
+    let (into_ctx, _) = mapping.document.target();
+
+    let references = [
+      (GOODS.to_vec(), "goods", &row.goods),
+      (UOM.to_vec(), "uom", &row.uom),
+      (from_ctx, "from", &row.from),
+      (into_ctx, "into", &row.into),
+    ];
+    for (ctx, what, name) in references {
+      if name.is_empty() {
+        continue;
+      }
+      if self.find(&ctx, name).map_err(|e| e.to_string())?.is_none() {
+        return Err(format!("{what} `{name}` not found"));
+      }
+    }
+    Ok(())
+  }
+
+  fn reference(&mut self, ctx: &[&'static str], name: &str) -> Result<JsonValue, Error> {
+    if name.is_empty() {
+      Ok(JsonValue::String("".into()))
+    } else {
+      self.obtain(ctx, name, || object! { name: name })
+    }
+  }
+
+  fn document(&mut self, kind: DocumentKind, row: &Row) -> Result<JsonValue, Error> {
+    let (from_ctx, from_field) = kind.source();
+    let (into_ctx, into_field) = kind.target();
+
+    let from = self.reference(&from_ctx, &row.from)?;
+    let into = self.reference(&into_ctx, &row.into)?;
+
+    let mut document = object! { number: row.number.clone(), date: row.date.clone() };
+    document[from_field] = from["_id"].clone();
+    document[into_field] = into["_id"].clone();
+
+    match self.find_all(&kind.document_ctx(), document.clone())?.into_iter().next() {
+      Some(document) => Ok(document),
+      None => self.create(&kind.document_ctx(), document),
+    }
+  }
+
+  fn operation(
+    &mut self,
+    mapping: &Mapping,
+    document: &JsonValue,
+    row: &Row,
+  ) -> Result<JsonValue, Error> {
+    let uom = self.obtain(&UOM, &row.uom, || object! { name: row.uom.clone() })?;
+
+    let category = if row.category.is_empty() {
+      JsonValue::Null
+    } else {
+      self.reference(&CATEGORY, &row.category)?["_id"].clone()
+    };
+
+    let goods = self.obtain(&GOODS, &row.goods, || {
+      let mut goods = object! {
+        name: row.goods.clone(),
+        vendor_code: row.vendor_code.clone(),
+        uom: uom["_id"].clone(),
+      };
+      if !category.is_null() {
+        goods["category"] = category.clone();
+      }
+      goods
+    })?;
+
+    let mut data = object! {
+      document: document["_id"].clone(),
+      goods: goods["_id"].clone(),
+      qty: object! { number: row.qty.to_json(), uom: uom["_uuid"].clone() },
+    };
+
+    if let Some(cost) = row.cost {
+      let name = mapping.currency.clone();
+      let currency = self.obtain(&CURRENCY, &name, || object! { name: name.clone() })?;
+      let cost = object! { number: cost.to_json(), currency: currency["_id"].clone() };
+      match mapping.document {
+        DocumentKind::Receive => data["cost"] = cost,
+        DocumentKind::Dispatch => data["sell_cost"] = cost,
+        DocumentKind::Transfer => {},
+      }
+    }
+
+    self.create(&mapping.document.ctx(), data)
+  }
+}
@@ -0,0 +1,169 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::error::WHError;
+use crate::operations::OpMutation;
+use crate::staging::StagedDB;
+use rocksdb::{Direction, IteratorMode};
+use serde::{Deserialize, Serialize};
+
+const CF_NAME: &str = "cf_journal";
+
+// | kind | seq |
+const RECORD: u8 = 0;
+const PENDING: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JournalState {
+  /// accepted, but not applied to topologies yet
+  Pending,
+  Applied,
+  RolledBack,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalRecord {
+  pub seq: u64,
+  pub state: JournalState,
+  pub mutation: OpMutation,
+}
+
+/// What to do with mutations left `Pending`, see `Journal::accept`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+  Replay,
+  Rollback,
+}
+
+/// Outcome of `Db::recover`, failed records are kept pending.
+#[derive(Debug, Default)]
+pub struct Recovered {
+  pub done: Vec<u64>,
+  pub failed: Vec<(u64, WHError)>,
+}
+
+/// Append-only journal of accepted `OpMutation`s.
+#[derive(Clone)]
+pub struct Journal {
+  pub db: Arc<StagedDB>,
+  seq: Arc<AtomicU64>,
+}
+
+impl Journal {
+  pub fn cf_name() -> &'static str {
+    CF_NAME
+  }
+
+  pub fn new(db: Arc<StagedDB>) -> Result<Self, WHError> {
+    let journal = Journal { db, seq: Arc::new(AtomicU64::new(0)) };
+
+    let records = journal.key(RECORD, 0)..journal.key(PENDING, 0);
+
+    let last = match journal.db.iterator_range(CF_NAME, records, IteratorMode::End)?.next() {
+      Some(item) => {
+        let (k, _) = item?;
+        u64::from_be_bytes(k[1..=8].try_into().unwrap())
+      },
+      None => 0,
+    };
+    journal.seq.store(last, Ordering::SeqCst);
+
+    Ok(journal)
+  }
+
+  fn key(&self, kind: u8, seq: u64) -> Vec<u8> {
+    [kind].iter().chain(seq.to_be_bytes().iter()).copied().collect()
+  }
+
+  fn to_bytes(&self, record: &JournalRecord) -> Result<Vec<u8>, WHError> {
+    let mut bs = Vec::new();
+    ciborium::ser::into_writer(record, &mut bs)?;
+    Ok(bs)
+  }
+
+  fn from_bytes(&self, bytes: &[u8]) -> Result<JournalRecord, WHError> {
+    Ok(ciborium::de::from_reader(bytes)?)
+  }
+
+  /// Record applied mutations, inside of `StagedDB::stage` records are committed in the same
+  /// batch as topologies they changed and discarded together with them.
+  pub fn append(&self, ops: &[OpMutation]) -> Result<Vec<JournalRecord>, WHError> {
+    self.write(ops, JournalState::Applied)
+  }
+
+  /// Record mutations accepted to be applied later, they are committed on their own and left
+  /// pending till `Db::recover` replay or roll them back. Not to be called inside of a stage.
+  pub fn accept(&self, ops: &[OpMutation]) -> Result<Vec<JournalRecord>, WHError> {
+    self.write(ops, JournalState::Pending)
+  }
+
+  fn write(&self, ops: &[OpMutation], state: JournalState) -> Result<Vec<JournalRecord>, WHError> {
+    // sequence is taken under writer lock, so it follows order of commits
+    self.db.stage(|| {
+      let mut records = Vec::with_capacity(ops.len());
+      for op in ops {
+        let seq = self.seq.fetch_add(1, Ordering::SeqCst) + 1;
+        let record = JournalRecord { seq, state, mutation: op.clone() };
+
+        self.db.put_cf(CF_NAME, self.key(RECORD, seq), self.to_bytes(&record)?)?;
+        if state == JournalState::Pending {
+          self.db.put_cf(CF_NAME, self.key(PENDING, seq), [])?;
+        }
+
+        records.push(record);
+      }
+      Ok(records)
+    })
+  }
+
+  /// Inside of `StagedDB::stage` the state is committed together with mutation.
+  pub fn mark(&self, record: &JournalRecord, state: JournalState) -> Result<(), WHError> {
+    let record = JournalRecord { seq: record.seq, state, mutation: record.mutation.clone() };
+
+    self.db.put_cf(CF_NAME, self.key(RECORD, record.seq), self.to_bytes(&record)?)?;
+    if state != JournalState::Pending {
+      self.db.delete_cf(CF_NAME, self.key(PENDING, record.seq))?;
+    }
+
+    Ok(())
+  }
+
+  pub fn get(&self, seq: u64) -> Result<Option<JournalRecord>, WHError> {
+    match self.db.get_cf(CF_NAME, self.key(RECORD, seq))? {
+      Some(bytes) => Ok(Some(self.from_bytes(&bytes)?)),
+      None => Ok(None),
+    }
+  }
+
+  /// Records from `seq` in order they were accepted.
+  pub fn records(&self, seq: u64) -> Result<Vec<JournalRecord>, WHError> {
+    let from = self.key(RECORD, seq);
+
+    let till = self.key(PENDING, 0);
+
+    let mut res = Vec::new();
+    for item in self.db.iterator_range(
+      CF_NAME,
+      from.clone()..till,
+      IteratorMode::From(&from, Direction::Forward),
+    )? {
+      let (_k, v) = item?;
+      res.push(self.from_bytes(&v)?);
+    }
+    Ok(res)
+  }
+
+  pub fn pending(&self) -> Result<Vec<JournalRecord>, WHError> {
+    let pending = self.key(PENDING, 0)..self.key(PENDING + 1, 0);
+
+    let mut res = Vec::new();
+    for item in self.db.iterator_range(CF_NAME, pending, IteratorMode::Start)? {
+      let (k, _) = item?;
+      let seq = u64::from_be_bytes(k[1..=8].try_into().unwrap());
+      if let Some(record) = self.get(seq)? {
+        res.push(record);
+      }
+    }
+    Ok(res)
+  }
+}
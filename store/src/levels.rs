@@ -0,0 +1,172 @@
+use crate::conversions::Factors;
+use crate::db::Db;
+use crate::elements::{Goods, Store, ToJson};
+use crate::error::WHError;
+use crate::operations::OpMutation;
+use crate::qty::Qty;
+use chrono::Utc;
+use json::{object, JsonValue};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+/// Quantities of goods to keep at store, empty `max` means reorder up to `min`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StockLevel {
+  pub min: Qty,
+  pub max: Qty,
+}
+
+impl StockLevel {
+  /// True if balance is under minimum, balances without common unit with it are not.
+  pub fn is_below(&self, balance: &Qty, factors: &Factors) -> bool {
+    if self.min.is_zero() {
+      false
+    } else if balance.is_zero() || balance.is_negative() {
+      true
+    } else {
+      !balance.is_greater_or_equal(&self.min, factors).unwrap_or(true)
+    }
+  }
+
+  /// Quantity to order to bring balance up to maximum.
+  pub fn suggested(&self, balance: &Qty, factors: &Factors) -> Qty {
+    let target = if self.max.is_zero() { &self.min } else { &self.max };
+    if balance.is_zero() {
+      target.clone()
+    } else {
+      target.sub_with(balance, factors)
+    }
+  }
+}
+
+/// Levels of goods at stores. Kept by `warehouse/level` memories, which are indexed at start
+/// and on every save of them.
+#[derive(Clone, Default)]
+pub struct StockLevels {
+  levels: Arc<RwLock<BTreeMap<(Store, Goods), StockLevel>>>,
+}
+
+impl StockLevels {
+  /// Record levels of goods at store, remove them if `None`.
+  pub fn set(&self, store: Store, goods: Goods, level: Option<StockLevel>) -> Result<(), WHError> {
+    let mut levels = self.levels.write().map_err(|_| WHError::new("levels lock poisoned"))?;
+    match level {
+      Some(level) => levels.insert((store, goods), level),
+      None => levels.remove(&(store, goods)),
+    };
+    Ok(())
+  }
+
+  pub fn get(&self, store: Store, goods: Goods) -> Result<Option<StockLevel>, WHError> {
+    let levels = self.levels.read().map_err(|_| WHError::new("levels lock poisoned"))?;
+    Ok(levels.get(&(store, goods)).cloned())
+  }
+
+  /// All recorded levels ordered by store and goods.
+  pub fn list(&self) -> Result<Vec<(Store, Goods, StockLevel)>, WHError> {
+    let levels = self.levels.read().map_err(|_| WHError::new("levels lock poisoned"))?;
+    Ok(
+      levels
+        .iter()
+        .map(|((store, goods), level)| (*store, *goods, level.clone()))
+        .collect(),
+    )
+  }
+
+  /// Levels of store and goods touched by operations.
+  pub(crate) fn for_ops(
+    &self,
+    ops: &[OpMutation],
+  ) -> Result<Vec<(Store, Goods, StockLevel)>, WHError> {
+    let mut res: Vec<(Store, Goods, StockLevel)> = Vec::new();
+    for op in ops {
+      if res.iter().any(|(s, g, _)| *s == op.store && *g == op.goods) {
+        continue;
+      }
+      if let Some(level) = self.get(op.store, op.goods)? {
+        res.push((op.store, op.goods, level));
+      }
+    }
+    Ok(res)
+  }
+}
+
+/// Goods under minimum at store with quantity to order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reorder {
+  pub store: Store,
+  pub goods: Goods,
+  pub level: StockLevel,
+  pub balance: Qty,
+  pub suggested: Qty,
+}
+
+impl ToJson for Reorder {
+  fn to_json(&self) -> JsonValue {
+    object! {
+      store: self.store.to_json(),
+      goods: self.goods.to_json(),
+      min: self.level.min.to_json(),
+      max: self.level.max.to_json(),
+      qty: self.balance.to_json(),
+      suggested: self.suggested.to_json(),
+    }
+  }
+}
+
+type Listener = Arc<dyn Fn(&Reorder) + Send + Sync>;
+
+/// Listeners notified when balance of goods drops below its minimum.
+#[derive(Clone, Default)]
+pub struct ReorderAlerts {
+  listeners: Arc<RwLock<Vec<Listener>>>,
+}
+
+impl ReorderAlerts {
+  pub fn subscribe(&self, listener: impl Fn(&Reorder) + Send + Sync + 'static) {
+    self.listeners.write().unwrap().push(Arc::new(listener));
+  }
+
+  pub(crate) fn notify(&self, reorder: &Reorder) {
+    for listener in self.listeners.read().unwrap().iter() {
+      listener(reorder);
+    }
+  }
+}
+
+impl Db {
+  /// Reorder of goods at store if its current balance is under minimum.
+  pub fn reorder(
+    &self,
+    store: Store,
+    goods: Goods,
+    level: &StockLevel,
+  ) -> Result<Option<Reorder>, WHError> {
+    let factors = self.conversions.of(goods);
+    let balance = self
+      .on_hand(store, goods, Utc::now())?
+      .into_values()
+      .fold(Qty::default(), |a, b| a.add_with(&b.qty, &factors));
+    if level.is_below(&balance, &factors) {
+      let suggested = level.suggested(&balance, &factors);
+      Ok(Some(Reorder { store, goods, level: level.clone(), balance, suggested }))
+    } else {
+      Ok(None)
+    }
+  }
+
+  /// Goods under their minimum at given stores or at all of them if none given.
+  pub fn reorders(&self, stores: &[Store]) -> Result<Vec<Reorder>, WHError> {
+    let mut res = Vec::new();
+    for (store, goods, level) in self.levels.list()? {
+      if !stores.is_empty() && !stores.contains(&store) {
+        continue;
+      }
+      if let Some(reorder) = self.reorder(store, goods, &level)? {
+        res.push(reorder);
+      }
+    }
+    Ok(res)
+  }
+}
@@ -2,16 +2,32 @@ use wh_storage::WHStorage;
 
 pub mod aggregations;
 pub mod balance;
+pub mod barcodes;
 pub mod batch;
 pub mod checkpoints;
+pub mod conversions;
+pub mod costing;
 mod db;
 pub mod elements;
 pub mod error;
+pub mod expiry;
+pub mod grouping;
+pub mod import;
+pub mod journal;
+pub mod levels;
+pub mod negative_stock;
 pub mod operations;
 pub mod ordered_topology;
+pub mod periods;
 pub mod process_records;
 pub mod qty;
+pub mod rebuild;
+pub mod reservations;
+pub mod staging;
+pub mod stocktake;
 pub mod topologies;
+pub mod turnover;
+pub mod verify;
 pub mod wh_storage;
 
 pub trait GetWarehouse {
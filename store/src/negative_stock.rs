@@ -0,0 +1,250 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::batch::Batch;
+use crate::db::Db;
+use crate::elements::{Goods, Store, ToJson};
+use crate::error::WHError;
+use crate::operations::OpMutation;
+use crate::qty::Qty;
+use crate::staging::StagedDB;
+use chrono::{DateTime, Utc};
+use json::{object, JsonValue};
+use serde::{Deserialize, Serialize};
+
+const CF_NAME: &str = "cf_negative_stock_store";
+
+/// What to do with operations that make balance of store negative.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
+pub enum NegativeStockPolicy {
+  #[default]
+  Allow,
+  /// accept operations, but log shortage
+  Warn,
+  /// reject operations with `WHError::negative_stock`
+  Reject,
+}
+
+impl NegativeStockPolicy {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      NegativeStockPolicy::Allow => "allow",
+      NegativeStockPolicy::Warn => "warn",
+      NegativeStockPolicy::Reject => "reject",
+    }
+  }
+}
+
+impl TryFrom<&str> for NegativeStockPolicy {
+  type Error = WHError;
+
+  fn try_from(value: &str) -> Result<Self, Self::Error> {
+    match value.to_lowercase().as_str() {
+      "allow" => Ok(NegativeStockPolicy::Allow),
+      "warn" => Ok(NegativeStockPolicy::Warn),
+      "reject" => Ok(NegativeStockPolicy::Reject),
+      _ => Err(WHError::new(&format!("unknown negative stock policy {value}"))),
+    }
+  }
+}
+
+/// Balance of goods & batch at store that went negative after operation at date.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shortage {
+  pub store: Store,
+  pub goods: Goods,
+  pub batch: Batch,
+  /// quantity missing to keep balance at zero
+  pub shortfall: Qty,
+  pub date: DateTime<Utc>,
+}
+
+impl fmt::Display for Shortage {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
+      "negative balance of goods {} batch {} at store {} on {}, shortfall {}",
+      self.goods,
+      self.batch.id,
+      self.store,
+      self.date.to_rfc3339(),
+      self.shortfall.to_json().dump(),
+    )
+  }
+}
+
+impl ToJson for Shortage {
+  fn to_json(&self) -> JsonValue {
+    object! {
+      store: self.store.to_json(),
+      goods: self.goods.to_json(),
+      batch: self.batch.to_json(),
+      shortfall: self.shortfall.to_json(),
+      date: self.date.to_json(),
+    }
+  }
+}
+
+/// Negative stock policies recorded per store.
+#[derive(Clone)]
+pub struct NegativeStockPolicies {
+  pub db: Arc<StagedDB>,
+}
+
+impl NegativeStockPolicies {
+  pub fn cf_name() -> &'static str {
+    CF_NAME
+  }
+
+  pub fn set(&self, store: Store, policy: NegativeStockPolicy) -> Result<(), WHError> {
+    let mut bs = Vec::new();
+    ciborium::ser::into_writer(&policy, &mut bs)?;
+    self.db.put_cf(CF_NAME, store.as_bytes(), bs)
+  }
+
+  /// allow if nothing is recorded
+  pub fn get(&self, store: Store) -> Result<NegativeStockPolicy, WHError> {
+    match self.db.get_cf(CF_NAME, store.as_bytes())? {
+      Some(bytes) => Ok(ciborium::de::from_reader(bytes.as_slice())?),
+      None => Ok(NegativeStockPolicy::default()),
+    }
+  }
+}
+
+/// Negative balances of goods at stores under control, keyed by batch and date of operation
+/// or checkpoint.
+pub(crate) type Negatives = HashMap<(Batch, DateTime<Utc>), Shortage>;
+
+impl Db {
+  /// Negative balances that mutation may change, taken before it is applied.
+  pub(crate) fn negatives_before(&self, op: &OpMutation) -> Result<Option<Negatives>, WHError> {
+    let mut controlled = false;
+    let mut res = HashMap::new();
+    for store in self.affected_stores(op) {
+      if self.negative_stock.get(store)? != NegativeStockPolicy::Allow {
+        controlled = true;
+        res.extend(self.negatives(store, op.goods, op.date)?);
+      }
+    }
+    Ok(if controlled { Some(res) } else { None })
+  }
+
+  /// Apply store policy to balances that went negative or decreased below zero by mutation,
+  /// back-dated operations are caught by later operations of its interval and by checkpoints.
+  pub(crate) fn check_negatives(&self, op: &OpMutation, before: Negatives) -> Result<(), WHError> {
+    for store in self.affected_stores(op) {
+      let policy = self.negative_stock.get(store)?;
+      if policy == NegativeStockPolicy::Allow {
+        continue;
+      }
+
+      let shortage = self
+        .negatives(store, op.goods, op.date)?
+        .into_iter()
+        .filter(|(key, shortage)| match before.get(key) {
+          Some(old) => is_short(&(&old.shortfall - &shortage.shortfall)),
+          None => true,
+        })
+        .map(|(_, shortage)| shortage)
+        .min_by_key(|shortage| shortage.date);
+
+      if let Some(shortage) = shortage {
+        let shortage = self.first_shortage(shortage, op.date)?;
+        match policy {
+          NegativeStockPolicy::Reject => return Err(WHError::negative_stock(shortage)),
+          _ => log::warn!("{shortage}"),
+        }
+      }
+    }
+    Ok(())
+  }
+
+  fn affected_stores(&self, op: &OpMutation) -> Vec<Store> {
+    let mut stores = vec![op.store];
+    if let Some(store) = op.transfer {
+      if store != op.store {
+        stores.push(store);
+      }
+    }
+    stores
+  }
+
+  // balances before mutation date are not affected by it, ones till the end of its interval
+  // are taken after every operation, later ones at checkpoints
+  fn negatives(
+    &self,
+    store: Store,
+    goods: Goods,
+    date: DateTime<Utc>,
+  ) -> Result<Negatives, WHError> {
+    let mut res = HashMap::new();
+    let mut add = |shortage: Shortage| match res.entry((shortage.batch.clone(), shortage.date)) {
+      Entry::Occupied(mut entry) => {
+        let old: &Shortage = entry.get();
+        if is_short(&(&old.shortfall - &shortage.shortfall)) {
+          entry.insert(shortage);
+        }
+      },
+      Entry::Vacant(entry) => {
+        entry.insert(shortage);
+      },
+    };
+
+    let primary = &self.ordered_topologies[0];
+    let next = self.granularities.get(store)?.next(date);
+    for op in self.ops_for_store_goods(store, goods, date, next)? {
+      if op.date >= next {
+        continue;
+      }
+      if let Some((op, balance)) = primary.get(&op)? {
+        if op.dependant.is_empty() && is_short(&balance.qty) {
+          add(Shortage { store, goods, batch: op.batch, shortfall: -balance.qty, date: op.date });
+        }
+      }
+    }
+
+    for balance in self.checkpoints_after(store, goods, date)? {
+      if is_short(&balance.number.qty) {
+        add(Shortage {
+          store: balance.store,
+          goods: balance.goods,
+          batch: balance.batch,
+          shortfall: -balance.number.qty,
+          date: balance.date,
+        });
+      }
+    }
+    Ok(res)
+  }
+
+  // operation of the interval that made balance negative, read only when policy is violated
+  fn first_shortage(&self, shortage: Shortage, from: DateTime<Utc>) -> Result<Shortage, WHError> {
+    let primary = &self.ordered_topologies[0];
+    let ops = self.ops_for_store_goods_and_batch(
+      shortage.store,
+      shortage.goods,
+      &shortage.batch,
+      from,
+      shortage.date,
+    )?;
+    for op in ops {
+      if let Some((op, balance)) = primary.get(&op)? {
+        if op.dependant.is_empty() && is_short(&balance.qty) {
+          return Ok(Shortage { shortfall: -balance.qty, date: op.date, ..shortage });
+        }
+      }
+    }
+    Ok(shortage)
+  }
+}
+
+/// `true` if any number of quantity is below zero
+pub(crate) fn is_short(qty: &Qty) -> bool {
+  qty
+    .lower()
+    .inner()
+    .iter()
+    .any(|n| n.number().is_sign_negative() && !n.is_zero())
+}
@@ -1,5 +1,6 @@
 use crate::balance::{BalanceDelta, BalanceForGoods, Cost};
 use crate::batch::Batch;
+use crate::conversions::Factors;
 use crate::elements::{Goods, Mode, Store, ToJson};
 use crate::qty::Qty;
 use chrono::{DateTime, Utc};
@@ -366,6 +367,9 @@ pub struct OpMutation {
   // value
   pub before: Option<(InternalOperation, bool)>,
   pub after: Option<(InternalOperation, bool)>,
+  /// document of operation, its issues fulfil reservations made for it
+  #[serde(default)]
+  pub document: Option<Uuid>,
   // internal
   // pub is_dependent: bool,
   // pub dependant: Vec<Dependant>,
@@ -391,6 +395,7 @@ impl OpMutation {
       batch,
       before: before.map(|o| (o, false)),
       after: after.map(|o| (o, false)),
+      document: None,
     }
   }
 
@@ -412,6 +417,7 @@ impl OpMutation {
       batch,
       before: None,
       after: Some((InternalOperation::Receive(qty, cost), false)),
+      document: None,
     }
   }
 
@@ -490,6 +496,7 @@ impl OpMutation {
         batch: a.batch.clone(),
         before: Some((b.op.clone(), false)),
         after: Some((a.op.clone(), false)),
+        document: None,
       }
     } else if let Some(b) = &before {
       OpMutation {
@@ -501,6 +508,7 @@ impl OpMutation {
         batch: b.batch.clone(),
         before: Some((b.op.clone(), false)),
         after: None,
+        document: None,
       }
     } else if let Some(a) = &after {
       OpMutation {
@@ -512,6 +520,7 @@ impl OpMutation {
         batch: a.batch.clone(),
         before: None,
         after: Some((a.op.clone(), false)),
+        document: None,
       }
     } else {
       panic!("must no happen")
@@ -555,16 +564,23 @@ pub enum InternalOperation {
 }
 
 impl InternalOperation {
-  pub fn apply(&self, balance: &BalanceForGoods) -> (BalanceForGoods, BalanceDelta) {
+  pub fn apply(
+    &self,
+    balance: &BalanceForGoods,
+    factors: &Factors,
+  ) -> (BalanceForGoods, BalanceDelta) {
     match self {
       InternalOperation::Inventory(b, _, m) => {
-        let qty = &b.qty - &balance.qty;
+        let qty = b.qty.sub_with(&balance.qty, factors);
 
-        let cost = if m == &Mode::Auto {
+        let cost = match m {
           // balance.clone().price(common.clone()).cost(qty.clone(), common)
-          qty.cost(balance)
-        } else {
-          b.cost - balance.cost
+          Mode::Auto => qty.cost(balance, factors),
+          Mode::Manual => b.cost - balance.cost,
+          // surplus without balance is valued by counted cost
+          Mode::Counted if balance.qty.is_zero() => b.cost - balance.cost,
+          Mode::Counted if qty.is_negative() => -qty.abs().cost(balance, factors),
+          Mode::Counted => qty.cost(balance, factors),
         };
 
         (b.clone(), BalanceDelta { qty: qty.clone(), cost })
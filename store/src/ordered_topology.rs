@@ -3,9 +3,12 @@ use crate::aggregations::{
 };
 use crate::balance::{BalanceDelta, BalanceForGoods, Cost};
 use crate::batch::Batch;
+use crate::conversions::{Conversions, Factors};
+use crate::costing::CostingMethod;
 use crate::db::Db;
 use crate::elements::{dt, Goods, Mode, Report, Store, WHError};
 use crate::operations::{Dependant, InternalOperation, Op, OpMutation};
+use crate::qty::Qty;
 
 use chrono::{DateTime, Utc};
 use json::JsonValue;
@@ -70,6 +73,17 @@ pub trait OrderedTopology {
     Err(WHError::new("Not supported"))
   }
 
+  // operations of batch at all stores
+  fn ops_for_goods_batch(
+    &self,
+    _goods: Goods,
+    _batch: &Batch,
+    _from_date: DateTime<Utc>,
+    _till_date: DateTime<Utc>,
+  ) -> Result<Vec<Op>, WHError> {
+    Err(WHError::new("Not supported"))
+  }
+
   fn ops_for_goods(
     &self,
     _goods: &Vec<Goods>,
@@ -112,7 +126,13 @@ pub trait OrderedTopology {
 
     let ops = db.ops_for_store_goods_and_batch(store, goods, batch, op_from_date, till_date)?;
 
-    let items = get_aggregations_for_one_goods(balances, ops, from_date, till_date)?;
+    let items = get_aggregations_for_one_goods(
+      balances,
+      ops,
+      from_date,
+      till_date,
+      &db.conversions.of(goods),
+    )?;
 
     Ok(items)
   }
@@ -133,7 +153,7 @@ pub trait OrderedTopology {
 
     let ops = db.ops_for_store_goods(store, goods, op_from_date, till_date)?;
 
-    let items = aggregations_for_store_goods_batch(balances, ops, from_date);
+    let items = aggregations_for_store_goods_batch(balances, ops, from_date, &db.conversions);
 
     Ok(Report { from_date, till_date, items })
   }
@@ -249,7 +269,8 @@ pub trait OrderedTopology {
         // recalculate balance inside undefined time
         let before_balance: BalanceForGoods = self.balance_before(&op)?; // Vec<(Batch, BalanceForGoods)>
         log::debug!("before evaluating: old balance {before_balance:?}");
-        let (calculated_op, new_balance) = self.evaluate(&before_balance, &op);
+        let (calculated_op, new_balance) =
+          self.evaluate(&before_balance, &op, &db.conversions.of(op.goods));
         log::debug!(
           "after evaluating: calculated_op {calculated_op:?}\nnew_balance {new_balance:?}"
         );
@@ -278,7 +299,8 @@ pub trait OrderedTopology {
           }
 
           log::debug!("before evaluating: old balance {before_balance:?}");
-          let (calculated_op, new_balance) = self.evaluate(&before_balance, &loaded_op);
+          let (calculated_op, new_balance) =
+            self.evaluate(&before_balance, &loaded_op, &db.conversions.of(loaded_op.goods));
           log::debug!(
             "after evaluating: calculated_op {calculated_op:?}\nnew_balance {new_balance:?}"
           );
@@ -388,6 +410,7 @@ pub trait OrderedTopology {
               batch: op.batch.clone(),
               before: Some((before_op.op.clone(), before_op.is_dependent)),
               after: None,
+              document: None,
             },
             next_op_date,
             balance_after, // TODO make sure this is right balance!
@@ -414,6 +437,7 @@ pub trait OrderedTopology {
             batch: op.batch.clone(),
             before,
             after: Some((op.op.clone(), op.is_dependent)),
+            document: None,
           },
           next_op_date,
           balance_after,
@@ -477,6 +501,7 @@ pub trait OrderedTopology {
           batch: op.batch.clone(),
           before,
           after: None,
+          document: None,
         },
         next_op_date,
         &balance_before,
@@ -495,7 +520,8 @@ pub trait OrderedTopology {
     // calculate balance
     let before_balance: BalanceForGoods = self.balance_before(&op)?; // Vec<(Batch, BalanceForGoods)>
     log::debug!("before evaluating: old balance {before_balance:?}");
-    let (calculated_op, new_balance) = self.evaluate(&before_balance, &op);
+    let (calculated_op, new_balance) =
+      self.evaluate(&before_balance, &op, &db.conversions.of(op.goods));
     log::debug!("after evaluating: calculated_op {calculated_op:?}\nnew_balance {new_balance:?}");
 
     let (before_op, current_balance) = if let Some((o, b)) = self.get(&op)? {
@@ -572,12 +598,31 @@ pub trait OrderedTopology {
     Ok(())
   }
 
-  fn evaluate(&self, balance: &BalanceForGoods, op: &Op) -> (Op, BalanceForGoods) {
+  fn evaluate(
+    &self,
+    balance: &BalanceForGoods,
+    op: &Op,
+    factors: &Factors,
+  ) -> (Op, BalanceForGoods) {
     match &op.op {
+      InternalOperation::Inventory(b, _, Mode::Counted) => {
+        // difference to counted balance is evaluated against balance before operation
+        let (_, delta) = op.op.apply(balance, factors);
+        let after = balance.add_with(&delta, factors);
+
+        let mut op = op.clone();
+        op.op = InternalOperation::Inventory(
+          BalanceForGoods { qty: b.qty.clone(), cost: after.cost },
+          delta,
+          Mode::Counted,
+        );
+
+        (op, after)
+      },
       InternalOperation::Inventory(b, d, m) => {
         let mut cost = d.cost;
         let op = if m == &Mode::Auto {
-          cost = d.qty.cost(balance);
+          cost = d.qty.cost(balance, factors);
 
           Op {
             id: op.id,
@@ -598,15 +643,19 @@ pub trait OrderedTopology {
           op.clone()
         };
 
-        (op, BalanceForGoods { qty: &balance.qty + &d.qty, cost: balance.cost - cost })
-      },
-      InternalOperation::Receive(q, c) => {
-        (op.clone(), BalanceForGoods { qty: &balance.qty + &q, cost: balance.cost + *c })
+        (
+          op,
+          BalanceForGoods { qty: balance.qty.add_with(&d.qty, factors), cost: balance.cost - cost },
+        )
       },
+      InternalOperation::Receive(q, c) => (
+        op.clone(),
+        BalanceForGoods { qty: balance.qty.add_with(q, factors), cost: balance.cost + *c },
+      ),
       InternalOperation::Issue(q, c, m) => {
         let mut cost = *c;
         let op = if m == &Mode::Auto {
-          cost = if balance.qty == *q { balance.cost } else { q.cost(balance) };
+          cost = if balance.qty == *q { balance.cost } else { q.cost(balance, factors) };
           Op {
             id: op.id,
             date: op.date,
@@ -622,7 +671,7 @@ pub trait OrderedTopology {
           op.clone()
         };
 
-        (op, BalanceForGoods { qty: &balance.qty - &q, cost: balance.cost - cost })
+        (op, BalanceForGoods { qty: balance.qty.sub_with(q, factors), cost: balance.cost - cost })
       },
     }
   }
@@ -672,6 +721,7 @@ pub trait OrderedTopology {
     till_date: DateTime<Utc>,
     goods: &Vec<Goods>,
     checkpoints: HashMap<Uuid, BalanceForGoods>,
+    conversions: &Conversions,
   ) -> Result<HashMap<Uuid, BalanceForGoods>, WHError> {
     let mut result = checkpoints;
 
@@ -681,7 +731,7 @@ pub trait OrderedTopology {
     for op in ops {
       result
         .entry(op.goods)
-        .and_modify(|bal| bal.apply(&op.op))
+        .and_modify(|bal| bal.apply(&op.op, &conversions.of(op.goods)))
         .or_insert(match &op.op {
           InternalOperation::Inventory(_, d, _) => {
             BalanceForGoods { qty: d.qty.clone(), cost: d.cost }
@@ -701,6 +751,7 @@ pub trait OrderedTopology {
     store: &Store,
     goods: &Goods,
     checkpoints: HashMap<Uuid, BalanceForGoods>,
+    conversions: &Conversions,
   ) -> Result<HashMap<Uuid, BalanceForGoods>, WHError> {
     let mut result = checkpoints;
 
@@ -708,11 +759,14 @@ pub trait OrderedTopology {
     let ops = self.ops_for_store_goods(*store, *goods, from_date, till_date)?;
 
     for op in ops {
-      result.entry(op.goods).and_modify(|bal| bal.apply(&op.op)).or_insert_with(|| {
-        let mut b = BalanceForGoods::default();
-        b.apply(&op.op);
-        b
-      });
+      result
+        .entry(op.goods)
+        .and_modify(|bal| bal.apply(&op.op, &conversions.of(op.goods)))
+        .or_insert_with(|| {
+          let mut b = BalanceForGoods::default();
+          b.apply(&op.op, &conversions.of(op.goods));
+          b
+        });
     }
 
     Ok(result)
@@ -723,6 +777,7 @@ pub trait OrderedTopology {
     from_date: DateTime<Utc>,
     till_date: DateTime<Utc>,
     checkpoints: HashMap<Store, HashMap<Goods, HashMap<Batch, BalanceForGoods>>>,
+    conversions: &Conversions,
   ) -> Result<HashMap<Store, HashMap<Goods, HashMap<Batch, BalanceForGoods>>>, WHError> {
     let mut result = checkpoints;
 
@@ -736,10 +791,10 @@ pub trait OrderedTopology {
         .entry(op.goods)
         .or_insert_with(HashMap::new)
         .entry(op.batch)
-        .and_modify(|bal| bal.apply(&op.op))
+        .and_modify(|bal| bal.apply(&op.op, &conversions.of(op.goods)))
         .or_insert_with(|| {
           let mut b = BalanceForGoods::default();
-          b.apply(&op.op);
+          b.apply(&op.op, &conversions.of(op.goods));
           b
         });
     }
@@ -760,6 +815,9 @@ pub trait OrderedTopology {
   //   log::debug!("cleanup_and_push ops.push {ops:#?}");
   // }
 
+  /// name of column family
+  fn name(&self) -> &'static str;
+
   fn cf(&self) -> Result<Arc<BoundColumnFamily>, WHError>;
 
   fn db(&self) -> Arc<DB>;
@@ -848,14 +906,14 @@ impl<'a> PropagationFront<'a> {
 
   fn distribution_inventory(&mut self, mut op: Op) -> Result<Op, WHError> {
     // self.cleanup(ops, op);
+    let factors = self.db.conversions.of(op.goods);
 
     let balance_before_operation = self.db.balances_for_store_goods_before_operation(&op)?;
     let balance_before = balance_before_operation.get(&op.batch).cloned().unwrap_or_default();
 
-    // sort for FIFO
-    let mut balance_before_operation: Vec<(Batch, BalanceForGoods)> =
-      balance_before_operation.into_iter().map(|(k, v)| (k, v)).collect();
-    balance_before_operation.sort_by(|(a, _), (b, _)| a.date.cmp(&b.date));
+    // order by costing method of store & goods
+    let (_method, balance_before_operation, average) =
+      self.ordered_by_costing(&op, balance_before_operation, &factors)?;
 
     log::debug!("INVENTORY BEFORE BALANCE: {:#?}", balance_before_operation);
 
@@ -863,12 +921,12 @@ impl<'a> PropagationFront<'a> {
     for (_batch, balance) in balance_before_operation.iter() {
       // we need all balances (including negative) for stock
       // if balance.qty.is_positive() {
-      stock_balance.qty += &balance.qty;
+      stock_balance.qty = stock_balance.qty.add_with(&balance.qty, &factors);
       stock_balance.cost += balance.cost;
       // }
     }
 
-    let (balance_after, diff_balance) = op.op.apply(&stock_balance);
+    let (balance_after, diff_balance) = op.op.apply(&stock_balance, &factors);
     // log::debug!("diff_balance: {diff_balance:?}");
 
     // TODO cover cost difference
@@ -895,30 +953,30 @@ impl<'a> PropagationFront<'a> {
       for (batch, balance) in balance_before_operation {
         if !balance.qty.is_positive() || batch == Batch::no() {
           continue;
-        } else if qty.abs().is_greater_or_equal(&balance.qty)? {
+        } else if qty.abs().is_greater_or_equal(&balance.qty, &factors)? {
           let mut new = op.clone();
           new.is_dependent = true;
           new.dependant = vec![];
           new.batch = batch;
-          new.op = InternalOperation::Issue(balance.qty.clone(), balance.cost, Mode::Auto);
+          new.op = self.issue_op(balance.qty.clone(), balance.cost, &average, &factors);
           // log::debug!("NEW_OP inventory partly: qty {qty} balance {balance:?} op {new:?}");
 
           new_dependant.push(Dependant::from(&new));
           self.insert(new)?;
 
-          qty += &balance.qty; // qty is always negative here
+          qty = qty.add_with(&balance.qty, &factors); // qty is always negative here
         } else {
           let mut new = op.clone();
           new.is_dependent = true;
           new.dependant = vec![];
           new.batch = batch;
-          let cost = if let Some(_common) = balance.qty.common(&qty) {
+          let cost = if let Some(_common) = balance.qty.common(&qty, &factors) {
             // balance.price(common.clone()).cost(qty.abs(), common)
-            qty.abs().cost(&balance)
+            qty.abs().cost(&balance, &factors)
           } else {
             Cost::ZERO
           };
-          new.op = InternalOperation::Issue(qty.abs(), cost, Mode::Auto);
+          new.op = self.issue_op(qty.abs(), cost, &average, &factors);
           // log::debug!("NEW_OP inventory full: qty {qty} balance {balance:?} op {new:?}");
 
           new_dependant.push(Dependant::from(&new));
@@ -947,15 +1005,19 @@ impl<'a> PropagationFront<'a> {
 
   fn distribution_issue(&mut self, mut op: Op) -> Result<Op, WHError> {
     // self.cleanup(ops, op);
+    let factors = self.db.conversions.of(op.goods);
 
     // calculate balance
     let balances_before_operation = self.db.balances_for_store_goods_before_operation(&op)?;
     let balance_before = balances_before_operation.get(&op.batch).cloned().unwrap_or_default();
 
-    // sort for FIFO
-    let mut balances_before_operation: Vec<(Batch, BalanceForGoods)> =
-      balances_before_operation.into_iter().map(|(k, v)| (k, v)).collect();
-    balances_before_operation.sort_by(|(a, _), (b, _)| a.date.cmp(&b.date));
+    // order by costing method of store & goods
+    let (method, balances_before_operation, average) =
+      self.ordered_by_costing(&op, balances_before_operation, &factors)?;
+
+    if method == CostingMethod::Specific {
+      return Err(WHError::new("batch is required for issue by specific identification"));
+    }
 
     log::debug!("BEFORE BALANCE: {:#?}\nISSUE: {:#?}", balances_before_operation, op);
 
@@ -975,18 +1037,18 @@ impl<'a> PropagationFront<'a> {
 
       if !balance.qty.is_positive() || batch == Batch::no() {
         continue;
-      } else if let Some(common) = qty.common(&balance.qty) {
-        let left = qty.lowering(&common).unwrap();
-        let right = balance.qty.lowering(&common).unwrap();
+      } else if let Some(common) = qty.common(&balance.qty, &factors) {
+        let left = qty.lowering(&common, &factors).unwrap();
+        let right = balance.qty.lowering(&common, &factors).unwrap();
 
         if left.number() == right.number() {
-          let result = &balance.qty - &qty;
+          let result = balance.qty.sub_with(&qty, &factors);
           if result.is_zero() {
             let mut new = op.clone();
             new.is_dependent = true;
             new.dependant = vec![];
             new.batch = batch;
-            new.op = InternalOperation::Issue(qty.clone(), balance.cost, Mode::Auto);
+            new.op = self.issue_op(qty.clone(), balance.cost, &average, &factors);
             log::debug!("NEW_OP partly: qty {qty:?} balance {balance:?} op {new:#?}");
 
             // let balance_before = self.mt.balance_before(&new)?;
@@ -1000,13 +1062,13 @@ impl<'a> PropagationFront<'a> {
             continue;
           }
         } else if left.number() > right.number() {
-          let issue = right.elevate_to_qty(&qty);
+          let issue = right.elevate_to_qty(&qty, &factors);
 
           let mut new = op.clone();
           new.is_dependent = true;
           new.dependant = vec![];
           new.batch = batch;
-          new.op = InternalOperation::Issue(issue.clone(), balance.cost, Mode::Auto);
+          new.op = self.issue_op(issue.clone(), balance.cost, &average, &factors);
           log::debug!("NEW_OP partly: qty {qty:?} balance {balance:?} op {new:#?}");
 
           // let balance_before = self.mt.balance_before(&new)?;
@@ -1015,11 +1077,11 @@ impl<'a> PropagationFront<'a> {
           new_dependant.push(Dependant::from(&new));
           self.insert(new)?;
 
-          qty -= &issue;
+          qty = qty.sub_with(&issue, &factors);
 
           // log::debug!("NEW_OP: qty {:?}", qty);
         } else {
-          let price = if let Some(price) = qty.price(&balance) {
+          let price = if let Some(price) = qty.price(&balance, &factors) {
             price
           } else {
             continue;
@@ -1029,8 +1091,8 @@ impl<'a> PropagationFront<'a> {
           new.is_dependent = true;
           new.dependant = vec![];
           new.batch = batch;
-          let cost = &qty * price;
-          new.op = InternalOperation::Issue(qty.clone(), cost, Mode::Auto);
+          let cost = qty.cost_at(price, &factors);
+          new.op = self.issue_op(qty.clone(), cost, &average, &factors);
           log::debug!("NEW_OP full: qty {qty:?} balance {balance:?} op {new:#?}");
 
           // let balance_before = self.balance_before(&new)?;
@@ -1074,6 +1136,64 @@ impl<'a> PropagationFront<'a> {
     Ok(op)
   }
 
+  // FIFO & weighted average issue from oldest batches, LIFO from newest;
+  // for weighted average also return stock balance to calculate issue cost from
+  fn ordered_by_costing(
+    &self,
+    op: &Op,
+    balances: HashMap<Batch, BalanceForGoods>,
+    factors: &Factors,
+  ) -> Result<(CostingMethod, Vec<(Batch, BalanceForGoods)>, Option<BalanceForGoods>), WHError> {
+    let method = self.db.costing.get(op.store, op.goods)?;
+
+    let mut balances: Vec<(Batch, BalanceForGoods)> = balances.into_iter().collect();
+    match method {
+      CostingMethod::Lifo => balances.sort_by(|(a, _), (b, _)| b.date.cmp(&a.date)),
+      CostingMethod::Fefo => {
+        let mut expiries = HashMap::new();
+        for (batch, _) in balances.iter() {
+          let expiry = self.db.expiries.get(op.goods, batch)?;
+          expiries.insert(batch.clone(), expiry.unwrap_or(DateTime::<Utc>::MAX_UTC));
+        }
+        balances.sort_by(|(a, _), (b, _)| (expiries[a], a.date).cmp(&(expiries[b], b.date)))
+      },
+      _ => balances.sort_by(|(a, _), (b, _)| a.date.cmp(&b.date)),
+    }
+
+    let average = if method == CostingMethod::WeightedAverage {
+      let mut stock = BalanceForGoods::default();
+      for (batch, balance) in balances.iter() {
+        if balance.qty.is_positive() && batch != &Batch::no() {
+          stock.qty = stock.qty.add_with(&balance.qty, factors);
+          stock.cost += balance.cost;
+        }
+      }
+      Some(stock)
+    } else {
+      None
+    };
+
+    Ok((method, balances, average))
+  }
+
+  // cost of issue at weighted average is fixed, otherwise it evaluated from batch balance;
+  // fixed cost is recalculated on redistribution, changes before issue propagate to it
+  // through batch-less topology
+  fn issue_op(
+    &self,
+    qty: Qty,
+    cost: Cost,
+    average: &Option<BalanceForGoods>,
+    factors: &Factors,
+  ) -> InternalOperation {
+    if let Some(average) = average {
+      let cost = if average.qty == qty { average.cost } else { qty.cost(average, factors) };
+      InternalOperation::Issue(qty, cost, Mode::Manual)
+    } else {
+      InternalOperation::Issue(qty, cost, Mode::Auto)
+    }
+  }
+
   fn cleanup_dependent(
     &mut self,
     op: &Op,
@@ -0,0 +1,252 @@
+use std::sync::Arc;
+
+use crate::elements::{
+  first_day_current_month, first_day_next_month, Store, ToJson, UUID_MAX, UUID_NIL,
+};
+use crate::error::WHError;
+use crate::operations::OpMutation;
+use crate::staging::StagedDB;
+use chrono::{DateTime, Utc};
+use json::{object, JsonValue};
+use rocksdb::IteratorMode;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+const CF_NAME: &str = "cf_periods";
+
+const CLOSED: u8 = 0;
+const AUDIT: u8 = 1;
+const STORE: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PeriodAction {
+  Close,
+  Reopen,
+}
+
+/// Audit record of closing or reopening of period.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PeriodRecord {
+  pub seq: u64,
+  pub action: PeriodAction,
+  /// operations before this date are locked after the action
+  pub closed_till: Option<DateTime<Utc>>,
+  pub by: String,
+  pub at: DateTime<Utc>,
+}
+
+impl ToJson for PeriodRecord {
+  fn to_json(&self) -> JsonValue {
+    object! {
+      seq: self.seq,
+      action: match self.action {
+        PeriodAction::Close => "close",
+        PeriodAction::Reopen => "reopen",
+      },
+      closed_till: self.closed_till.map(|d| d.to_json()).unwrap_or(JsonValue::Null),
+      by: self.by.clone(),
+      at: self.at.to_json(),
+    }
+  }
+}
+
+/// Closed accounting periods of workspaces: months before `closed_till` accept no operations
+/// at stores of the workspace, so their checkpoints at or before that date never change.
+#[derive(Clone)]
+pub struct Periods {
+  pub db: Arc<StagedDB>,
+}
+
+impl Periods {
+  pub fn cf_name() -> &'static str {
+    CF_NAME
+  }
+
+  // | kind | length of workspace | workspace | seq |
+  fn key(&self, kind: u8, wid: &str, seq: u64) -> Vec<u8> {
+    [kind, wid.len() as u8]
+      .iter()
+      .chain(wid.as_bytes().iter())
+      .chain(seq.to_be_bytes().iter())
+      .copied()
+      .collect()
+  }
+
+  // | kind | store |
+  fn store_key(&self, store: Store) -> Vec<u8> {
+    [STORE].iter().chain(store.as_bytes().iter()).copied().collect()
+  }
+
+  /// Record that operations of stores belong to workspace, known ones are kept as they are.
+  pub fn assign(&self, wid: &str, stores: &[Store]) -> Result<(), WHError> {
+    self.db.stage(|| {
+      for store in stores {
+        if self.workspace(*store)?.is_none() {
+          self.db.put_cf(CF_NAME, self.store_key(*store), wid.as_bytes())?;
+        }
+      }
+      Ok(())
+    })
+  }
+
+  /// Workspace of store, `None` if no operations of store were recorded for workspace.
+  pub fn workspace(&self, store: Store) -> Result<Option<String>, WHError> {
+    match self.db.get_cf(CF_NAME, self.store_key(store))? {
+      Some(bytes) => Ok(Some(String::from_utf8_lossy(&bytes).to_string())),
+      None => Ok(None),
+    }
+  }
+
+  pub fn closed_till(&self, wid: &str) -> Result<Option<DateTime<Utc>>, WHError> {
+    match self.db.get_cf(CF_NAME, self.key(CLOSED, wid, 0))? {
+      Some(bytes) => Ok(Some(ciborium::de::from_reader(bytes.as_slice())?)),
+      None => Ok(None),
+    }
+  }
+
+  /// Closed period of workspace of store.
+  pub fn closed_till_of(&self, store: Store) -> Result<Option<DateTime<Utc>>, WHError> {
+    match self.workspace(store)? {
+      Some(wid) => self.closed_till(&wid),
+      None => Ok(None),
+    }
+  }
+
+  /// Closed periods of stores, others have none.
+  pub fn closed_stores(&self) -> Result<HashMap<Store, DateTime<Utc>>, WHError> {
+    let stores = self.store_key(UUID_NIL)..self.store_key(UUID_MAX);
+
+    let mut res = HashMap::new();
+    for item in self.db.iterator_range(CF_NAME, stores, IteratorMode::Start)? {
+      let (k, v) = item?;
+      if let Some(closed) = self.closed_till(&String::from_utf8_lossy(&v))? {
+        res.insert(Uuid::from_slice(&k[1..])?, closed);
+      }
+    }
+    Ok(res)
+  }
+
+  /// Close month of `date` and all months before it.
+  pub fn close(&self, wid: &str, date: DateTime<Utc>, by: &str) -> Result<PeriodRecord, WHError> {
+    let till = first_day_next_month(date);
+    self.db.stage(|| {
+      if let Some(closed) = self.closed_till(wid)? {
+        if till <= closed {
+          return Err(WHError::closed_period(
+            &format!("period till {} is already closed", closed.to_rfc3339()),
+            closed,
+          ));
+        }
+      }
+      self.record(wid, PeriodAction::Close, Some(till), by)
+    })
+  }
+
+  /// Reopen month of `date` and all months after it.
+  pub fn reopen(&self, wid: &str, date: DateTime<Utc>, by: &str) -> Result<PeriodRecord, WHError> {
+    let till = first_day_current_month(date);
+    self.db.stage(|| {
+      match self.closed_till(wid)? {
+        Some(closed) if till < closed => {},
+        _ => return Err(WHError::new(&format!("period {} is not closed", till.to_rfc3339()))),
+      }
+      let till = if till.timestamp() == 0 { None } else { Some(till) };
+      self.record(wid, PeriodAction::Reopen, till, by)
+    })
+  }
+
+  fn record(
+    &self,
+    wid: &str,
+    action: PeriodAction,
+    closed_till: Option<DateTime<Utc>>,
+    by: &str,
+  ) -> Result<PeriodRecord, WHError> {
+    let audit = self.key(AUDIT, wid, 0)..self.key(AUDIT, wid, u64::MAX);
+    let seq = match self.db.iterator_range(CF_NAME, audit, IteratorMode::End)?.next() {
+      Some(item) => {
+        let (k, _) = item?;
+        u64::from_be_bytes(k[k.len() - 8..].try_into().unwrap()) + 1
+      },
+      None => 1,
+    };
+
+    let record = PeriodRecord { seq, action, closed_till, by: by.to_string(), at: Utc::now() };
+
+    let mut bs = Vec::new();
+    ciborium::ser::into_writer(&record, &mut bs)?;
+
+    self.db.stage(|| {
+      match closed_till {
+        Some(till) => {
+          let mut date = Vec::new();
+          ciborium::ser::into_writer(&till, &mut date)?;
+          self.db.put_cf(CF_NAME, self.key(CLOSED, wid, 0), date)?;
+        },
+        None => self.db.delete_cf(CF_NAME, self.key(CLOSED, wid, 0))?,
+      }
+      self.db.put_cf(CF_NAME, self.key(AUDIT, wid, seq), bs)
+    })?;
+
+    Ok(record)
+  }
+
+  /// Audit records of workspace in order of actions.
+  pub fn audit(&self, wid: &str) -> Result<Vec<PeriodRecord>, WHError> {
+    let audit = self.key(AUDIT, wid, 0)..self.key(AUDIT, wid, u64::MAX);
+
+    let mut res = Vec::new();
+    for item in self.db.iterator_range(CF_NAME, audit, IteratorMode::Start)? {
+      let (_, v) = item?;
+      res.push(ciborium::de::from_reader(&v[..])?);
+    }
+    Ok(res)
+  }
+
+  /// `true` if any workspace has closed period.
+  pub fn any_closed(&self) -> Result<bool, WHError> {
+    let closed = vec![CLOSED]..vec![AUDIT];
+    Ok(self.db.iterator_range(CF_NAME, closed, IteratorMode::Start)?.next().is_some())
+  }
+
+  /// Reject mutations dated inside of closed period of workspace of their store or of store
+  /// they transfer to. Store without workspace is rejected while any period is closed.
+  pub fn check(&self, ops: &[OpMutation]) -> Result<(), WHError> {
+    let any_closed = self.any_closed()?;
+
+    let mut closed_of = HashMap::new();
+    for op in ops {
+      for store in [Some(op.store), op.transfer].into_iter().flatten() {
+        if let Entry::Vacant(entry) = closed_of.entry(store) {
+          let closed = match self.workspace(store)? {
+            Some(wid) => self.closed_till(&wid)?,
+            None if any_closed => {
+              return Err(WHError::new(&format!(
+                "store {store} of operation {} belong to no workspace, but periods are closed",
+                op.id
+              )))
+            },
+            None => None,
+          };
+          entry.insert(closed);
+        }
+        if let Some(closed) = closed_of[&store] {
+          if op.date < closed {
+            return Err(WHError::closed_period(
+              &format!(
+                "operation {} at {} is inside of closed period till {} of store {store}",
+                op.id,
+                op.date.to_rfc3339(),
+                closed.to_rfc3339()
+              ),
+              closed,
+            ));
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+}
@@ -8,16 +8,16 @@ use service::utils::json::JsonParams;
 use service::{Context, Services};
 use values::c;
 
-const COUNTERPARTY: [&str; 1] = ["counterparty"];
-const STORAGE: [&str; 2] = ["warehouse", "storage"];
-const RECEIVE_DOCUMENT: [&str; 3] = ["warehouse", "receive", "document"];
+pub(crate) const COUNTERPARTY: [&str; 1] = ["counterparty"];
+pub(crate) const STORAGE: [&str; 2] = ["warehouse", "storage"];
+pub(crate) const RECEIVE_DOCUMENT: [&str; 3] = ["warehouse", "receive", "document"];
 const INVENTORY_DOCUMENT: [&str; 3] = ["warehouse", "inventory", "document"];
-const TRANSFER_DOCUMENT: [&str; 3] = ["warehouse", "transfer", "document"];
-const DISPATCH_DOCUMENT: [&str; 3] = ["warehouse", "dispatch", "document"];
-const UOM: [&str; 1] = ["uom"];
-const GOODS: [&str; 1] = ["goods"];
-const CATEGORY: [&str; 2] = ["goods", "category"];
-const CURRENCY: [&str; 1] = ["currency"];
+pub(crate) const TRANSFER_DOCUMENT: [&str; 3] = ["warehouse", "transfer", "document"];
+pub(crate) const DISPATCH_DOCUMENT: [&str; 3] = ["warehouse", "dispatch", "document"];
+pub(crate) const UOM: [&str; 1] = ["uom"];
+pub(crate) const GOODS: [&str; 1] = ["goods"];
+pub(crate) const CATEGORY: [&str; 2] = ["goods", "category"];
+pub(crate) const CURRENCY: [&str; 1] = ["currency"];
 
 const OID: &str = "yjmgJUmDo_kn9uxVi8s9Mj9mgGRJISxRt63wT46NyTQ";
 
@@ -1,4 +1,5 @@
 use crate::balance::{BalanceForGoods, Cost, Price};
+use crate::conversions::Factors;
 use crate::elements::{ToJson, UUID_NIL};
 use crate::error::WHError;
 use crate::operations::InternalOperation;
@@ -156,6 +157,21 @@ impl Uom {
   //   }
   // }
 
+  /// innermost unit and how many of it are in one of this unit
+  pub(crate) fn base(&self) -> (Uuid, Decimal) {
+    let mut units = Decimal::ONE;
+    let mut uuid = self.uuid();
+    let mut data = self.named();
+
+    while let Some(qty) = data {
+      units *= qty.number;
+      uuid = qty.name.uuid();
+      data = qty.name.named();
+    }
+
+    (uuid, units)
+  }
+
   pub(crate) fn depth(&self) -> usize {
     let mut result = 0;
     let mut data = self.named().clone();
@@ -425,7 +441,20 @@ impl Number<Uom> {
     None
   }
 
-  pub(crate) fn common(&self, rhs: &Self) -> Option<Uom> {
+  pub(crate) fn common(&self, rhs: &Self, factors: &Factors) -> Option<Uom> {
+    self.nested_common(rhs).or_else(|| {
+      // unrelated units are compared in the innermost unit of left side
+      let (from, _) = self.name.base();
+      let (into, _) = rhs.name.base();
+      if from != into && factors.is_convertible(into, from) {
+        Some(Uom::In(from, None))
+      } else {
+        None
+      }
+    })
+  }
+
+  fn nested_common(&self, rhs: &Self) -> Option<Uom> {
     if self.name == rhs.name {
       return Some(self.name.clone());
     } else {
@@ -455,11 +484,17 @@ impl Number<Uom> {
     None
   }
 
-  pub(crate) fn lowering(&self, name: &Uom) -> Option<Number<Uom>> {
+  pub(crate) fn lowering(&self, name: &Uom, factors: &Factors) -> Option<Number<Uom>> {
     if &self.name == name {
       return Some(self.clone());
     }
 
+    if self.named().is_none() {
+      if let Some(converted) = self.converted(name, factors) {
+        return Some(converted);
+      }
+    }
+
     let mut result = Number::new(Decimal::ZERO, name.uuid(), name.named());
 
     let mut tmp = self.clone();
@@ -473,7 +508,7 @@ impl Number<Uom> {
       }
 
       if inner_qty.named().is_none() {
-        return None;
+        return self.converted(name, factors);
       }
 
       tmp = *inner_qty;
@@ -482,14 +517,32 @@ impl Number<Uom> {
     Some(result)
   }
 
-  pub(crate) fn elevate_to_qty(&self, balance: &Qty) -> Qty {
+  /// Number in the innermost unit.
+  pub(crate) fn base(&self) -> Number<Uom> {
+    let (uuid, units) = self.name.base();
+    Number::new(self.number * units, uuid, None)
+  }
+
+  /// Number in unit without common nested unit by registered conversion.
+  pub(crate) fn converted(&self, name: &Uom, factors: &Factors) -> Option<Number<Uom>> {
+    let from = self.base();
+    let (into, units) = name.base();
+    if from.uuid() == into {
+      return None;
+    }
+
+    let number = factors.convert(from.number, from.uuid(), into)?;
+    Some(Number::new_named(number / units, name.clone()))
+  }
+
+  pub(crate) fn elevate_to_qty(&self, balance: &Qty, factors: &Factors) -> Qty {
     let mut result = Qty::new(vec![self.clone()]);
     let prev = self.clone();
 
     for b in balance.inner.iter() {
-      if let Some(_common) = self.common(b) {
+      if let Some(_common) = self.common(b, factors) {
         if b.name.depth() > prev.name.depth() {
-          result = self.elevate_to_uom(&b.name);
+          result = self.elevate_to_uom(&b.name, factors);
         }
       }
     }
@@ -497,7 +550,7 @@ impl Number<Uom> {
     result
   }
 
-  pub fn elevate_to_uom(&self, uom: &Uom) -> Qty {
+  pub fn elevate_to_uom(&self, uom: &Uom, factors: &Factors) -> Qty {
     let mut result = Qty::new(vec![]);
 
     if &self.name == uom {
@@ -505,6 +558,14 @@ impl Number<Uom> {
       return result;
     }
 
+    // packaging of other unit
+    if uom.depth() > 0 {
+      let (into, _) = uom.base();
+      if let Some(converted) = self.converted(&Uom::In(into, None), factors) {
+        return converted.elevate_to_uom(uom, factors);
+      }
+    }
+
     if self.name.depth() >= uom.depth() {
       result.inner.push(self.clone());
       return result;
@@ -522,7 +583,7 @@ impl Number<Uom> {
 
           // println!("{:?} / {:?} = {:?}", self.number, inner.number, amount);
           if (amount / Decimal::ONE) >= Decimal::ONE {
-            let mut upper_numbers = new_number.elevate_to_uom(uom).inner;
+            let mut upper_numbers = new_number.elevate_to_uom(uom, factors).inner;
             // println!("upper_numbers {upper_numbers:?}");
             result.inner.append(&mut upper_numbers);
           } else {
@@ -663,11 +724,11 @@ impl Qty {
     }
   }
 
-  pub(crate) fn lowering(&self, name: &Uom) -> Option<Number<Uom>> {
+  pub(crate) fn lowering(&self, name: &Uom, factors: &Factors) -> Option<Number<Uom>> {
     let mut result = Number::new(Decimal::ZERO, name.uuid(), name.named());
 
     for qty in &self.inner {
-      if let Some(sum) = qty.lowering(name) {
+      if let Some(sum) = qty.lowering(name, factors) {
         result.number += sum.number;
       }
     }
@@ -690,12 +751,12 @@ impl Qty {
     result
   }
 
-  pub fn common(&self, rhs: &Self) -> Option<Uom> {
+  pub fn common(&self, rhs: &Self, factors: &Factors) -> Option<Uom> {
     let mut result: Option<Uom> = None;
 
     for left in &self.inner {
       for right in &rhs.inner {
-        if let Some(common) = left.common(&right) {
+        if let Some(common) = left.common(&right, factors) {
           if let Some(res) = &result {
             // we must write a minimal uom from qty, otherwise cost will not calculate correctly
             if common.depth() < res.depth() {
@@ -714,13 +775,13 @@ impl Qty {
     result
   }
 
-  pub(crate) fn cost(&self, balance: &BalanceForGoods) -> Cost {
+  pub(crate) fn cost(&self, balance: &BalanceForGoods, factors: &Factors) -> Cost {
     if self.is_zero() || balance.is_zero() {
       Cost::ZERO
     } else {
-      if let Some(common) = self.common(&balance.qty) {
-        if let Some(lower) = self.lowering(&common) {
-          let price = balance.price(&common);
+      if let Some(common) = self.common(&balance.qty, factors) {
+        if let Some(lower) = self.lowering(&common, factors) {
+          let price = balance.price(&common, factors);
           (lower.number * price.number()).round_dp(5).into()
         } else {
           Cost::ERROR
@@ -731,27 +792,45 @@ impl Qty {
     }
   }
 
-  pub(crate) fn price(&self, balance: &BalanceForGoods) -> Option<Price> {
+  pub(crate) fn price(&self, balance: &BalanceForGoods, factors: &Factors) -> Option<Price> {
     if self.is_zero() {
       None
     } else {
-      if let Some(common) = self.common(&balance.qty) {
-        Some(balance.price(&common))
+      if let Some(common) = self.common(&balance.qty, factors) {
+        Some(balance.price(&common, factors))
       } else {
         None
       }
     }
   }
 
-  pub(crate) fn is_greater_or_equal(&self, rhs: &Self) -> Result<bool, WHError> {
-    if let Some(uom) = self.common(&rhs) {
-      let left = self.lowering(&uom).unwrap().number;
-      let right = rhs.lowering(&uom).unwrap().number;
+  pub(crate) fn is_greater_or_equal(&self, rhs: &Self, factors: &Factors) -> Result<bool, WHError> {
+    if let Some(uom) = self.common(&rhs, factors) {
+      let left = self.lowering(&uom, factors).unwrap().number;
+      let right = rhs.lowering(&uom, factors).unwrap().number;
       Ok(left >= right)
     } else {
       Err(WHError::new("two Qty don't have common part"))
     }
   }
+  /// Sum with units converted by factors of goods.
+  pub fn add_with(&self, rhs: &Qty, factors: &Factors) -> Qty {
+    add(self, rhs, factors)
+  }
+
+  /// Cost of quantity at `price`, units converted by factors of goods.
+  pub(crate) fn cost_at(&self, price: Price, factors: &Factors) -> Cost {
+    if let Some(lower) = self.lowering(&price.uom(), factors) {
+      let number = lower.number * price.number();
+      return Cost::from(number);
+    }
+    Cost::ERROR
+  }
+
+  /// Difference with units converted by factors of goods.
+  pub fn sub_with(&self, rhs: &Qty, factors: &Factors) -> Qty {
+    sub(self, rhs, factors)
+  }
 
   // pub(crate) fn plus_with_relax(&self, balance: &Self) -> Self {
   //   let mut result = Qty::new(vec![]);
@@ -800,11 +879,7 @@ impl Mul<Price> for &Qty {
   type Output = Cost;
 
   fn mul(self, price: Price) -> Self::Output {
-    if let Some(lower) = self.lowering(&price.uom()) {
-      let number = lower.number * price.number();
-      return Cost::from(number);
-    }
-    Cost::ERROR
+    self.cost_at(price, &Factors::default())
   }
 }
 
@@ -812,7 +887,7 @@ impl Add for Qty {
   type Output = Qty;
 
   fn add(self, rhs: Self) -> Self::Output {
-    add(&self, &rhs)
+    add(&self, &rhs, &Factors::default())
   }
 }
 
@@ -820,11 +895,11 @@ impl Add for &Qty {
   type Output = Qty;
 
   fn add(self, rhs: Self) -> Self::Output {
-    add(&self, rhs)
+    add(&self, rhs, &Factors::default())
   }
 }
 
-fn add(lhs: &Qty, rhs: &Qty) -> Qty {
+fn add(lhs: &Qty, rhs: &Qty, factors: &Factors) -> Qty {
   if lhs.is_zero() && rhs.is_zero() {
     return Qty::default();
   } else if lhs.is_zero() {
@@ -876,7 +951,7 @@ fn add(lhs: &Qty, rhs: &Qty) -> Qty {
     let mut named: Vec<(usize, Uom)> = ls
       .iter()
       .enumerate()
-      .map(|(i, l)| (i, l, l.common(&r)))
+      .map(|(i, l)| (i, l, l.common(&r, factors)))
       .filter(|(_, _, c)| c.is_some())
       .map(|(i, l, c)| (i, l, c.unwrap()))
       .filter(|(_, l, c)| {
@@ -910,15 +985,19 @@ fn add(lhs: &Qty, rhs: &Qty) -> Qty {
 
       let l = ls.remove(i);
 
-      let ll = l.lowering(&common).unwrap();
-      let rl = r.lowering(&common).unwrap();
+      let ll = l.lowering(&common, factors).unwrap();
+      let rl = r.lowering(&common, factors).unwrap();
 
       let product = ll.number() + rl.number();
       // println!("ADD2: {:?} + {:?} = {:?}", ll.number(), rl.number(), product);
       r.number = Decimal::ZERO;
       let upper_uom = if l.name.depth() > r.name.depth() { &l.name } else { &r.name };
       if product != Decimal::ZERO {
-        ls.append(&mut Number::new_named(product, ll.name.clone()).elevate_to_uom(upper_uom).inner);
+        ls.append(
+          &mut Number::new_named(product, ll.name.clone())
+            .elevate_to_uom(upper_uom, factors)
+            .inner,
+        );
       }
     }
   }
@@ -938,7 +1017,7 @@ impl Sub for Qty {
   type Output = Qty;
 
   fn sub(self, rhs: Self) -> Self::Output {
-    sub(&self, &rhs)
+    sub(&self, &rhs, &Factors::default())
   }
 }
 
@@ -946,11 +1025,11 @@ impl Sub for &Qty {
   type Output = Qty;
 
   fn sub(self, rhs: Self) -> Self::Output {
-    sub(&self, rhs)
+    sub(&self, rhs, &Factors::default())
   }
 }
 
-fn sub(lhs: &Qty, rhs: &Qty) -> Qty {
+fn sub(lhs: &Qty, rhs: &Qty, factors: &Factors) -> Qty {
   if lhs.is_zero() && rhs.is_zero() {
     return Qty::default();
   } else if lhs.is_zero() {
@@ -1004,7 +1083,7 @@ fn sub(lhs: &Qty, rhs: &Qty) -> Qty {
     let mut named: Vec<(usize, Uom)> = ls
       .iter()
       .enumerate()
-      .map(|(i, l)| (i, l, l.common(&r)))
+      .map(|(i, l)| (i, l, l.common(&r, factors)))
       .filter(|(_, _, c)| c.is_some())
       .map(|(i, l, c)| (i, l, c.unwrap()))
       .filter(|(_, l, c)| {
@@ -1038,17 +1117,25 @@ fn sub(lhs: &Qty, rhs: &Qty) -> Qty {
 
       let l = ls.remove(i);
 
-      let ll = l.lowering(&common).unwrap();
-      let rl = r.lowering(&common).unwrap();
+      let ll = l.lowering(&common, factors).unwrap();
+      let rl = r.lowering(&common, factors).unwrap();
 
       let product = ll.number() - rl.number();
       // println!("SUB2: {:?} - {:?} = {:?}", ll.number(), rl.number(), product);
       r.number = Decimal::ZERO;
       let upper_uom = if l.name.depth() > r.name.depth() { &l.name } else { &r.name };
       if product > Decimal::ZERO {
-        ls.append(&mut Number::new_named(product, ll.name.clone()).elevate_to_uom(upper_uom).inner);
+        ls.append(
+          &mut Number::new_named(product, ll.name.clone())
+            .elevate_to_uom(upper_uom, factors)
+            .inner,
+        );
       } else if product < Decimal::ZERO {
-        rs.append(&mut Number::new_named(-product, rl.name.clone()).elevate_to_uom(upper_uom).inner);
+        rs.append(
+          &mut Number::new_named(-product, rl.name.clone())
+            .elevate_to_uom(upper_uom, factors)
+            .inner,
+        );
       }
     }
   }
@@ -1122,6 +1209,7 @@ impl TryInto<Qty> for JsonValue {
 
 #[cfg(test)]
 mod tests {
+  use crate::conversions::Factors;
   use crate::elements::ToJson;
   use crate::error::WHError;
   use crate::qty::{Number, Qty, Uom};
@@ -1383,7 +1471,7 @@ mod tests {
 
     let name = Uom::In(uom2, None);
 
-    let lower = data0.lowering(&name).unwrap();
+    let lower = data0.lowering(&name, &Factors::default()).unwrap();
 
     assert_eq!(lower.number, Decimal::from(2000));
     assert_eq!(lower.name, name);
@@ -1424,7 +1512,7 @@ mod tests {
     // u1 x 100 u2
     let uom = qty0.inner[0].name.named().unwrap().name;
 
-    let lower0 = qty0.lowering(&uom).unwrap();
+    let lower0 = qty0.lowering(&uom, &Factors::default()).unwrap();
     // println!("lower0 {lower0:?}");
 
     assert_eq!(qty1.inner[0], lower0);
@@ -1450,7 +1538,7 @@ mod tests {
     // u0
     let uom = qty2.inner[0].clone().name;
 
-    let lower1 = qty3.lowering(&uom);
+    let lower1 = qty3.lowering(&uom, &Factors::default());
     // println!("lower1 {lower1:?}");
 
     assert_eq!(lower1.is_none(), true);
@@ -1459,7 +1547,7 @@ mod tests {
     let uom = qty3.inner[0].clone().name;
     // println!("uom {uom:?}");
 
-    let lower2 = qty2.lowering(&uom);
+    let lower2 = qty2.lowering(&uom, &Factors::default());
     // println!("lower2 {lower2:?}");
 
     assert_eq!(lower2.is_none(), true);
@@ -1478,7 +1566,7 @@ mod tests {
     // u0
     let uom = qty4.inner[0].clone().name;
 
-    let lower3 = qty4.lowering(&uom);
+    let lower3 = qty4.lowering(&uom, &Factors::default());
     // println!("lower3 {lower3:?}");
 
     assert_eq!(qty4.inner[0], lower3.unwrap());
@@ -1497,7 +1585,7 @@ mod tests {
       Some(Box::new(Number::new(Decimal::from(11), u1, None))),
     )]);
 
-    let common00 = qty00.common(&qty00).unwrap();
+    let common00 = qty00.common(&qty00, &Factors::default()).unwrap();
     // println!("common00 {common00:?}");
     assert_eq!(common00.uuid(), u0);
     assert_eq!(common00.named().unwrap().uuid(), u1);
@@ -1510,7 +1598,7 @@ mod tests {
     // )]);
     //
     // // same uom only in the end
-    // let common01 = qty00.common(&qty01).unwrap();
+    // let common01 = qty00.common(&qty01, &Factors::default()).unwrap();
     // // println!("common01 {common01:?}");
     // assert_eq!(common01.uuid(), u1);
 
@@ -1528,7 +1616,7 @@ mod tests {
     let qty0: Qty = data0.try_into().unwrap();
     let qty01: Qty = data01.try_into().unwrap();
 
-    let common01 = qty0.common(&qty01);
+    let common01 = qty0.common(&qty01, &Factors::default());
     assert_eq!(common01.is_none(), true);
 
     let data1 = object! {
@@ -1538,7 +1626,7 @@ mod tests {
 
     let qty1: Qty = data1.try_into().unwrap();
 
-    let common0 = qty0.common(&qty1).unwrap();
+    let common0 = qty0.common(&qty1, &Factors::default()).unwrap();
     // println!("common0 {common0:?}");
 
     assert_eq!(common0, qty0.inner[0].name);
@@ -1566,7 +1654,7 @@ mod tests {
 
     let qty3: Qty = data3.try_into().unwrap();
 
-    let common1 = qty2.common(&qty3).unwrap();
+    let common1 = qty2.common(&qty3, &Factors::default()).unwrap();
     // println!("common1 {common1:?}");
 
     assert_eq!(common1, qty2.inner[0].name);
@@ -1599,11 +1687,11 @@ mod tests {
     let qty5: Qty = data5.try_into().unwrap();
 
     // // different top uoms
-    // let common2_0 = qty4.common(&qty3).unwrap();
+    // let common2_0 = qty4.common(&qty3, &Factors::default()).unwrap();
     // println!("common2_0 {common2_0:?}");
     // assert_eq!(common2_0, qty5.inner[0].name);
 
-    let common2 = qty4.common(&qty5).unwrap();
+    let common2 = qty4.common(&qty5, &Factors::default()).unwrap();
     // println!("common2 {common2:?}");
 
     assert_eq!(common2, qty5.inner[0].name);
@@ -1628,7 +1716,7 @@ mod tests {
     };
     let qty7: Qty = data7.try_into().unwrap();
 
-    let common3 = qty6.common(&qty7).unwrap();
+    let common3 = qty6.common(&qty7, &Factors::default()).unwrap();
     // println!("common3 {common3:?}");
 
     assert_eq!(common3, Uom::In(u2, None));
@@ -1734,7 +1822,7 @@ mod tests {
 
     let data1 = Number::new(Decimal::from(1000), uom2, None);
 
-    let elevate0 = data1.elevate_to_qty(&data0);
+    let elevate0 = data1.elevate_to_qty(&data0, &Factors::default());
     // println!("elevate0 {elevate0:?}");
 
     let check0 = Qty::new(vec![Number::new(
@@ -1771,7 +1859,7 @@ mod tests {
       Some(Box::new(Number::new(Decimal::from(100), uom2, None))),
     );
 
-    let result0 = data1.elevate_to_uom(&data0);
+    let result0 = data1.elevate_to_uom(&data0, &Factors::default());
     // println!("result0 {result0:?}");
 
     let compare0 = Qty::new(vec![Number::new(
@@ -1789,7 +1877,7 @@ mod tests {
 
     let data2 = Number::new(Decimal::from(1200), uom2, None);
 
-    let result1 = data2.elevate_to_uom(&data0);
+    let result1 = data2.elevate_to_uom(&data0, &Factors::default());
     println!("result1 {result1:?}");
 
     let compare1 = Qty::new(vec![
@@ -1818,7 +1906,7 @@ mod tests {
 
     let data4 = Number::new(Decimal::from(-11), uom2, None);
 
-    let result2 = data4.elevate_to_uom(&data3);
+    let result2 = data4.elevate_to_uom(&data3, &Factors::default());
     // println!("result2 {result2:?}");
 
     let compare2 = Qty::new(vec![
@@ -0,0 +1,467 @@
+use crate::balance::{Balance, BalanceForGoods};
+use crate::batch::Batch;
+use crate::checkpoints::check_store_batch_date::is_unchanged;
+use crate::checkpoints::granularity::Granularity;
+use crate::checkpoints::CheckpointTopology;
+use crate::conversions::{Conversions, Factors};
+use crate::db::Db;
+use crate::elements::{Goods, Store, WHError};
+use crate::operations::{InternalOperation, Op};
+use crate::ordered_topology::OrderedTopology;
+use chrono::{DateTime, Utc};
+use rocksdb::{Direction, IteratorMode, Options};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const CF_NAME: &str = "cf_rebuild";
+
+/// State of topology rebuild, kept till the end of rebuild to continue it after interruption.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Progress {
+  pub topology: String,
+  /// store which checkpoints are rebuilt, all stores if none
+  #[serde(default)]
+  pub store: Option<Store>,
+  /// records of primary topology processed
+  pub operations: usize,
+  /// key of the last processed record of primary topology
+  pub last: Option<Vec<u8>>,
+  /// date of the latest checkpoint to build
+  pub latest: Option<DateTime<Utc>>,
+  pub done: bool,
+}
+
+enum Target<'a> {
+  Ordered(&'a (dyn OrderedTopology + Sync + Send)),
+  Checkpoint(&'a (dyn CheckpointTopology + Sync + Send)),
+}
+
+/// Sparse checkpoints of store, goods & batch from its operations in date order.
+pub(crate) struct CheckpointsBuilder {
+  latest: DateTime<Utc>,
+  granularities: HashMap<Store, Granularity>,
+  granularity: Granularity,
+  key: Option<(Store, Goods, Batch)>,
+  /// conversions of units of goods in key
+  factors: Factors,
+  balance: BalanceForGoods,
+  /// balance of the last checkpoint
+  checkpoint: BalanceForGoods,
+  next: Option<DateTime<Utc>>,
+  /// checkpoints of store at or before its date are not produced
+  closed_stores: HashMap<Store, DateTime<Utc>>,
+  /// closed period of store in key
+  closed: Option<DateTime<Utc>>,
+  /// checkpoint of closed period was skipped, so the next one is produced even if unchanged
+  skipped: bool,
+}
+
+impl CheckpointsBuilder {
+  pub(crate) fn new(latest: DateTime<Utc>, granularities: HashMap<Store, Granularity>) -> Self {
+    CheckpointsBuilder {
+      latest,
+      granularities,
+      granularity: Granularity::default(),
+      key: None,
+      factors: Factors::default(),
+      balance: BalanceForGoods::default(),
+      checkpoint: BalanceForGoods::default(),
+      next: None,
+      closed_stores: HashMap::new(),
+      closed: None,
+      skipped: false,
+    }
+  }
+
+  /// Leave checkpoints of closed periods of stores as they are.
+  pub(crate) fn closed_till(mut self, closed: HashMap<Store, DateTime<Utc>>) -> Self {
+    self.closed_stores = closed;
+    self
+  }
+
+  /// `true` if operation belong to other store, goods or batch than previous ones
+  pub(crate) fn is_next(&self, op: &Op) -> bool {
+    match &self.key {
+      Some((store, goods, batch)) => *store != op.store || *goods != op.goods || *batch != op.batch,
+      None => false,
+    }
+  }
+
+  /// Apply operation and return checkpoints before it.
+  pub(crate) fn push(&mut self, op: &Op, conversions: &Conversions) -> Vec<Balance> {
+    let mut result = if self.is_next(op) { self.finish() } else { Vec::new() };
+
+    if self.key.is_none() {
+      self.key = Some((op.store, op.goods, op.batch.clone()));
+      self.granularity = self.granularities.get(&op.store).copied().unwrap_or_default();
+      self.factors = conversions.of(op.goods);
+      self.closed = self.closed_stores.get(&op.store).copied();
+    }
+    self.checkpoints(op.date, &mut result);
+
+    match &op.op {
+      // unlike `BalanceForGoods::apply` do not trust balance recorded at inventory
+      InternalOperation::Inventory(_, delta, _) => {
+        self.balance = self.balance.add_with(delta, &self.factors)
+      },
+      _ => self.balance.apply(&op.op, &self.factors),
+    }
+    if self.next.is_none() {
+      self.next = Some(self.granularity.next(op.date));
+    }
+
+    result
+  }
+
+  /// Checkpoints after the last operation, builder is ready for next store, goods & batch.
+  pub(crate) fn finish(&mut self) -> Vec<Balance> {
+    let mut result = Vec::new();
+    self.checkpoints(self.granularity.ceil(self.latest), &mut result);
+
+    self.key = None;
+    self.balance = BalanceForGoods::default();
+    self.checkpoint = BalanceForGoods::default();
+    self.next = None;
+    self.skipped = false;
+
+    result
+  }
+
+  // checkpoint contain operations before its date and only changed balance is kept
+  fn checkpoints(&mut self, till: DateTime<Utc>, result: &mut Vec<Balance>) {
+    let (store, goods, batch) = match &self.key {
+      Some(key) => key.clone(),
+      None => return,
+    };
+    // store have checkpoints till the first one of its granularity at or after the latest
+    let latest = self.granularity.ceil(self.latest);
+    if let Some(date) = self.next {
+      if date > till || date > latest {
+        return;
+      }
+      if self.closed.is_some_and(|closed| date <= closed) {
+        self.skipped = true;
+      } else if self.skipped || !is_unchanged(&self.checkpoint, &self.balance) {
+        result.push(Balance { date, store, goods, batch, number: self.balance.clone() });
+        self.checkpoint = self.balance.clone();
+        self.skipped = false;
+      }
+      self.next = None;
+    }
+  }
+}
+
+impl Db {
+  /// Drop column family of topology and fill it again by the primary topology, which keep
+  /// operations of store, goods & batch in date order. Interrupted rebuild is continued
+  /// from saved progress, `report` is called at every save and may interrupt rebuild by error.
+  /// Saved rebuild of checkpoints of one store, see `start_store_rebuild`, is continued alike.
+  pub fn rebuild(
+    &self,
+    name: &str,
+    step: usize,
+    report: &mut dyn FnMut(&Progress) -> Result<(), WHError>,
+  ) -> Result<Progress, WHError> {
+    let primary = &self.ordered_topologies[0];
+    if primary.name() == name {
+      return Err(WHError::new("primary topology can't be rebuilt"));
+    }
+
+    let target = if let Some(topology) = self.ordered_topologies.iter().find(|t| t.name() == name) {
+      Target::Ordered(topology.as_ref())
+    } else if let Some(topology) = self.checkpoint_topologies.iter().find(|t| t.name() == name) {
+      Target::Checkpoint(topology.as_ref())
+    } else {
+      return Err(WHError::new(&format!("unknown topology {name}")));
+    };
+
+    // no mutation is committed while topology is rebuilt
+    self.db.exclusive(|| {
+      let mut progress = match self.rebuild_progress(name)? {
+        Some(progress) => progress,
+        None => self.start_rebuild(name, &target)?,
+      };
+      log::info!("rebuild {progress:?}");
+
+      let granularities = self.granularities.all()?;
+      let closed = self.periods.closed_stores()?;
+      let mut checkpoints = progress.latest.map(|latest| {
+        CheckpointsBuilder::new(latest, granularities.clone()).closed_till(closed.clone())
+      });
+
+      // every step is committed together with its progress
+      while !self
+        .db
+        .stage(|| self.rebuild_step(&target, &mut progress, checkpoints.as_mut(), &closed, step))?
+      {
+        report(&progress)?;
+      }
+
+      self.db.stage(|| {
+        if let (Target::Checkpoint(topology), Some(builder)) = (&target, checkpoints.as_mut()) {
+          for balance in builder.finish() {
+            set_checkpoint(*topology, &balance, &closed)?;
+          }
+          topology.set_latest_checkpoint_date(builder.latest)?;
+        }
+        self.db.delete_cf(CF_NAME, name.as_bytes())
+      })?;
+
+      progress.done = true;
+      report(&progress)?;
+
+      Ok(progress)
+    })
+  }
+
+  fn start_rebuild(&self, name: &str, target: &Target) -> Result<Progress, WHError> {
+    let latest = match target {
+      Target::Ordered(_) => None,
+      Target::Checkpoint(topology) => self.checkpoints_till(*topology)?,
+    };
+
+    match target {
+      // checkpoints of closed periods are kept
+      Target::Checkpoint(topology) if !self.periods.closed_stores()?.is_empty() => {
+        self.db.stage(|| self.delete_checkpoints(*topology, None))?
+      },
+      _ => {
+        self.db.drop_cf(name)?;
+        self.db.create_cf(name, &Options::default())?;
+      },
+    }
+
+    let progress = Progress { topology: name.to_string(), latest, ..Default::default() };
+    self.save_rebuild_progress(&progress)?;
+    Ok(progress)
+  }
+
+  // process operations of primary topology after `progress.last` till the first point where at
+  // least `step` of them are written, true if there are no operations left
+  fn rebuild_step(
+    &self,
+    target: &Target,
+    progress: &mut Progress,
+    mut checkpoints: Option<&mut CheckpointsBuilder>,
+    closed: &HashMap<Store, DateTime<Utc>>,
+    step: usize,
+  ) -> Result<bool, WHError> {
+    let primary = &self.ordered_topologies[0];
+
+    let mode = match (&progress.last, &progress.store) {
+      (Some(key), _) => IteratorMode::From(key, Direction::Forward),
+      (None, Some(store)) => IteratorMode::From(store.as_bytes(), Direction::Forward),
+      (None, None) => IteratorMode::Start,
+    };
+
+    let mut processed = 0;
+
+    let (db, cf) = (primary.db(), primary.cf()?);
+    for item in db.iterator_cf(&cf, mode) {
+      let (k, value) = item?;
+      // records of primary topology start with store
+      if progress.store.is_some_and(|store| !k.starts_with(store.as_bytes())) {
+        break;
+      }
+      if progress.last.as_deref() == Some(&k[..]) {
+        continue;
+      }
+
+      let (op, balance) = primary.from_bytes(&value)?;
+
+      if op.dependant.is_empty() {
+        // progress is saved only when all checkpoints of previous batch are written
+        let safe = match (target, checkpoints.as_deref_mut()) {
+          (Target::Checkpoint(topology), Some(builder)) if builder.is_next(&op) => {
+            for balance in builder.finish() {
+              set_checkpoint(*topology, &balance, closed)?;
+            }
+            true
+          },
+          (Target::Checkpoint(_), _) => false,
+          (Target::Ordered(_), _) => true,
+        };
+        if safe && processed >= step.max(1) {
+          self.save_rebuild_progress(progress)?;
+          return Ok(false);
+        }
+
+        match target {
+          Target::Ordered(topology) => {
+            topology.put(&op, &balance)?;
+          },
+          Target::Checkpoint(topology) => {
+            if let Some(builder) = checkpoints.as_deref_mut() {
+              for balance in builder.push(&op, &self.conversions) {
+                set_checkpoint(*topology, &balance, closed)?;
+              }
+            }
+          },
+        }
+      }
+
+      processed += 1;
+      progress.operations += 1;
+      progress.last = Some(k.to_vec());
+    }
+
+    Ok(true)
+  }
+
+  /// Delete checkpoints of store in all checkpoint topologies and save progress of their
+  /// rebuild, which is done by `rebuild` of each topology. Call it inside of stage.
+  pub(crate) fn start_store_rebuild(&self, store: Store) -> Result<(), WHError> {
+    for topology in self.checkpoint_topologies.iter() {
+      let name = topology.name();
+      match self.rebuild_progress(name)? {
+        Some(progress) if progress.store != Some(store) => {
+          return Err(WHError::new(&format!("rebuild of {name} is not finished")));
+        },
+        _ => {},
+      }
+
+      let latest = self.checkpoints_till(topology.as_ref())?;
+      self.delete_checkpoints(topology.as_ref(), Some(store))?;
+
+      let progress =
+        Progress { topology: name.to_string(), store: Some(store), latest, ..Default::default() };
+      self.save_rebuild_progress(&progress)?;
+    }
+    Ok(())
+  }
+
+  /// Continue all unfinished rebuilds, see `rebuild`.
+  pub fn resume_rebuilds(
+    &self,
+    step: usize,
+    report: &mut dyn FnMut(&Progress) -> Result<(), WHError>,
+  ) -> Result<Vec<Progress>, WHError> {
+    let names = self
+      .ordered_topologies
+      .iter()
+      .skip(1)
+      .map(|t| t.name())
+      .chain(self.checkpoint_topologies.iter().map(|t| t.name()));
+
+    let mut result = Vec::new();
+    for name in names {
+      if self.rebuild_progress(name)?.is_some() {
+        result.push(self.rebuild(name, step, report)?);
+      }
+    }
+    Ok(result)
+  }
+
+  /// Saved progress of unfinished rebuild.
+  pub fn rebuild_progress(&self, name: &str) -> Result<Option<Progress>, WHError> {
+    match self.db.get_cf(CF_NAME, name.as_bytes())? {
+      Some(bytes) => Ok(Some(ciborium::de::from_reader(&bytes[..])?)),
+      None => Ok(None),
+    }
+  }
+
+  fn save_rebuild_progress(&self, progress: &Progress) -> Result<(), WHError> {
+    let mut bs = Vec::new();
+    ciborium::ser::into_writer(progress, &mut bs)?;
+    self.db.put_cf(CF_NAME, progress.topology.as_bytes(), bs)
+  }
+
+  /// Delete checkpoints of topology, only of `store` if it given, except ones of closed periods.
+  pub(crate) fn delete_checkpoints(
+    &self,
+    topology: &(dyn CheckpointTopology + Sync + Send),
+    store: Option<Store>,
+  ) -> Result<(), WHError> {
+    let closed = self.periods.closed_stores()?;
+    let latest_key = topology.key_latest_checkpoint_date();
+
+    let mut keys = Vec::new();
+    for item in self.db.iterator(topology.name(), IteratorMode::Start)? {
+      let (k, _) = item?;
+      if k[..] == latest_key[..] {
+        continue;
+      }
+      let (date, s, _, _) = topology.key_to_data(k.to_vec())?;
+      if store.is_none_or(|store| store == s) && closed.get(&s).is_none_or(|closed| date > *closed) {
+        keys.push(k.to_vec());
+      }
+    }
+    for key in keys {
+      topology.del_balance(&key)?;
+    }
+    Ok(())
+  }
+
+  /// Date of the latest checkpoint of topology or the latest start of interval after operation
+  /// by granularity of its store if topology have no checkpoints.
+  pub(crate) fn checkpoints_till(
+    &self,
+    topology: &(dyn CheckpointTopology + Sync + Send),
+  ) -> Result<Option<DateTime<Utc>>, WHError> {
+    let date = topology.get_latest_checkpoint_date()?;
+    if date.timestamp() != 0 {
+      return Ok(Some(date));
+    }
+
+    let granularities = self.granularities.all()?;
+
+    let primary = &self.ordered_topologies[0];
+    let mut latest: Option<DateTime<Utc>> = None;
+    let (db, cf) = (primary.db(), primary.cf()?);
+    for item in db.iterator_cf(&cf, IteratorMode::Start) {
+      let (_, value) = item?;
+      let (op, _) = primary.from_bytes(&value)?;
+      let granularity = granularities.get(&op.store).copied().unwrap_or_default();
+      latest = latest.max(Some(granularity.next(op.date)));
+    }
+
+    Ok(latest)
+  }
+
+  /// Checkpoints recomputed by operations of the primary topology.
+  pub(crate) fn recompute_checkpoints(
+    &self,
+    latest: DateTime<Utc>,
+    closed: HashMap<Store, DateTime<Utc>>,
+  ) -> Result<Vec<Balance>, WHError> {
+    let mut builder = CheckpointsBuilder::new(latest, self.granularities.all()?).closed_till(closed);
+    let mut result = Vec::new();
+
+    let primary = &self.ordered_topologies[0];
+    let (db, cf) = (primary.db(), primary.cf()?);
+    for item in db.iterator_cf(&cf, IteratorMode::Start) {
+      let (_, value) = item?;
+      let (op, _) = primary.from_bytes(&value)?;
+      if op.dependant.is_empty() {
+        result.extend(builder.push(&op, &self.conversions));
+      }
+    }
+    result.extend(builder.finish());
+
+    Ok(result)
+  }
+}
+
+/// Write checkpoint, after closed period it is skipped if lookup already give the same balance
+/// by checkpoints kept there.
+pub(crate) fn set_checkpoint(
+  topology: &(dyn CheckpointTopology + Sync + Send),
+  balance: &Balance,
+  closed: &HashMap<Store, DateTime<Utc>>,
+) -> Result<(), WHError> {
+  if closed.contains_key(&balance.store) {
+    let before =
+      topology.balance_before(balance.store, balance.goods, &balance.batch, balance.date)?;
+    if is_unchanged(&before, &balance.number) {
+      return Ok(());
+    }
+  }
+  let key = topology.key(balance.store, balance.goods, balance.batch.clone(), balance.date);
+  topology.set_balance(&key, &balance.number)
+}
+
+impl Progress {
+  pub fn cf_name() -> &'static str {
+    CF_NAME
+  }
+}
@@ -0,0 +1,410 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::balance::BalanceForGoods;
+use crate::batch::Batch;
+use crate::conversions::{Conversions, Factors};
+use crate::db::Db;
+use crate::elements::{Goods, Store, ToJson};
+use crate::error::WHError;
+use crate::negative_stock::is_short;
+use crate::operations::{InternalOperation, OpMutation};
+use crate::qty::Qty;
+use crate::staging::StagedDB;
+use chrono::{DateTime, Utc};
+use json::{object, JsonValue};
+use rocksdb::IteratorMode;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const CF_NAME: &str = "cf_reservations";
+
+/// Quantity of goods held at store for an order till expiry, without moving it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Reservation {
+  pub id: Uuid,
+  pub store: Store,
+  pub goods: Goods,
+  /// `Batch::no()` reserve goods of any batch
+  pub batch: Batch,
+  pub qty: Qty,
+  pub expires: DateTime<Utc>,
+  /// order or document the goods are held for, its issues take them from reservation
+  #[serde(default)]
+  pub document: Option<Uuid>,
+}
+
+impl Reservation {
+  pub fn is_active(&self, date: DateTime<Utc>) -> bool {
+    self.expires > date
+  }
+}
+
+impl ToJson for Reservation {
+  fn to_json(&self) -> JsonValue {
+    object! {
+      id: self.id.to_json(),
+      store: self.store.to_json(),
+      goods: self.goods.to_json(),
+      batch: self.batch.to_json(),
+      qty: self.qty.to_json(),
+      expires: self.expires.to_json(),
+      document: self.document.map(|d| d.to_json()).unwrap_or(JsonValue::Null),
+    }
+  }
+}
+
+/// Reserved quantities per store, goods & batch.
+pub type Reserved = HashMap<Store, HashMap<Goods, HashMap<Batch, Qty>>>;
+
+#[derive(Clone)]
+pub struct Reservations {
+  pub db: Arc<StagedDB>,
+}
+
+impl Reservations {
+  pub fn cf_name() -> &'static str {
+    CF_NAME
+  }
+
+  // | store | goods | id |
+  fn key(&self, reservation: &Reservation) -> Vec<u8> {
+    self
+      .prefix(reservation.store, reservation.goods)
+      .iter()
+      .chain(reservation.id.as_bytes().iter())
+      .copied()
+      .collect()
+  }
+
+  fn prefix(&self, store: Store, goods: Goods) -> Vec<u8> {
+    store.as_bytes().iter().chain(goods.as_bytes().iter()).copied().collect()
+  }
+
+  fn from_bytes(bytes: &[u8]) -> Result<Reservation, WHError> {
+    Ok(ciborium::de::from_reader(bytes)?)
+  }
+
+  /// Create or replace reservation.
+  pub fn put(&self, reservation: &Reservation) -> Result<(), WHError> {
+    let mut bs = Vec::new();
+    ciborium::ser::into_writer(reservation, &mut bs)?;
+    self.db.put_cf(CF_NAME, self.key(reservation), bs)
+  }
+
+  pub fn remove(&self, reservation: &Reservation) -> Result<(), WHError> {
+    self.db.delete_cf(CF_NAME, self.key(reservation))
+  }
+
+  /// Reservations of goods at store, including expired ones.
+  pub fn for_store_goods(&self, store: Store, goods: Goods) -> Result<Vec<Reservation>, WHError> {
+    let mut res = Vec::new();
+    for (_, v) in self.db.prefix(CF_NAME, self.prefix(store, goods))? {
+      res.push(Self::from_bytes(&v)?);
+    }
+    Ok(res)
+  }
+
+  pub fn get(&self, store: Store, goods: Goods, id: Uuid) -> Result<Option<Reservation>, WHError> {
+    Ok(self.for_store_goods(store, goods)?.into_iter().find(|r| r.id == id))
+  }
+
+  /// Quantities of reservations active at date, summed with conversions of its goods.
+  pub fn reserved(
+    &self,
+    date: DateTime<Utc>,
+    conversions: &Conversions,
+  ) -> Result<Reserved, WHError> {
+    let mut res: Reserved = HashMap::new();
+    for item in self.db.iterator(CF_NAME, IteratorMode::Start)? {
+      let (_, v) = item?;
+      let reservation = Self::from_bytes(&v)?;
+      if reservation.is_active(date) {
+        let qty = res
+          .entry(reservation.store)
+          .or_default()
+          .entry(reservation.goods)
+          .or_default()
+          .entry(reservation.batch)
+          .or_default();
+        *qty = qty.add_with(&reservation.qty, &conversions.of(reservation.goods));
+      }
+    }
+    Ok(res)
+  }
+
+  /// Delete reservations expired at date, return how many were deleted.
+  pub fn cleanup(&self, date: DateTime<Utc>) -> Result<usize, WHError> {
+    let mut expired = Vec::new();
+    for item in self.db.iterator(CF_NAME, IteratorMode::Start)? {
+      let (_, v) = item?;
+      let reservation = Self::from_bytes(&v)?;
+      if !reservation.is_active(date) {
+        expired.push(reservation);
+      }
+    }
+
+    self.db.stage(|| {
+      for reservation in expired.iter() {
+        self.remove(reservation)?;
+      }
+      Ok(expired.len())
+    })
+  }
+}
+
+impl Db {
+  /// Balances of goods at store per batch at date, later operations are not included.
+  pub fn on_hand(
+    &self,
+    store: Store,
+    goods: Goods,
+    date: DateTime<Utc>,
+  ) -> Result<HashMap<Batch, BalanceForGoods>, WHError> {
+    let primary = &self.ordered_topologies[0];
+    let prefix: Vec<u8> = store.as_bytes().iter().chain(goods.as_bytes().iter()).copied().collect();
+
+    let mut res = HashMap::new();
+    for (_, v) in self.db.prefix(primary.name(), prefix)? {
+      let (op, balance) = primary.from_bytes(&v)?;
+      // records are ordered by date, so the last one till date keep balance of batch
+      if op.dependant.is_empty() && op.date <= date {
+        res.insert(op.batch, balance);
+      }
+    }
+    Ok(res)
+  }
+
+  /// The lowest balances of goods at store per batch at date and after it, so an issue at date
+  /// can't take goods that later operations need.
+  pub fn on_hand_from(
+    &self,
+    store: Store,
+    goods: Goods,
+    date: DateTime<Utc>,
+  ) -> Result<HashMap<Batch, BalanceForGoods>, WHError> {
+    let primary = &self.ordered_topologies[0];
+    let prefix: Vec<u8> = store.as_bytes().iter().chain(goods.as_bytes().iter()).copied().collect();
+    let factors = self.conversions.of(goods);
+
+    let mut res: HashMap<Batch, BalanceForGoods> = HashMap::new();
+    for (_, v) in self.db.prefix(primary.name(), prefix)? {
+      let (op, balance) = primary.from_bytes(&v)?;
+      if !op.dependant.is_empty() {
+        continue;
+      }
+      // records are ordered by date, balance at date is replaced by the last one till it
+      if op.date <= date {
+        res.insert(op.batch, balance);
+      } else {
+        let lowest = res.entry(op.batch).or_default();
+        if is_short(&balance.qty.sub_with(&lowest.qty, &factors)) {
+          *lowest = balance;
+        }
+      }
+    }
+    Ok(res)
+  }
+
+  /// Record reservation if goods available at store are enough for it.
+  pub fn reserve(&self, reservation: &Reservation) -> Result<(), WHError> {
+    self.db.stage(|| {
+      let mut reservations =
+        self.reservations.for_store_goods(reservation.store, reservation.goods)?;
+      reservations.retain(|r| r.id != reservation.id);
+      reservations.push(reservation.clone());
+
+      self.check_reservations(
+        reservation.store,
+        reservation.goods,
+        Utc::now(),
+        &reservations,
+        &HashMap::new(),
+      )?;
+
+      self.reservations.put(reservation)
+    })
+  }
+
+  /// Reject mutations that leave less goods at store than reserved by active reservations.
+  /// Resulting balance is checked for every one that lower stock: new or increased issue,
+  /// deleted or reduced receive, issue moved to another batch.
+  ///
+  /// Issues of document take goods from its reservations, fulfilled ones are released.
+  pub(crate) fn check_issues(&self, ops: &[OpMutation]) -> Result<(), WHError> {
+    // quantity leaving store, negative for incoming one
+    let outgoing = |op: &Option<(InternalOperation, bool)>| match op {
+      Some((InternalOperation::Issue(qty, ..), _)) => qty.clone(),
+      Some((InternalOperation::Receive(qty, _), _)) => -qty.clone(),
+      _ => Qty::default(),
+    };
+    // goods transferred come to the other store
+    let transferred = |op: &Option<(InternalOperation, bool)>| match op {
+      Some((InternalOperation::Issue(qty, ..), _)) => qty.clone(),
+      _ => Qty::default(),
+    };
+
+    let mut issues: HashMap<(Store, Goods), HashMap<Batch, Qty>> = HashMap::new();
+    let mut lowered: HashMap<(Store, Goods), DateTime<Utc>> = HashMap::new();
+    let mut documents: HashMap<(Store, Goods), HashMap<Uuid, HashMap<Batch, Qty>>> = HashMap::new();
+    let mut add = |store: Store, goods: Goods, batch: &Batch, qty: Qty, date: DateTime<Utc>| {
+      if qty.is_zero() {
+        return;
+      }
+      if !qty.is_negative() {
+        let from = lowered.entry((store, goods)).or_insert(date);
+        *from = (*from).min(date);
+      }
+      let factors = self.conversions.of(goods);
+      let issued = issues.entry((store, goods)).or_default().entry(batch.clone()).or_default();
+      *issued = issued.add_with(&qty, &factors);
+    };
+
+    for op in ops {
+      let factors = self.conversions.of(op.goods);
+      let issued = outgoing(&op.after).sub_with(&outgoing(&op.before), &factors);
+      if let Some(document) = op.document {
+        if !issued.is_zero() && !issued.is_negative() {
+          let fulfilled = documents
+            .entry((op.store, op.goods))
+            .or_default()
+            .entry(document)
+            .or_default()
+            .entry(op.batch.clone())
+            .or_default();
+          *fulfilled = fulfilled.add_with(&issued, &factors);
+        }
+      }
+      add(op.store, op.goods, &op.batch, issued, op.date);
+      if let Some(store) = op.transfer {
+        add(
+          store,
+          op.goods,
+          &op.batch,
+          transferred(&op.before).sub_with(&transferred(&op.after), &factors),
+          op.date,
+        );
+      }
+    }
+
+    for ((store, goods), issued) in issues {
+      let date = match lowered.get(&(store, goods)) {
+        Some(date) => *date,
+        None => continue,
+      };
+      let mut reservations = self.reservations.for_store_goods(store, goods)?;
+      if reservations.is_empty() {
+        continue;
+      }
+
+      let mut changed = Vec::new();
+      if let Some(documents) = documents.get(&(store, goods)) {
+        let factors = self.conversions.of(goods);
+        for (document, fulfilled) in documents {
+          changed.extend(fulfil(&mut reservations, *document, fulfilled, &factors));
+        }
+      }
+
+      self.check_reservations(store, goods, date, &reservations, &issued)?;
+
+      for reservation in changed {
+        if reservation.qty.is_zero() {
+          self.reservations.remove(&reservation)?;
+        } else {
+          self.reservations.put(&reservation)?;
+        }
+      }
+    }
+    Ok(())
+  }
+
+  // issue without batch is checked against goods total only
+  fn check_reservations(
+    &self,
+    store: Store,
+    goods: Goods,
+    date: DateTime<Utc>,
+    reservations: &[Reservation],
+    issued: &HashMap<Batch, Qty>,
+  ) -> Result<(), WHError> {
+    let now = Utc::now();
+    let factors = self.conversions.of(goods);
+    let sum = |qty: Vec<&Qty>| qty.into_iter().fold(Qty::default(), |a, q| a.add_with(q, &factors));
+
+    let mut available = self.on_hand_from(store, goods, date)?;
+    for (batch, qty) in issued {
+      let balance = available.entry(batch.clone()).or_default();
+      balance.qty = balance.qty.sub_with(qty, &factors);
+    }
+    let total = sum(available.values().map(|b| &b.qty).collect());
+
+    let mut reserved: HashMap<Batch, Qty> = HashMap::new();
+    for reservation in reservations.iter().filter(|r| r.is_active(now)) {
+      let qty = reserved.entry(reservation.batch.clone()).or_default();
+      *qty = qty.add_with(&reservation.qty, &factors);
+    }
+    let total_reserved = sum(reserved.values().collect());
+
+    let short = |available: &Qty, reserved: &Qty| is_short(&available.sub_with(reserved, &factors));
+
+    if short(&total, &total_reserved) {
+      return Err(WHError::new(&format!("goods {goods} at store {store} are reserved")));
+    }
+    for (batch, qty) in reserved.iter().filter(|(b, _)| !b.is_empty()) {
+      let available = available.get(batch).map(|b| b.qty.clone()).unwrap_or_default();
+      if short(&available, qty) {
+        return Err(WHError::new(&format!(
+          "batch {} of goods {goods} at store {store} is reserved",
+          batch.id
+        )));
+      }
+    }
+    Ok(())
+  }
+}
+
+// reduce reservations of document by quantities issued for it, first ones of the same batch,
+// then ones of any batch, return reservations that changed
+fn fulfil(
+  reservations: &mut [Reservation],
+  document: Uuid,
+  issued: &HashMap<Batch, Qty>,
+  factors: &Factors,
+) -> Vec<Reservation> {
+  let mut left = issued.clone();
+  let mut any = Qty::default();
+  let mut changed = Vec::new();
+
+  let mut take = |reservation: &mut Reservation, qty: &mut Qty| {
+    if qty.is_zero() || reservation.qty.is_zero() {
+      return;
+    }
+    let rest = reservation.qty.sub_with(qty, factors);
+    if rest.is_zero() || is_short(&rest) {
+      *qty = qty.sub_with(&reservation.qty, factors);
+      reservation.qty = Qty::default();
+    } else {
+      reservation.qty = rest;
+      *qty = Qty::default();
+    }
+    changed.push(reservation.clone());
+  };
+
+  for reservation in reservations.iter_mut().filter(|r| r.document == Some(document)) {
+    if !reservation.batch.is_empty() {
+      if let Some(qty) = left.get_mut(&reservation.batch) {
+        take(reservation, qty);
+      }
+    }
+  }
+  for qty in left.values() {
+    any = any.add_with(qty, factors);
+  }
+  for reservation in reservations.iter_mut().filter(|r| r.document == Some(document)) {
+    if reservation.batch.is_empty() {
+      take(reservation, &mut any);
+    }
+  }
+
+  changed
+}
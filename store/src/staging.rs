@@ -0,0 +1,348 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::iter::Peekable;
+use std::ops::{Deref, Range};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+
+use crate::error::WHError;
+use rocksdb::{
+  BoundColumnFamily, DBIteratorWithThreadMode, Direction, IteratorMode, ReadOptions, WriteBatch, DB,
+};
+
+type Changes = HashMap<String, BTreeMap<Vec<u8>, Option<Vec<u8>>>>;
+type Record = (Box<[u8]>, Box<[u8]>);
+type Change = (Vec<u8>, Option<Vec<u8>>);
+type Callback = Box<dyn FnOnce() + Send>;
+
+struct Staged {
+  thread: ThreadId,
+  changes: Changes,
+  // called once changes are committed
+  committed: Vec<Callback>,
+}
+
+/// RocksDB where writes of one mutation are kept in memory and committed as single `WriteBatch`.
+///
+/// Thread that stage the mutation read its own writes, others see only committed state.
+/// Outside of `stage` writes go directly to database.
+pub struct StagedDB {
+  db: Arc<DB>,
+  writer: Mutex<()>,
+  // thread that hold writer lock between stages
+  exclusive: Mutex<Option<ThreadId>>,
+  staged: Mutex<Option<Staged>>,
+}
+
+impl StagedDB {
+  pub fn new(db: Arc<DB>) -> Self {
+    StagedDB { db, writer: Mutex::new(()), exclusive: Mutex::new(None), staged: Mutex::new(None) }
+  }
+
+  pub fn inner(&self) -> Arc<DB> {
+    self.db.clone()
+  }
+
+  pub fn cf_handle(&self, cf_name: &str) -> Result<Arc<BoundColumnFamily<'_>>, WHError> {
+    if let Some(cf) = self.db.cf_handle(cf_name) {
+      Ok(cf)
+    } else {
+      Err(WHError::new("can't get CF"))
+    }
+  }
+
+  /// Run `f` with staged writes, commit them if `f` succeed and discard otherwise.
+  ///
+  /// Nested `stage` joins the outer one and is committed with it.
+  pub fn stage<T, F>(&self, f: F) -> Result<T, WHError>
+  where
+    F: FnOnce() -> Result<T, WHError>,
+  {
+    if self
+      .lock()?
+      .as_ref()
+      .map(|s| s.thread == thread::current().id())
+      .unwrap_or(false)
+    {
+      return f();
+    }
+
+    // writer lock is already held by `exclusive`
+    let _writer = if self.is_exclusive()? { None } else { Some(self.writer()?) };
+
+    *self.lock()? = Some(Staged {
+      thread: thread::current().id(),
+      changes: HashMap::new(),
+      committed: Vec::new(),
+    });
+
+    let result = f();
+
+    let staged = self.lock()?.take();
+
+    match (result, staged) {
+      (Ok(res), Some(staged)) => {
+        self.write(staged.changes)?;
+        for callback in staged.committed {
+          callback();
+        }
+        Ok(res)
+      },
+      (Ok(_), None) => Err(WHError::new("staged changes lost")),
+      (Err(e), _) => Err(e),
+    }
+  }
+
+  /// Run `f` holding writer lock, so no stage of other threads is committed till it finish.
+  ///
+  /// `stage` inside of `f` is committed at its end as usual, for work too big for one batch.
+  pub fn exclusive<T, F>(&self, f: F) -> Result<T, WHError>
+  where
+    F: FnOnce() -> Result<T, WHError>,
+  {
+    let staging = self
+      .lock()?
+      .as_ref()
+      .map(|s| s.thread == thread::current().id())
+      .unwrap_or(false);
+    if staging || self.is_exclusive()? {
+      return f();
+    }
+
+    let _writer = self.writer()?;
+
+    *self.holder()? = Some(thread::current().id());
+    let result = f();
+    *self.holder()? = None;
+
+    result
+  }
+
+  /// Call `f` once writes of current stage are committed, or at once outside of `stage`.
+  /// It is not called if the stage is discarded, for state kept in memory next to database.
+  pub fn after_commit<F>(&self, f: F) -> Result<(), WHError>
+  where
+    F: FnOnce() + Send + 'static,
+  {
+    {
+      let mut staged = self.lock()?;
+      if let Some(staged) = staged.as_mut() {
+        if staged.thread == thread::current().id() {
+          staged.committed.push(Box::new(f));
+          return Ok(());
+        }
+      }
+    }
+    f();
+    Ok(())
+  }
+
+  fn is_exclusive(&self) -> Result<bool, WHError> {
+    Ok(*self.holder()? == Some(thread::current().id()))
+  }
+
+  fn holder(&self) -> Result<std::sync::MutexGuard<'_, Option<ThreadId>>, WHError> {
+    self.exclusive.lock().map_err(|_| WHError::new("exclusive lock poisoned"))
+  }
+
+  fn writer(&self) -> Result<std::sync::MutexGuard<'_, ()>, WHError> {
+    self.writer.lock().map_err(|_| WHError::new("writer lock poisoned"))
+  }
+
+  fn lock(&self) -> Result<std::sync::MutexGuard<'_, Option<Staged>>, WHError> {
+    self.staged.lock().map_err(|_| WHError::new("staging lock poisoned"))
+  }
+
+  fn write(&self, changes: Changes) -> Result<(), WHError> {
+    let mut batch = WriteBatch::default();
+    for (cf_name, records) in changes {
+      let cf = self.cf_handle(&cf_name)?;
+      for (key, value) in records {
+        match value {
+          Some(value) => batch.put_cf(&cf, key, value),
+          None => batch.delete_cf(&cf, key),
+        }
+      }
+    }
+    self.db.write(batch)?;
+    Ok(())
+  }
+
+  // false if there is no staging for current thread
+  fn change(&self, cf_name: &str, key: Vec<u8>, value: Option<Vec<u8>>) -> Result<bool, WHError> {
+    let mut staged = self.lock()?;
+    match staged.as_mut() {
+      Some(staged) if staged.thread == thread::current().id() => {
+        staged.changes.entry(cf_name.to_string()).or_default().insert(key, value);
+        Ok(true)
+      },
+      _ => Ok(false),
+    }
+  }
+
+  pub fn put_cf<K, V>(&self, cf_name: &str, key: K, value: V) -> Result<(), WHError>
+  where
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]>,
+  {
+    if !self.change(cf_name, key.as_ref().to_vec(), Some(value.as_ref().to_vec()))? {
+      self.db.put_cf(&self.cf_handle(cf_name)?, key, value)?;
+    }
+    Ok(())
+  }
+
+  pub fn delete_cf<K: AsRef<[u8]>>(&self, cf_name: &str, key: K) -> Result<(), WHError> {
+    if !self.change(cf_name, key.as_ref().to_vec(), None)? {
+      self.db.delete_cf(&self.cf_handle(cf_name)?, key)?;
+    }
+    Ok(())
+  }
+
+  pub fn get_cf<K: AsRef<[u8]>>(&self, cf_name: &str, key: K) -> Result<Option<Vec<u8>>, WHError> {
+    {
+      let staged = self.lock()?;
+      if let Some(staged) = staged.as_ref() {
+        if staged.thread == thread::current().id() {
+          if let Some(value) = staged.changes.get(cf_name).and_then(|c| c.get(key.as_ref())) {
+            return Ok(value.clone());
+          }
+        }
+      }
+    }
+    Ok(self.db.get_cf(&self.cf_handle(cf_name)?, key)?)
+  }
+
+  pub fn iterator(&self, cf_name: &str, mode: IteratorMode) -> Result<StagedIterator<'_>, WHError> {
+    self.merged(cf_name, None, mode)
+  }
+
+  /// Like `iterator`, but limited by `range` (end is exclusive).
+  pub fn iterator_range(
+    &self,
+    cf_name: &str,
+    range: Range<Vec<u8>>,
+    mode: IteratorMode,
+  ) -> Result<StagedIterator<'_>, WHError> {
+    self.merged(cf_name, Some(range), mode)
+  }
+
+  /// All records with given prefix.
+  pub fn prefix(&self, cf_name: &str, prefix: Vec<u8>) -> Result<Vec<Record>, WHError> {
+    let mut result = vec![];
+
+    for res in self.iterator(cf_name, IteratorMode::From(&prefix, Direction::Forward))? {
+      let (k, v) = res?;
+
+      if k.len() >= prefix.len() && prefix[..] == k[0..prefix.len()] {
+        result.push((k, v))
+      } else {
+        break;
+      }
+    }
+
+    Ok(result)
+  }
+
+  fn merged(
+    &self,
+    cf_name: &str,
+    range: Option<Range<Vec<u8>>>,
+    mode: IteratorMode,
+  ) -> Result<StagedIterator<'_>, WHError> {
+    let (from, reverse) = match mode {
+      IteratorMode::Start => (None, false),
+      IteratorMode::End => (None, true),
+      IteratorMode::From(key, Direction::Forward) => (Some(key.to_vec()), false),
+      IteratorMode::From(key, Direction::Reverse) => (Some(key.to_vec()), true),
+    };
+
+    let mut staged: Vec<Change> = Vec::new();
+    {
+      let lock = self.lock()?;
+      if let Some(changes) = lock
+        .as_ref()
+        .filter(|s| s.thread == thread::current().id())
+        .and_then(|s| s.changes.get(cf_name))
+      {
+        for (key, value) in changes.iter() {
+          if let Some(range) = range.as_ref() {
+            if key < &range.start || key >= &range.end {
+              continue;
+            }
+          }
+          if let Some(from) = from.as_ref() {
+            if (!reverse && key < from) || (reverse && key > from) {
+              continue;
+            }
+          }
+          staged.push((key.clone(), value.clone()));
+        }
+      }
+    }
+    if reverse {
+      staged.reverse();
+    }
+
+    let cf = self.cf_handle(cf_name)?;
+    let inner = if let Some(range) = range {
+      let mut options = ReadOptions::default();
+      options.set_iterate_range(range);
+      self.db.iterator_cf_opt(&cf, options, mode)
+    } else {
+      self.db.iterator_cf(&cf, mode)
+    };
+
+    Ok(StagedIterator { inner: inner.peekable(), staged: staged.into_iter().peekable(), reverse })
+  }
+}
+
+/// Direct access to committed state, writes through it bypass staging.
+impl Deref for StagedDB {
+  type Target = DB;
+
+  fn deref(&self) -> &DB {
+    &self.db
+  }
+}
+
+/// Committed records merged with staged ones, staged win on same key.
+pub struct StagedIterator<'a> {
+  inner: Peekable<DBIteratorWithThreadMode<'a, DB>>,
+  staged: Peekable<std::vec::IntoIter<Change>>,
+  reverse: bool,
+}
+
+impl<'a> Iterator for StagedIterator<'a> {
+  type Item = Result<Record, rocksdb::Error>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      let order = match (self.inner.peek(), self.staged.peek()) {
+        (None, None) => return None,
+        (Some(Err(_)), _) | (Some(_), None) => return self.inner.next(),
+        (None, Some(_)) => Ordering::Greater,
+        (Some(Ok((k, _))), Some((s, _))) => {
+          let order = k.as_ref().cmp(s.as_slice());
+          if self.reverse {
+            order.reverse()
+          } else {
+            order
+          }
+        },
+      };
+
+      match order {
+        Ordering::Less => return self.inner.next(),
+        Ordering::Equal => {
+          self.inner.next();
+        },
+        Ordering::Greater => {},
+      }
+
+      if let Some((k, Some(v))) = self.staged.next() {
+        return Some(Ok((k.into_boxed_slice(), v.into_boxed_slice())));
+      }
+      // deleted at staging
+    }
+  }
+}
@@ -0,0 +1,246 @@
+use crate::balance::{BalanceDelta, BalanceForGoods, Cost};
+use crate::batch::Batch;
+use crate::conversions::{Conversions, Factors};
+use crate::db::Db;
+use crate::elements::{Goods, Mode, Store, ToJson, WHError};
+use crate::operations::{InternalOperation, OpMutation};
+use crate::qty::Qty;
+use chrono::{DateTime, Utc};
+use json::{object, JsonValue};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const CF_NAME: &str = "cf_stocktake";
+
+/// Physical count of store at date: expected balances snapshot and counted quantities.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StockTake {
+  pub id: Uuid,
+  pub date: DateTime<Utc>,
+  pub store: Store,
+  pub lines: Vec<StockTakeLine>,
+  pub posted: bool,
+  /// id of `warehouse/inventory` document that posted differences
+  pub document: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StockTakeLine {
+  pub goods: Goods,
+  pub batch: Batch,
+  /// balance by warehouse at the date of stock-take
+  pub expected: BalanceForGoods,
+  /// `None` till goods is counted, such lines are not adjusted on posting
+  pub counted: Option<Qty>,
+  /// cost of surplus when there is no expected balance to value it by
+  pub cost: Option<Cost>,
+}
+
+impl StockTakeLine {
+  /// Counted minus expected, cost is valued by the price of expected balance.
+  pub fn difference(&self, factors: &Factors) -> Option<BalanceForGoods> {
+    let counted = self.counted.as_ref()?;
+    let qty = counted.sub_with(&self.expected.qty, factors);
+
+    let cost = if qty.is_zero() {
+      Cost::ZERO
+    } else if self.expected.qty.is_zero() {
+      self.cost.unwrap_or(Cost::ZERO)
+    } else if qty.is_negative() {
+      -qty.abs().cost(&self.expected, factors)
+    } else {
+      qty.cost(&self.expected, factors)
+    };
+
+    Some(BalanceForGoods { qty, cost })
+  }
+
+  /// Inventory operation that make balance equal to counted one at its date, surplus without
+  /// balance is valued by cost of line.
+  fn inventory(&self) -> Option<InternalOperation> {
+    let counted = self.counted.clone()?;
+    let cost = self.cost.unwrap_or(Cost::ZERO);
+    Some(InternalOperation::Inventory(
+      BalanceForGoods { qty: counted, cost },
+      BalanceDelta::default(),
+      Mode::Counted,
+    ))
+  }
+}
+
+impl StockTake {
+  /// Record counted quantity of goods & batch, goods missing at snapshot are added as a new line.
+  pub fn count(
+    &mut self,
+    goods: Goods,
+    batch: Batch,
+    qty: Qty,
+    cost: Option<Cost>,
+  ) -> Result<(), WHError> {
+    if self.posted {
+      return Err(WHError::new("stock-take is already posted"));
+    }
+
+    if let Some(line) = self.lines.iter_mut().find(|l| l.goods == goods && l.batch == batch) {
+      line.counted = Some(qty);
+      line.cost = cost;
+    } else {
+      self.lines.push(StockTakeLine {
+        goods,
+        batch,
+        expected: BalanceForGoods::default(),
+        counted: Some(qty),
+        cost,
+      });
+    }
+    Ok(())
+  }
+
+  /// Operations that bring balances of counted lines to counted quantities, inventory document
+  /// of stock-take records them, surplus of goods without batch is received into the batch of
+  /// stock-take.
+  pub fn adjustments(&self) -> Vec<OpMutation> {
+    self.lines.iter().filter_map(|line| self.adjustment(line)).collect()
+  }
+
+  fn adjustment(&self, line: &StockTakeLine) -> Option<OpMutation> {
+    let op = line.inventory()?;
+    let batch = if line.batch.is_empty() { self.batch() } else { line.batch.clone() };
+
+    // each line is separate operation
+    let id = Uuid::new_v5(&self.id, &line.batch.to_bytes(&line.goods));
+
+    Some(OpMutation::new(id, self.date, self.store, None, line.goods, batch, None, Some(op)))
+  }
+
+  pub fn batch(&self) -> Batch {
+    Batch { id: self.id, date: self.date }
+  }
+}
+
+impl StockTake {
+  /// Lines with differences and totals of them, quantities of goods are converted by its factors.
+  pub fn to_json(&self, conversions: &Conversions) -> JsonValue {
+    let mut lines = JsonValue::new_array();
+    let (mut surplus, mut shortage) = (Cost::ZERO, Cost::ZERO);
+
+    for line in self.lines.iter() {
+      let difference = line.difference(&conversions.of(line.goods));
+      if let Some(difference) = difference.as_ref() {
+        if difference.qty.is_negative() {
+          shortage -= difference.cost;
+        } else {
+          surplus += difference.cost;
+        }
+      }
+
+      lines
+        .push(object! {
+          goods: line.goods.to_json(),
+          batch: line.batch.to_json(),
+          expected: line.expected.to_json(),
+          counted: line.counted.as_ref().map(|q| q.to_json()).unwrap_or(JsonValue::Null),
+          difference: difference.map(|d| d.to_json()).unwrap_or(JsonValue::Null),
+        })
+        .unwrap();
+    }
+
+    object! {
+      id: self.id.to_json(),
+      date: self.date.to_json(),
+      store: self.store.to_json(),
+      posted: self.posted,
+      document: self.document.clone(),
+      lines: lines,
+      surplus: surplus.to_json(),
+      shortage: shortage.to_json(),
+      total: (surplus - shortage).to_json(),
+    }
+  }
+}
+
+impl StockTake {
+  pub fn cf_name() -> &'static str {
+    CF_NAME
+  }
+}
+
+// | length of workspace | workspace | id |
+fn key(wid: &str, id: Uuid) -> Vec<u8> {
+  [wid.len() as u8]
+    .iter()
+    .chain(wid.as_bytes().iter())
+    .chain(id.as_bytes().iter())
+    .copied()
+    .collect()
+}
+
+impl Db {
+  /// Create stock-take of store with snapshot of expected balances at date, store of other
+  /// workspace is rejected.
+  pub fn stocktake_start(
+    &self,
+    wid: &str,
+    id: Uuid,
+    store: Store,
+    date: DateTime<Utc>,
+  ) -> Result<StockTake, WHError> {
+    if self.stocktake(wid, id)?.is_some() {
+      return Err(WHError::new(&format!("stock-take {id} already exist")));
+    }
+    if matches!(self.periods.workspace(store)?, Some(w) if w != wid) {
+      return Err(WHError::new(&format!("store {store} belongs to other workspace")));
+    }
+
+    let mut lines: Vec<StockTakeLine> = self
+      .get_balance_for_all(date)?
+      .remove(&store)
+      .unwrap_or_default()
+      .into_iter()
+      .flat_map(|(goods, batches)| {
+        batches.into_iter().filter(|(_, b)| !b.is_zero()).map(move |(batch, expected)| {
+          StockTakeLine { goods, batch, expected, counted: None, cost: None }
+        })
+      })
+      .collect();
+    lines.sort_by(|a, b| (a.goods, &a.batch).cmp(&(b.goods, &b.batch)));
+
+    let stocktake = StockTake { id, date, store, lines, posted: false, document: None };
+    self.save_stocktake(wid, &stocktake)?;
+
+    Ok(stocktake)
+  }
+
+  pub fn stocktake(&self, wid: &str, id: Uuid) -> Result<Option<StockTake>, WHError> {
+    match self.db.get_cf(CF_NAME, key(wid, id))? {
+      Some(bytes) => Ok(Some(ciborium::de::from_reader(&bytes[..])?)),
+      None => Ok(None),
+    }
+  }
+
+  pub fn save_stocktake(&self, wid: &str, stocktake: &StockTake) -> Result<(), WHError> {
+    let mut bs = Vec::new();
+    ciborium::ser::into_writer(stocktake, &mut bs)?;
+    self.db.put_cf(CF_NAME, key(wid, stocktake.id), bs)
+  }
+
+  /// Mark stock-take posted by inventory document, fails if it is already posted.
+  pub fn stocktake_post(&self, wid: &str, id: Uuid, document: &str) -> Result<StockTake, WHError> {
+    self.db.stage(|| {
+      let mut stocktake = match self.stocktake(wid, id)? {
+        Some(stocktake) => stocktake,
+        None => return Err(WHError::new(&format!("stock-take {id} not found"))),
+      };
+      if stocktake.posted {
+        return Err(WHError::new("stock-take is already posted"));
+      }
+
+      // expected balances stay as at snapshot
+      stocktake.posted = true;
+      stocktake.document = Some(document.to_string());
+      self.save_stocktake(wid, &stocktake)?;
+
+      Ok(stocktake)
+    })
+  }
+}
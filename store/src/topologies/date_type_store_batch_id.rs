@@ -1,25 +1,26 @@
 use crate::{
   balance::BalanceForGoods,
   db::Db,
-  elements::{first_day_current_month, Report, Store, UUID_MAX, UUID_NIL},
+  elements::{Report, Store, UUID_MAX, UUID_NIL},
   error::WHError,
 };
 
 use crate::ordered_topology::OrderedTopology;
+use crate::staging::StagedDB;
 
 use crate::aggregations::{aggregations_for_store_goods_batch, aggregations_store_goods};
 use crate::batch::Batch;
 use crate::elements::Goods;
 use crate::operations::Op;
 use chrono::{DateTime, Utc};
-use rocksdb::{BoundColumnFamily, ColumnFamilyDescriptor, IteratorMode, Options, ReadOptions, DB};
+use rocksdb::{BoundColumnFamily, ColumnFamilyDescriptor, IteratorMode, Options, DB};
 use std::convert::TryFrom;
 use std::sync::Arc;
 use uuid::Uuid;
 
 const CF_NAME: &str = "cf_date_type_store_batch_id";
 pub struct DateTypeStoreBatchId {
-  pub db: Arc<DB>,
+  pub db: Arc<StagedDB>,
 }
 
 impl DateTypeStoreBatchId {
@@ -39,23 +40,22 @@ impl OrderedTopology for DateTypeStoreBatchId {
     }
     debug_assert!(!op.op.is_zero(), "{} | {:#?} | {:#?}", op.batch.is_empty(), op, balance);
 
-    let cf = self.cf()?;
     let key = self.key(op);
     // log::debug!("put {key:?}");
     // log::debug!("{op:?}");
 
-    let before = match self.db.get_cf(&cf, &key)? {
+    let before = match self.db.get_cf(CF_NAME, &key)? {
       None => None,
       Some(bs) => Some(self.from_bytes(&bs)?),
     };
 
-    self.db.put_cf(&self.cf()?, key, self.to_bytes(op, balance)?)?;
+    self.db.put_cf(CF_NAME, key, self.to_bytes(op, balance)?)?;
 
     Ok(before)
   }
 
   fn get(&self, op: &Op) -> Result<Option<(Op, BalanceForGoods)>, WHError> {
-    if let Some(bytes) = self.db.get_cf(&self.cf()?, self.key(op))? {
+    if let Some(bytes) = self.db.get_cf(CF_NAME, self.key(op))? {
       Ok(Some(self.from_bytes(&bytes)?))
     } else {
       Ok(None)
@@ -66,7 +66,7 @@ impl OrderedTopology for DateTypeStoreBatchId {
     let key = self.key(op);
     // log::debug!("del {key:?}");
     // log::debug!("{op:?}");
-    Ok(self.db.delete_cf(&self.cf()?, key)?)
+    self.db.delete_cf(CF_NAME, key)
   }
 
   fn balance_before(&self, _op: &Op) -> Result<BalanceForGoods, WHError> {
@@ -125,9 +125,6 @@ impl OrderedTopology for DateTypeStoreBatchId {
       .copied()
       .collect();
 
-    let mut options = ReadOptions::default();
-    options.set_iterate_range(from..till);
-
     // store
     let expected: Vec<u8> = store.as_bytes().to_vec();
 
@@ -135,7 +132,7 @@ impl OrderedTopology for DateTypeStoreBatchId {
 
     let mut res = Vec::new();
 
-    for item in self.db.iterator_cf_opt(&self.cf()?, options, IteratorMode::Start) {
+    for item in self.db.iterator_range(CF_NAME, from..till, IteratorMode::Start)? {
       let (k, value) = item?;
 
       // log::debug!("k__ {k:?}");
@@ -185,12 +182,9 @@ impl OrderedTopology for DateTypeStoreBatchId {
       .copied()
       .collect();
 
-    let mut options = ReadOptions::default();
-    options.set_iterate_range(from..till);
-
     let mut res = Vec::new();
 
-    for item in self.db.iterator_cf_opt(&self.cf()?, options, IteratorMode::Start) {
+    for item in self.db.iterator_range(CF_NAME, from..till, IteratorMode::Start)? {
       let (_, value) = item?;
 
       let (op, _) = self.from_bytes(&value)?;
@@ -234,15 +228,12 @@ impl OrderedTopology for DateTypeStoreBatchId {
       .copied()
       .collect();
 
-    let mut options = ReadOptions::default();
-    options.set_iterate_range(from..till);
-
     let expected_store: Vec<u8> = store.as_bytes().to_vec();
     let expected_goods: Vec<u8> = goods.as_bytes().to_vec();
 
     let mut res = Vec::new();
 
-    for item in self.db.iterator_cf_opt(&self.cf()?, options, IteratorMode::Start) {
+    for item in self.db.iterator_range(CF_NAME, from..till, IteratorMode::Start)? {
       let (k, value) = item?;
 
       if k[9..25] != expected_store || k[25..41] != expected_goods {
@@ -260,7 +251,7 @@ impl OrderedTopology for DateTypeStoreBatchId {
         let (store, batch, op_order) = dependant.tuple();
 
         if let Some(bs) = self.db.get_cf(
-          &self.cf()?,
+          CF_NAME,
           self.key_build(
             store,
             op.goods,
@@ -319,12 +310,9 @@ impl OrderedTopology for DateTypeStoreBatchId {
       .copied()
       .collect();
 
-    let mut options = ReadOptions::default();
-    options.set_iterate_range(from..till);
-
     let mut res = Vec::new();
 
-    for item in self.db.iterator_cf_opt(&self.cf()?, options, IteratorMode::Start) {
+    for item in self.db.iterator_range(CF_NAME, from..till, IteratorMode::Start)? {
       let (k, value) = item?;
 
       if byte_goods.contains(&k[25..41].to_vec()) {
@@ -345,9 +333,9 @@ impl OrderedTopology for DateTypeStoreBatchId {
   ) -> Result<Report, WHError> {
     let balances = db.checkpoints_for_store_before_date(store, from_date)?;
 
-    let ops = self.ops_for_store(store, first_day_current_month(from_date), till_date)?;
+    let ops = self.ops_for_store(store, db.checkpoint_date(store, from_date)?, till_date)?;
 
-    let items = aggregations_store_goods(balances, ops, from_date);
+    let items = aggregations_store_goods(balances, ops, from_date, &db.conversions);
 
     Ok(Report { from_date, till_date, items })
   }
@@ -378,15 +366,15 @@ impl OrderedTopology for DateTypeStoreBatchId {
       .collect()
   }
 
+  fn name(&self) -> &'static str {
+    CF_NAME
+  }
+
   fn cf(&self) -> Result<Arc<BoundColumnFamily>, WHError> {
-    if let Some(cf) = self.db.cf_handle(DateTypeStoreBatchId::cf_name()) {
-      Ok(cf)
-    } else {
-      Err(WHError::new("can't get CF"))
-    }
+    self.db.cf_handle(DateTypeStoreBatchId::cf_name())
   }
 
   fn db(&self) -> Arc<DB> {
-    self.db.clone()
+    self.db.inner()
   }
 }
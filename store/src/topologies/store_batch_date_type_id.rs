@@ -5,18 +5,17 @@ use crate::elements::Goods;
 use crate::elements::{UUID_MAX, UUID_NIL};
 use crate::operations::Op;
 use crate::ordered_topology::OrderedTopology;
+use crate::staging::StagedDB;
 use chrono::{DateTime, Utc};
 
-use rocksdb::{
-  BoundColumnFamily, ColumnFamilyDescriptor, Direction, IteratorMode, Options, ReadOptions, DB,
-};
+use rocksdb::{BoundColumnFamily, ColumnFamilyDescriptor, Direction, IteratorMode, Options, DB};
 use std::sync::Arc;
 use uuid::Uuid;
 
 const CF_NAME: &str = "cf_store_batch_date_type_id";
 
 pub struct StoreBatchDateTypeId {
-  pub db: Arc<DB>,
+  pub db: Arc<StagedDB>,
 }
 
 impl StoreBatchDateTypeId {
@@ -36,23 +35,22 @@ impl OrderedTopology for StoreBatchDateTypeId {
     }
     debug_assert!(!op.op.is_zero(), "{} | {:#?} | {:#?}", op.batch.is_empty(), op, balance);
 
-    let cf = self.cf()?;
     let key = self.key(op);
     // log::debug!("put {key:?}");
     // log::debug!("{op:?}");
 
-    let result = match self.db.get_cf(&cf, &key)? {
+    let result = match self.db.get_cf(CF_NAME, &key)? {
       None => None,
       Some(bs) => Some(self.from_bytes(&bs)?),
     };
 
-    self.db.put_cf(&cf, key, self.to_bytes(op, balance)?)?;
+    self.db.put_cf(CF_NAME, key, self.to_bytes(op, balance)?)?;
 
     Ok(result)
   }
 
   fn get(&self, op: &Op) -> Result<Option<(Op, BalanceForGoods)>, WHError> {
-    if let Some(bytes) = self.db.get_cf(&self.cf()?, self.key(op))? {
+    if let Some(bytes) = self.db.get_cf(CF_NAME, self.key(op))? {
       Ok(Some(self.from_bytes(&bytes)?))
     } else {
       Ok(None)
@@ -63,7 +61,7 @@ impl OrderedTopology for StoreBatchDateTypeId {
     let key = self.key(op);
     // log::debug!("del {key:?}");
     // log::debug!("{op:?}");
-    Ok(self.db.delete_cf(&self.cf()?, key)?)
+    self.db.delete_cf(CF_NAME, key)
   }
 
   fn balance_before(&self, op: &Op) -> Result<BalanceForGoods, WHError> {
@@ -73,7 +71,7 @@ impl OrderedTopology for StoreBatchDateTypeId {
 
     let iter = self
       .db
-      .iterator_cf(&self.cf()?, IteratorMode::From(&key, rocksdb::Direction::Reverse));
+      .iterator(CF_NAME, IteratorMode::From(&key, rocksdb::Direction::Reverse))?;
 
     for bytes in iter {
       let (k, v) = bytes?;
@@ -104,7 +102,7 @@ impl OrderedTopology for StoreBatchDateTypeId {
 
     let iter = self
       .db
-      .iterator_cf(&self.cf()?, IteratorMode::From(&key, rocksdb::Direction::Reverse));
+      .iterator(CF_NAME, IteratorMode::From(&key, rocksdb::Direction::Reverse))?;
 
     for bytes in iter {
       let (_k, v) = bytes?;
@@ -141,14 +139,12 @@ impl OrderedTopology for StoreBatchDateTypeId {
     //   println!("{b:#010b}");
     // }
 
-    let mut options = ReadOptions::default();
-    options.set_iterate_range(from.clone()..till);
-
     // TODO change iterator with range from..till?
-    let iter =
-      self
-        .db
-        .iterator_cf_opt(&self.cf()?, options, IteratorMode::From(&from, Direction::Forward));
+    let iter = self.db.iterator_range(
+      CF_NAME,
+      from.clone()..till,
+      IteratorMode::From(&from, Direction::Forward),
+    )?;
 
     for bytes in iter {
       if let Ok((k, v)) = bytes {
@@ -191,14 +187,12 @@ impl OrderedTopology for StoreBatchDateTypeId {
     //   println!("{b:#010b}");
     // }
 
-    let mut options = ReadOptions::default();
-    options.set_iterate_range(key.clone()..till);
-
     // TODO change iterator with range from..till?
-    let iter =
-      self
-        .db
-        .iterator_cf_opt(&self.cf()?, options, IteratorMode::From(&key, Direction::Forward));
+    let iter = self.db.iterator_range(
+      CF_NAME,
+      key.clone()..till,
+      IteratorMode::From(&key, Direction::Forward),
+    )?;
 
     for bytes in iter {
       if let Ok((k, v)) = bytes {
@@ -232,6 +226,48 @@ impl OrderedTopology for StoreBatchDateTypeId {
     ColumnFamilyDescriptor::new(StoreBatchDateTypeId::cf_name(), opts)
   }
 
+  fn ops_for_goods_batch(
+    &self,
+    goods: Goods,
+    batch: &Batch,
+    from_date: DateTime<Utc>,
+    till_date: DateTime<Utc>,
+  ) -> Result<Vec<Op>, WHError> {
+    let mut res = Vec::new();
+
+    // seek to next store and scan batch operations at it
+    let mut store = UUID_NIL;
+    loop {
+      let start = store.as_bytes().to_vec();
+      store = match self
+        .db
+        .iterator(CF_NAME, IteratorMode::From(&start, Direction::Forward))?
+        .next()
+      {
+        Some(item) => Uuid::from_slice(&item?.0[0..16])?,
+        None => break,
+      };
+
+      let from =
+        self.key_build(store, goods, batch.clone(), from_date.timestamp(), u8::MIN, UUID_NIL, false);
+      let till =
+        self.key_build(store, goods, batch.clone(), till_date.timestamp(), u8::MAX, UUID_MAX, true);
+
+      for item in self.db.iterator_range(CF_NAME, from..till, IteratorMode::Start)? {
+        let (_k, value) = item?;
+        let (op, _) = self.from_bytes(&value)?;
+        res.push(op);
+      }
+
+      store = match store.as_u128().checked_add(1) {
+        Some(next) => Uuid::from_u128(next),
+        None => break,
+      };
+    }
+
+    Ok(res)
+  }
+
   fn ops_for_store_goods_batch(
     &self,
     store: Store,
@@ -247,12 +283,9 @@ impl OrderedTopology for StoreBatchDateTypeId {
     let till: Vec<u8> =
       self.key_build(store, goods, batch.clone(), till_date.timestamp(), u8::MAX, UUID_MAX, true);
 
-    let mut options = ReadOptions::default();
-    options.set_iterate_range(from..till);
-
     let mut res = Vec::new();
 
-    for item in self.db.iterator_cf_opt(&self.cf()?, options, IteratorMode::Start) {
+    for item in self.db.iterator_range(CF_NAME, from..till, IteratorMode::Start)? {
       let (_k, value) = item?;
 
       let (op, _) = self.from_bytes(&value)?;
@@ -310,15 +343,15 @@ impl OrderedTopology for StoreBatchDateTypeId {
       .collect()
   }
 
+  fn name(&self) -> &'static str {
+    CF_NAME
+  }
+
   fn cf(&self) -> Result<Arc<BoundColumnFamily>, WHError> {
-    if let Some(cf) = self.db.cf_handle(StoreBatchDateTypeId::cf_name()) {
-      Ok(cf)
-    } else {
-      Err(WHError::new("can't get CF"))
-    }
+    self.db.cf_handle(StoreBatchDateTypeId::cf_name())
   }
 
   fn db(&self) -> Arc<DB> {
-    self.db.clone()
+    self.db.inner()
   }
 }
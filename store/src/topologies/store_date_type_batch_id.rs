@@ -1,7 +1,7 @@
 use crate::{
   balance::BalanceForGoods,
   db::Db,
-  elements::{first_day_current_month, Report, Store},
+  elements::{Report, Store},
   error::WHError,
 };
 
@@ -11,16 +11,17 @@ use crate::elements::Goods;
 use crate::elements::{UUID_MAX, UUID_NIL};
 use crate::operations::Op;
 use crate::ordered_topology::OrderedTopology;
+use crate::staging::StagedDB;
 use chrono::{DateTime, Utc};
 
-use rocksdb::{BoundColumnFamily, ColumnFamilyDescriptor, IteratorMode, Options, ReadOptions, DB};
+use rocksdb::{BoundColumnFamily, ColumnFamilyDescriptor, IteratorMode, Options, DB};
 use std::sync::Arc;
 use uuid::Uuid;
 
 const CF_NAME: &str = "cf_store_date_type_batch_id";
 
 pub struct StoreDateTypeBatchId {
-  pub db: Arc<DB>,
+  pub db: Arc<StagedDB>,
 }
 
 impl StoreDateTypeBatchId {
@@ -40,23 +41,22 @@ impl OrderedTopology for StoreDateTypeBatchId {
     }
     debug_assert!(!op.op.is_zero(), "{} | {:#?} | {:#?}", op.batch.is_empty(), op, balance);
 
-    let cf = self.cf()?;
     let key = self.key(op);
     // log::debug!("put {key:?}");
     // log::debug!("{op:?}");
 
-    let result = match self.db.get_cf(&cf, &key)? {
+    let result = match self.db.get_cf(CF_NAME, &key)? {
       None => None,
       Some(bs) => Some(self.from_bytes(&bs)?),
     };
 
-    self.db.put_cf(&cf, key, self.to_bytes(op, balance)?)?;
+    self.db.put_cf(CF_NAME, key, self.to_bytes(op, balance)?)?;
 
     Ok(result)
   }
 
   fn get(&self, op: &Op) -> Result<Option<(Op, BalanceForGoods)>, WHError> {
-    if let Some(bytes) = self.db.get_cf(&self.cf()?, self.key(op))? {
+    if let Some(bytes) = self.db.get_cf(CF_NAME, self.key(op))? {
       Ok(Some(self.from_bytes(&bytes)?))
     } else {
       Ok(None)
@@ -67,7 +67,7 @@ impl OrderedTopology for StoreDateTypeBatchId {
     let key = self.key(op);
     // log::debug!("del {key:?}");
     // log::debug!("{op:?}");
-    Ok(self.db.delete_cf(&self.cf()?, key)?)
+    self.db.delete_cf(CF_NAME, key)
   }
 
   fn balance_before(&self, _op: &Op) -> Result<BalanceForGoods, WHError> {
@@ -118,9 +118,6 @@ impl OrderedTopology for StoreDateTypeBatchId {
       .copied()
       .collect();
 
-    let mut options = ReadOptions::default();
-    options.set_iterate_range(from..till);
-
     // store
     let expected: Vec<u8> = storage.as_bytes().to_vec();
 
@@ -128,7 +125,7 @@ impl OrderedTopology for StoreDateTypeBatchId {
 
     let mut res = Vec::new();
 
-    for item in self.db.iterator_cf_opt(&self.cf()?, options, IteratorMode::Start) {
+    for item in self.db.iterator_range(CF_NAME, from..till, IteratorMode::Start)? {
       let (k, value) = item?;
 
       // log::debug!("k__ {k:?}");
@@ -184,14 +181,11 @@ impl OrderedTopology for StoreDateTypeBatchId {
       .copied()
       .collect();
 
-    let mut options = ReadOptions::default();
-    options.set_iterate_range(from..till);
-
     let expected_goods: Vec<u8> = goods.as_bytes().to_vec();
 
     let mut res = Vec::new();
 
-    for item in self.db.iterator_cf_opt(&self.cf()?, options, IteratorMode::Start) {
+    for item in self.db.iterator_range(CF_NAME, from..till, IteratorMode::Start)? {
       let (k, value) = item?;
 
       if k[25..41] != expected_goods {
@@ -214,8 +208,7 @@ impl OrderedTopology for StoreDateTypeBatchId {
   ) -> Result<Vec<Op>, WHError> {
     // let goods: Vec<[u8; 16]> = goods.into_iter().as_slice().iter().map(|b| *b).collect();
 
-    let mut byte_goods: Vec<Vec<u8>> = Vec::new();
-    let _ = goods.iter().map(|g: &Goods| byte_goods.push(g.as_bytes().to_vec()));
+    let byte_goods: Vec<Vec<u8>> = goods.iter().map(|g: &Goods| g.as_bytes().to_vec()).collect();
 
     let ts_from = u64::try_from(from_date.timestamp()).unwrap_or_default();
     let from: Vec<u8> = UUID_NIL
@@ -243,14 +236,17 @@ impl OrderedTopology for StoreDateTypeBatchId {
       .copied()
       .collect();
 
-    let mut options = ReadOptions::default();
-    options.set_iterate_range(from..till);
-
     let mut res = Vec::new();
 
-    for item in self.db.iterator_cf_opt(&self.cf()?, options, IteratorMode::Start) {
+    for item in self.db.iterator_range(CF_NAME, from..till, IteratorMode::Start)? {
       let (k, value) = item?;
 
+      // range cover dates of other stores too
+      let ts = u64::from_be_bytes(k[16..24].try_into().unwrap_or_default());
+      if ts < ts_from || ts > ts_till {
+        continue;
+      }
+
       if byte_goods.contains(&k[25..41].to_vec()) {
         let (op, _) = self.from_bytes(&value)?;
         res.push(op);
@@ -270,9 +266,9 @@ impl OrderedTopology for StoreDateTypeBatchId {
     // log::debug!("STORE_DATE_TYPE_BATCH.get_report");
     let balances = db.checkpoints_for_store_before_date(store, from_date)?;
 
-    let ops = self.ops_for_store(store, first_day_current_month(from_date), till_date)?;
+    let ops = self.ops_for_store(store, db.checkpoint_date(store, from_date)?, till_date)?;
 
-    let items = aggregations_store_goods(balances, ops, from_date);
+    let items = aggregations_store_goods(balances, ops, from_date, &db.conversions);
 
     Ok(Report { from_date, till_date, items })
     // Err(WHError::new("test"))
@@ -304,15 +300,15 @@ impl OrderedTopology for StoreDateTypeBatchId {
       .collect()
   }
 
+  fn name(&self) -> &'static str {
+    CF_NAME
+  }
+
   fn cf(&self) -> Result<Arc<BoundColumnFamily>, WHError> {
-    if let Some(cf) = self.db.cf_handle(StoreDateTypeBatchId::cf_name()) {
-      Ok(cf)
-    } else {
-      Err(WHError::new("can't get CF"))
-    }
+    self.db.cf_handle(StoreDateTypeBatchId::cf_name())
   }
 
   fn db(&self) -> Arc<DB> {
-    self.db.clone()
+    self.db.inner()
   }
 }
@@ -5,18 +5,18 @@ use crate::elements::Goods;
 use crate::elements::UUID_NIL;
 use crate::operations::Op;
 use crate::ordered_topology::OrderedTopology;
+use crate::staging::StagedDB;
 use chrono::{DateTime, Utc};
 
-use db::RangeIterator;
 use log::debug;
-use rocksdb::{BoundColumnFamily, ColumnFamilyDescriptor, Options, DB};
+use rocksdb::{BoundColumnFamily, ColumnFamilyDescriptor, IteratorMode, Options, DB};
 use std::sync::Arc;
 use uuid::Uuid;
 
 const CF_NAME: &str = "cf_store_goods_date_type_id_batch";
 
 pub struct StoreGoodsDateTypeIdBatch {
-  pub db: Arc<DB>,
+  pub db: Arc<StagedDB>,
 }
 
 impl StoreGoodsDateTypeIdBatch {
@@ -36,23 +36,22 @@ impl OrderedTopology for StoreGoodsDateTypeIdBatch {
     }
     debug_assert!(!op.op.is_zero(), "{} | {:#?} | {:#?}", op.batch.is_empty(), op, balance);
 
-    let cf = self.cf()?;
     let key = self.key(op);
     // log::debug!("put {key:?}");
     log::debug!("put put put {op:#?}\n > {balance:?}");
 
-    let before = match self.db.get_cf(&cf, &key)? {
+    let before = match self.db.get_cf(CF_NAME, &key)? {
       None => None,
       Some(bs) => Some(self.from_bytes(&bs)?),
     };
 
-    self.db.put_cf(&cf, key, self.to_bytes(op, balance)?)?;
+    self.db.put_cf(CF_NAME, key, self.to_bytes(op, balance)?)?;
 
     Ok(before)
   }
 
   fn get(&self, op: &Op) -> Result<Option<(Op, BalanceForGoods)>, WHError> {
-    if let Some(bytes) = self.db.get_cf(&self.cf()?, self.key(op))? {
+    if let Some(bytes) = self.db.get_cf(CF_NAME, self.key(op))? {
       Ok(Some(self.from_bytes(&bytes)?))
     } else {
       Ok(None)
@@ -63,7 +62,7 @@ impl OrderedTopology for StoreGoodsDateTypeIdBatch {
     let key = self.key(op);
     // log::debug!("del {key:?}");
     log::debug!("del del del {op:?}");
-    Ok(self.db.delete_cf(&self.cf()?, key)?)
+    self.db.delete_cf(CF_NAME, key)
   }
 
   fn balance_before(&self, _op: &Op) -> Result<BalanceForGoods, WHError> {
@@ -116,7 +115,8 @@ impl OrderedTopology for StoreGoodsDateTypeIdBatch {
 
     let mut res = Vec::new();
 
-    for (_k, value) in (bytes_from.clone()..bytes_till.clone()).lookup(&self.db, &self.cf()?)? {
+    for item in self.db.iterator_range(CF_NAME, bytes_from..bytes_till, IteratorMode::Start)? {
+      let (_k, value) = item?;
       let (op, b) = self.from_bytes(&value)?;
 
       debug!("loaded_op {op:#?}\n > {b:?}");
@@ -174,15 +174,15 @@ impl OrderedTopology for StoreGoodsDateTypeIdBatch {
       .collect()
   }
 
+  fn name(&self) -> &'static str {
+    CF_NAME
+  }
+
   fn cf(&self) -> Result<Arc<BoundColumnFamily>, WHError> {
-    if let Some(cf) = self.db.cf_handle(StoreGoodsDateTypeIdBatch::cf_name()) {
-      Ok(cf)
-    } else {
-      Err(WHError::new("can't get CF"))
-    }
+    self.db.cf_handle(StoreGoodsDateTypeIdBatch::cf_name())
   }
 
   fn db(&self) -> Arc<DB> {
-    self.db.clone()
+    self.db.inner()
   }
 }
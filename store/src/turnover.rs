@@ -0,0 +1,136 @@
+use crate::balance::{BalanceDelta, BalanceForGoods};
+use crate::batch::Batch;
+use crate::checkpoints::granularity::Granularity;
+use crate::db::Db;
+use crate::elements::{time_to_naive_string, Goods, Store, ToJson};
+use crate::error::WHError;
+use crate::grouping::{Dimension, Grouping, Key, Movement};
+use crate::operations::Op;
+use chrono::{DateTime, Utc};
+use json::{object, JsonValue};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Receive, issue and balances of goods in one interval of turnover series.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Turnover {
+  pub date: DateTime<Utc>,
+  pub open_balance: BalanceForGoods,
+  pub receive: BalanceDelta,
+  pub issue: BalanceDelta,
+  pub close_balance: BalanceForGoods,
+}
+
+impl ToJson for Turnover {
+  fn to_json(&self) -> JsonValue {
+    object! {
+      date: time_to_naive_string(self.date),
+      open_balance: self.open_balance.to_json(),
+      receive: self.receive.to_json(),
+      issue: self.issue.reverse().to_json(),
+      close_balance: self.close_balance.to_json(),
+    }
+  }
+}
+
+impl Db {
+  /// Turnover of goods at store, or at all stores if none given, for every interval of
+  /// granularity between dates. Balance comes from the nearest checkpoint before `from_date`,
+  /// so only operations after it are read.
+  pub fn turnover(
+    &self,
+    goods: Goods,
+    store: Option<Store>,
+    granularity: Granularity,
+    from_date: DateTime<Utc>,
+    till_date: DateTime<Utc>,
+  ) -> Result<Vec<Turnover>, WHError> {
+    if granularity == Granularity::Adaptive {
+      return Err(WHError::new("turnover require daily, weekly, monthly or quarterly intervals"));
+    }
+
+    let (date, balance) = match store {
+      Some(store) => {
+        let factors = self.conversions.of(goods);
+        let mut balance = BalanceForGoods::default();
+        for checkpoint in self.get_checkpoints_for_goods(store, goods, from_date)? {
+          balance = balance.add_balance(&checkpoint.number, &factors);
+        }
+        (self.checkpoint_date(store, from_date)?, balance)
+      },
+      None => {
+        let (date, mut balances) = self.checkpoints_for_many_goods(from_date, &vec![goods])?;
+        (date, balances.remove(&goods).unwrap_or_default())
+      },
+    };
+
+    let dimensions = vec![Dimension::Date(granularity)];
+    let mut grouping = Grouping::new(dimensions, from_date, till_date, &(), &self.conversions);
+    grouping.balance(store.unwrap_or_default(), goods, &Batch::no(), &balance);
+    for op in self.goods_ops(store, goods, date, till_date)? {
+      if op.goods == goods {
+        grouping.operation(&op);
+      }
+    }
+
+    let mut intervals: HashMap<Key, Movement> =
+      grouping.build().children.into_iter().map(|g| (g.key, g.movement)).collect();
+
+    // intervals without balance and operations are part of series too
+    let mut series = Vec::new();
+    let mut date = granularity.current(from_date);
+    while date <= till_date {
+      let movement = intervals.remove(&Key::Date(date)).unwrap_or_default();
+      series.push(Turnover {
+        date,
+        open_balance: movement.open_balance,
+        receive: movement.receive,
+        issue: movement.issue,
+        close_balance: movement.close_balance,
+      });
+      date = granularity.next(date);
+    }
+
+    Ok(series)
+  }
+
+  fn checkpoints_for_many_goods(
+    &self,
+    date: DateTime<Utc>,
+    goods: &Vec<Goods>,
+  ) -> Result<(DateTime<Utc>, HashMap<Uuid, BalanceForGoods>), WHError> {
+    for checkpoint_topology in self.checkpoint_topologies.iter() {
+      match checkpoint_topology.get_checkpoints_for_many_goods(date, goods) {
+        Ok(result) => return Ok(result),
+        Err(e) => {
+          if e.message() == *"Not supported" {
+            continue;
+          } else {
+            return Err(e);
+          }
+        },
+      }
+    }
+    Err(WHError::new("can't get checkpoint before date"))
+  }
+
+  fn goods_ops(
+    &self,
+    store: Option<Store>,
+    goods: Goods,
+    from_date: DateTime<Utc>,
+    till_date: DateTime<Utc>,
+  ) -> Result<Vec<Op>, WHError> {
+    for ordered_topology in self.ordered_topologies.iter() {
+      let ops = match store {
+        Some(store) => ordered_topology.ops_for_store_goods(store, goods, from_date, till_date),
+        None => ordered_topology.ops_for_goods(&vec![goods], from_date, till_date),
+      };
+      if let Ok(ops) = ops {
+        return Ok(ops);
+      }
+    }
+
+    Err(WHError::new("fn ops_for_goods not implemented"))
+  }
+}
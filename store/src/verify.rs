@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+
+use crate::balance::{Balance, BalanceForGoods};
+use crate::checkpoints::check_store_batch_date::is_unchanged;
+use crate::db::Db;
+use crate::elements::{ToJson, WHError};
+use crate::operations::Op;
+use json::{object, JsonValue};
+use rocksdb::IteratorMode;
+
+/// Disagreement of topology with the primary one or of checkpoint with operations.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Discrepancy {
+  /// operation of primary topology is absent
+  Missing { topology: &'static str, op: Op, balance: BalanceForGoods },
+  /// operation is absent at primary topology
+  Unexpected { topology: &'static str, op: Op, balance: BalanceForGoods },
+  /// operation or balance after it differ from primary topology
+  Mismatch {
+    topology: &'static str,
+    op: Op,
+    balance: BalanceForGoods,
+    found: Box<(Op, BalanceForGoods)>,
+  },
+  /// checkpoint differ from balance recomputed from operations, zero if absent
+  Checkpoint { topology: &'static str, expected: Balance, found: BalanceForGoods },
+}
+
+impl Discrepancy {
+  pub fn topology(&self) -> &'static str {
+    match self {
+      Discrepancy::Missing { topology, .. } => topology,
+      Discrepancy::Unexpected { topology, .. } => topology,
+      Discrepancy::Mismatch { topology, .. } => topology,
+      Discrepancy::Checkpoint { topology, .. } => topology,
+    }
+  }
+}
+
+fn op_to_json(op: &Op, balance: &BalanceForGoods) -> JsonValue {
+  let mut data = op.to_json();
+  data["batch"] = op.batch.to_json();
+  data["balance"] = balance.to_json();
+  data
+}
+
+impl ToJson for Discrepancy {
+  fn to_json(&self) -> JsonValue {
+    match self {
+      Discrepancy::Missing { topology, op, balance } => object! {
+        kind: "missing",
+        topology: *topology,
+        expected: op_to_json(op, balance),
+      },
+      Discrepancy::Unexpected { topology, op, balance } => object! {
+        kind: "unexpected",
+        topology: *topology,
+        found: op_to_json(op, balance),
+      },
+      Discrepancy::Mismatch { topology, op, balance, found } => object! {
+        kind: "mismatch",
+        topology: *topology,
+        expected: op_to_json(op, balance),
+        found: op_to_json(&found.0, &found.1),
+      },
+      Discrepancy::Checkpoint { topology, expected, found } => object! {
+        kind: "checkpoint",
+        topology: *topology,
+        date: expected.date.to_json(),
+        store: expected.store.to_json(),
+        goods: expected.goods.to_json(),
+        batch: expected.batch.to_json(),
+        expected: expected.number.to_json(),
+        found: found.to_json(),
+      },
+    }
+  }
+}
+
+/// Result of checking topologies, see `Db::verify`.
+#[derive(Debug, Default)]
+pub struct Verification {
+  /// operations of primary topology
+  pub operations: usize,
+  /// checkpoints recomputed from operations
+  pub checkpoints: usize,
+  pub discrepancies: Vec<Discrepancy>,
+}
+
+impl Verification {
+  pub fn is_consistent(&self) -> bool {
+    self.discrepancies.is_empty()
+  }
+
+  /// Names of topologies with discrepancies.
+  pub fn broken(&self) -> Vec<&'static str> {
+    let mut names: Vec<&'static str> = Vec::new();
+    for discrepancy in self.discrepancies.iter() {
+      if !names.contains(&discrepancy.topology()) {
+        names.push(discrepancy.topology());
+      }
+    }
+    names
+  }
+}
+
+impl ToJson for Verification {
+  fn to_json(&self) -> JsonValue {
+    let mut discrepancies = JsonValue::new_array();
+    for discrepancy in self.discrepancies.iter() {
+      discrepancies.push(discrepancy.to_json()).unwrap();
+    }
+
+    object! {
+      consistent: self.is_consistent(),
+      operations: self.operations,
+      checkpoints: self.checkpoints,
+      broken: self.broken(),
+      discrepancies: discrepancies,
+    }
+  }
+}
+
+impl Db {
+  /// Check ordered topologies against the primary one and checkpoints against balances
+  /// recomputed from operations of the primary topology.
+  pub fn verify(&self) -> Result<Verification, WHError> {
+    let mut result = Verification::default();
+
+    let primary = &self.ordered_topologies[0];
+    let (db, cf) = (primary.db(), primary.cf()?);
+    for item in db.iterator_cf(&cf, IteratorMode::Start) {
+      let (_, value) = item?;
+      let (op, balance) = primary.from_bytes(&value)?;
+
+      // only independent of others operations are propagated to other topologies
+      if !op.dependant.is_empty() {
+        continue;
+      }
+      result.operations += 1;
+
+      for topology in self.ordered_topologies.iter().skip(1) {
+        match topology.get(&op)? {
+          None => result.discrepancies.push(Discrepancy::Missing {
+            topology: topology.name(),
+            op: op.clone(),
+            balance: balance.clone(),
+          }),
+          Some(found) if found.0 != op || found.1 != balance => {
+            result.discrepancies.push(Discrepancy::Mismatch {
+              topology: topology.name(),
+              op: op.clone(),
+              balance: balance.clone(),
+              found: Box::new(found),
+            })
+          },
+          _ => {},
+        }
+      }
+    }
+
+    for topology in self.ordered_topologies.iter().skip(1) {
+      let (db, cf) = (topology.db(), topology.cf()?);
+      for item in db.iterator_cf(&cf, IteratorMode::Start) {
+        let (_, value) = item?;
+        let (op, balance) = topology.from_bytes(&value)?;
+
+        match primary.get(&op)? {
+          Some((o, _)) if o.dependant.is_empty() => {},
+          _ => result.discrepancies.push(Discrepancy::Unexpected {
+            topology: topology.name(),
+            op,
+            balance,
+          }),
+        }
+      }
+    }
+
+    // checkpoints of closed periods are kept as they are
+    let closed = self.periods.closed_stores()?;
+
+    for topology in self.checkpoint_topologies.iter() {
+      let checkpoints = match self.checkpoints_till(topology.as_ref())? {
+        Some(latest) => self.recompute_checkpoints(latest, closed.clone())?,
+        None => Vec::new(),
+      };
+      result.checkpoints += checkpoints.len();
+
+      let mut expected: BTreeMap<Vec<u8>, Balance> = BTreeMap::new();
+      for b in checkpoints {
+        if closed.contains_key(&b.store)
+          && is_unchanged(&topology.balance_before(b.store, b.goods, &b.batch, b.date)?, &b.number)
+        {
+          continue;
+        }
+        expected.insert(topology.key(b.store, b.goods, b.batch.clone(), b.date), b);
+      }
+
+      let latest = topology.key_latest_checkpoint_date();
+      let (db, cf) = (topology.db(), topology.cf()?);
+      for item in db.iterator_cf(&cf, IteratorMode::Start) {
+        let (k, value) = item?;
+        if k[..] == latest[..] {
+          continue;
+        }
+        if !closed.is_empty() {
+          let (date, store, _, _) = topology.key_to_data(k.to_vec())?;
+          if closed.get(&store).is_some_and(|closed| date <= *closed) {
+            continue;
+          }
+        }
+        let found = topology.from_bytes(&value)?;
+
+        match expected.remove(&k[..]) {
+          Some(balance) if balance.number == found => {},
+          Some(balance) => result.discrepancies.push(Discrepancy::Checkpoint {
+            topology: topology.name(),
+            expected: balance,
+            found,
+          }),
+          None => {
+            let (date, store, goods, batch) = topology.key_to_data(k.to_vec())?;
+            result.discrepancies.push(Discrepancy::Checkpoint {
+              topology: topology.name(),
+              expected: Balance { date, store, goods, batch, number: BalanceForGoods::default() },
+              found,
+            });
+          },
+        }
+      }
+
+      for (_, balance) in expected {
+        result.discrepancies.push(Discrepancy::Checkpoint {
+          topology: topology.name(),
+          expected: balance,
+          found: BalanceForGoods::default(),
+        });
+      }
+    }
+
+    Ok(result)
+  }
+
+  /// Rebuild from the primary topology those topologies that `verify` found broken.
+  pub fn repair(&self, verification: &Verification) -> Result<(), WHError> {
+    for name in verification.broken() {
+      log::info!("repair {name}");
+      self.rebuild(name, usize::MAX, &mut |_| Ok(()))?;
+    }
+    Ok(())
+  }
+}
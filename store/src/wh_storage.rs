@@ -1,27 +1,396 @@
+use crate::balance::Cost;
+use crate::barcodes::{Barcodes, Scan};
+use crate::batch::Batch;
+use crate::checkpoints::check_store_batch_date::CheckStoreBatchDate;
+use crate::checkpoints::granularity::{Granularities, Granularity};
 use crate::checkpoints::CheckpointTopology;
+use crate::conversions::{Conversion, Conversions};
+use crate::costing::{CostingMethod, CostingPolicies};
+use crate::elements::{Goods, Store};
+use crate::expiry::{BatchExpiry, Expiries, Expiring};
+use crate::journal::{Journal, Recovered, Recovery};
+use crate::levels::{Reorder, ReorderAlerts, StockLevel, StockLevels};
+use crate::negative_stock::{NegativeStockPolicies, NegativeStockPolicy};
 use crate::operations::OpMutation;
 use crate::ordered_topology::OrderedTopology;
+use crate::periods::{PeriodRecord, Periods};
+use crate::qty::Qty;
+use crate::rebuild::Progress;
+use crate::reservations::{Reservation, Reservations};
+use crate::staging::StagedDB;
+use crate::stocktake::StockTake;
 use crate::topologies::store_batch_date_type_id::StoreBatchDateTypeId;
 use crate::topologies::store_goods_date_type_id_batch::StoreGoodsDateTypeIdBatch;
+use crate::verify::Verification;
 use crate::{
   checkpoints::check_date_store_batch::CheckDateStoreBatch, db::Db, error::WHError,
   topologies::date_type_store_batch_id::DateTypeStoreBatchId,
   topologies::store_date_type_batch_id::StoreDateTypeBatchId,
 };
+use chrono::{DateTime, Utc};
 use rocksdb::{ColumnFamilyDescriptor, Options, DB};
 use std::{path::Path, sync::Arc};
+use uuid::Uuid;
 
 #[derive(Clone)]
 pub struct WHStorage {
   pub database: Db,
+  pub alerts: ReorderAlerts,
 }
 
 impl WHStorage {
+  /// Record operations and notify alert listeners about goods which balances dropped
+  /// below minimum because of them.
   pub fn mutate(&self, ops: &Vec<OpMutation>) -> Result<(), WHError> {
-    self.database.record_ops(ops)
+    self.mutate_with_expiries(ops, &[])
   }
 
+  /// Apply mutations together with expiry dates of batches, see `Db::record_ops_with_expiries`.
+  pub fn mutate_with_expiries(
+    &self,
+    ops: &Vec<OpMutation>,
+    expiries: &[BatchExpiry],
+  ) -> Result<(), WHError> {
+    self.mutate_in_workspace(None, ops, expiries)
+  }
+
+  /// Apply mutations of workspace `wid` with expiry dates of batches, see
+  /// `Db::record_ops_in_workspace`.
+  pub fn mutate_in_workspace(
+    &self,
+    wid: Option<&str>,
+    ops: &Vec<OpMutation>,
+    expiries: &[BatchExpiry],
+  ) -> Result<(), WHError> {
+    let levels = self.database.levels.for_ops(ops)?;
+    let mut below = Vec::with_capacity(levels.len());
+    for (store, goods, level) in levels.iter() {
+      below.push(self.database.reorder(*store, *goods, level)?.is_some());
+    }
+
+    self.database.record_ops_in_workspace(wid, ops, expiries)?;
+
+    for ((store, goods, level), was_below) in levels.iter().zip(below) {
+      if was_below {
+        continue;
+      }
+      if let Some(reorder) = self.database.reorder(*store, *goods, level)? {
+        self.alerts.notify(&reorder);
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Record costing method for store (`goods` is `None`) or for goods at store,
+  /// applied from `warehouse/costing` memories.
+  pub fn set_costing(
+    &self,
+    store: Store,
+    goods: Option<Goods>,
+    method: CostingMethod,
+  ) -> Result<(), WHError> {
+    self.database.costing.set(store, goods, method)
+  }
+
+  /// Record what to do with operations that make balances of store negative,
+  /// applied from `negative_stock` of `warehouse/storage` memories.
+  pub fn set_negative_stock(
+    &self,
+    store: Store,
+    policy: NegativeStockPolicy,
+  ) -> Result<(), WHError> {
+    self.database.negative_stock.set(store, policy)
+  }
+
+  /// Stores get closed periods of workspace `wid`, the first workspace of store is kept.
+  pub fn assign_stores(&self, wid: &str, stores: &[Store]) -> Result<(), WHError> {
+    self.database.periods.assign(wid, stores)
+  }
+
+  /// Close month of `date` with all months before it for operations at stores of workspace.
+  pub fn close_period(
+    &self,
+    wid: &str,
+    date: DateTime<Utc>,
+    by: &str,
+  ) -> Result<PeriodRecord, WHError> {
+    self.database.periods.close(wid, date, by)
+  }
+
+  /// Reopen month of `date` with all months after it.
+  pub fn reopen_period(
+    &self,
+    wid: &str,
+    date: DateTime<Utc>,
+    by: &str,
+  ) -> Result<PeriodRecord, WHError> {
+    self.database.periods.reopen(wid, date, by)
+  }
+
+  /// Date of the first day after closed periods of workspace, `None` if nothing is closed.
+  pub fn closed_till(&self, wid: &str) -> Result<Option<DateTime<Utc>>, WHError> {
+    self.database.periods.closed_till(wid)
+  }
+
+  /// Closing and reopening of periods of workspace in order of actions.
+  pub fn periods_audit(&self, wid: &str) -> Result<Vec<PeriodRecord>, WHError> {
+    self.database.periods.audit(wid)
+  }
+
+  /// Record conversion factor between units, for goods or for any if `goods` is `None`,
+  /// applied from `warehouse/conversion` memories. Factor in use can't be changed while goods
+  /// have operations in both units.
+  pub fn set_conversion(&self, conversion: &Conversion) -> Result<(), WHError> {
+    let (goods, from, into) = (conversion.goods, conversion.from, conversion.into);
+    self.database.db.stage(|| {
+      let conversions = &self.database.conversions;
+      if conversions.get(goods, from, into)? != Some(conversion.factor) {
+        self.database.check_conversion_change(goods, from, into)?;
+      }
+      conversions.set(conversion)
+    })
+  }
+
+  /// Remove conversion factor, see `set_conversion`.
+  pub fn remove_conversion(
+    &self,
+    goods: Option<Goods>,
+    from: Uuid,
+    into: Uuid,
+  ) -> Result<(), WHError> {
+    self.database.db.stage(|| {
+      let conversions = &self.database.conversions;
+      if conversions.get(goods, from, into)?.is_some() {
+        self.database.check_conversion_change(goods, from, into)?;
+        conversions.remove(goods, from, into)?;
+      }
+      Ok(())
+    })
+  }
+
+  /// Set interval between checkpoints of store, return the one in use if it is adaptive.
+  pub fn set_checkpoint_granularity(
+    &self,
+    store: Store,
+    granularity: Granularity,
+  ) -> Result<Granularity, WHError> {
+    self.database.set_checkpoint_granularity(store, granularity)
+  }
+
+  /// Check that interval between checkpoints of store may change and set it in background,
+  /// applied from `checkpoint_granularity` of `warehouse/storage` memories. Rebuild of its
+  /// checkpoints interrupted by shutdown is continued by `resume_rebuilds`.
+  pub fn schedule_checkpoint_granularity(
+    &self,
+    store: Store,
+    granularity: Granularity,
+  ) -> Result<(), WHError> {
+    if granularity != self.database.granularities.chosen(store)? {
+      self.database.check_granularity_change(store)?;
+    }
+
+    // rebuild of checkpoints of store hold writer lock till it finish
+    let storage = self.clone();
+    std::thread::spawn(move || {
+      let result = storage.database.change_checkpoint_granularity(
+        store,
+        granularity,
+        10_000,
+        &mut |progress| {
+          log::info!(
+            "rebuild {} of store {store}: {} operations",
+            progress.topology,
+            progress.operations
+          );
+          Ok(())
+        },
+      );
+      if let Err(e) = result {
+        log::error!("can't set checkpoint granularity of store {store}: {}", e.message());
+      }
+    });
+
+    Ok(())
+  }
+
+  /// Continue rebuilds interrupted by shutdown, see `Db::resume_rebuilds`.
+  pub fn resume_rebuilds(
+    &self,
+    step: usize,
+    report: &mut dyn FnMut(&Progress) -> Result<(), WHError>,
+  ) -> Result<Vec<Progress>, WHError> {
+    self.database.resume_rebuilds(step, report)
+  }
+
+  /// Replay or roll back mutations that were accepted but not applied, see `Journal::accept`.
+  pub fn recover(&self, recovery: Recovery) -> Result<Recovered, WHError> {
+    self.database.recover(recovery)
+  }
+
+  /// Check all topologies against the primary one, see `Db::verify`.
+  pub fn verify(&self) -> Result<Verification, WHError> {
+    self.database.verify()
+  }
+
+  /// Rebuild topologies found broken by `verify`.
+  pub fn repair(&self, verification: &Verification) -> Result<(), WHError> {
+    self.database.repair(verification)
+  }
+
+  /// Fill topology `name` from scratch by the primary one or continue interrupted rebuild,
+  /// `report` is called after every `step` operations.
+  pub fn rebuild(
+    &self,
+    name: &str,
+    step: usize,
+    report: &mut dyn FnMut(&Progress) -> Result<(), WHError>,
+  ) -> Result<Progress, WHError> {
+    self.database.rebuild(name, step, report)
+  }
+
+  /// Start stock-take of store with expected balances at date.
+  pub fn stocktake_start(
+    &self,
+    wid: &str,
+    id: Uuid,
+    store: Store,
+    date: DateTime<Utc>,
+  ) -> Result<StockTake, WHError> {
+    self.database.stocktake_start(wid, id, store, date)
+  }
+
+  /// Record counted quantity of goods & batch at unposted stock-take.
+  pub fn stocktake_count(
+    &self,
+    wid: &str,
+    id: Uuid,
+    goods: Goods,
+    batch: Batch,
+    qty: Qty,
+    cost: Option<Cost>,
+  ) -> Result<StockTake, WHError> {
+    self.database.db.stage(|| {
+      let mut stocktake = match self.database.stocktake(wid, id)? {
+        Some(stocktake) => stocktake,
+        None => return Err(WHError::new(&format!("stock-take {id} not found"))),
+      };
+      stocktake.count(goods, batch, qty, cost)?;
+      self.database.save_stocktake(wid, &stocktake)?;
+      Ok(stocktake)
+    })
+  }
+
+  /// Mark stock-take posted by inventory document that records its differences.
+  pub fn stocktake_post(&self, wid: &str, id: Uuid, document: &str) -> Result<StockTake, WHError> {
+    self.database.stocktake_post(wid, id, document)
+  }
+
+  /// Reserve goods at store till expiry, fails if available quantity is not enough.
+  pub fn reserve(&self, reservation: &Reservation) -> Result<(), WHError> {
+    self.database.reserve(reservation)
+  }
+
+  pub fn release(&self, store: Store, goods: Goods, id: Uuid) -> Result<(), WHError> {
+    match self.database.reservations.get(store, goods, id)? {
+      Some(reservation) => self.database.reservations.remove(&reservation),
+      None => Err(WHError::new(&format!("reservation {id} not found"))),
+    }
+  }
+
+  /// Record expiry date of batch of goods, remove it if `None`. Issues at FEFO stores are
+  /// distributed again by it.
+  pub fn set_expiry(
+    &self,
+    goods: Goods,
+    batch: &Batch,
+    expiry: Option<DateTime<Utc>>,
+  ) -> Result<(), WHError> {
+    let expiry = BatchExpiry { goods, batch: batch.clone(), expiry };
+    self.database.db.stage(|| self.database.update_expiries(&[expiry]))
+  }
+
+  /// Batches at stores expiring within `days` from now, see `Db::expiring`.
+  pub fn expiring(&self, stores: &[Store], days: i64) -> Result<Vec<Expiring>, WHError> {
+    self.database.expiring(stores, Utc::now(), days)
+  }
+
+  /// Index minimum and maximum of goods at store from `warehouse/level` memories, remove them
+  /// if `None`.
+  pub fn set_level(
+    &self,
+    store: Store,
+    goods: Goods,
+    level: Option<StockLevel>,
+  ) -> Result<(), WHError> {
+    self.database.levels.set(store, goods, level)
+  }
+
+  /// Goods under minimum at stores with quantities to order, see `Db::reorders`.
+  pub fn reorders(&self, stores: &[Store]) -> Result<Vec<Reorder>, WHError> {
+    self.database.reorders(stores)
+  }
+
+  /// Call `listener` every time balance of goods at store drops below its minimum.
+  pub fn on_reorder(&self, listener: impl Fn(&Reorder) + Send + Sync + 'static) {
+    self.alerts.subscribe(listener)
+  }
+
+  /// Goods, batch and balances at stores of scanned barcode.
+  pub fn scan(&self, code: &str) -> Result<Option<Scan>, WHError> {
+    self.database.scan(code, Utc::now())
+  }
+
+  /// Open storage, database with dense checkpoints of older version has to be migrated
+  /// by `migrate_checkpoints` first.
   pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, WHError> {
+    let storage = Self::open_unchecked(path)?;
+    if storage.has_dense_checkpoints() {
+      return Err(WHError::new(
+        "checkpoints of older version, migrate them before use (`--mode migrate`)",
+      ));
+    }
+    Ok(storage)
+  }
+
+  /// Replace dense checkpoints of older database by sparse ones, dense ones are dropped only
+  /// after verification of the result pass (with broken topologies rebuilt if `repair`).
+  /// Interrupted migration continue at next call.
+  pub fn migrate_checkpoints<P: AsRef<Path>>(
+    path: P,
+    repair: bool,
+    report: &mut dyn FnMut(&Progress) -> Result<(), WHError>,
+  ) -> Result<Verification, WHError> {
+    Self::open_unchecked(path)?.migrate(repair, report)
+  }
+
+  fn migrate(
+    &self,
+    repair: bool,
+    report: &mut dyn FnMut(&Progress) -> Result<(), WHError>,
+  ) -> Result<Verification, WHError> {
+    let has_dense = self.has_dense_checkpoints();
+    let sparse = CheckStoreBatchDate::cf_name();
+    if has_dense || self.database.rebuild_progress(sparse)?.is_some() {
+      self.rebuild(sparse, 10_000, report)?;
+    }
+
+    let mut verification = self.verify()?;
+    if repair && !verification.is_consistent() {
+      self.repair(&verification)?;
+      verification = self.verify()?;
+    }
+    if has_dense && verification.is_consistent() {
+      self.database.db.drop_cf(CheckDateStoreBatch::cf_name())?;
+    }
+    Ok(verification)
+  }
+
+  fn has_dense_checkpoints(&self) -> bool {
+    self.database.db.cf_handle(CheckDateStoreBatch::cf_name()).is_ok()
+  }
+
+  fn open_unchecked<P: AsRef<Path>>(path: P) -> Result<Self, WHError> {
     std::fs::create_dir_all(&path)
       .map_err(|_e| WHError::new("Can't create folder for WHStorage"))?;
 
@@ -33,7 +402,7 @@ impl WHStorage {
       Ok(list) => list,
       Err(_) => Vec::new(),
     };
-    let is_empty = cfs.is_empty();
+    let existing = cfs.clone();
     let cf_descriptors = cfs.into_iter().map(|name| {
       let cf_opts = Options::default();
       ColumnFamilyDescriptor::new(name, cf_opts)
@@ -44,41 +413,71 @@ impl WHStorage {
     let tmp_db = DB::open_cf_descriptors(&opts, &path, cf_descriptors)
       .expect("Can't open database in settings.database.inventory");
 
-    if is_empty {
-      let cf_names: Vec<&str> = vec![
-        StoreBatchDateTypeId::cf_name(),
-        StoreDateTypeBatchId::cf_name(),
-        DateTypeStoreBatchId::cf_name(),
-        StoreGoodsDateTypeIdBatch::cf_name(),
-        CheckDateStoreBatch::cf_name(),
-        // CheckBatchStoreDate::cf_name(),
-      ];
-
-      for name in cf_names {
+    let cf_names: Vec<&str> = vec![
+      StoreBatchDateTypeId::cf_name(),
+      StoreDateTypeBatchId::cf_name(),
+      DateTypeStoreBatchId::cf_name(),
+      StoreGoodsDateTypeIdBatch::cf_name(),
+      CheckStoreBatchDate::cf_name(),
+      // CheckBatchStoreDate::cf_name(),
+      CostingPolicies::cf_name(),
+      Journal::cf_name(),
+      Progress::cf_name(),
+      Reservations::cf_name(),
+      NegativeStockPolicies::cf_name(),
+      Periods::cf_name(),
+      Conversions::cf_name(),
+      Granularities::cf_name(),
+      StockTake::cf_name(),
+      Barcodes::cf_name(),
+      Expiries::cf_name(),
+    ];
+
+    // create missing one, so new CF appear at existing databases too
+    for name in cf_names {
+      if !existing.iter().any(|n| n == name) {
         let _ = tmp_db.create_cf(name, &opts);
       }
     }
 
     let inner_db = Arc::new(tmp_db);
+    let staged_db = Arc::new(StagedDB::new(inner_db));
 
     let checkpoint_topologies: Vec<Box<dyn CheckpointTopology + Sync + Send>> = vec![
-      Box::new(CheckDateStoreBatch { db: inner_db.clone() }),
-      // Box::new(CheckBatchStoreDate { db: inner_db.clone() }),
+      Box::new(CheckStoreBatchDate { db: staged_db.clone() }),
+      // Box::new(CheckBatchStoreDate { db: staged_db.clone() }),
     ];
 
     let ordered_topologies: Vec<Box<dyn OrderedTopology + Sync + Send>> = vec![
-      Box::new(StoreBatchDateTypeId { db: inner_db.clone() }),
-      Box::new(StoreGoodsDateTypeIdBatch { db: inner_db.clone() }),
-      Box::new(StoreDateTypeBatchId { db: inner_db.clone() }),
-      Box::new(DateTypeStoreBatchId { db: inner_db.clone() }),
+      Box::new(StoreBatchDateTypeId { db: staged_db.clone() }),
+      Box::new(StoreGoodsDateTypeIdBatch { db: staged_db.clone() }),
+      Box::new(StoreDateTypeBatchId { db: staged_db.clone() }),
+      Box::new(DateTypeStoreBatchId { db: staged_db.clone() }),
     ];
 
     let outer_db = Db {
-      db: inner_db,
+      costing: CostingPolicies { db: staged_db.clone() },
+      journal: Journal::new(staged_db.clone())?,
+      reservations: Reservations { db: staged_db.clone() },
+      negative_stock: NegativeStockPolicies { db: staged_db.clone() },
+      periods: Periods { db: staged_db.clone() },
+      conversions: Conversions::new(staged_db.clone())?,
+      granularities: Granularities { db: staged_db.clone() },
+      barcodes: Barcodes { db: staged_db.clone() },
+      expiries: Expiries { db: staged_db.clone() },
+      levels: StockLevels::default(),
+      db: staged_db,
       checkpoint_topologies: Arc::new(checkpoint_topologies),
       ordered_topologies: Arc::new(ordered_topologies),
     };
 
-    Ok(WHStorage { database: outer_db })
+    let storage = WHStorage { database: outer_db, alerts: ReorderAlerts::default() };
+
+    // batches received before barcodes index
+    if !existing.is_empty() && !existing.iter().any(|name| name == Barcodes::cf_name()) {
+      storage.database.index_barcodes()?;
+    }
+
+    Ok(storage)
   }
 }
@@ -0,0 +1,121 @@
+use rust_decimal::Decimal;
+use std::sync::Arc;
+use store::batch::Batch;
+use store::costing::CostingMethod;
+use store::elements::{dt, Mode};
+use store::error::WHError;
+use store::journal::JournalState;
+use store::operations::{InternalOperation, OpMutation};
+use store::qty::{Number, Qty};
+use store::wh_storage::WHStorage;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+const G1: Uuid = Uuid::from_u128(1);
+
+#[test]
+fn store_test_atomic_write() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let start_d = dt("2023-03-01").unwrap();
+  let end_d = dt("2023-03-31").unwrap();
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let batch = Batch { id: Uuid::new_v4(), date: dt("2023-03-05").unwrap() };
+
+  let receive = OpMutation::new(
+    Uuid::new_v4(),
+    batch.date,
+    w1,
+    None,
+    G1,
+    batch.clone(),
+    None,
+    Some(InternalOperation::Receive(
+      Qty::new(vec![Number::new(Decimal::from(2), uom, None)]),
+      20.into(),
+    )),
+  );
+  db.record_ops(&vec![receive]).unwrap();
+
+  // fail in the middle of mutation: issue without batch under specific identification
+  wh.set_costing(w1, None, CostingMethod::Specific).unwrap();
+  let issue = OpMutation::new(
+    Uuid::new_v4(),
+    dt("2023-03-10").unwrap(),
+    w1,
+    None,
+    G1,
+    Batch::no(),
+    None,
+    Some(InternalOperation::Issue(
+      Qty::new(vec![Number::new(Decimal::from(1), uom, None)]),
+      0.into(),
+      Mode::Auto,
+    )),
+  );
+  assert!(db.record_ops(&vec![issue.clone()]).is_err());
+
+  // nothing of it reached any topology
+  let op = issue.to_op_after().unwrap();
+  for topology in db.ordered_topologies.iter() {
+    assert_eq!(topology.get(&op).unwrap(), None);
+  }
+
+  let res = db.report_for_store(w1, start_d, end_d).unwrap();
+  assert_eq!(res.items.1.len(), 1);
+  assert_eq!(res.items.1[0].issue.cost, 0.into());
+  assert_eq!(res.items.1[0].close_balance.cost, 20.into());
+
+  // journal record is discarded with the mutation
+  let records = db.journal.records(0).unwrap();
+  assert_eq!(records.len(), 1);
+  assert_eq!(records[0].state, JournalState::Applied);
+  assert!(db.journal.pending().unwrap().is_empty());
+
+  tmp_dir.close().unwrap();
+}
+
+#[test]
+fn store_test_staged_db() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let staged = wh.database.db.clone();
+  let cf = store::journal::Journal::cf_name();
+
+  // discarded on error
+  let res: Result<(), WHError> = staged.stage(|| {
+    staged.put_cf(cf, b"k1", b"v1")?;
+    assert_eq!(staged.get_cf(cf, b"k1")?, Some(b"v1".to_vec()));
+    Err(WHError::new("test"))
+  });
+  assert!(res.is_err());
+  assert_eq!(staged.get_cf(cf, b"k1").unwrap(), None);
+
+  // other threads see only committed state
+  staged
+    .stage(|| {
+      staged.put_cf(cf, b"k1", b"v1")?;
+      staged.put_cf(cf, b"k2", b"v2")?;
+      staged.delete_cf(cf, b"k1")?;
+
+      let other = Arc::clone(&staged);
+      let seen = std::thread::spawn(move || other.get_cf(cf, b"k2").unwrap()).join().unwrap();
+      assert_eq!(seen, None);
+
+      let keys: Vec<Box<[u8]>> =
+        staged.prefix(cf, b"k".to_vec())?.into_iter().map(|(k, _)| k).collect();
+      assert_eq!(keys, vec![b"k2".to_vec().into_boxed_slice()]);
+      Ok(())
+    })
+    .unwrap();
+
+  assert_eq!(staged.get_cf(cf, b"k1").unwrap(), None);
+  assert_eq!(staged.get_cf(cf, b"k2").unwrap(), Some(b"v2".to_vec()));
+
+  tmp_dir.close().unwrap();
+}
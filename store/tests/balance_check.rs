@@ -50,6 +50,7 @@ fn store_test_balance_check() {
       ),
       false,
     )),
+    document: None,
   }];
   db.record_ops(&op1).unwrap();
   db.checkpoint_topologies[0].debug().unwrap();
@@ -70,6 +71,7 @@ fn store_test_balance_check() {
       ),
       false,
     )),
+    document: None,
   }];
   db.record_ops(&op2).unwrap();
   db.checkpoint_topologies[0].debug().unwrap();
@@ -90,6 +92,7 @@ fn store_test_balance_check() {
       ),
       false,
     )),
+    document: None,
   }];
   db.record_ops(&op3).unwrap();
   db.checkpoint_topologies[0].debug().unwrap();
@@ -110,6 +113,7 @@ fn store_test_balance_check() {
       ),
       false,
     )),
+    document: None,
   }];
   db.record_ops(&op4).unwrap();
   db.checkpoint_topologies[0].debug().unwrap();
@@ -130,6 +134,7 @@ fn store_test_balance_check() {
       ),
       false,
     )),
+    document: None,
   }];
   db.record_ops(&op5).unwrap();
   db.checkpoint_topologies[0].debug().unwrap();
@@ -152,6 +157,7 @@ fn store_test_balance_check() {
       ),
       false,
     )),
+    document: None,
   }];
   db.record_ops(&op6).unwrap();
   db.checkpoint_topologies[0].debug().unwrap();
@@ -0,0 +1,84 @@
+use rust_decimal::Decimal;
+use store::barcodes::{ean13_check_digit, ean13_modules, ean13_svg, is_valid_ean13};
+use store::batch::Batch;
+use store::elements::{dt, Mode};
+use store::operations::{InternalOperation, OpMutation};
+use store::qty::{Number, Qty};
+use store::wh_storage::WHStorage;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+const G1: Uuid = Uuid::from_u128(1);
+const G2: Uuid = Uuid::from_u128(2);
+
+#[test]
+fn store_test_barcodes() {
+  assert_eq!(ean13_check_digit("400638133393").unwrap(), '1');
+  assert!(is_valid_ean13("4006381333931"));
+  assert!(!is_valid_ean13("4006381333932"));
+  assert!(ean13_check_digit("40063813339a").is_err());
+
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let w2 = Uuid::new_v4();
+  // same date and digits of id give same code
+  let b1 = Batch { id: Uuid::from_u128(10), date: dt("2023-01-05").unwrap() };
+  let b2 = Batch { id: Uuid::from_u128(20), date: dt("2023-01-05").unwrap() };
+  assert_eq!(b1.to_barcode(), b2.to_barcode());
+  assert_eq!(b1.to_barcode(), "2230105000003");
+
+  let qty = |q: i32| Qty::new(vec![Number::new(Decimal::from(q), uom, None)]);
+  let op = |store: Uuid, goods: Uuid, batch: &Batch, operation: InternalOperation| {
+    OpMutation::new(
+      Uuid::new_v4(),
+      batch.date,
+      store,
+      None,
+      goods,
+      batch.clone(),
+      None,
+      Some(operation),
+    )
+  };
+
+  wh.mutate(&vec![
+    op(w1, G1, &b1, InternalOperation::Receive(qty(10), 100.into())),
+    op(w2, G1, &b1, InternalOperation::Receive(qty(3), 30.into())),
+    op(w1, G1, &b1, InternalOperation::Issue(qty(4), 40.into(), Mode::Manual)),
+    op(w1, G2, &b2, InternalOperation::Receive(qty(7), 70.into())),
+  ])
+  .unwrap();
+
+  let code1 = db.barcodes.get(G1, &b1).unwrap().unwrap();
+  let code2 = db.barcodes.get(G2, &b2).unwrap().unwrap();
+  assert_eq!(code1, b1.to_barcode());
+  assert_ne!(code1, code2);
+  assert!(is_valid_ean13(&code2));
+
+  let scan = wh.scan(&code1).unwrap().unwrap();
+  assert_eq!((scan.goods, scan.batch.clone()), (G1, b1.clone()));
+  let mut expected = vec![(w1, qty(6)), (w2, qty(3))];
+  expected.sort_by_key(|(store, _)| *store);
+  let balances: Vec<_> = scan.balances.iter().map(|(s, b)| (*s, b.qty.clone())).collect();
+  assert_eq!(balances, expected);
+
+  let scan = wh.scan(&code2).unwrap().unwrap();
+  assert_eq!((scan.goods, scan.batch), (G2, b2.clone()));
+
+  assert!(wh.scan("4006381333931").unwrap().is_none());
+  assert!(wh.scan("4006381333932").is_err());
+
+  // indexing again keeps assigned codes
+  db.index_barcodes().unwrap();
+  assert_eq!(db.barcodes.get(G2, &b2).unwrap().unwrap(), code2);
+
+  assert_eq!(ean13_modules(&code1).unwrap().len(), 95);
+  assert!(ean13_svg(&code1).unwrap().starts_with("<svg"));
+
+  tmp_dir.close().unwrap();
+}
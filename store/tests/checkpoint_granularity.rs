@@ -0,0 +1,181 @@
+use rust_decimal::Decimal;
+use store::balance::BalanceForGoods;
+use store::batch::Batch;
+use store::checkpoints::granularity::Granularity;
+use store::elements::{dt, Mode};
+use store::error::WHError;
+use store::operations::{InternalOperation, OpMutation};
+use store::qty::{Number, Qty};
+use store::wh_storage::WHStorage;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+const G1: Uuid = Uuid::from_u128(1);
+const G2: Uuid = Uuid::from_u128(2);
+
+#[test]
+fn store_test_granularity_intervals() {
+  let weekly = Granularity::Weekly;
+  assert_eq!(weekly.current(dt("2023-03-08").unwrap()), dt("2023-03-06").unwrap());
+  // week is split at first day of month
+  assert_eq!(weekly.current(dt("2023-03-02").unwrap()), dt("2023-03-01").unwrap());
+  assert_eq!(weekly.next(dt("2023-03-29").unwrap()), dt("2023-04-01").unwrap());
+
+  let quarterly = Granularity::Quarterly;
+  assert_eq!(quarterly.current(dt("2023-05-10").unwrap()), dt("2023-04-01").unwrap());
+  assert_eq!(quarterly.next(dt("2023-12-10").unwrap()), dt("2024-01-01").unwrap());
+  assert_eq!(quarterly.ceil(dt("2023-02-01").unwrap()), dt("2023-04-01").unwrap());
+  assert_eq!(quarterly.ceil(dt("2023-04-01").unwrap()), dt("2023-04-01").unwrap());
+
+  assert_eq!(Granularity::Daily.next(dt("2023-02-28").unwrap()), dt("2023-03-01").unwrap());
+  assert_eq!(Granularity::for_activity(20_000), Granularity::Daily);
+  assert_eq!(Granularity::for_activity(3), Granularity::Quarterly);
+}
+
+#[test]
+fn store_test_checkpoint_granularity() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let w2 = Uuid::new_v4();
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-05").unwrap() };
+
+  let qty = |q: i32| Qty::new(vec![Number::new(Decimal::from(q), uom, None)]);
+  let op = |store: Uuid, date: &str, operation: InternalOperation| {
+    OpMutation::new(
+      Uuid::new_v4(),
+      dt(date).unwrap(),
+      store,
+      None,
+      G1,
+      b1.clone(),
+      None,
+      Some(operation),
+    )
+  };
+
+  assert_eq!(wh.set_checkpoint_granularity(w1, Granularity::Daily).unwrap(), Granularity::Daily);
+
+  wh.mutate(&vec![
+    op(w1, "2023-01-05", InternalOperation::Receive(qty(5), 50.into())),
+    op(w1, "2023-01-10", InternalOperation::Receive(qty(3), 30.into())),
+    op(w1, "2023-01-12", InternalOperation::Issue(qty(2), 20.into(), Mode::Manual)),
+    op(w2, "2023-01-07", InternalOperation::Receive(qty(4), 40.into())),
+  ])
+  .unwrap();
+
+  // busy store use checkpoint of the day, other one of the month
+  assert_eq!(db.checkpoint_date(w1, dt("2023-01-11").unwrap()).unwrap(), dt("2023-01-11").unwrap());
+  assert_eq!(db.checkpoint_date(w2, dt("2023-01-11").unwrap()).unwrap(), dt("2023-01-01").unwrap());
+
+  let checkpoints = db.get_checkpoints_for_goods(w1, G1, dt("2023-01-11").unwrap()).unwrap();
+  assert_eq!(checkpoints.len(), 1);
+  assert_eq!(checkpoints[0].number, BalanceForGoods { qty: qty(8), cost: 80.into() });
+
+  let check = |date: &str| {
+    let balances = db.get_balance_for_all(dt(date).unwrap()).unwrap();
+    assert_eq!(balances[&w1][&G1][&b1], BalanceForGoods { qty: qty(6), cost: 60.into() });
+    assert_eq!(balances[&w2][&G1][&b1], BalanceForGoods { qty: qty(4), cost: 40.into() });
+
+    let report = db.report_for_store(w1, dt("2023-01-11").unwrap(), dt(date).unwrap());
+    let item = &report.unwrap().items.1[0];
+    assert_eq!(item.open_balance.qty, qty(8));
+    assert_eq!(item.close_balance.qty, qty(6));
+  };
+  check("2023-01-20");
+
+  let verification = wh.verify().unwrap();
+  assert!(verification.is_consistent(), "{:#?}", verification.discrepancies);
+
+  // quiet store, its checkpoints are rebuilt
+  assert_eq!(
+    wh.set_checkpoint_granularity(w1, Granularity::Adaptive).unwrap(),
+    Granularity::Quarterly
+  );
+  assert_eq!(db.granularities.chosen(w1).unwrap(), Granularity::Adaptive);
+  assert_eq!(db.checkpoint_date(w1, dt("2023-01-11").unwrap()).unwrap(), dt("2023-01-01").unwrap());
+  assert!(db
+    .get_checkpoints_for_goods(w1, G1, dt("2023-01-11").unwrap())
+    .unwrap()
+    .is_empty());
+  check("2023-01-20");
+
+  wh.mutate(&vec![op(w2, "2023-03-07", InternalOperation::Receive(qty(1), 10.into()))])
+    .unwrap();
+  check("2023-01-20");
+
+  let verification = wh.verify().unwrap();
+  assert!(verification.is_consistent(), "{:#?}", verification.discrepancies);
+
+  tmp_dir.close().unwrap();
+}
+
+#[test]
+fn store_test_checkpoint_granularity_resume() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let w2 = Uuid::new_v4();
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-05").unwrap() };
+
+  let qty = |q: i32| Qty::new(vec![Number::new(Decimal::from(q), uom, None)]);
+  let op = |store: Uuid, goods: Uuid, date: &str, operation: InternalOperation| {
+    OpMutation::new(
+      Uuid::new_v4(),
+      dt(date).unwrap(),
+      store,
+      None,
+      goods,
+      b1.clone(),
+      None,
+      Some(operation),
+    )
+  };
+
+  wh.mutate(&vec![
+    op(w1, G1, "2023-01-05", InternalOperation::Receive(qty(5), 50.into())),
+    op(w1, G1, "2023-01-10", InternalOperation::Receive(qty(3), 30.into())),
+    op(w1, G2, "2023-01-07", InternalOperation::Receive(qty(2), 20.into())),
+    op(w1, G2, "2023-02-03", InternalOperation::Issue(qty(1), 10.into(), Mode::Manual)),
+    op(w2, G1, "2023-01-07", InternalOperation::Receive(qty(4), 40.into())),
+  ])
+  .unwrap();
+
+  // granularity is recorded together with progress of interrupted rebuild
+  let res = db.change_checkpoint_granularity(w1, Granularity::Daily, 1, &mut |_| {
+    Err(WHError::new("interrupted"))
+  });
+  assert!(res.is_err());
+  assert_eq!(db.granularities.get(w1).unwrap(), Granularity::Daily);
+
+  let name = db.checkpoint_topologies[0].name();
+  let saved = db.rebuild_progress(name).unwrap().unwrap();
+  assert_eq!(saved.store, Some(w1));
+  assert!(!saved.done);
+
+  let resumed = wh.resume_rebuilds(1, &mut |_| Ok(())).unwrap();
+  assert_eq!(resumed.len(), db.checkpoint_topologies.len());
+  assert!(resumed.iter().all(|progress| progress.done && progress.store == Some(w1)));
+  assert_eq!(db.rebuild_progress(name).unwrap(), None);
+
+  assert_eq!(db.checkpoint_date(w1, dt("2023-01-11").unwrap()).unwrap(), dt("2023-01-11").unwrap());
+  let checkpoints = db.get_checkpoints_for_goods(w1, G1, dt("2023-01-11").unwrap()).unwrap();
+  assert_eq!(checkpoints[0].number, BalanceForGoods { qty: qty(8), cost: 80.into() });
+
+  let balances = db.get_balance_for_all(dt("2023-02-10").unwrap()).unwrap();
+  assert_eq!(balances[&w1][&G2][&b1], BalanceForGoods { qty: qty(1), cost: 10.into() });
+  assert_eq!(balances[&w2][&G1][&b1], BalanceForGoods { qty: qty(4), cost: 40.into() });
+
+  let verification = wh.verify().unwrap();
+  assert!(verification.is_consistent(), "{:#?}", verification.discrepancies);
+
+  tmp_dir.close().unwrap();
+}
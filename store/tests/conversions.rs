@@ -0,0 +1,243 @@
+use chrono::Utc;
+use rust_decimal::Decimal;
+use store::batch::Batch;
+use store::checkpoints::granularity::Granularity;
+use store::conversions::Conversion;
+use store::elements::{dt, Mode};
+use store::error::WHError;
+use store::operations::{InternalOperation, OpMutation};
+use store::qty::{Number, Qty};
+use store::wh_storage::WHStorage;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+const G1: Uuid = Uuid::from_u128(1);
+const G2: Uuid = Uuid::from_u128(2);
+
+#[test]
+fn store_test_uom_conversions() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let kg = Uuid::new_v4();
+  let g = Uuid::new_v4();
+  let roll = Uuid::new_v4();
+  let metre = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-05").unwrap() };
+
+  let qty = |number: &str, uom: Uuid| {
+    Qty::new(vec![Number::new(Decimal::from_str_exact(number).unwrap(), uom, None)])
+  };
+  let op = |goods: Uuid, date: &str, operation: InternalOperation| {
+    OpMutation::new(
+      Uuid::new_v4(),
+      dt(date).unwrap(),
+      w1,
+      None,
+      goods,
+      b1.clone(),
+      None,
+      Some(operation),
+    )
+  };
+
+  wh.set_conversion(&Conversion { goods: None, from: kg, into: g, factor: 1000.into() })
+    .unwrap();
+  wh.set_conversion(&Conversion { goods: Some(G2), from: roll, into: metre, factor: 50.into() })
+    .unwrap();
+  assert!(wh
+    .set_conversion(&Conversion { goods: None, from: kg, into: g, factor: Decimal::ZERO })
+    .is_err());
+
+  // received in kg, issued in g
+  wh.mutate(&vec![
+    op(G1, "2023-01-05", InternalOperation::Receive(qty("10", kg), 100.into())),
+    op(G1, "2023-01-10", InternalOperation::Issue(qty("500", g), 5.into(), Mode::Manual)),
+  ])
+  .unwrap();
+
+  let balance = db.on_hand(w1, G1, Utc::now()).unwrap();
+  assert_eq!(balance[&b1].qty, qty("9.5", kg));
+  assert_eq!(balance[&b1].cost, 95.into());
+
+  // per goods conversion is used only for that goods
+  wh.mutate(&vec![
+    op(G2, "2023-01-05", InternalOperation::Receive(qty("2", roll), 40.into())),
+    op(G2, "2023-01-10", InternalOperation::Issue(qty("25", metre), 10.into(), Mode::Manual)),
+  ])
+  .unwrap();
+
+  let balance = db.on_hand(w1, G2, Utc::now()).unwrap();
+  assert_eq!(balance[&b1].qty, qty("1.5", roll));
+  assert_eq!(balance[&b1].cost, 30.into());
+
+  assert_eq!(wh.database.conversions.list().unwrap().len(), 2);
+
+  tmp_dir.close().unwrap();
+}
+
+#[test]
+fn store_test_uom_conversions_of_goods_in_reports() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  // registries of storages are independent
+  let wh = WHStorage::open(&tmp_dir.path().join("one")).unwrap();
+  let other = WHStorage::open(&tmp_dir.path().join("other")).unwrap();
+
+  let roll = Uuid::new_v4();
+  let metre = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-05").unwrap() };
+
+  let qty = |number: &str, uom: Uuid| {
+    Qty::new(vec![Number::new(Decimal::from_str_exact(number).unwrap(), uom, None)])
+  };
+  let ops = vec![
+    OpMutation::new(
+      Uuid::new_v4(),
+      dt("2023-01-05").unwrap(),
+      w1,
+      None,
+      G1,
+      b1.clone(),
+      None,
+      Some(InternalOperation::Receive(qty("2", roll), 60.into())),
+    ),
+    OpMutation::new(
+      Uuid::new_v4(),
+      dt("2023-01-10").unwrap(),
+      w1,
+      None,
+      G1,
+      b1.clone(),
+      None,
+      Some(InternalOperation::Issue(qty("3", metre), 30.into(), Mode::Manual)),
+    ),
+  ];
+
+  wh.set_conversion(&Conversion { goods: Some(G1), from: roll, into: metre, factor: 3.into() })
+    .unwrap();
+  wh.mutate(&ops).unwrap();
+  other.mutate(&ops).unwrap();
+
+  // metres are converted into rolls without loss of precision
+  let balance = wh.database.on_hand(w1, G1, Utc::now()).unwrap();
+  assert_eq!(balance[&b1].qty, qty("1", roll));
+
+  let report = wh
+    .database
+    .report_for_store(w1, dt("2023-01-01").unwrap(), dt("2023-01-31").unwrap())
+    .unwrap();
+  assert_eq!(report.items.1.len(), 1);
+  assert_eq!(report.items.1[0].close_balance.qty, qty("1", roll));
+  assert_eq!(report.items.1[0].close_balance.cost, 30.into());
+
+  let turnover = wh
+    .database
+    .turnover(
+      G1,
+      Some(w1),
+      Granularity::Monthly,
+      dt("2023-01-01").unwrap(),
+      dt("2023-01-31").unwrap(),
+    )
+    .unwrap();
+  assert_eq!(turnover[0].close_balance.qty, qty("1", roll));
+
+  let balance = other.database.on_hand(w1, G1, Utc::now()).unwrap();
+  assert_ne!(balance[&b1].qty, qty("1", roll));
+
+  tmp_dir.close().unwrap();
+}
+
+#[test]
+fn store_test_uom_conversion_change_with_operations() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+
+  let kg = Uuid::new_v4();
+  let g = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-05").unwrap() };
+
+  let qty = |number: &str, uom: Uuid| {
+    Qty::new(vec![Number::new(Decimal::from_str_exact(number).unwrap(), uom, None)])
+  };
+  let op = |goods: Uuid, date: &str, operation: InternalOperation| {
+    OpMutation::new(
+      Uuid::new_v4(),
+      dt(date).unwrap(),
+      w1,
+      None,
+      goods,
+      b1.clone(),
+      None,
+      Some(operation),
+    )
+  };
+  let kg_g = |factor: u32| Conversion { goods: Some(G1), from: kg, into: g, factor: factor.into() };
+
+  wh.set_conversion(&kg_g(1000)).unwrap();
+
+  // operations in one unit only do not depend on factor
+  wh.mutate(&vec![op(G1, "2023-01-05", InternalOperation::Receive(qty("10", kg), 100.into()))])
+    .unwrap();
+  wh.set_conversion(&kg_g(100)).unwrap();
+  wh.set_conversion(&kg_g(1000)).unwrap();
+
+  wh.mutate(&vec![op(
+    G1,
+    "2023-01-10",
+    InternalOperation::Issue(qty("500", g), 5.into(), Mode::Manual),
+  )])
+  .unwrap();
+
+  // balances were summed by the factor in use
+  assert!(wh.set_conversion(&kg_g(100)).is_err());
+  assert!(wh.remove_conversion(Some(G1), kg, g).is_err());
+  assert_eq!(wh.database.conversions.get(Some(G1), kg, g).unwrap(), Some(Decimal::from(1000)));
+
+  // the same factor is not a change
+  wh.set_conversion(&kg_g(1000)).unwrap();
+
+  // other goods are not affected
+  wh.set_conversion(&Conversion { goods: Some(G2), from: kg, into: g, factor: 10.into() })
+    .unwrap();
+
+  let balance = wh.database.on_hand(w1, G1, Utc::now()).unwrap();
+  assert_eq!(balance[&b1].qty, qty("9.5", kg));
+
+  tmp_dir.close().unwrap();
+}
+
+#[test]
+fn store_test_uom_conversion_discarded_stage() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+
+  let kg = Uuid::new_v4();
+  let g = Uuid::new_v4();
+
+  let staged: Result<(), WHError> = wh.database.db.stage(|| {
+    wh.set_conversion(&Conversion { goods: None, from: kg, into: g, factor: 1000.into() })?;
+    Err(WHError::new("failed after conversion"))
+  });
+  assert!(staged.is_err());
+
+  // registry is updated only by committed changes
+  assert!(wh.database.conversions.list().unwrap().is_empty());
+  let kilo = Qty::new(vec![Number::new(Decimal::ONE, kg, None)]);
+  let grams = Qty::new(vec![Number::new(Decimal::from(1000), g, None)]);
+  assert!(!kilo.sub_with(&grams, &wh.database.conversions.of(G1)).is_zero());
+
+  wh.set_conversion(&Conversion { goods: None, from: kg, into: g, factor: 1000.into() })
+    .unwrap();
+  assert!(kilo.sub_with(&grams, &wh.database.conversions.of(G1)).is_zero());
+
+  tmp_dir.close().unwrap();
+}
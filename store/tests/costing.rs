@@ -0,0 +1,164 @@
+use rust_decimal::Decimal;
+use store::batch::Batch;
+use store::costing::CostingMethod;
+use store::elements::{dt, Mode};
+use store::operations::{InternalOperation, OpMutation};
+use store::qty::{Number, Qty};
+use store::wh_storage::WHStorage;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+const G1: Uuid = Uuid::from_u128(1);
+
+fn ops(w1: Uuid, uom: Uuid, b1: &Batch, b2: &Batch) -> Vec<OpMutation> {
+  vec![
+    OpMutation::new(
+      Uuid::new_v4(),
+      b1.date,
+      w1,
+      None,
+      G1,
+      b1.clone(),
+      None,
+      Some(InternalOperation::Receive(
+        Qty::new(vec![Number::new(Decimal::from(2), uom, None)]),
+        20.into(),
+      )),
+    ),
+    OpMutation::new(
+      Uuid::new_v4(),
+      b2.date,
+      w1,
+      None,
+      G1,
+      b2.clone(),
+      None,
+      Some(InternalOperation::Receive(
+        Qty::new(vec![Number::new(Decimal::from(2), uom, None)]),
+        40.into(),
+      )),
+    ),
+    OpMutation::new(
+      Uuid::new_v4(),
+      dt("2023-01-20").unwrap(),
+      w1,
+      None,
+      G1,
+      Batch::no(),
+      None,
+      Some(InternalOperation::Issue(
+        Qty::new(vec![Number::new(Decimal::from(1), uom, None)]),
+        0.into(),
+        Mode::Auto,
+      )),
+    ),
+  ]
+}
+
+#[test]
+fn store_test_costing_lifo_and_average() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let start_d = dt("2023-01-01").unwrap();
+  let end_d = dt("2023-01-31").unwrap();
+  let uom = Uuid::new_v4();
+
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-05").unwrap() };
+  let b2 = Batch { id: Uuid::new_v4(), date: dt("2023-01-10").unwrap() };
+
+  let qty = |q: i32| Qty::new(vec![Number::new(Decimal::from(q), uom, None)]);
+  let balance = |store: Uuid, batch: &Batch| {
+    let balances = db.get_balance_for_all(end_d).unwrap();
+    let balance = &balances[&store][&G1][batch];
+    (balance.qty.clone(), balance.cost)
+  };
+
+  // FIFO by default
+  let w0 = Uuid::new_v4();
+  db.record_ops(&ops(w0, uom, &b1, &b2)).unwrap();
+
+  let res = db.report_for_store(w0, start_d, end_d).unwrap();
+  assert_eq!(res.items.1[0].costing, Some(CostingMethod::Fifo));
+  assert_eq!(balance(w0, &b1), (qty(1), 10.into()));
+  assert_eq!(balance(w0, &b2), (qty(2), 40.into()));
+
+  // LIFO issue the newest batch
+  let w1 = Uuid::new_v4();
+  wh.set_costing(w1, None, CostingMethod::Lifo).unwrap();
+  db.record_ops(&ops(w1, uom, &b1, &b2)).unwrap();
+
+  let res = db.report_for_store(w1, start_d, end_d).unwrap();
+  assert_eq!(res.items.1[0].costing, Some(CostingMethod::Lifo));
+  assert_eq!(balance(w1, &b1), (qty(2), 20.into()));
+  assert_eq!(balance(w1, &b2), (qty(1), 20.into()));
+
+  // weighted average issue at average cost of the stock
+  let w2 = Uuid::new_v4();
+  wh.set_costing(w2, None, CostingMethod::Lifo).unwrap();
+  wh.set_costing(w2, Some(G1), CostingMethod::WeightedAverage).unwrap();
+  db.record_ops(&ops(w2, uom, &b1, &b2)).unwrap();
+
+  let res = db.report_for_store(w2, start_d, end_d).unwrap();
+  assert_eq!(res.items.1[0].costing, Some(CostingMethod::WeightedAverage));
+  assert_eq!(res.items.1[0].issue.cost, (-15).into());
+  assert_eq!(res.items.1[0].close_balance.cost, 45.into());
+
+  // specific identification refuse issue without batch
+  let w3 = Uuid::new_v4();
+  wh.set_costing(w3, None, CostingMethod::Specific).unwrap();
+  assert!(db.record_ops(&ops(w3, uom, &b1, &b2)).is_err());
+
+  tmp_dir.close().unwrap();
+}
+
+#[test]
+fn store_test_costing_average_back_dated_receive() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let start_d = dt("2023-01-01").unwrap();
+  let end_d = dt("2023-01-31").unwrap();
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+
+  let b0 = Batch { id: Uuid::new_v4(), date: dt("2023-01-03").unwrap() };
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-05").unwrap() };
+  let b2 = Batch { id: Uuid::new_v4(), date: dt("2023-01-25").unwrap() };
+
+  wh.set_costing(w1, Some(G1), CostingMethod::WeightedAverage).unwrap();
+
+  let mut ops = ops(w1, uom, &b1, &b2);
+  // stock before issue is 2 at 20
+  ops.remove(1);
+  db.record_ops(&ops).unwrap();
+
+  let res = db.report_for_store(w1, start_d, end_d).unwrap();
+  assert_eq!(res.items.1[0].issue.cost, (-10).into());
+
+  // receive back-dated before the issue change average cost of it to (20 + 40) / 4
+  db.record_ops(&vec![OpMutation::new(
+    Uuid::new_v4(),
+    b0.date,
+    w1,
+    None,
+    G1,
+    b0.clone(),
+    None,
+    Some(InternalOperation::Receive(
+      Qty::new(vec![Number::new(Decimal::from(2), uom, None)]),
+      40.into(),
+    )),
+  )])
+  .unwrap();
+
+  let res = db.report_for_store(w1, start_d, end_d).unwrap();
+  assert_eq!(res.items.1[0].issue.cost, (-15).into());
+  assert_eq!(res.items.1[0].close_balance.cost, 45.into());
+
+  tmp_dir.close().unwrap();
+}
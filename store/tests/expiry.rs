@@ -0,0 +1,112 @@
+use rust_decimal::Decimal;
+use store::batch::Batch;
+use store::costing::CostingMethod;
+use store::elements::{dt, Mode};
+use store::expiry::BatchExpiry;
+use store::operations::{InternalOperation, OpMutation};
+use store::qty::{Number, Qty};
+use store::wh_storage::WHStorage;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+const G1: Uuid = Uuid::from_u128(1);
+
+#[test]
+fn store_test_expiry_fefo() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let w2 = Uuid::new_v4();
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-01").unwrap() };
+  let b2 = Batch { id: Uuid::new_v4(), date: dt("2023-01-02").unwrap() };
+  let b3 = Batch { id: Uuid::new_v4(), date: dt("2023-01-03").unwrap() };
+
+  wh.set_costing(w1, None, CostingMethod::Fefo).unwrap();
+  wh.set_expiry(G1, &b1, Some(dt("2023-03-01").unwrap())).unwrap();
+  wh.set_expiry(G1, &b2, Some(dt("2023-02-01").unwrap())).unwrap();
+  assert_eq!(db.expiries.get(G1, &b2).unwrap(), Some(dt("2023-02-01").unwrap()));
+  assert_eq!(db.expiries.get(G1, &b3).unwrap(), None);
+
+  let qty = |q: i32| Qty::new(vec![Number::new(Decimal::from(q), uom, None)]);
+  let op = |store: Uuid, batch: &Batch, date: &str, operation: InternalOperation| {
+    OpMutation::new(
+      Uuid::new_v4(),
+      dt(date).unwrap(),
+      store,
+      None,
+      G1,
+      batch.clone(),
+      None,
+      Some(operation),
+    )
+  };
+
+  wh.mutate(&vec![
+    op(w1, &b1, "2023-01-01", InternalOperation::Receive(qty(5), 50.into())),
+    op(w1, &b2, "2023-01-02", InternalOperation::Receive(qty(5), 60.into())),
+    op(w1, &b3, "2023-01-03", InternalOperation::Receive(qty(5), 70.into())),
+    op(w2, &b1, "2023-01-04", InternalOperation::Receive(qty(2), 20.into())),
+    // soonest to expire b2 first, then b1
+    op(w1, &Batch::no(), "2023-01-10", InternalOperation::Issue(qty(6), 0.into(), Mode::Auto)),
+  ])
+  .unwrap();
+
+  let balances = db.get_balance_for_all(dt("2023-01-20").unwrap()).unwrap();
+  let at_w1 = &balances[&w1][&G1];
+  assert!(at_w1.get(&b2).map(|b| b.is_zero()).unwrap_or(true));
+  assert_eq!(at_w1[&b1].qty, qty(4));
+  assert_eq!(at_w1[&b3].qty, qty(5));
+
+  // b2 is issued completely, b1 expire later than in 15 days
+  let expiring = db.expiring(&[], dt("2023-01-20").unwrap(), 15).unwrap();
+  assert!(expiring.is_empty());
+
+  let expiring = db.expiring(&[], dt("2023-01-20").unwrap(), 45).unwrap();
+  let found: Vec<_> = expiring
+    .iter()
+    .map(|e| (e.store, e.batch.clone(), e.balance.qty.clone()))
+    .collect();
+  let mut expected = vec![(w1, b1.clone(), qty(4)), (w2, b1.clone(), qty(2))];
+  expected.sort_by_key(|(store, _, _)| *store);
+  assert_eq!(found, expected);
+
+  let expiring = db.expiring(&[w2], dt("2023-01-20").unwrap(), 45).unwrap();
+  assert_eq!(expiring.len(), 1);
+  assert_eq!(expiring[0].expiry, dt("2023-03-01").unwrap());
+
+  // no expiry date anymore
+  wh.set_expiry(G1, &b1, None).unwrap();
+  assert!(db.expiring(&[], dt("2023-01-20").unwrap(), 45).unwrap().is_empty());
+
+  // b3 expire sooner than b1 now, so issue is distributed to it instead
+  wh.set_expiry(G1, &b3, Some(dt("2023-01-25").unwrap())).unwrap();
+
+  let balances = db.get_balance_for_all(dt("2023-01-20").unwrap()).unwrap();
+  let at_w1 = &balances[&w1][&G1];
+  assert!(at_w1.get(&b3).map(|b| b.is_zero()).unwrap_or(true));
+  assert_eq!(at_w1[&b1].qty, qty(5));
+  assert_eq!(at_w1[&b2].qty, qty(4));
+
+  let verification = wh.verify().unwrap();
+  assert!(verification.is_consistent(), "{:#?}", verification.discrepancies);
+
+  // expiry of receive is written with it
+  let b4 = Batch { id: Uuid::new_v4(), date: dt("2023-01-05").unwrap() };
+  wh.mutate_with_expiries(
+    &vec![op(w1, &b4, "2023-01-05", InternalOperation::Receive(qty(1), 10.into()))],
+    &[BatchExpiry { goods: G1, batch: b4.clone(), expiry: Some(dt("2023-01-06").unwrap()) }],
+  )
+  .unwrap();
+  assert_eq!(db.expiries.get(G1, &b4).unwrap(), Some(dt("2023-01-06").unwrap()));
+
+  let balances = db.get_balance_for_all(dt("2023-01-20").unwrap()).unwrap();
+  let at_w1 = &balances[&w1][&G1];
+  assert!(at_w1.get(&b4).map(|b| b.is_zero()).unwrap_or(true));
+  assert_eq!(at_w1[&b2].qty, qty(5));
+
+  tmp_dir.close().unwrap();
+}
@@ -0,0 +1,166 @@
+use rust_decimal::Decimal;
+use store::balance::{BalanceForGoods, Cost};
+use store::batch::Batch;
+use store::checkpoints::granularity::Granularity;
+use store::elements::{dt, Goods, Mode, ToJson};
+use store::grouping::{Attributes, Dimension, Group, Key, Measure};
+use store::operations::{InternalOperation, Op, OpMutation};
+use store::qty::{Number, Qty};
+use store::wh_storage::WHStorage;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+const G1: Uuid = Uuid::from_u128(1);
+const G2: Uuid = Uuid::from_u128(2);
+
+struct Categories;
+
+impl Attributes for Categories {
+  fn category(&self, _goods: Goods) -> Key {
+    Key::Id("paint".into())
+  }
+
+  fn document(&self, op: &Op) -> Key {
+    Key::Uuid(op.id)
+  }
+}
+
+fn child<'a>(group: &'a Group, key: &Key) -> &'a Group {
+  group.children.iter().find(|g| &g.key == key).unwrap()
+}
+
+#[test]
+fn store_test_grouped_report() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let w2 = Uuid::new_v4();
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-10").unwrap() };
+  let b2 = Batch { id: Uuid::new_v4(), date: dt("2023-02-05").unwrap() };
+  let b3 = Batch { id: Uuid::new_v4(), date: dt("2023-03-01").unwrap() };
+
+  let qty = |q: i32| Qty::new(vec![Number::new(Decimal::from(q), uom, None)]);
+  let op = |store: Uuid, goods: Uuid, batch: &Batch, date: &str, operation: InternalOperation| {
+    OpMutation::new(
+      Uuid::new_v4(),
+      dt(date).unwrap(),
+      store,
+      None,
+      goods,
+      batch.clone(),
+      None,
+      Some(operation),
+    )
+  };
+
+  wh.mutate(&vec![
+    op(w1, G1, &b1, "2023-01-10", InternalOperation::Receive(qty(10), 100.into())),
+    op(w1, G2, &b2, "2023-02-05", InternalOperation::Receive(qty(5), 50.into())),
+    op(w1, G1, &b1, "2023-02-10", InternalOperation::Issue(qty(4), 40.into(), Mode::Manual)),
+    op(w2, G1, &b3, "2023-03-01", InternalOperation::Receive(qty(3), 30.into())),
+  ])
+  .unwrap();
+
+  let (from, till) = (dt("2023-02-01").unwrap(), dt("2023-03-31").unwrap());
+
+  // all stores by store and goods
+  let report = db
+    .grouped_report(
+      &[],
+      vec![Dimension::Store, Dimension::Goods],
+      Measure::ALL.to_vec(),
+      from,
+      till,
+      &(),
+    )
+    .unwrap();
+
+  let total = &report.total.movement;
+  assert_eq!(total.open_balance.cost, 100.into());
+  assert_eq!(total.receive.cost, 80.into());
+  assert_eq!(total.issue.cost, Cost::from(-40));
+  assert_eq!(total.close_balance.cost, 140.into());
+  assert_eq!(report.total.children.len(), 2);
+
+  let s1 = child(&report.total, &Key::Uuid(w1));
+  assert_eq!(s1.children.len(), 2);
+  assert_eq!(
+    child(s1, &Key::Uuid(G1)).movement.close_balance,
+    BalanceForGoods { qty: qty(6), cost: 60.into() }
+  );
+
+  // subtotal of store is the same as in warehouse report
+  let store = db.report_for_store(w1, from, till).unwrap().items.0;
+  assert_eq!(s1.movement.open_balance.cost, store.open_balance);
+  assert_eq!(s1.movement.receive.cost, store.receive);
+  assert_eq!(s1.movement.issue.cost, store.issue);
+  assert_eq!(s1.movement.close_balance.cost, store.close_balance);
+
+  let json = report.to_json();
+  assert_eq!(json["dimensions"], json::array!["store", "goods"]);
+  assert_eq!(json["total"]["dimension"], "store");
+  assert_eq!(json["total"]["issue_cost"], "40");
+  assert_eq!(json["total"]["children"].len(), 2);
+
+  // balance continues through months without operations
+  let report = db
+    .grouped_report(
+      &[w1],
+      vec![Dimension::Goods, Dimension::Date(Granularity::Monthly)],
+      vec![Measure::CloseQty],
+      dt("2023-01-01").unwrap(),
+      till,
+      &(),
+    )
+    .unwrap();
+
+  let g1 = child(&report.total, &Key::Uuid(G1));
+  let months: Vec<(Key, Cost)> = g1
+    .children
+    .iter()
+    .map(|g| (g.key.clone(), g.movement.close_balance.cost))
+    .collect();
+  assert_eq!(
+    months,
+    vec![
+      (Key::Date(dt("2023-01-01").unwrap()), 100.into()),
+      (Key::Date(dt("2023-02-01").unwrap()), 60.into()),
+      (Key::Date(dt("2023-03-01").unwrap()), 60.into()),
+    ]
+  );
+  let march = child(g1, &Key::Date(dt("2023-03-01").unwrap()));
+  assert_eq!(march.movement.open_balance.cost, 60.into());
+  assert_eq!(march.movement.receive.cost, Cost::ZERO);
+
+  let g2 = child(&report.total, &Key::Uuid(G2));
+  assert_eq!(g2.children.len(), 2);
+
+  // attributes provided by caller
+  let report = db
+    .grouped_report(
+      &[w1, w2],
+      vec![Dimension::Category, Dimension::Document],
+      Measure::ALL.to_vec(),
+      from,
+      till,
+      &Categories,
+    )
+    .unwrap();
+
+  assert_eq!(report.total.children.len(), 1);
+  let paint = child(&report.total, &Key::Id("paint".into()));
+  assert_eq!(paint.movement.close_balance.cost, 140.into());
+  // open balance has no document, then one per operation of period
+  assert_eq!(paint.children.len(), 4);
+  assert_eq!(child(paint, &Key::None).movement.open_balance.cost, 100.into());
+
+  assert_eq!(Dimension::try_from("monthly").unwrap(), Dimension::Date(Granularity::Monthly));
+  assert!(Dimension::try_from("adaptive").is_err());
+  assert_eq!(Measure::try_from("issue_qty").unwrap(), Measure::IssueQty);
+
+  tmp_dir.close().unwrap();
+}
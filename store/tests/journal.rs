@@ -0,0 +1,238 @@
+use rust_decimal::Decimal;
+use store::batch::Batch;
+use store::elements::{dt, Mode};
+use store::error::WHError;
+use store::journal::{JournalState, Recovery};
+use store::negative_stock::NegativeStockPolicy;
+use store::operations::{InternalOperation, OpMutation};
+use store::qty::{Number, Qty};
+use store::wh_storage::WHStorage;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+const G1: Uuid = Uuid::from_u128(1);
+
+fn receive(w1: Uuid, uom: Uuid) -> OpMutation {
+  let date = dt("2023-02-03").unwrap();
+  OpMutation::new(
+    Uuid::new_v4(),
+    date,
+    w1,
+    None,
+    G1,
+    Batch { id: Uuid::new_v4(), date },
+    None,
+    Some(InternalOperation::Receive(
+      Qty::new(vec![Number::new(Decimal::from(3), uom, None)]),
+      30.into(),
+    )),
+  )
+}
+
+#[test]
+fn store_test_journal_replay_and_rollback() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let start_d = dt("2023-02-01").unwrap();
+  let end_d = dt("2023-02-28").unwrap();
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let w2 = Uuid::new_v4();
+
+  {
+    let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+    let db = wh.database;
+
+    db.record_ops(&vec![receive(w1, uom)]).unwrap();
+
+    let records = db.journal.records(0).unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].state, JournalState::Applied);
+
+    // accepted, but process died before applying
+    db.journal.accept(&[receive(w1, uom), receive(w2, uom)]).unwrap();
+    assert_eq!(db.journal.pending().unwrap().len(), 2);
+  }
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  assert_eq!(wh.database.journal.pending().unwrap().len(), 2);
+
+  let recovered = wh.recover(Recovery::Replay).unwrap();
+  assert_eq!(recovered.done.len(), 2);
+  assert!(recovered.failed.is_empty());
+  assert_eq!(wh.database.journal.pending().unwrap().len(), 0);
+
+  let res = wh.database.report_for_store(w1, start_d, end_d).unwrap();
+  assert_eq!(res.items.1[0].close_balance.cost, 60.into());
+
+  // roll back accepted mutation
+  let records = wh.database.journal.accept(&[receive(w2, uom)]).unwrap();
+  assert_eq!(wh.recover(Recovery::Rollback).unwrap().done, vec![records[0].seq]);
+
+  let record = wh.database.journal.get(records[0].seq).unwrap().unwrap();
+  assert_eq!(record.state, JournalState::RolledBack);
+
+  let res = wh.database.report_for_store(w2, start_d, end_d).unwrap();
+  assert_eq!(res.items.1[0].close_balance.cost, 30.into());
+
+  // sequence continue after reopening
+  assert_eq!(wh.database.journal.records(0).unwrap().len(), 4);
+
+  tmp_dir.close().unwrap();
+}
+
+#[test]
+fn store_test_journal_failed_mutation_rolled_back() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+
+  {
+    let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+    wh.set_negative_stock(w1, NegativeStockPolicy::Reject).unwrap();
+
+    let receive = receive(w1, uom);
+    let issue = OpMutation::new(
+      Uuid::new_v4(),
+      dt("2023-02-10").unwrap(),
+      w1,
+      None,
+      G1,
+      receive.batch.clone(),
+      None,
+      Some(InternalOperation::Issue(
+        Qty::new(vec![Number::new(Decimal::from(5), uom, None)]),
+        50.into(),
+        Mode::Manual,
+      )),
+    );
+    assert!(wh.mutate(&vec![receive, issue]).is_err());
+
+    // none of mutations is applied or journaled
+    assert!(wh.database.journal.records(0).unwrap().is_empty());
+    assert!(wh.database.get_balance_for_all(dt("2023-02-28").unwrap()).unwrap().is_empty());
+    assert_eq!(wh.database.journal.pending().unwrap().len(), 0);
+  }
+
+  // nothing left to replay at start
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  assert!(wh.recover(Recovery::Replay).unwrap().done.is_empty());
+
+  tmp_dir.close().unwrap();
+}
+
+#[test]
+fn store_test_journal_discarded_stage_rolled_back() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+
+  {
+    let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+
+    // mutation and its journal record are discarded with the stage it is part of
+    let staged: Result<(), WHError> = wh.database.db.stage(|| {
+      wh.database.record_ops(&vec![receive(w1, uom)])?;
+      Err(WHError::new("failed after mutation"))
+    });
+    assert!(staged.is_err());
+
+    assert!(wh.database.journal.records(0).unwrap().is_empty());
+    assert!(wh.database.get_balance_for_all(dt("2023-02-28").unwrap()).unwrap().is_empty());
+  }
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  assert!(wh.recover(Recovery::Replay).unwrap().done.is_empty());
+
+  tmp_dir.close().unwrap();
+}
+
+#[test]
+fn store_test_journal_rollback_keeps_balances() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let start_d = dt("2023-02-01").unwrap();
+  let end_d = dt("2023-02-28").unwrap();
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+
+  let receive = receive(w1, uom);
+  wh.mutate(&vec![receive.clone()]).unwrap();
+
+  // change of receive accepted, but never applied
+  let mut change = receive.clone();
+  change.before = receive.after.clone();
+  change.after = Some((
+    InternalOperation::Receive(Qty::new(vec![Number::new(Decimal::from(5), uom, None)]), 50.into()),
+    false,
+  ));
+  let records = wh.database.journal.accept(&[change]).unwrap();
+
+  assert_eq!(wh.recover(Recovery::Rollback).unwrap().done.len(), 1);
+
+  let record = wh.database.journal.get(records[0].seq).unwrap().unwrap();
+  assert_eq!(record.state, JournalState::RolledBack);
+
+  let res = wh.database.report_for_store(w1, start_d, end_d).unwrap();
+  assert_eq!(res.items.1.len(), 1);
+  assert_eq!(res.items.1[0].close_balance.cost, 30.into());
+
+  let balances = wh.database.get_balance_for_all(end_d).unwrap();
+  let balance = &balances[&w1][&G1][&receive.batch];
+  assert_eq!(balance.qty, Qty::new(vec![Number::new(Decimal::from(3), uom, None)]));
+  assert_eq!(balance.cost, 30.into());
+
+  tmp_dir.close().unwrap();
+}
+
+#[test]
+fn store_test_journal_failed_replay_kept_pending() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  wh.set_negative_stock(w1, NegativeStockPolicy::Reject).unwrap();
+
+  let receive = receive(w1, uom);
+  wh.mutate(&vec![receive.clone()]).unwrap();
+
+  // accepted issue can't be applied anymore
+  let issue = OpMutation::new(
+    Uuid::new_v4(),
+    dt("2023-02-10").unwrap(),
+    w1,
+    None,
+    G1,
+    receive.batch.clone(),
+    None,
+    Some(InternalOperation::Issue(
+      Qty::new(vec![Number::new(Decimal::from(5), uom, None)]),
+      50.into(),
+      Mode::Manual,
+    )),
+  );
+  let records = wh.database.journal.accept(&[issue]).unwrap();
+
+  let recovered = wh.recover(Recovery::Replay).unwrap();
+  assert!(recovered.done.is_empty());
+  assert_eq!(recovered.failed.len(), 1);
+  assert_eq!(recovered.failed[0].0, records[0].seq);
+
+  // left for operator to decide
+  let record = wh.database.journal.get(records[0].seq).unwrap().unwrap();
+  assert_eq!(record.state, JournalState::Pending);
+
+  assert_eq!(wh.recover(Recovery::Rollback).unwrap().done, vec![records[0].seq]);
+  assert!(wh.database.journal.pending().unwrap().is_empty());
+
+  let balances = wh.database.get_balance_for_all(dt("2023-02-28").unwrap()).unwrap();
+  assert_eq!(balances[&w1][&G1][&receive.batch].cost, 30.into());
+
+  tmp_dir.close().unwrap();
+}
@@ -0,0 +1,99 @@
+use rust_decimal::Decimal;
+use std::sync::{Arc, Mutex};
+use store::batch::Batch;
+use store::elements::{dt, Mode};
+use store::levels::StockLevel;
+use store::operations::{InternalOperation, OpMutation};
+use store::qty::{Number, Qty};
+use store::wh_storage::WHStorage;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+const G1: Uuid = Uuid::from_u128(1);
+const G2: Uuid = Uuid::from_u128(2);
+
+#[test]
+fn store_test_levels_and_reorders() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let w2 = Uuid::new_v4();
+  let batch = Batch { id: Uuid::new_v4(), date: dt("2023-01-01").unwrap() };
+
+  let qty = |q: i32| Qty::new(vec![Number::new(Decimal::from(q), uom, None)]);
+  let op = |store: Uuid, goods: Uuid, date: &str, operation: InternalOperation| {
+    OpMutation::new(
+      Uuid::new_v4(),
+      dt(date).unwrap(),
+      store,
+      None,
+      goods,
+      batch.clone(),
+      None,
+      Some(operation),
+    )
+  };
+
+  let alerts = Arc::new(Mutex::new(Vec::new()));
+  wh.on_reorder({
+    let alerts = alerts.clone();
+    move |reorder| alerts.lock().unwrap().push(reorder.clone())
+  });
+
+  wh.set_level(w1, G1, Some(StockLevel { min: qty(10), max: qty(50) })).unwrap();
+  // without maximum reorder up to minimum
+  wh.set_level(w2, G2, Some(StockLevel { min: qty(5), max: Qty::default() }))
+    .unwrap();
+
+  // nothing at stores yet
+  let reorders = wh.reorders(&[]).unwrap();
+  assert_eq!(reorders.len(), 2);
+  let w2_reorder = reorders.iter().find(|r| r.store == w2).unwrap();
+  assert_eq!(w2_reorder.suggested, qty(5));
+
+  wh.mutate(&vec![
+    op(w1, G1, "2023-01-01", InternalOperation::Receive(qty(30), 300.into())),
+    op(w2, G2, "2023-01-01", InternalOperation::Receive(qty(8), 80.into())),
+  ])
+  .unwrap();
+  assert!(wh.reorders(&[]).unwrap().is_empty());
+
+  // still above minimum
+  wh.mutate(&vec![op(
+    w1,
+    G1,
+    "2023-01-02",
+    InternalOperation::Issue(qty(15), 0.into(), Mode::Auto),
+  )])
+  .unwrap();
+  assert!(alerts.lock().unwrap().is_empty());
+
+  wh.mutate(&vec![op(w1, G1, "2023-01-03", InternalOperation::Issue(qty(9), 0.into(), Mode::Auto))])
+    .unwrap();
+  {
+    let alerts = alerts.lock().unwrap();
+    assert_eq!(alerts.len(), 1);
+    assert_eq!((alerts[0].store, alerts[0].goods), (w1, G1));
+    assert_eq!(alerts[0].balance, qty(6));
+    assert_eq!(alerts[0].suggested, qty(44));
+  }
+
+  // already below minimum, no new alert
+  wh.mutate(&vec![op(w1, G1, "2023-01-04", InternalOperation::Issue(qty(1), 0.into(), Mode::Auto))])
+    .unwrap();
+  assert_eq!(alerts.lock().unwrap().len(), 1);
+
+  let reorders = wh.reorders(&[w1]).unwrap();
+  assert_eq!(reorders.len(), 1);
+  assert_eq!(reorders[0].suggested, qty(45));
+  assert!(wh.reorders(&[w2]).unwrap().is_empty());
+
+  // level removed
+  wh.set_level(w1, G1, None).unwrap();
+  assert!(wh.reorders(&[]).unwrap().is_empty());
+
+  tmp_dir.close().unwrap();
+}
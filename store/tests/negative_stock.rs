@@ -0,0 +1,139 @@
+use chrono::Utc;
+use rust_decimal::Decimal;
+use store::batch::Batch;
+use store::elements::{dt, Mode};
+use store::negative_stock::NegativeStockPolicy;
+use store::operations::{InternalOperation, OpMutation};
+use store::qty::{Number, Qty};
+use store::wh_storage::WHStorage;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+const G1: Uuid = Uuid::from_u128(1);
+
+#[test]
+fn store_test_negative_stock() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-05").unwrap() };
+
+  let qty = |q: i32| Qty::new(vec![Number::new(Decimal::from(q), uom, None)]);
+  let issue = |date: &str, q: i32| {
+    OpMutation::new(
+      Uuid::new_v4(),
+      dt(date).unwrap(),
+      w1,
+      None,
+      G1,
+      b1.clone(),
+      None,
+      Some(InternalOperation::Issue(qty(q), (q * 10).into(), Mode::Manual)),
+    )
+  };
+
+  let receive = OpMutation::new(
+    Uuid::new_v4(),
+    b1.date,
+    w1,
+    None,
+    G1,
+    b1.clone(),
+    None,
+    Some(InternalOperation::Receive(qty(3), 30.into())),
+  );
+  wh.mutate(&vec![receive]).unwrap();
+
+  wh.set_negative_stock(w1, NegativeStockPolicy::Reject).unwrap();
+
+  let err = wh.mutate(&vec![issue("2023-01-10", 5)]).unwrap_err();
+  let shortage = err.shortage().unwrap();
+  assert_eq!(shortage.store, w1);
+  assert_eq!(shortage.goods, G1);
+  assert_eq!(shortage.batch, b1);
+  assert_eq!(shortage.shortfall, qty(2));
+  assert_eq!(shortage.date, dt("2023-01-10").unwrap());
+
+  wh.mutate(&vec![issue("2023-01-10", 2)]).unwrap();
+
+  // back-dated issue fits its own date, but breaks the later balance
+  let err = wh.mutate(&vec![issue("2023-01-07", 2)]).unwrap_err();
+  let shortage = err.shortage().unwrap();
+  assert_eq!(shortage.shortfall, qty(1));
+  assert_eq!(shortage.date, dt("2023-01-10").unwrap());
+
+  let balance = db.on_hand(w1, G1, Utc::now()).unwrap();
+  assert_eq!(balance[&b1].qty, qty(1));
+
+  // warn accept it
+  wh.set_negative_stock(w1, NegativeStockPolicy::Warn).unwrap();
+  wh.mutate(&vec![issue("2023-01-12", 2)]).unwrap();
+
+  // balance that was negative before do not block unrelated operations
+  wh.set_negative_stock(w1, NegativeStockPolicy::Reject).unwrap();
+  let b2 = Batch { id: Uuid::new_v4(), date: dt("2023-01-15").unwrap() };
+  let receive = OpMutation::new(
+    Uuid::new_v4(),
+    b2.date,
+    w1,
+    None,
+    G1,
+    b2.clone(),
+    None,
+    Some(InternalOperation::Receive(qty(1), 10.into())),
+  );
+  wh.mutate(&vec![receive]).unwrap();
+  assert!(wh.mutate(&vec![issue("2023-01-16", 1)]).is_err());
+
+  let balance = db.on_hand(w1, G1, Utc::now()).unwrap();
+  assert_eq!(balance[&b1].qty, qty(-1));
+  assert_eq!(balance[&b2].qty, qty(1));
+
+  tmp_dir.close().unwrap();
+}
+
+#[test]
+fn store_test_negative_stock_inside_interval() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-05").unwrap() };
+
+  let qty = |q: i32| Qty::new(vec![Number::new(Decimal::from(q), uom, None)]);
+  let op = |date: &str, op: InternalOperation| {
+    OpMutation::new(Uuid::new_v4(), dt(date).unwrap(), w1, None, G1, b1.clone(), None, Some(op))
+  };
+
+  wh.mutate(&vec![
+    op("2023-01-05", InternalOperation::Receive(qty(3), 30.into())),
+    op("2023-01-20", InternalOperation::Receive(qty(4), 40.into())),
+  ])
+  .unwrap();
+
+  wh.set_negative_stock(w1, NegativeStockPolicy::Reject).unwrap();
+
+  // balance at the end of month stay positive, but goes negative till the later receive
+  let err = wh
+    .mutate(&vec![op("2023-01-10", InternalOperation::Issue(qty(5), 50.into(), Mode::Manual))])
+    .unwrap_err();
+  let shortage = err.shortage().unwrap();
+  assert_eq!(shortage.batch, b1);
+  assert_eq!(shortage.shortfall, qty(2));
+  assert_eq!(shortage.date, dt("2023-01-10").unwrap());
+
+  let balance = db.on_hand(w1, G1, Utc::now()).unwrap();
+  assert_eq!(balance[&b1].qty, qty(7));
+
+  wh.mutate(&vec![op("2023-01-10", InternalOperation::Issue(qty(3), 30.into(), Mode::Manual))])
+    .unwrap();
+
+  tmp_dir.close().unwrap();
+}
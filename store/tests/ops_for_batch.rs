@@ -0,0 +1,64 @@
+use rust_decimal::Decimal;
+use store::batch::Batch;
+use store::elements::{dt, Mode};
+use store::operations::{InternalOperation, OpMutation};
+use store::qty::{Number, Qty};
+use store::wh_storage::WHStorage;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+const G1: Uuid = Uuid::from_u128(1);
+const G2: Uuid = Uuid::from_u128(2);
+
+#[test]
+fn store_test_ops_for_batch() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+
+  let uom = Uuid::new_v4();
+  let (w1, w2) = (Uuid::from_u128(10), Uuid::from_u128(20));
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-05").unwrap() };
+  let b2 = Batch { id: Uuid::new_v4(), date: dt("2023-01-05").unwrap() };
+
+  let qty = |q: i32| Qty::new(vec![Number::new(Decimal::from(q), uom, None)]);
+  let op = |date: &str, store, transfer, goods, batch: &Batch, operation| {
+    OpMutation::new(
+      Uuid::new_v4(),
+      dt(date).unwrap(),
+      store,
+      transfer,
+      goods,
+      batch.clone(),
+      None,
+      Some(operation),
+    )
+  };
+
+  wh.mutate(&vec![
+    op("2023-01-05", w1, None, G1, &b1, InternalOperation::Receive(qty(5), 50.into())),
+    op("2023-01-05", w1, None, G1, &b2, InternalOperation::Receive(qty(3), 30.into())),
+    op("2023-01-05", w1, None, G2, &b1, InternalOperation::Receive(qty(1), 10.into())),
+    op(
+      "2023-01-10",
+      w1,
+      Some(w2),
+      G1,
+      &b1,
+      InternalOperation::Issue(qty(2), 20.into(), Mode::Manual),
+    ),
+    op("2023-01-12", w2, None, G1, &b1, InternalOperation::Issue(qty(1), 10.into(), Mode::Manual)),
+  ])
+  .unwrap();
+
+  // batch of goods at both stores, including receive of transfer
+  let ops = wh.database.ops_for_batch(G1, &b1).unwrap();
+  assert!(ops.iter().all(|op| op.goods == G1 && op.batch == b1));
+  assert_eq!(ops.iter().filter(|op| op.store == w1).count(), 2);
+  assert_eq!(ops.iter().filter(|op| op.store == w2).count(), 2);
+
+  let ops = wh.database.ops_for_batch(G2, &b1).unwrap();
+  assert_eq!(ops.len(), 1);
+
+  tmp_dir.close().unwrap();
+}
@@ -0,0 +1,231 @@
+use chrono::Utc;
+use rust_decimal::Decimal;
+use store::balance::BalanceForGoods;
+use store::batch::Batch;
+use store::checkpoints::check_store_batch_date::CheckStoreBatchDate;
+use store::checkpoints::granularity::Granularity;
+use store::elements::{dt, Mode};
+use store::operations::{InternalOperation, OpMutation};
+use store::periods::PeriodAction;
+use store::qty::{Number, Qty};
+use store::wh_storage::WHStorage;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+const G1: Uuid = Uuid::from_u128(1);
+const WS1: &str = "ws1";
+const WS2: &str = "ws2";
+
+#[test]
+fn store_test_closed_periods() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let w2 = Uuid::new_v4();
+  wh.assign_stores(WS1, &[w1]).unwrap();
+  wh.assign_stores(WS2, &[w2]).unwrap();
+
+  let receive_at = |store: Uuid, date: &str| {
+    let batch = Batch { id: Uuid::new_v4(), date: dt(date).unwrap() };
+    OpMutation::new(
+      Uuid::new_v4(),
+      batch.date,
+      store,
+      None,
+      G1,
+      batch,
+      None,
+      Some(InternalOperation::Receive(
+        Qty::new(vec![Number::new(Decimal::from(1), uom, None)]),
+        10.into(),
+      )),
+    )
+  };
+  let receive = |date: &str| receive_at(w1, date);
+
+  let january = receive("2023-01-10");
+  wh.mutate(&vec![january.clone(), receive("2023-02-10")]).unwrap();
+
+  let record = wh.close_period(WS1, dt("2023-01-20").unwrap(), "accountant").unwrap();
+  assert_eq!(record.closed_till, Some(dt("2023-02-01").unwrap()));
+  let err = wh.close_period(WS1, dt("2023-01-05").unwrap(), "accountant").unwrap_err();
+  assert_eq!(err.closed_till(), Some(dt("2023-02-01").unwrap()));
+
+  // other workspace is not closed and the first workspace of store is kept
+  wh.mutate(&vec![receive_at(w2, "2023-01-15")]).unwrap();
+  assert_eq!(wh.closed_till(WS2).unwrap(), None);
+  wh.assign_stores(WS2, &[w1]).unwrap();
+  assert_eq!(db.periods.workspace(w1).unwrap(), Some(WS1.to_string()));
+
+  // new, changed and removed operations of closed period are rejected
+  assert!(wh.mutate(&vec![receive("2023-01-31")]).is_err());
+  let removal = OpMutation { before: january.after.clone(), after: None, ..january.clone() };
+  assert!(wh.mutate(&vec![removal.clone()]).is_err());
+  wh.mutate(&vec![receive("2023-02-01")]).unwrap();
+
+  // all or nothing
+  assert!(wh.mutate(&vec![receive("2023-03-01"), receive("2023-01-15")]).is_err());
+  assert_eq!(db.on_hand(w1, G1, Utc::now()).unwrap().len(), 3);
+
+  wh.close_period(WS1, dt("2023-02-20").unwrap(), "accountant").unwrap();
+  assert_eq!(db.periods.closed_till(WS1).unwrap(), Some(dt("2023-03-01").unwrap()));
+
+  let err = wh.reopen_period(WS1, dt("2023-03-05").unwrap(), "manager").unwrap_err();
+  assert_eq!(err.closed_till(), None);
+  let record = wh.reopen_period(WS1, dt("2023-01-15").unwrap(), "manager").unwrap();
+  assert_eq!(record.closed_till, Some(dt("2023-01-01").unwrap()));
+  wh.mutate(&vec![removal]).unwrap();
+
+  assert!(db.periods.audit(WS2).unwrap().is_empty());
+  let audit = db.periods.audit(WS1).unwrap();
+  let actions: Vec<(PeriodAction, &str)> = audit.iter().map(|r| (r.action, r.by.as_str())).collect();
+  assert_eq!(
+    actions,
+    vec![
+      (PeriodAction::Close, "accountant"),
+      (PeriodAction::Close, "accountant"),
+      (PeriodAction::Reopen, "manager"),
+    ]
+  );
+  assert_eq!(audit.iter().map(|r| r.seq).collect::<Vec<_>>(), vec![1, 2, 3]);
+
+  tmp_dir.close().unwrap();
+}
+
+#[test]
+fn store_test_closed_period_checkpoints_kept() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let w2 = Uuid::new_v4();
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-05").unwrap() };
+
+  let qty = |q: i32| Qty::new(vec![Number::new(Decimal::from(q), uom, None)]);
+  let receive = |store: Uuid, date: &str, q: i32| {
+    OpMutation::new(
+      Uuid::new_v4(),
+      dt(date).unwrap(),
+      store,
+      None,
+      G1,
+      b1.clone(),
+      None,
+      Some(InternalOperation::Receive(qty(q), (q * 10).into())),
+    )
+  };
+
+  wh.assign_stores(WS1, &[w1, w2]).unwrap();
+  wh.set_checkpoint_granularity(w1, Granularity::Daily).unwrap();
+  wh.set_checkpoint_granularity(w2, Granularity::Daily).unwrap();
+  wh.mutate(&vec![
+    receive(w1, "2023-01-05", 5),
+    receive(w1, "2023-01-10", 3),
+    receive(w1, "2023-02-10", 1),
+    receive(w2, "2023-01-07", 4),
+  ])
+  .unwrap();
+
+  wh.close_period(WS1, dt("2023-01-20").unwrap(), "accountant").unwrap();
+
+  let topology = &db.checkpoint_topologies[0];
+  let kept =
+    |store: Uuid, date: &str| topology.balance_before(store, G1, &b1, dt(date).unwrap()).unwrap();
+  let check = || {
+    assert_eq!(kept(w1, "2023-01-12"), BalanceForGoods { qty: qty(8), cost: 80.into() });
+    assert_eq!(kept(w2, "2023-01-12"), BalanceForGoods { qty: qty(4), cost: 40.into() });
+
+    let balances = db.get_balance_for_all(dt("2023-03-15").unwrap()).unwrap();
+    assert_eq!(balances[&w1][&G1][&b1], BalanceForGoods { qty: qty(9), cost: 90.into() });
+    assert_eq!(balances[&w2][&G1][&b1], BalanceForGoods { qty: qty(4), cost: 40.into() });
+
+    let verification = wh.verify().unwrap();
+    assert!(verification.is_consistent(), "{:#?}", verification.discrepancies);
+  };
+  check();
+
+  // interval of checkpoints kept for closed January can't change
+  let err = wh.set_checkpoint_granularity(w1, Granularity::Monthly).unwrap_err();
+  assert_eq!(err.closed_till(), Some(dt("2023-02-01").unwrap()));
+  assert!(wh.schedule_checkpoint_granularity(w2, Granularity::Monthly).is_err());
+  assert_eq!(db.granularities.get(w1).unwrap(), Granularity::Daily);
+  check();
+
+  wh.rebuild(CheckStoreBatchDate::cf_name(), 1, &mut |_| Ok(())).unwrap();
+  check();
+
+  // after reopen all checkpoints follow granularity again
+  wh.reopen_period(WS1, dt("2023-01-01").unwrap(), "manager").unwrap();
+  wh.set_checkpoint_granularity(w1, Granularity::Monthly).unwrap();
+  wh.rebuild(CheckStoreBatchDate::cf_name(), 1, &mut |_| Ok(())).unwrap();
+  assert_eq!(kept(w1, "2023-01-12"), BalanceForGoods::default());
+  let verification = wh.verify().unwrap();
+  assert!(verification.is_consistent(), "{:#?}", verification.discrepancies);
+
+  tmp_dir.close().unwrap();
+}
+
+#[test]
+fn store_test_closed_periods_of_transfer_and_unknown_store() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let w2 = Uuid::new_v4();
+  let w3 = Uuid::new_v4();
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-05").unwrap() };
+
+  let qty = || Qty::new(vec![Number::new(Decimal::from(1), uom, None)]);
+  let op = |store: Uuid, transfer: Option<Uuid>, date: &str, op: InternalOperation| {
+    OpMutation::new(
+      Uuid::new_v4(),
+      dt(date).unwrap(),
+      store,
+      transfer,
+      G1,
+      b1.clone(),
+      None,
+      Some(op),
+    )
+  };
+  let receive =
+    |store: Uuid, date: &str| op(store, None, date, InternalOperation::Receive(qty(), 10.into()));
+  let transfer =
+    |date: &str| op(w2, Some(w1), date, InternalOperation::Issue(qty(), 10.into(), Mode::Manual));
+
+  wh.mutate_in_workspace(Some(WS1), &vec![receive(w1, "2023-01-05")], &[])
+    .unwrap();
+  wh.mutate_in_workspace(Some(WS2), &vec![receive(w2, "2023-01-05")], &[])
+    .unwrap();
+  wh.close_period(WS1, dt("2023-01-20").unwrap(), "accountant").unwrap();
+
+  // transfer from open workspace into closed period of other one
+  let err = wh
+    .mutate_in_workspace(Some(WS2), &vec![transfer("2023-01-10")], &[])
+    .unwrap_err();
+  assert_eq!(err.closed_till(), Some(dt("2023-02-01").unwrap()));
+  wh.mutate_in_workspace(Some(WS2), &vec![transfer("2023-02-10")], &[]).unwrap();
+
+  // store without workspace is rejected and rejected mutation assign no workspace to it
+  assert!(wh.mutate(&vec![receive(w3, "2023-03-10")]).is_err());
+  let err = wh
+    .mutate_in_workspace(Some(WS1), &vec![receive(w3, "2023-01-10")], &[])
+    .unwrap_err();
+  assert!(err.closed_till().is_some());
+  assert_eq!(db.periods.workspace(w3).unwrap(), None);
+  wh.mutate_in_workspace(Some(WS2), &vec![receive(w3, "2023-01-10")], &[])
+    .unwrap();
+  assert_eq!(db.periods.workspace(w3).unwrap(), Some(WS2.to_string()));
+
+  tmp_dir.close().unwrap();
+}
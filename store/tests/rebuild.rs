@@ -0,0 +1,170 @@
+use rust_decimal::Decimal;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use store::batch::Batch;
+use store::elements::{dt, Mode};
+use store::error::WHError;
+use store::operations::{InternalOperation, OpMutation};
+use store::qty::{Number, Qty};
+use store::wh_storage::WHStorage;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+const G1: Uuid = Uuid::from_u128(1);
+const G2: Uuid = Uuid::from_u128(2);
+
+#[test]
+fn store_test_rebuild() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let w2 = Uuid::new_v4();
+
+  let qty = |q: i32| Qty::new(vec![Number::new(Decimal::from(q), uom, None)]);
+
+  let mut ops = Vec::new();
+  for (i, date) in ["2023-01-05", "2023-02-07", "2023-03-11", "2023-04-02"].iter().enumerate() {
+    let batch = Batch { id: Uuid::new_v4(), date: dt(date).unwrap() };
+    for goods in [G1, G2] {
+      ops.push(OpMutation::new(
+        Uuid::new_v4(),
+        batch.date,
+        w1,
+        None,
+        goods,
+        batch.clone(),
+        None,
+        Some(InternalOperation::Receive(qty(3), (30 * (i as i32 + 1)).into())),
+      ));
+    }
+  }
+  ops.push(OpMutation::new(
+    Uuid::new_v4(),
+    dt("2023-03-20").unwrap(),
+    w1,
+    Some(w2),
+    G1,
+    Batch::no(),
+    None,
+    Some(InternalOperation::Issue(qty(5), 0.into(), Mode::Auto)),
+  ));
+  db.record_ops(&ops).unwrap();
+
+  assert!(wh.verify().unwrap().is_consistent());
+
+  let primary = db.ordered_topologies[0].name();
+  assert!(wh.rebuild(primary, 1, &mut |_| Ok(())).is_err());
+  assert!(wh.rebuild("cf_unknown", 1, &mut |_| Ok(())).is_err());
+
+  let mut names: Vec<&str> = db.ordered_topologies.iter().skip(1).map(|t| t.name()).collect();
+  names.extend(db.checkpoint_topologies.iter().map(|t| t.name()));
+
+  let mut operations = 0;
+  for name in names.iter() {
+    let mut reports = 0;
+    let progress = wh
+      .rebuild(name, 2, &mut |_| {
+        reports += 1;
+        Ok(())
+      })
+      .unwrap();
+    assert!(progress.done);
+    assert!(reports > 1);
+    operations = progress.operations;
+
+    let verification = wh.verify().unwrap();
+    assert!(verification.is_consistent(), "{name}: {:#?}", verification.discrepancies);
+  }
+
+  // interrupted rebuild continue from saved progress
+  let name = db.checkpoint_topologies[0].name();
+  let res = wh.rebuild(name, 2, &mut |progress| {
+    if progress.done {
+      Ok(())
+    } else {
+      Err(WHError::new("interrupted"))
+    }
+  });
+  assert!(res.is_err());
+
+  let saved = db.rebuild_progress(name).unwrap().unwrap();
+  assert!(!saved.done);
+  assert!(saved.operations > 0 && saved.operations < operations);
+  assert!(!wh.verify().unwrap().is_consistent());
+
+  let progress = wh.rebuild(name, 2, &mut |_| Ok(())).unwrap();
+  assert_eq!(progress.operations, operations);
+  assert_eq!(db.rebuild_progress(name).unwrap(), None);
+
+  let verification = wh.verify().unwrap();
+  assert!(verification.is_consistent(), "{:#?}", verification.discrepancies);
+
+  tmp_dir.close().unwrap();
+}
+
+#[test]
+fn store_test_rebuild_blocks_writes() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+
+  let receive = |date: &str| {
+    let batch = Batch { id: Uuid::new_v4(), date: dt(date).unwrap() };
+    OpMutation::new(
+      Uuid::new_v4(),
+      batch.date,
+      w1,
+      None,
+      G1,
+      batch,
+      None,
+      Some(InternalOperation::Receive(
+        Qty::new(vec![Number::new(Decimal::from(3), uom, None)]),
+        30.into(),
+      )),
+    )
+  };
+
+  let ops: Vec<OpMutation> = ["2023-01-05", "2023-02-07", "2023-03-11"]
+    .iter()
+    .map(|date| receive(date))
+    .collect();
+  db.record_ops(&ops).unwrap();
+
+  let (tx, rx) = mpsc::channel();
+  let mut writer = None;
+
+  let name = db.checkpoint_topologies[0].name();
+  wh.rebuild(name, 1, &mut |progress| {
+    if writer.is_none() {
+      let (db, tx, op) = (db.clone(), tx.clone(), receive("2023-02-15"));
+      writer = Some(thread::spawn(move || {
+        db.record_ops(&vec![op]).unwrap();
+        tx.send(()).unwrap();
+      }));
+    }
+    // mutation wait till rebuild is finished
+    if !progress.done {
+      assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+    Ok(())
+  })
+  .unwrap();
+
+  writer.unwrap().join().unwrap();
+  assert!(rx.try_recv().is_ok());
+
+  let verification = wh.verify().unwrap();
+  assert!(verification.is_consistent(), "{:#?}", verification.discrepancies);
+
+  tmp_dir.close().unwrap();
+}
@@ -0,0 +1,270 @@
+use chrono::{Duration, Utc};
+use rust_decimal::Decimal;
+use store::batch::Batch;
+use store::elements::{dt, Mode};
+use store::operations::{InternalOperation, OpMutation};
+use store::qty::{Number, Qty};
+use store::reservations::Reservation;
+use store::wh_storage::WHStorage;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+const G1: Uuid = Uuid::from_u128(1);
+
+#[test]
+fn store_test_reservations() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-05").unwrap() };
+  let b2 = Batch { id: Uuid::new_v4(), date: dt("2023-01-07").unwrap() };
+
+  let qty = |q: i32| Qty::new(vec![Number::new(Decimal::from(q), uom, None)]);
+  let issue = |batch: &Batch, q: i32| {
+    let mode = if batch.is_empty() { Mode::Auto } else { Mode::Manual };
+    OpMutation::new(
+      Uuid::new_v4(),
+      dt("2023-01-10").unwrap(),
+      w1,
+      None,
+      G1,
+      batch.clone(),
+      None,
+      Some(InternalOperation::Issue(qty(q), (q * 10).into(), mode)),
+    )
+  };
+
+  let ops = vec![
+    OpMutation::new(
+      Uuid::new_v4(),
+      b1.date,
+      w1,
+      None,
+      G1,
+      b1.clone(),
+      None,
+      Some(InternalOperation::Receive(qty(5), 50.into())),
+    ),
+    OpMutation::new(
+      Uuid::new_v4(),
+      b2.date,
+      w1,
+      None,
+      G1,
+      b2.clone(),
+      None,
+      Some(InternalOperation::Receive(qty(5), 50.into())),
+    ),
+  ];
+  db.record_ops(&ops).unwrap();
+
+  let expires = Utc::now() + Duration::days(1);
+  let r1 = Reservation {
+    id: Uuid::new_v4(),
+    store: w1,
+    goods: G1,
+    batch: b1.clone(),
+    qty: qty(4),
+    expires,
+    document: None,
+  };
+  let r2 = Reservation {
+    id: Uuid::new_v4(),
+    store: w1,
+    goods: G1,
+    batch: Batch::no(),
+    qty: qty(3),
+    expires,
+    document: None,
+  };
+  wh.reserve(&r1).unwrap();
+  wh.reserve(&r2).unwrap();
+
+  // more than available
+  let r3 = Reservation {
+    id: Uuid::new_v4(),
+    store: w1,
+    goods: G1,
+    batch: Batch::no(),
+    qty: qty(4),
+    expires,
+    document: None,
+  };
+  assert!(wh.reserve(&r3).is_err());
+
+  let reserved = db.reservations.reserved(Utc::now(), &db.conversions).unwrap();
+  assert_eq!(reserved[&w1][&G1][&b1], qty(4));
+  assert_eq!(reserved[&w1][&G1][&Batch::no()], qty(3));
+
+  // batch b1 keep only 1 free, goods keep 3 free
+  assert!(db.record_ops(&vec![issue(&b1, 2)]).is_err());
+  assert!(db.record_ops(&vec![issue(&b2, 4)]).is_err());
+  db.record_ops(&vec![issue(&b1, 1)]).unwrap();
+  db.record_ops(&vec![issue(&b2, 2)]).unwrap();
+  assert!(db.record_ops(&vec![issue(&Batch::no(), 1)]).is_err());
+
+  let on_hand = db.on_hand(w1, G1, Utc::now()).unwrap();
+  assert_eq!(on_hand[&b1].qty, qty(4));
+  assert_eq!(on_hand[&b2].qty, qty(3));
+
+  // released and expired reservations do not hold goods
+  wh.release(w1, G1, r1.id).unwrap();
+  assert!(wh.release(w1, G1, r1.id).is_err());
+  wh.reserve(&Reservation { expires: Utc::now() - Duration::days(1), ..r2.clone() })
+    .unwrap();
+  db.record_ops(&vec![issue(&b1, 4)]).unwrap();
+
+  assert_eq!(db.reservations.cleanup(Utc::now()).unwrap(), 1);
+  assert!(db.reservations.reserved(Utc::now(), &db.conversions).unwrap().is_empty());
+
+  tmp_dir.close().unwrap();
+}
+
+#[test]
+fn store_test_reservations_lowering_ops() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-05").unwrap() };
+  let b2 = Batch { id: Uuid::new_v4(), date: dt("2023-01-07").unwrap() };
+
+  let qty = |q: i32| Qty::new(vec![Number::new(Decimal::from(q), uom, None)]);
+  let receive = |batch: &Batch, q: i32| {
+    OpMutation::new(
+      Uuid::new_v4(),
+      batch.date,
+      w1,
+      None,
+      G1,
+      batch.clone(),
+      None,
+      Some(InternalOperation::Receive(qty(q), (q * 10).into())),
+    )
+  };
+
+  let (r1, r2) = (receive(&b1, 5), receive(&b2, 5));
+  db.record_ops(&vec![r1.clone(), r2.clone()]).unwrap();
+
+  let issue = OpMutation::new(
+    Uuid::new_v4(),
+    dt("2023-01-10").unwrap(),
+    w1,
+    None,
+    G1,
+    b1.clone(),
+    None,
+    Some(InternalOperation::Issue(qty(1), 10.into(), Mode::Manual)),
+  );
+  db.record_ops(&vec![issue.clone()]).unwrap();
+
+  wh.reserve(&Reservation {
+    id: Uuid::new_v4(),
+    store: w1,
+    goods: G1,
+    batch: b2.clone(),
+    qty: qty(4),
+    expires: Utc::now() + Duration::days(1),
+    document: None,
+  })
+  .unwrap();
+
+  // reduced and deleted receive of reserved batch
+  let mut reduce = r2.clone();
+  reduce.before = r2.after.clone();
+  reduce.after = Some((InternalOperation::Receive(qty(3), 30.into()), false));
+  assert!(db.record_ops(&vec![reduce]).is_err());
+
+  let mut delete = r2.clone();
+  delete.before = r2.after.clone();
+  delete.after = None;
+  assert!(db.record_ops(&vec![delete]).is_err());
+
+  // issue moved to reserved batch
+  let mut from = issue.clone();
+  from.before = issue.after.clone();
+  from.after = None;
+  let mut into = issue.clone();
+  into.batch = b2.clone();
+  into.after = Some((InternalOperation::Issue(qty(2), 20.into(), Mode::Manual), false));
+  assert!(db.record_ops(&vec![from.clone(), into]).is_err());
+
+  // receive of other batch may change
+  let mut change = r1.clone();
+  change.before = r1.after.clone();
+  change.after = Some((InternalOperation::Receive(qty(2), 20.into()), false));
+  db.record_ops(&vec![change]).unwrap();
+
+  // goods received in future are not on hand yet
+  let future = Batch { id: Uuid::new_v4(), date: Utc::now() + Duration::days(10) };
+  db.record_ops(&vec![receive(&future, 7)]).unwrap();
+  let on_hand = db.on_hand(w1, G1, Utc::now()).unwrap();
+  assert_eq!(on_hand.get(&future), None);
+  assert_eq!(on_hand[&b2].qty, qty(5));
+  assert_eq!(db.on_hand(w1, G1, future.date).unwrap()[&future].qty, qty(7));
+
+  tmp_dir.close().unwrap();
+}
+
+#[test]
+fn store_test_reservations_of_document() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let order = Uuid::new_v4();
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-05").unwrap() };
+
+  let qty = |q: i32| Qty::new(vec![Number::new(Decimal::from(q), uom, None)]);
+  let op = |date: &str, op: InternalOperation, document: Option<Uuid>| OpMutation {
+    document,
+    ..OpMutation::new(Uuid::new_v4(), dt(date).unwrap(), w1, None, G1, b1.clone(), None, Some(op))
+  };
+  let issue = |date: &str, q: i32, document: Option<Uuid>| {
+    op(date, InternalOperation::Issue(qty(q), (q * 10).into(), Mode::Manual), document)
+  };
+
+  db.record_ops(&vec![op("2023-01-05", InternalOperation::Receive(qty(5), 50.into()), None)])
+    .unwrap();
+
+  let reservation = Reservation {
+    id: Uuid::new_v4(),
+    store: w1,
+    goods: G1,
+    batch: Batch::no(),
+    qty: qty(4),
+    expires: Utc::now() + Duration::days(1),
+    document: Some(order),
+  };
+  wh.reserve(&reservation).unwrap();
+
+  // other documents can't take reserved goods, the order take them from its reservation
+  assert!(db.record_ops(&vec![issue("2023-01-10", 2, None)]).is_err());
+  db.record_ops(&vec![issue("2023-01-10", 3, Some(order))]).unwrap();
+  assert_eq!(db.reservations.get(w1, G1, reservation.id).unwrap().unwrap().qty, qty(1));
+
+  // fulfilled reservation is released
+  db.record_ops(&vec![issue("2023-01-11", 1, Some(order))]).unwrap();
+  assert_eq!(db.reservations.get(w1, G1, reservation.id).unwrap(), None);
+  db.record_ops(&vec![issue("2023-01-12", 1, None)]).unwrap();
+
+  // back-dated issue is checked against balance from its date, not only today's one
+  db.record_ops(&vec![op("2023-02-01", InternalOperation::Receive(qty(3), 30.into()), None)])
+    .unwrap();
+  wh.reserve(&Reservation { id: Uuid::new_v4(), qty: qty(2), document: None, ..reservation })
+    .unwrap();
+  assert!(db.record_ops(&vec![issue("2023-01-15", 1, None)]).is_err());
+  db.record_ops(&vec![issue("2023-02-02", 1, None)]).unwrap();
+
+  tmp_dir.close().unwrap();
+}
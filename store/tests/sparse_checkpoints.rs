@@ -0,0 +1,253 @@
+use rocksdb::{Options, DB};
+use rust_decimal::Decimal;
+use std::sync::Arc;
+use store::balance::{Balance, BalanceForGoods};
+use store::batch::Batch;
+use store::checkpoints::check_date_store_batch::CheckDateStoreBatch;
+use store::checkpoints::check_store_batch_date::CheckStoreBatchDate;
+use store::checkpoints::CheckpointTopology;
+use store::elements::{dt, Mode};
+use store::operations::{InternalOperation, OpMutation};
+use store::qty::{Number, Qty};
+use store::wh_storage::WHStorage;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+const G1: Uuid = Uuid::from_u128(1);
+
+#[test]
+fn store_test_sparse_checkpoints() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-05").unwrap() };
+  let b2 = Batch { id: Uuid::new_v4(), date: dt("2023-04-10").unwrap() };
+
+  let qty = |q: i32| Qty::new(vec![Number::new(Decimal::from(q), uom, None)]);
+  let balance = |q: i32| BalanceForGoods { qty: qty(q), cost: (q * 10).into() };
+  let op = |date: &str, batch: &Batch, operation: InternalOperation| {
+    OpMutation::new(
+      Uuid::new_v4(),
+      dt(date).unwrap(),
+      w1,
+      None,
+      G1,
+      batch.clone(),
+      None,
+      Some(operation),
+    )
+  };
+
+  let check = |wh: &WHStorage| {
+    let checkpoints = &wh.database.checkpoint_topologies[0];
+
+    let found = checkpoints
+      .get_checkpoints_for_one_storage_before_date(w1, dt("2023-03-15").unwrap())
+      .unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].date, dt("2023-03-01").unwrap());
+    assert_eq!(found[0].batch, b1);
+    assert_eq!(found[0].number, balance(5));
+
+    let found = checkpoints
+      .get_checkpoint_for_goods_and_batch(w1, G1, &b1, dt("2023-04-20").unwrap())
+      .unwrap()
+      .unwrap();
+    assert_eq!(found.number, balance(5));
+
+    let mut found = checkpoints
+      .get_checkpoints_for_one_goods(w1, G1, dt("2023-05-20").unwrap())
+      .unwrap();
+    found.sort_by_key(|b| b.batch.date);
+    assert_eq!(found.iter().map(|b| b.date).collect::<Vec<_>>(), vec![dt("2023-05-01").unwrap(); 2]);
+    assert_eq!(found[1].number, balance(2));
+
+    // issued batch is gone
+    let found = checkpoints
+      .get_checkpoints_for_all_storages_before_date(dt("2023-06-10").unwrap())
+      .unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].batch, b2);
+
+    let verification = wh.verify().unwrap();
+    assert!(verification.is_consistent(), "{:#?}", verification.discrepancies);
+  };
+
+  {
+    let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+    let ops = vec![
+      op("2023-01-05", &b1, InternalOperation::Receive(qty(5), 50.into())),
+      op("2023-04-10", &b2, InternalOperation::Receive(qty(2), 20.into())),
+      op("2023-05-03", &b1, InternalOperation::Issue(qty(5), 50.into(), Mode::Manual)),
+    ];
+    wh.mutate(&ops).unwrap();
+
+    // balance of quiet months is not copied
+    let checkpoints = &wh.database.checkpoint_topologies[0];
+    let stored = |date: &str| {
+      let key = checkpoints.key(w1, G1, b1.clone(), dt(date).unwrap());
+      checkpoints.get_balance(&key).unwrap()
+    };
+    assert_eq!(stored("2023-02-01"), balance(5));
+    assert_eq!(stored("2023-03-01"), BalanceForGoods::default());
+    assert_eq!(stored("2023-05-01"), BalanceForGoods::default());
+
+    check(&wh);
+
+    // database of older version, with dense checkpoints only and one topology broken
+    wh.database.db.drop_cf(CheckStoreBatchDate::cf_name()).unwrap();
+    wh.database
+      .db
+      .create_cf(CheckDateStoreBatch::cf_name(), &Options::default())
+      .unwrap();
+
+    wh.database.ordered_topologies[2].del(&ops[1].to_op_after().unwrap()).unwrap();
+  }
+
+  let has_dense = || {
+    let cfs = DB::list_cf(&Options::default(), tmp_dir.path()).unwrap();
+    cfs.iter().any(|name| name == CheckDateStoreBatch::cf_name())
+  };
+
+  // storage is not opened till it is migrated
+  assert!(WHStorage::open(&tmp_dir.path()).is_err());
+
+  // dense checkpoints are kept while verification fails
+  let verification =
+    WHStorage::migrate_checkpoints(&tmp_dir.path(), false, &mut |_| Ok(())).unwrap();
+  assert!(!verification.is_consistent());
+  assert!(has_dense());
+  assert!(WHStorage::open(&tmp_dir.path()).is_err());
+
+  // migration with repair of broken topologies
+  let verification = WHStorage::migrate_checkpoints(&tmp_dir.path(), true, &mut |_| Ok(())).unwrap();
+  assert!(verification.is_consistent());
+  assert!(!has_dense());
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  check(&wh);
+  drop(wh);
+
+  tmp_dir.close().unwrap();
+}
+
+#[test]
+fn store_test_sparse_checkpoints_match_dense() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let w2 = Uuid::new_v4();
+  let g2 = Uuid::from_u128(2);
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-05").unwrap() };
+  let b2 = Batch { id: Uuid::new_v4(), date: dt("2023-02-20").unwrap() };
+  let b3 = Batch { id: Uuid::new_v4(), date: dt("2023-04-10").unwrap() };
+
+  let qty = |q: i32| Qty::new(vec![Number::new(Decimal::from(q), uom, None)]);
+  let op = |date: &str, store: Uuid, goods: Uuid, batch: &Batch, operation: InternalOperation| {
+    OpMutation::new(
+      Uuid::new_v4(),
+      dt(date).unwrap(),
+      store,
+      None,
+      goods,
+      batch.clone(),
+      None,
+      Some(operation),
+    )
+  };
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  wh.database
+    .db
+    .create_cf(CheckDateStoreBatch::cf_name(), &Options::default())
+    .unwrap();
+
+  // both kinds of checkpoints are written by the same mutations
+  let checkpoint_topologies: Vec<Box<dyn CheckpointTopology + Sync + Send>> = vec![
+    Box::new(CheckStoreBatchDate { db: wh.database.db.clone() }),
+    Box::new(CheckDateStoreBatch { db: wh.database.db.clone() }),
+  ];
+  let mut db = wh.database.clone();
+  db.checkpoint_topologies = Arc::new(checkpoint_topologies);
+
+  let ops = vec![
+    op("2023-01-05", w1, G1, &b1, InternalOperation::Receive(qty(5), 50.into())),
+    op("2023-01-20", w1, G1, &b1, InternalOperation::Issue(qty(1), 10.into(), Mode::Manual)),
+    op("2023-02-20", w1, g2, &b2, InternalOperation::Receive(qty(3), 60.into())),
+    op("2023-04-10", w2, G1, &b3, InternalOperation::Receive(qty(2), 20.into())),
+    op("2023-04-12", w1, G1, &b1, InternalOperation::Issue(qty(2), 20.into(), Mode::Manual)),
+    op("2023-05-03", w1, G1, &b1, InternalOperation::Issue(qty(2), 20.into(), Mode::Manual)),
+    op("2023-06-15", w1, g2, &b2, InternalOperation::Issue(qty(1), 20.into(), Mode::Manual)),
+  ];
+  for op in ops.iter() {
+    db.record_ops(&vec![op.clone()]).unwrap();
+  }
+
+  let (sparse, dense) = (&db.checkpoint_topologies[0], &db.checkpoint_topologies[1]);
+
+  let sorted = |mut balances: Vec<Balance>| {
+    balances.sort_by_key(|b| (b.store, b.goods, b.batch.date, b.batch.id, b.date));
+    balances
+  };
+
+  for date in ["2023-02-15", "2023-03-01", "2023-03-15", "2023-04-30", "2023-05-20", "2023-07-10"] {
+    let date = dt(date).unwrap();
+
+    assert_eq!(
+      sorted(sparse.get_checkpoints_for_all_storages_before_date(date).unwrap()),
+      sorted(dense.get_checkpoints_for_all_storages_before_date(date).unwrap()),
+      "all storages at {date}"
+    );
+    assert_eq!(
+      sparse.get_checkpoints_for_all(date).unwrap(),
+      dense.get_checkpoints_for_all(date).unwrap(),
+      "all at {date}"
+    );
+    assert_eq!(
+      sparse.get_checkpoints_for_many_goods(date, &vec![G1, g2]).unwrap(),
+      dense.get_checkpoints_for_many_goods(date, &vec![G1, g2]).unwrap(),
+      "many goods at {date}"
+    );
+
+    for store in [w1, w2] {
+      assert_eq!(
+        sorted(sparse.get_checkpoints_for_one_storage_before_date(store, date).unwrap()),
+        sorted(dense.get_checkpoints_for_one_storage_before_date(store, date).unwrap()),
+        "storage {store} at {date}"
+      );
+
+      for goods in [G1, g2] {
+        assert_eq!(
+          sorted(sparse.get_checkpoints_for_one_goods(store, goods, date).unwrap()),
+          sorted(dense.get_checkpoints_for_one_goods(store, goods, date).unwrap()),
+          "goods {goods} of {store} at {date}"
+        );
+        assert_eq!(
+          sparse.get_checkpoints_for_one_goods_with_date(store, goods, date).unwrap(),
+          dense.get_checkpoints_for_one_goods_with_date(store, goods, date).unwrap(),
+          "goods {goods} of {store} with date at {date}"
+        );
+        assert_eq!(
+          sparse.balances_for_store_goods(date, store, goods).unwrap(),
+          dense.balances_for_store_goods(date, store, goods).unwrap(),
+          "balances of {goods} at {store} at {date}"
+        );
+
+        for batch in [&b1, &b2, &b3] {
+          assert_eq!(
+            sparse.get_checkpoint_for_goods_and_batch(store, goods, batch, date).unwrap(),
+            dense.get_checkpoint_for_goods_and_batch(store, goods, batch, date).unwrap(),
+            "batch {batch:?} of {goods} at {store} at {date}"
+          );
+        }
+      }
+    }
+  }
+
+  drop(db);
+  drop(wh);
+
+  tmp_dir.close().unwrap();
+}
@@ -0,0 +1,167 @@
+use rust_decimal::Decimal;
+use store::balance::{BalanceForGoods, Cost};
+use store::batch::Batch;
+use store::elements::{dt, Mode, ToJson};
+use store::operations::{InternalOperation, OpMutation};
+use store::qty::{Number, Qty};
+use store::wh_storage::WHStorage;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+const G1: Uuid = Uuid::from_u128(1);
+const G2: Uuid = Uuid::from_u128(2);
+const G3: Uuid = Uuid::from_u128(3);
+
+const WID: &str = "yjmgJUmDo_kn9uxVi8s9Mj9mgGRJISxRt63wT46NyTQ";
+const OTHER: &str = "Fs3mvEYC9gcnSwlsKOeCL0xVnYbPbjGPZtrSK6F_Fzk";
+
+#[test]
+fn store_test_stocktake() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-05").unwrap() };
+  let b2 = Batch { id: Uuid::new_v4(), date: dt("2023-01-07").unwrap() };
+
+  let qty = |q: i32| Qty::new(vec![Number::new(Decimal::from(q), uom, None)]);
+
+  let ops = vec![
+    OpMutation::new(
+      Uuid::new_v4(),
+      b1.date,
+      w1,
+      None,
+      G1,
+      b1.clone(),
+      None,
+      Some(InternalOperation::Receive(qty(10), 100.into())),
+    ),
+    OpMutation::new(
+      Uuid::new_v4(),
+      b2.date,
+      w1,
+      None,
+      G2,
+      b2.clone(),
+      None,
+      Some(InternalOperation::Receive(qty(4), 20.into())),
+    ),
+  ];
+  db.record_ops_in_workspace(Some(WID), &ops, &[]).unwrap();
+
+  let id = Uuid::new_v4();
+  let date = dt("2023-02-10").unwrap();
+  let stocktake = wh.stocktake_start(WID, id, w1, date).unwrap();
+  assert_eq!(stocktake.lines.len(), 2);
+  assert_eq!(stocktake.lines[0].expected, BalanceForGoods { qty: qty(10), cost: 100.into() });
+  assert!(wh.stocktake_start(WID, id, w1, date).is_err());
+
+  // shortage of G1, surplus of G2 and goods missing at snapshot
+  wh.stocktake_count(WID, id, G1, b1.clone(), qty(7), None).unwrap();
+  wh.stocktake_count(WID, id, G2, b2.clone(), qty(5), None).unwrap();
+  let stocktake = wh.stocktake_count(WID, id, G3, Batch::no(), qty(2), Some(8.into())).unwrap();
+
+  let report = stocktake.to_json(&db.conversions);
+  assert_eq!(report["lines"].len(), 3);
+  assert_eq!(report["shortage"], Cost::from(30).to_json());
+  assert_eq!(report["surplus"], Cost::from(13).to_json());
+  assert_eq!(report["total"], Cost::from(-17).to_json());
+
+  // stock-take of store is not seen from other workspace
+  assert!(wh.database.stocktake(OTHER, id).unwrap().is_none());
+  assert!(wh.stocktake_start(OTHER, Uuid::new_v4(), w1, date).is_err());
+
+  let posted = wh.stocktake_post(WID, id, "warehouse/inventory/document/1").unwrap();
+  assert!(posted.posted);
+  assert_eq!(posted.document.as_deref(), Some("warehouse/inventory/document/1"));
+  assert!(wh.stocktake_post(WID, id, "warehouse/inventory/document/2").is_err());
+  assert!(wh.stocktake_count(WID, id, G1, b1.clone(), qty(1), None).is_err());
+
+  // as inventory document of stock-take does
+  db.record_ops(&posted.adjustments()).unwrap();
+
+  let balances = db.get_balance_for_all(date).unwrap();
+  let store = &balances[&w1];
+  assert_eq!(store[&G1][&b1], BalanceForGoods { qty: qty(7), cost: 70.into() });
+  assert_eq!(store[&G2][&b2], BalanceForGoods { qty: qty(5), cost: 25.into() });
+  assert_eq!(store[&G3][&posted.batch()], BalanceForGoods { qty: qty(2), cost: 8.into() });
+
+  // balances match counted quantities now
+  let check = wh.stocktake_start(WID, Uuid::new_v4(), w1, date).unwrap();
+  for line in check.lines {
+    let counted = posted.lines.iter().find(|l| l.goods == line.goods).unwrap();
+    assert_eq!(Some(line.expected.qty), counted.counted);
+  }
+
+  tmp_dir.close().unwrap();
+}
+
+#[test]
+fn store_test_stocktake_post_keeps_snapshot() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-05").unwrap() };
+  let b2 = Batch { id: Uuid::new_v4(), date: dt("2023-01-07").unwrap() };
+
+  let qty = |q: i32| Qty::new(vec![Number::new(Decimal::from(q), uom, None)]);
+  let receive = |batch: &Batch, q: i32, c: i32| {
+    OpMutation::new(
+      Uuid::new_v4(),
+      batch.date,
+      w1,
+      None,
+      G1,
+      batch.clone(),
+      None,
+      Some(InternalOperation::Receive(qty(q), c.into())),
+    )
+  };
+  db.record_ops(&vec![receive(&b1, 10, 100), receive(&b2, 4, 20)]).unwrap();
+
+  let id = Uuid::new_v4();
+  let date = dt("2023-02-10").unwrap();
+  wh.stocktake_start(WID, id, w1, date).unwrap();
+
+  // issue before stock-take date recorded after snapshot
+  let issue = OpMutation::new(
+    Uuid::new_v4(),
+    dt("2023-02-01").unwrap(),
+    w1,
+    None,
+    G1,
+    b1.clone(),
+    None,
+    Some(InternalOperation::Issue(qty(1), 10.into(), Mode::Manual)),
+  );
+  db.record_ops(&vec![issue]).unwrap();
+
+  wh.stocktake_count(WID, id, G1, b1.clone(), qty(7), None).unwrap();
+  wh.stocktake_count(WID, id, G1, b2.clone(), qty(4), None).unwrap();
+  let posted = wh.stocktake_post(WID, id, "warehouse/inventory/document/1").unwrap();
+  db.record_ops(&posted.adjustments()).unwrap();
+
+  let line = posted.lines.iter().find(|l| l.batch == b1).unwrap();
+  assert_eq!(line.expected, BalanceForGoods { qty: qty(10), cost: 100.into() });
+  assert_eq!(posted.to_json(&db.conversions)["shortage"], Cost::from(30).to_json());
+
+  let balances = db.get_balance_for_all(date).unwrap();
+  assert_eq!(balances[&w1][&G1][&b1], BalanceForGoods { qty: qty(7), cost: 70.into() });
+  assert_eq!(balances[&w1][&G1][&b2], BalanceForGoods { qty: qty(4), cost: 20.into() });
+
+  // operation per line
+  let ops = db.ops_for_store_goods(w1, G1, date, date).unwrap();
+  assert_eq!(ops.len(), 2);
+  assert_ne!(ops[0].id, ops[1].id);
+  assert!(ops.iter().all(|op| op.is_inventory()));
+
+  tmp_dir.close().unwrap();
+}
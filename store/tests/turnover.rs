@@ -0,0 +1,96 @@
+use rust_decimal::Decimal;
+use store::balance::{BalanceForGoods, Cost};
+use store::batch::Batch;
+use store::checkpoints::granularity::Granularity;
+use store::elements::{dt, Mode, ToJson};
+use store::operations::{InternalOperation, OpMutation};
+use store::qty::{Number, Qty};
+use store::wh_storage::WHStorage;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+const G1: Uuid = Uuid::from_u128(1);
+const G2: Uuid = Uuid::from_u128(2);
+
+#[test]
+fn store_test_turnover() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let w2 = Uuid::new_v4();
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-10").unwrap() };
+  let b2 = Batch { id: Uuid::new_v4(), date: dt("2023-03-01").unwrap() };
+
+  let qty = |q: i32| Qty::new(vec![Number::new(Decimal::from(q), uom, None)]);
+  let op = |store: Uuid, goods: Uuid, batch: &Batch, date: &str, operation: InternalOperation| {
+    OpMutation::new(
+      Uuid::new_v4(),
+      dt(date).unwrap(),
+      store,
+      None,
+      goods,
+      batch.clone(),
+      None,
+      Some(operation),
+    )
+  };
+
+  wh.mutate(&vec![
+    op(w1, G1, &b1, "2023-01-10", InternalOperation::Receive(qty(10), 100.into())),
+    op(w1, G1, &b1, "2023-02-10", InternalOperation::Issue(qty(4), 40.into(), Mode::Manual)),
+    op(w2, G1, &b2, "2023-03-01", InternalOperation::Receive(qty(3), 30.into())),
+    op(w1, G2, &b1, "2023-02-15", InternalOperation::Receive(qty(7), 70.into())),
+  ])
+  .unwrap();
+
+  let (from, till) = (dt("2023-01-01").unwrap(), dt("2023-04-30").unwrap());
+
+  let series = db.turnover(G1, Some(w1), Granularity::Monthly, from, till).unwrap();
+  let dates: Vec<_> = series.iter().map(|t| t.date).collect();
+  assert_eq!(
+    dates,
+    vec![
+      dt("2023-01-01").unwrap(),
+      dt("2023-02-01").unwrap(),
+      dt("2023-03-01").unwrap(),
+      dt("2023-04-01").unwrap()
+    ]
+  );
+  let close: Vec<Cost> = series.iter().map(|t| t.close_balance.cost).collect();
+  assert_eq!(close, vec![100.into(), 60.into(), 60.into(), 60.into()]);
+  assert_eq!(series[0].receive.cost, 100.into());
+  assert_eq!(series[1].open_balance, BalanceForGoods { qty: qty(10), cost: 100.into() });
+  assert_eq!(series[1].issue.cost, Cost::from(-40));
+  assert_eq!(series[1].to_json()["issue"]["cost"], "40");
+
+  // all stores
+  let series = db.turnover(G1, None, Granularity::Monthly, from, till).unwrap();
+  let close: Vec<Cost> = series.iter().map(|t| t.close_balance.cost).collect();
+  assert_eq!(close, vec![100.into(), 60.into(), 90.into(), 90.into()]);
+  assert_eq!(series[2].receive.cost, 30.into());
+
+  // period starts after checkpoint
+  let series = db
+    .turnover(G1, Some(w1), Granularity::Daily, dt("2023-02-09").unwrap(), dt("2023-02-11").unwrap())
+    .unwrap();
+  assert_eq!(series.len(), 3);
+  assert_eq!(series[0].open_balance.cost, 100.into());
+  assert_eq!(series[0].close_balance.cost, 100.into());
+  assert_eq!(series[1].issue.qty, -qty(4));
+  assert_eq!(series[2].close_balance, BalanceForGoods { qty: qty(6), cost: 60.into() });
+
+  // interval without anything
+  let series = db
+    .turnover(G2, Some(w1), Granularity::Weekly, from, dt("2023-01-15").unwrap())
+    .unwrap();
+  assert_eq!(series.len(), 3);
+  assert!(series.iter().all(|t| t.close_balance.is_zero()));
+
+  assert!(db.turnover(G1, None, Granularity::Adaptive, from, till).is_err());
+
+  tmp_dir.close().unwrap();
+}
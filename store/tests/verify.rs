@@ -0,0 +1,111 @@
+use rust_decimal::Decimal;
+use store::balance::BalanceForGoods;
+use store::batch::Batch;
+use store::elements::{dt, Mode};
+use store::operations::{InternalOperation, OpMutation};
+use store::qty::{Number, Qty};
+use store::verify::Discrepancy;
+use store::wh_storage::WHStorage;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+const G1: Uuid = Uuid::from_u128(1);
+
+#[test]
+fn store_test_verify_and_repair() {
+  let tmp_dir = TempDir::new().unwrap();
+
+  let wh = WHStorage::open(&tmp_dir.path()).unwrap();
+  let db = wh.database.clone();
+
+  let uom = Uuid::new_v4();
+  let w1 = Uuid::new_v4();
+  let w2 = Uuid::new_v4();
+  let b1 = Batch { id: Uuid::new_v4(), date: dt("2023-01-05").unwrap() };
+  let b2 = Batch { id: Uuid::new_v4(), date: dt("2023-02-07").unwrap() };
+
+  let qty = |q: i32| Qty::new(vec![Number::new(Decimal::from(q), uom, None)]);
+
+  let ops = vec![
+    OpMutation::new(
+      Uuid::new_v4(),
+      b1.date,
+      w1,
+      None,
+      G1,
+      b1.clone(),
+      None,
+      Some(InternalOperation::Receive(qty(3), 30.into())),
+    ),
+    OpMutation::new(
+      Uuid::new_v4(),
+      b2.date,
+      w1,
+      None,
+      G1,
+      b2.clone(),
+      None,
+      Some(InternalOperation::Receive(qty(2), 40.into())),
+    ),
+    OpMutation::new(
+      Uuid::new_v4(),
+      dt("2023-02-10").unwrap(),
+      w1,
+      Some(w2),
+      G1,
+      Batch::no(),
+      None,
+      Some(InternalOperation::Issue(qty(4), 0.into(), Mode::Auto)),
+    ),
+    OpMutation::new(
+      Uuid::new_v4(),
+      dt("2023-03-15").unwrap(),
+      w2,
+      None,
+      G1,
+      b1.clone(),
+      None,
+      Some(InternalOperation::Issue(qty(1), 10.into(), Mode::Manual)),
+    ),
+  ];
+  db.record_ops(&ops).unwrap();
+
+  let verification = wh.verify().unwrap();
+  assert!(verification.is_consistent(), "{:#?}", verification.discrepancies);
+  assert!(verification.operations >= ops.len());
+  assert!(verification.checkpoints > 0);
+
+  // break one ordered topology and one checkpoint
+  let op = ops[0].to_op_after().unwrap();
+  let (_, balance) = db.ordered_topologies[0].get(&op).unwrap().unwrap();
+  db.ordered_topologies[2].del(&op).unwrap();
+
+  let checkpoints = &db.checkpoint_topologies[0];
+  let key = checkpoints.key(w1, G1, b1.clone(), dt("2023-02-01").unwrap());
+  checkpoints
+    .set_balance(&key, &BalanceForGoods { qty: qty(7), cost: 70.into() })
+    .unwrap();
+
+  let verification = wh.verify().unwrap();
+  assert_eq!(verification.discrepancies.len(), 2);
+  assert_eq!(
+    verification.discrepancies[0],
+    Discrepancy::Missing { topology: db.ordered_topologies[2].name(), op, balance }
+  );
+  match &verification.discrepancies[1] {
+    Discrepancy::Checkpoint { topology, expected, found } => {
+      assert_eq!(*topology, checkpoints.name());
+      assert_eq!(expected.number, BalanceForGoods { qty: qty(3), cost: 30.into() });
+      assert_eq!(found.cost, 70.into());
+    },
+    d => panic!("unexpected {d:?}"),
+  }
+  assert_eq!(verification.broken(), vec![db.ordered_topologies[2].name(), checkpoints.name()]);
+
+  wh.repair(&verification).unwrap();
+
+  let verification = wh.verify().unwrap();
+  assert!(verification.is_consistent(), "{:#?}", verification.discrepancies);
+
+  tmp_dir.close().unwrap();
+}
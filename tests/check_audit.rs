@@ -0,0 +1,114 @@
+mod test_init;
+
+use json::{object, JsonValue};
+use std::sync::Arc;
+
+use crate::test_init::init;
+use nae_backend::audit::Audit;
+use nae_backend::commutator::Application;
+use nae_backend::memories::MemoriesInFiles;
+use nae_backend::storage::Workspaces;
+use service::utils::json::JsonParams;
+use service::{Context, Origin, Services};
+use values::ID;
+
+const WID: &str = "yjmgJUmDo_kn9uxVi8s9Mj9mgGRJISxRt63wT46NyTQ";
+
+#[actix_web::test]
+async fn check_audit() {
+  let (tmp_dir, settings, db) = init();
+
+  let wss = Workspaces::new(tmp_dir.path().join("companies"));
+
+  let (mut app, _) = Application::new(Arc::new(settings), Arc::new(db), wss).await.unwrap();
+
+  app.register(MemoriesInFiles::new(app.clone(), "memories"));
+  app.register(Audit::new(app.clone()));
+
+  let user = ID::random();
+  let by_user = || {
+    let mut context = Context::local();
+    context.origin = Origin::Rest;
+    context.account.write().unwrap().id = user;
+    context
+  };
+
+  let receive = vec!["warehouse", "receive", "document"];
+  let issue = vec!["warehouse", "issue", "document"];
+
+  let d1 = app
+    .service("memories")
+    .create(by_user(), object! { number: "1" }, object! { oid: WID, ctx: receive.clone() })
+    .unwrap();
+  let d2 = app
+    .service("memories")
+    .create(Context::local(), object! { number: "2" }, object! { oid: WID, ctx: issue.clone() })
+    .unwrap();
+  let d1 = d1["_id"].string();
+  let d2 = d2["_id"].string();
+
+  std::thread::sleep(std::time::Duration::from_millis(5));
+  let patched = app
+    .service("memories")
+    .patch(
+      by_user(),
+      d1.clone(),
+      object! { number: "3" },
+      object! { oid: WID, ctx: receive.clone() },
+    )
+    .unwrap();
+
+  // stamps of revision are kept by audit log, not by document
+  assert!(patched["_account"].is_null());
+  assert!(patched["_origin"].is_null());
+  assert!(patched["_timestamp"].is_null());
+
+  let find = |filter: JsonValue| -> Vec<(String, String)> {
+    let result = app
+      .service("audit")
+      .find(Context::local(), object! { oid: WID, filter: filter })
+      .unwrap();
+    result["data"]
+      .members()
+      .map(|e| (e["document"].string(), e["action"].string()))
+      .collect()
+  };
+
+  // latest first
+  assert_eq!(
+    find(object! {}),
+    vec![
+      (d1.clone(), "update".to_string()),
+      (d2.clone(), "create".to_string()),
+      (d1.clone(), "create".to_string()),
+    ]
+  );
+  assert_eq!(
+    find(object! { document: d1.clone() }),
+    vec![(d1.clone(), "update".to_string()), (d1.clone(), "create".to_string())]
+  );
+  assert_eq!(find(object! { ctx: issue.clone() }), vec![(d2.clone(), "create".to_string())]);
+  assert_eq!(find(object! { account: user.to_base64() }).len(), 2);
+  let entry = app
+    .service("audit")
+    .find(Context::local(), object! { oid: WID, filter: { document: d1.clone() } })
+    .unwrap()["data"][0]
+    .clone();
+  assert_eq!(entry["account"], user.to_base64());
+  assert_eq!(entry["origin"], "rest");
+  assert!(entry["time"].is_string());
+  assert_eq!(find(object! { origin: "local" }), vec![(d2.clone(), "create".to_string())]);
+  assert_eq!(
+    find(object! { account: user.to_base64(), action: "create" }),
+    vec![(d1.clone(), "create".to_string())]
+  );
+
+  // other workspace has own log
+  let other = app
+    .service("audit")
+    .find(Context::local(), object! { oid: ID::random().to_base64(), filter: {} })
+    .unwrap();
+  assert_eq!(other["total"], 0);
+
+  tmp_dir.close().unwrap();
+}
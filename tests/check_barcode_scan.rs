@@ -0,0 +1,60 @@
+mod test_init;
+
+use json::object;
+use rust_decimal::Decimal;
+use std::sync::Arc;
+
+use crate::test_init::{goods, init, receive, store, uom};
+use nae_backend::commutator::Application;
+use nae_backend::memories::MemoriesInFiles;
+use nae_backend::storage::Workspaces;
+use service::{Context, Services};
+use store::batch::Batch;
+use store::elements::dt;
+use store::qty::{Number, Qty};
+use store::GetWarehouse;
+
+const WID: &str = "yjmgJUmDo_kn9uxVi8s9Mj9mgGRJISxRt63wT46NyTQ";
+
+#[actix_web::test]
+async fn check_barcode_scan() {
+  let (tmp_dir, settings, db) = init();
+
+  let wss = Workspaces::new(tmp_dir.path().join("companies"));
+
+  let (mut app, _) = Application::new(Arc::new(settings), Arc::new(db), wss).await.unwrap();
+
+  app.register(MemoriesInFiles::new(app.clone(), "memories"));
+  app.register(nae_backend::inventory::service::Inventory::new(app.clone()));
+
+  let s1 = store(&app, "склад");
+  let paint = goods(&app, "краска");
+  let kg = uom(&app, "кг");
+
+  let qty = Qty::new(vec![Number::new(Decimal::from(5), kg, None)]);
+  let id = receive(&app, "2023-01-10", s1, paint, qty, 50.into());
+  let batch = Batch { id, date: dt("2023-01-10").unwrap() };
+
+  let code = app.warehouse().database.barcodes.get(paint, &batch).unwrap().unwrap();
+  assert_eq!(code, batch.to_barcode());
+
+  let find = |code: &str| {
+    app
+      .service("inventory")
+      .find(Context::local(), object! { oid: WID, filter: { barcode: code } })
+  };
+
+  let result = find(&code).unwrap();
+  assert_eq!(result["total"], 1);
+  assert_eq!(result["data"][0]["goods"]["name"], "краска");
+  assert_eq!(result["data"][0]["batch"]["id"], id.to_string());
+  assert_eq!(result["data"][0]["balances"][0]["store"]["name"], "склад");
+  assert_eq!(result["data"][0]["balances"][0]["cost"], "50");
+
+  // valid code of nothing
+  assert_eq!(find("4006381333931").unwrap()["total"], 0);
+  // wrong check digit
+  assert!(find("4006381333932").is_err());
+
+  tmp_dir.close().unwrap();
+}
@@ -0,0 +1,51 @@
+mod test_init;
+
+use json::object;
+use std::sync::Arc;
+
+use crate::test_init::{init, DocumentCreation};
+use nae_backend::commutator::Application;
+use nae_backend::memories::MemoriesInFiles;
+use nae_backend::storage::Workspaces;
+use service::utils::json::JsonParams;
+use service::{Context, Services};
+use store::checkpoints::granularity::Granularity;
+use store::GetWarehouse;
+
+const WID: &str = "yjmgJUmDo_kn9uxVi8s9Mj9mgGRJISxRt63wT46NyTQ";
+
+#[actix_web::test]
+async fn check_checkpoint_granularity() {
+  let (tmp_dir, settings, db) = init();
+
+  let wss = Workspaces::new(tmp_dir.path().join("companies"));
+
+  let (mut app, _) = Application::new(Arc::new(settings), Arc::new(db), wss).await.unwrap();
+
+  app.register(MemoriesInFiles::new(app.clone(), "memories"));
+
+  let storage = vec!["warehouse", "storage"]
+    .create(&app, object! { name: "склад", checkpoint_granularity: "weekly" });
+  let s1 = storage["_uuid"].uuid().unwrap();
+
+  let granularities = || app.warehouse().database.granularities.clone();
+  assert_eq!(granularities().get(s1).unwrap(), Granularity::Weekly);
+
+  let patch = |granularity: &str| {
+    app.service("memories").patch(
+      Context::local(),
+      storage["_id"].string(),
+      object! { checkpoint_granularity: granularity },
+      object! { oid: WID, ctx: vec!["warehouse", "storage"] },
+    )
+  };
+
+  patch("daily").unwrap();
+  assert_eq!(granularities().get(s1).unwrap(), Granularity::Daily);
+
+  // unknown granularity keep the recorded one
+  assert!(patch("hourly").is_err());
+  assert_eq!(granularities().get(s1).unwrap(), Granularity::Daily);
+
+  tmp_dir.close().unwrap();
+}
@@ -0,0 +1,168 @@
+mod test_init;
+
+use json::object;
+use std::sync::Arc;
+
+use crate::test_init::init;
+use actix_web::ResponseError;
+use nae_backend::commutator::Application;
+use nae_backend::memories::{MemoriesHistory, MemoriesInFiles};
+use nae_backend::storage::Workspaces;
+use service::error::Error;
+use service::utils::json::JsonParams;
+use service::{Context, Services};
+
+const WID: &str = "yjmgJUmDo_kn9uxVi8s9Mj9mgGRJISxRt63wT46NyTQ";
+
+#[actix_web::test]
+async fn check_conflict() {
+  let (tmp_dir, settings, db) = init();
+
+  let wss = Workspaces::new(tmp_dir.path().join("companies"));
+
+  let (mut app, _) = Application::new(Arc::new(settings), Arc::new(db), wss).await.unwrap();
+
+  app.register(MemoriesInFiles::new(app.clone(), "memories"));
+  app.register(MemoriesHistory::new(app.clone(), "memories-history"));
+
+  let ctx = vec!["warehouse", "receive", "document"];
+
+  let doc = app
+    .service("memories")
+    .create(Context::local(), object! { number: "1" }, object! { oid: WID, ctx: ctx.clone() })
+    .unwrap();
+  let id = doc["_id"].string();
+
+  let revisions = || -> Vec<String> {
+    let history = app
+      .service("memories-history")
+      .find(Context::local(), object! { oid: WID, ctx: ctx.clone(), id: id.clone() })
+      .unwrap();
+    history["data"].members().map(|r| r["revision"].string()).collect()
+  };
+  let current = || revisions().last().unwrap().clone();
+
+  // both users loaded the same revision
+  let revision = current();
+
+  std::thread::sleep(std::time::Duration::from_millis(5));
+  app
+    .service("memories")
+    .patch(
+      Context::local(),
+      id.clone(),
+      object! { number: "2" },
+      object! { oid: WID, ctx: ctx.clone(), revision: revision.clone() },
+    )
+    .unwrap();
+  assert_ne!(current(), revision);
+
+  std::thread::sleep(std::time::Duration::from_millis(5));
+  let second = app.service("memories").patch(
+    Context::local(),
+    id.clone(),
+    object! { number: "3" },
+    object! { oid: WID, ctx: ctx.clone(), revision: revision.clone() },
+  );
+  let err = second.unwrap_err();
+  assert!(matches!(err, Error::Conflict(_)));
+  assert_eq!(err.to_json()["code"], 409);
+  assert_eq!(err.to_json()["name"], "Conflict");
+  assert_eq!(err.status_code().as_u16(), 409);
+
+  // stale `update` is rejected too
+  let update = app.service("memories").update(
+    Context::local(),
+    id.clone(),
+    object! { number: "4" },
+    object! { oid: WID, ctx: ctx.clone(), revision: revision.clone() },
+  );
+  assert!(matches!(update, Err(Error::Conflict(_))));
+
+  // nothing is overwritten
+  let latest = app
+    .service("memories")
+    .get(Context::local(), id.clone(), object! { oid: WID, ctx: ctx.clone() })
+    .unwrap();
+  assert_eq!(latest["number"], "2");
+
+  // after reload with current revision
+  let merged = app
+    .service("memories")
+    .patch(
+      Context::local(),
+      id.clone(),
+      object! { number: "3" },
+      object! { oid: WID, ctx: ctx.clone(), revision: current() },
+    )
+    .unwrap();
+  assert_eq!(merged["number"], "3");
+
+  // without revision writes are not checked
+  app
+    .service("memories")
+    .patch(
+      Context::local(),
+      id.clone(),
+      object! { number: "5" },
+      object! { oid: WID, ctx: ctx.clone() },
+    )
+    .unwrap();
+
+  // writes at the same millisecond don't share revision
+  let revision = current();
+  let count = revisions().len();
+  for number in ["6", "7", "8"] {
+    app
+      .service("memories")
+      .patch(
+        Context::local(),
+        id.clone(),
+        object! { number: number },
+        object! { oid: WID, ctx: ctx.clone() },
+      )
+      .unwrap();
+  }
+  let mut names = revisions();
+  assert_eq!(names.len(), count + 3);
+  names.dedup();
+  assert_eq!(names.len(), count + 3);
+
+  let stale = app.service("memories").patch(
+    Context::local(),
+    id.clone(),
+    object! { number: "9" },
+    object! { oid: WID, ctx: ctx.clone(), revision: revision },
+  );
+  assert!(matches!(stale, Err(Error::Conflict(_))));
+
+  // concurrent patches without revision keep changes of each other
+  let fields = ["supplier", "comment", "contract", "invoice"];
+  let writers: Vec<_> = fields
+    .into_iter()
+    .map(|field| {
+      let (app, id, ctx) = (app.clone(), id.clone(), ctx.clone());
+      std::thread::spawn(move || {
+        let mut data = object! {};
+        data[field] = "set".into();
+        app
+          .service("memories")
+          .patch(Context::local(), id, data, object! { oid: WID, ctx: ctx })
+      })
+    })
+    .collect();
+  for writer in writers {
+    writer.join().unwrap().unwrap();
+  }
+
+  let doc = app
+    .service("memories")
+    .get(Context::local(), id.clone(), object! { oid: WID, ctx: ctx.clone() })
+    .unwrap();
+  for field in fields {
+    assert_eq!(doc[field], "set", "{field} is lost");
+  }
+  assert_eq!(doc["_revision"], current().as_str());
+
+  tmp_dir.close().unwrap();
+}
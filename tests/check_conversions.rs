@@ -0,0 +1,67 @@
+mod test_init;
+
+use json::object;
+use rust_decimal::Decimal;
+use std::sync::Arc;
+
+use crate::test_init::{goods, init, uom, DocumentCreation};
+use nae_backend::commutator::Application;
+use nae_backend::memories::MemoriesInFiles;
+use nae_backend::storage::Workspaces;
+use service::utils::json::JsonParams;
+use service::{Context, Services};
+use store::qty::{Number, Qty};
+use store::GetWarehouse;
+
+const WID: &str = "yjmgJUmDo_kn9uxVi8s9Mj9mgGRJISxRt63wT46NyTQ";
+
+#[actix_web::test]
+async fn check_conversions() {
+  let (tmp_dir, settings, db) = init();
+
+  let wss = Workspaces::new(tmp_dir.path().join("companies"));
+
+  let (mut app, _) = Application::new(Arc::new(settings), Arc::new(db), wss).await.unwrap();
+
+  app.register(MemoriesInFiles::new(app.clone(), "memories"));
+
+  let paint = goods(&app, "краска");
+  let kg = uom(&app, "кг");
+  let g = uom(&app, "г");
+
+  let conversions = || app.warehouse().database.conversions.clone();
+  let grams = |n: u32| Qty::new(vec![Number::new(Decimal::from(n), g, None)]);
+  let kilos = |n: u32| Qty::new(vec![Number::new(Decimal::from(n), kg, None)]);
+
+  let record = vec!["warehouse", "conversion"].create(
+    &app,
+    object! { goods: paint.to_string(), from: kg.to_string(), into: g.to_string(), factor: "1000" },
+  );
+  assert_eq!(conversions().list().unwrap().len(), 1);
+
+  let factors = conversions().of(paint);
+  let rest = kilos(2).sub_with(&grams(500), &factors);
+  assert_eq!(rest, Qty::new(vec![Number::new(Decimal::new(15, 1), kg, None)]));
+
+  let update = |factor: &str| {
+    let mut data = record.clone();
+    data["factor"] = factor.into();
+    app.service("memories").update(
+      Context::local(),
+      record["_id"].string(),
+      data,
+      object! { oid: WID, ctx: vec!["warehouse", "conversion"] },
+    )
+  };
+
+  // invalid factor keep the recorded one
+  assert!(update("0").is_err());
+  assert_eq!(conversions().list().unwrap()[0].factor, Decimal::from(1000));
+
+  update("100").unwrap();
+  let list = conversions().list().unwrap();
+  assert_eq!(list.len(), 1);
+  assert_eq!(list[0].factor, Decimal::from(100));
+
+  tmp_dir.close().unwrap();
+}
@@ -0,0 +1,62 @@
+mod test_init;
+
+use json::object;
+use std::sync::Arc;
+
+use crate::test_init::{goods, init, store, DocumentCreation};
+use nae_backend::commutator::Application;
+use nae_backend::memories::MemoriesInFiles;
+use nae_backend::storage::Workspaces;
+use service::utils::json::JsonParams;
+use service::{Context, Services};
+use store::costing::CostingMethod;
+use store::GetWarehouse;
+
+const WID: &str = "yjmgJUmDo_kn9uxVi8s9Mj9mgGRJISxRt63wT46NyTQ";
+
+#[actix_web::test]
+async fn check_costing() {
+  let (tmp_dir, settings, db) = init();
+
+  let wss = Workspaces::new(tmp_dir.path().join("companies"));
+
+  let (mut app, _) = Application::new(Arc::new(settings), Arc::new(db), wss).await.unwrap();
+
+  app.register(MemoriesInFiles::new(app.clone(), "memories"));
+
+  let s1 = store(&app, "склад");
+  let paint = goods(&app, "краска");
+  let milk = goods(&app, "молоко");
+
+  let costing = || app.warehouse().database.costing.clone();
+
+  // store default
+  vec!["warehouse", "costing"].create(&app, object! { storage: s1.to_string(), method: "lifo" });
+  assert_eq!(costing().get(s1, paint).unwrap(), CostingMethod::Lifo);
+
+  // goods override the store one
+  let record = vec!["warehouse", "costing"]
+    .create(&app, object! { storage: s1.to_string(), goods: milk.to_string(), method: "fefo" });
+  assert_eq!(costing().get(s1, milk).unwrap(), CostingMethod::Fefo);
+  assert_eq!(costing().get(s1, paint).unwrap(), CostingMethod::Lifo);
+
+  let update = |method: &str| {
+    let mut data = record.clone();
+    data["method"] = method.into();
+    app.service("memories").update(
+      Context::local(),
+      record["_id"].string(),
+      data,
+      object! { oid: WID, ctx: vec!["warehouse", "costing"] },
+    )
+  };
+
+  update("average").unwrap();
+  assert_eq!(costing().get(s1, milk).unwrap(), CostingMethod::WeightedAverage);
+
+  // unknown method keep the recorded one
+  assert!(update("random").is_err());
+  assert_eq!(costing().get(s1, milk).unwrap(), CostingMethod::WeightedAverage);
+
+  tmp_dir.close().unwrap();
+}
@@ -0,0 +1,73 @@
+mod test_init;
+
+use json::{object, JsonValue};
+use rust_decimal::Decimal;
+use std::sync::Arc;
+
+use crate::test_init::{goods, init, store, uom, DocumentCreation};
+use nae_backend::commutator::Application;
+use nae_backend::memories::MemoriesInFiles;
+use nae_backend::storage::Workspaces;
+use service::utils::json::JsonParams;
+use service::{Context, Services};
+use store::batch::Batch;
+use store::elements::dt;
+use store::qty::{Number, Qty};
+use store::GetWarehouse;
+
+const WID: &str = "yjmgJUmDo_kn9uxVi8s9Mj9mgGRJISxRt63wT46NyTQ";
+
+#[actix_web::test]
+async fn check_expiry() {
+  let (tmp_dir, settings, db) = init();
+
+  let wss = Workspaces::new(tmp_dir.path().join("companies"));
+
+  let (mut app, _) = Application::new(Arc::new(settings), Arc::new(db), wss).await.unwrap();
+
+  app.register(MemoriesInFiles::new(app.clone(), "memories"));
+  app.register(nae_backend::inventory::service::Inventory::new(app.clone()));
+
+  let s1 = store(&app, "склад");
+  let s2 = store(&app, "магазин");
+  let milk = goods(&app, "молоко");
+  let l = uom(&app, "л");
+
+  let qty =
+    |n: u32| -> JsonValue { (&Qty::new(vec![Number::new(Decimal::from(n), l, None)])).into() };
+
+  let receive = vec!["warehouse", "receive", "document"]
+    .create(&app, object! { date: "2023-01-10", storage: s1.to_string(), number: "1" });
+  let record = vec!["warehouse", "receive"].create(
+    &app,
+    object! {
+      document: receive["_id"].string(),
+      goods: milk.to_string(),
+      qty: qty(20),
+      cost: { number: "200" },
+      expiry: "2023-02-01",
+    },
+  );
+  let batch = Batch { id: record["_uuid"].uuid().unwrap(), date: dt("2023-01-10").unwrap() };
+
+  let expiries = &app.warehouse().database.expiries;
+  assert_eq!(expiries.get(milk, &batch).unwrap(), Some(dt("2023-02-01").unwrap()));
+
+  let find = |filter: JsonValue| {
+    app
+      .service("inventory")
+      .find(Context::local(), object! { oid: WID, filter: filter })
+  };
+
+  // expiry date is long gone
+  let result = find(object! { expiring: 0 }).unwrap();
+  assert_eq!(result["total"], 1);
+  assert_eq!(result["data"][0]["store"]["name"], "склад");
+  assert_eq!(result["data"][0]["goods"]["name"], "молоко");
+  assert_eq!(result["data"][0]["expiry"], "2023-02-01");
+  assert_eq!(result["data"][0]["cost"], "200");
+
+  assert_eq!(find(object! { expiring: 0, storage: s2.to_string() }).unwrap()["total"], 0);
+
+  tmp_dir.close().unwrap();
+}
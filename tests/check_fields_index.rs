@@ -0,0 +1,87 @@
+mod test_init;
+
+use json::{object, JsonValue};
+use std::sync::Arc;
+
+use crate::test_init::{init, store, DocumentCreation};
+use nae_backend::commutator::Application;
+use nae_backend::memories::MemoriesInFiles;
+use nae_backend::storage::Workspaces;
+use service::utils::json::JsonParams;
+use service::{Context, Services};
+
+const WID: &str = "yjmgJUmDo_kn9uxVi8s9Mj9mgGRJISxRt63wT46NyTQ";
+
+#[actix_web::test]
+async fn check_fields_index() {
+  let (tmp_dir, settings, db) = init();
+
+  let wss = Workspaces::new(tmp_dir.path().join("companies"));
+
+  let (mut app, _) = Application::new(Arc::new(settings), Arc::new(db), wss).await.unwrap();
+
+  app.register(MemoriesInFiles::new(app.clone(), "memories"));
+
+  let s1 = store(&app, "склад");
+  let s2 = store(&app, "магазин");
+
+  let ctx = vec!["warehouse", "receive", "document"];
+  let mut ids = vec![];
+  for (date, storage, number) in [
+    ("2023-01-10", s1, "1"),
+    ("2023-01-20", s2, "2"),
+    ("2023-02-01", s1, "3"),
+    ("2023-02-15", s1, "4"),
+    ("2023-03-01", s2, "5"),
+  ] {
+    let doc = ctx
+      .clone()
+      .create(&app, object! { date: date, storage: storage.to_string(), number: number });
+    ids.push(doc["_id"].string());
+  }
+
+  let find = |filter: JsonValue| -> Vec<String> {
+    let result = app
+      .service("memories")
+      .find(Context::local(), object! { oid: WID, ctx: ctx.clone(), filter: filter, reverse: true })
+      .unwrap();
+    result["data"].members().map(|d| d["number"].string()).collect()
+  };
+
+  assert_eq!(find(object! { storage: s1.to_string() }), vec!["1", "3", "4"]);
+  assert_eq!(find(object! { "$starts-with": { date: "2023-02" } }), vec!["3", "4"]);
+  assert_eq!(
+    find(object! { date: { "$gte": "2023-01-20", "$lt": "2023-03-01" } }),
+    vec!["2", "3", "4"]
+  );
+  assert_eq!(
+    find(object! { date: { "$gt": "2023-01-20", "$lte": "2023-03-01" } }),
+    vec!["3", "4", "5"]
+  );
+  // indexed and not indexed fields together
+  assert_eq!(
+    find(object! { storage: s1.to_string(), date: { "$lte": "2023-02-01" }, number: "3" }),
+    vec!["3"]
+  );
+  assert!(find(object! { storage: s2.to_string(), "$starts-with": { date: "2023-02" } }).is_empty());
+
+  // index follows changes
+  app
+    .service("memories")
+    .patch(
+      Context::local(),
+      ids[0].clone(),
+      object! { storage: s2.to_string() },
+      object! { oid: WID, ctx: ctx.clone() },
+    )
+    .unwrap();
+  assert_eq!(find(object! { storage: s1.to_string() }), vec!["3", "4"]);
+  assert_eq!(find(object! { storage: s2.to_string() }), vec!["1", "2", "5"]);
+
+  // documents of older database are indexed once at start, stores and receive documents here
+  assert_eq!(app.index_fields().unwrap(), 7);
+  assert_eq!(app.index_fields().unwrap(), 0);
+  assert_eq!(find(object! { storage: s1.to_string() }), vec!["3", "4"]);
+
+  tmp_dir.close().unwrap();
+}
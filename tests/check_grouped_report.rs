@@ -0,0 +1,93 @@
+mod test_init;
+
+use json::object;
+use std::sync::Arc;
+
+use crate::test_init::init;
+use nae_backend::commutator::Application;
+use nae_backend::memories::{MemoriesImport, MemoriesInFiles};
+use nae_backend::storage::Workspaces;
+use service::utils::json::JsonParams;
+use service::{Context, Services};
+
+const WID: &str = "yjmgJUmDo_kn9uxVi8s9Mj9mgGRJISxRt63wT46NyTQ";
+
+#[actix_web::test]
+async fn check_grouped_report() {
+  let (tmp_dir, settings, db) = init();
+
+  let wss = Workspaces::new(tmp_dir.path().join("companies"));
+
+  let (mut app, _) = Application::new(Arc::new(settings), Arc::new(db), wss).await.unwrap();
+
+  app.register(MemoriesInFiles::new(app.clone(), "memories"));
+  app.register(MemoriesImport::new(app.clone(), "memories-import"));
+  app.register(nae_backend::inventory::service::Inventory::new(app.clone()));
+
+  let content = "Номер,Дата,Товар,Категория,Кол-во,Цена,Поставщик,Склад\n\
+    1,10.01.2023,краска,химия,5 кг,50,поставщик 1,склад\n\
+    1,10.01.2023,растворитель,химия,2 кг,10,поставщик 1,склад\n\
+    2,15.01.2023,гвозди,метизы,10 кг,20,поставщик 2,склад\n";
+
+  let data = object! {
+    mapping: {
+      document: "receive",
+      header: true,
+      columns: {
+        number: "Номер", date: "Дата", goods: "Товар", category: "Категория", qty: "Кол-во",
+        cost: "Цена", from: "Поставщик", into: "Склад",
+      },
+      create_missing: true,
+    },
+    format: "csv",
+    content: content,
+  };
+  let report = app
+    .service("memories-import")
+    .create(Context::local(), data, object! { oid: WID })
+    .unwrap();
+  assert_eq!(report["imported"], 3);
+
+  let find = |group_by: Vec<&str>| {
+    let params = object! {
+      oid: WID,
+      filter: {
+        dates: { from: "2023-01-01", till: "2023-01-31" },
+        group_by: group_by,
+        measures: ["receive_cost", "close_cost"],
+      },
+    };
+    let result = app.service("inventory").find(Context::local(), params).unwrap();
+    result["data"][0]["total"].clone()
+  };
+
+  let total = find(vec!["counterparty", "category"]);
+  assert_eq!(total["receive_cost"], "80");
+  assert_eq!(total["dimension"], "counterparty");
+  assert_eq!(total["children"].len(), 2);
+  assert!(total["open_cost"].is_null());
+
+  let mut costs: Vec<(String, String)> = total["children"]
+    .members()
+    .map(|c| (c["children"].len().to_string(), c["close_cost"].string()))
+    .collect();
+  costs.sort();
+  assert_eq!(costs, vec![("1".into(), "20".into()), ("1".into(), "60".into())]);
+
+  let total = find(vec!["category"]);
+  let mut categories: Vec<String> =
+    total["children"].members().map(|c| c["close_cost"].string()).collect();
+  categories.sort();
+  assert_eq!(categories, vec!["20".to_string(), "60".to_string()]);
+
+  // one group for every document
+  let total = find(vec!["store", "document"]);
+  assert_eq!(total["children"].len(), 1);
+  assert_eq!(total["children"][0]["children"].len(), 2);
+
+  let params = object! {
+    oid: WID,
+    filter: { dates: { from: "2023-01-01", till: "2023-01-31" }, group_by: ["colour"] },
+  };
+  assert!(app.service("inventory").find(Context::local(), params).is_err());
+}
@@ -0,0 +1,121 @@
+mod test_init;
+
+use json::object;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::test_init::init;
+use nae_backend::commutator::Application;
+use nae_backend::memories::{MemoriesHistory, MemoriesInFiles};
+use nae_backend::storage::Workspaces;
+use service::utils::json::JsonParams;
+use service::{Context, Services};
+
+const WID: &str = "yjmgJUmDo_kn9uxVi8s9Mj9mgGRJISxRt63wT46NyTQ";
+
+#[actix_web::test]
+async fn check_history() {
+  let (tmp_dir, settings, db) = init();
+
+  let wss = Workspaces::new(tmp_dir.path().join("companies"));
+
+  let (mut app, _) = Application::new(Arc::new(settings), Arc::new(db), wss).await.unwrap();
+
+  app.register(MemoriesInFiles::new(app.clone(), "memories"));
+  app.register(MemoriesHistory::new(app.clone(), "memories-history"));
+
+  let ctx = vec!["warehouse", "receive", "document"];
+  let params = object! { oid: WID, ctx: ctx.clone() };
+
+  let doc = app
+    .service("memories")
+    .create(Context::local(), object! { date: "2023-01-10", number: "1" }, params.clone())
+    .unwrap();
+  let id = doc["_id"].string();
+
+  // revisions are named by time in milliseconds
+  std::thread::sleep(Duration::from_millis(5));
+  app
+    .service("memories")
+    .patch(Context::local(), id.clone(), object! { number: "2" }, params.clone())
+    .unwrap();
+
+  std::thread::sleep(Duration::from_millis(5));
+  app
+    .service("memories")
+    .patch(
+      Context::local(),
+      id.clone(),
+      object! { date: "2023-01-11", comment: "fix" },
+      params.clone(),
+    )
+    .unwrap();
+
+  let history = app
+    .service("memories-history")
+    .find(Context::local(), object! { oid: WID, ctx: ctx.clone(), id: id.clone() })
+    .unwrap();
+  assert_eq!(history["total"], 3);
+
+  let revisions: Vec<String> = history["data"].members().map(|r| r["revision"].string()).collect();
+  assert!(revisions.windows(2).all(|w| w[0] < w[1]));
+  for revision in history["data"].members() {
+    assert_eq!(revision["account"], Context::local().account.read().unwrap().id.to_base64());
+  }
+
+  // point-in-time reads
+  let at = |at: &str| {
+    app.service("memories").get(
+      Context::local(),
+      id.clone(),
+      object! { oid: WID, ctx: ctx.clone(), at: at },
+    )
+  };
+  assert_eq!(at(&revisions[0]).unwrap()["number"], "1");
+  assert_eq!(at(&revisions[1]).unwrap()["number"], "2");
+  assert_eq!(at(&revisions[1]).unwrap()["date"], "2023-01-10");
+  assert_eq!(at(&revisions[2]).unwrap()["date"], "2023-01-11");
+  assert!(at("2000-01-01T00:00:00.000Z").is_err());
+
+  // latest revision by default
+  let latest = app
+    .service("memories")
+    .get(Context::local(), id.clone(), params.clone())
+    .unwrap();
+  assert_eq!(latest["comment"], "fix");
+
+  // changes of last revision
+  let diff = app
+    .service("memories-history")
+    .get(Context::local(), id.clone(), params.clone())
+    .unwrap();
+  assert_eq!(diff["from"], revisions[1].as_str());
+  assert_eq!(diff["to"], revisions[2].as_str());
+  let changes: Vec<(String, String, String)> = diff["changes"]
+    .members()
+    .map(|c| (c["path"].string(), c["before"].string(), c["after"].string()))
+    .collect();
+  assert_eq!(
+    changes,
+    vec![
+      ("date".into(), "2023-01-10".into(), "2023-01-11".into()),
+      ("comment".into(), "".into(), "fix".into()),
+    ]
+  );
+
+  // between any two revisions
+  let diff = app
+    .service("memories-history")
+    .get(
+      Context::local(),
+      id.clone(),
+      object! { oid: WID, ctx: ctx.clone(), from: revisions[0].clone(), to: revisions[1].clone() },
+    )
+    .unwrap();
+  assert_eq!(diff["changes"].len(), 1);
+  assert_eq!(diff["changes"][0]["path"], "number");
+  assert_eq!(diff["changes"][0]["before"], "1");
+  assert_eq!(diff["changes"][0]["after"], "2");
+
+  tmp_dir.close().unwrap();
+}
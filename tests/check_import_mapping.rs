@@ -0,0 +1,134 @@
+mod test_init;
+
+use json::object;
+use std::sync::Arc;
+
+use crate::test_init::init;
+use nae_backend::commutator::Application;
+use nae_backend::memories::{MemoriesImport, MemoriesInFiles};
+use nae_backend::storage::Workspaces;
+use service::utils::json::JsonParams;
+use service::{Context, Services};
+use store::balance::BalanceForGoods;
+use store::negative_stock::NegativeStockPolicy;
+use store::GetWarehouse;
+use values::c;
+
+const WID: &str = "yjmgJUmDo_kn9uxVi8s9Mj9mgGRJISxRt63wT46NyTQ";
+
+#[actix_web::test]
+async fn check_import_mapping() {
+  let (tmp_dir, settings, db) = init();
+
+  let wss = Workspaces::new(tmp_dir.path().join("companies"));
+
+  let (mut app, _) = Application::new(Arc::new(settings), Arc::new(db), wss).await.unwrap();
+
+  app.register(MemoriesInFiles::new(app.clone(), "memories"));
+  app.register(MemoriesImport::new(app.clone(), "memories-import"));
+  app.register(nae_backend::inventory::service::Inventory::new(app.clone()));
+
+  let create = |name: &str, ctx: Vec<&str>| {
+    app
+      .service("memories")
+      .create(Context::local(), object! { name: name }, object! { oid: WID, ctx: ctx })
+      .unwrap()
+  };
+  let storage = create("склад", vec!["warehouse", "storage"]);
+  create("краска", vec!["goods"]);
+  create("кг", vec!["uom"]);
+
+  let import = |content: &str, dry_run: bool| {
+    let data = object! {
+      mapping: {
+        document: "receive",
+        header: true,
+        columns: { number: "Номер", date: "Дата", goods: "Товар", qty: "Кол-во", cost: "Цена", into: "Склад" },
+        units: { "килограмм": "кг" },
+      },
+      format: "csv",
+      content: content,
+      dry_run: dry_run,
+    };
+    app
+      .service("memories-import")
+      .create(Context::local(), data, object! { oid: WID })
+  };
+
+  let header = "Номер,Дата,Товар,Кол-во,Цена,Склад\n";
+  let valid = "1,13.03.2023,краска,5 килограмм,50,склад\n1,13.03.2023,краска,\"2,5 кг\",25,склад\n";
+  let invalid = "2,14.03.2023,гвозди,10 кг,10,склад\n3,15.03.2023,краска,много,1,склад\n";
+
+  let report = import(&format!("{header}{valid}{invalid}"), true).unwrap();
+  assert_eq!(report["rows"], 4);
+  assert_eq!(report["imported"], 0);
+  assert_eq!(report["errors"][0], object! { row: 4, error: "goods `гвозди` not found" });
+  assert_eq!(report["errors"][1], object! { row: 5, error: "qty `много` is not valid" });
+
+  // nothing is imported while some row has error
+  let report = import(&format!("{header}{valid}{invalid}"), false).unwrap();
+  assert_eq!(report["imported"], 0);
+  assert_eq!(report["errors"].len(), 2);
+
+  let report = import(&format!("{header}{valid}"), true).unwrap();
+  assert!(report["errors"].is_empty());
+  assert_eq!(report["imported"], 0);
+  assert!(app
+    .warehouse()
+    .database
+    .get_balance_for_all(chrono::Utc::now())
+    .unwrap()
+    .is_empty());
+
+  let report = import(&format!("{header}{valid}"), false).unwrap();
+  assert_eq!(report["imported"], 2);
+  assert_eq!(report["documents"], 1);
+
+  let storage_id = storage[c::UUID].uuid().unwrap();
+  let stock = || {
+    let balances = app.warehouse().database.get_balance_for_all(chrono::Utc::now()).unwrap();
+    let mut balance = BalanceForGoods::default();
+    for batches in balances[&storage_id].values() {
+      for b in batches.values() {
+        balance += b.clone();
+      }
+    }
+    balance
+  };
+  let balance = stock();
+  assert_eq!(balance.cost, 75.into());
+
+  // rows stored before failed one are rolled back
+  app
+    .warehouse()
+    .set_negative_stock(storage_id, NegativeStockPolicy::Reject)
+    .unwrap();
+
+  let data = object! {
+    mapping: {
+      document: "dispatch",
+      header: false,
+      columns: { number: 0, date: 1, goods: 2, qty: 3, from: 4 },
+    },
+    content: "4,20.03.2023,краска,1 кг,склад\n4,20.03.2023,краска,100 кг,склад\n",
+  };
+  let result = app
+    .service("memories-import")
+    .create(Context::local(), data, object! { oid: WID });
+  assert!(result.is_err());
+
+  assert_eq!(stock(), balance);
+  let documents = app
+    .service("memories")
+    .find(Context::local(), object! { oid: WID, ctx: vec!["warehouse", "dispatch", "document"] })
+    .unwrap();
+  assert!(documents["data"].members().all(|d| d[c::STATUS] == c::DELETED));
+
+  // mapping without required column
+  let data =
+    object! { mapping: { document: "transfer", columns: { date: 0, goods: 1, qty: 2, into: 3 } } };
+  let result = app
+    .service("memories-import")
+    .create(Context::local(), data, object! { oid: WID });
+  assert!(result.is_err());
+}
@@ -0,0 +1,123 @@
+mod test_init;
+
+use rust_decimal::Decimal;
+use std::sync::Arc;
+
+use crate::test_init::{goods, init, receive, store, transfer, uom};
+use nae_backend::commutator::Application;
+use nae_backend::inventory::export::{movement_report, to_csv, to_xlsx, Cell};
+use nae_backend::memories::MemoriesInFiles;
+use nae_backend::storage::Workspaces;
+use service::Services;
+use store::elements::dt;
+use store::import::{read_rows, Format};
+use store::qty::{Number, Qty};
+use values::ID;
+
+const WID: &str = "yjmgJUmDo_kn9uxVi8s9Mj9mgGRJISxRt63wT46NyTQ";
+
+#[actix_web::test]
+async fn check_inventory_export() {
+  let (tmp_dir, settings, db) = init();
+
+  let wss = Workspaces::new(tmp_dir.path().join("companies"));
+
+  let (mut app, _) = Application::new(Arc::new(settings), Arc::new(db), wss).await.unwrap();
+
+  app.register(MemoriesInFiles::new(app.clone(), "memories"));
+  app.register(nae_backend::inventory::service::Inventory::new(app.clone()));
+
+  let s1 = store(&app, "склад");
+  let s2 = store(&app, "цех");
+  let paint = goods(&app, "краска");
+  let nails = goods(&app, "гвозди");
+  let kg = uom(&app, "кг");
+
+  let qty = |n: u32| Qty::new(vec![Number::new(Decimal::from(n), kg, None)]);
+
+  receive(&app, "2023-01-10", s1, paint, qty(5), 50.into());
+  receive(&app, "2023-01-10", s1, nails, qty(10), 20.into());
+  transfer(&app, "2023-01-15", s1, s2, paint, qty(2));
+
+  let ws = app.wss.get(&ID::from_base64(WID).unwrap());
+  let (from, till) = (dt("2023-01-01").unwrap(), dt("2023-01-31").unwrap());
+
+  let rows = movement_report(&app, &ws, &[s1, s2], from, till).unwrap();
+
+  let text = |s: &str| Cell::Text(s.into());
+  let num = |n: u32| Cell::Number(Decimal::from(n));
+
+  // goods are sorted by name, every store has subtotal and last row is total of all stores
+  assert_eq!(rows.len(), 6);
+  assert_eq!(
+    rows[0].cells,
+    vec![
+      text("склад"),
+      text("гвозди"),
+      text("кг"),
+      num(0),
+      num(0),
+      num(10),
+      num(20),
+      num(0),
+      num(0),
+      num(10),
+      num(20)
+    ]
+  );
+  assert_eq!(
+    rows[1].cells,
+    vec![
+      text("склад"),
+      text("краска"),
+      text("кг"),
+      num(0),
+      num(0),
+      num(5),
+      num(50),
+      num(2),
+      num(20),
+      num(3),
+      num(30)
+    ]
+  );
+  assert!(!rows[1].total);
+  assert_eq!(
+    rows[2].cells,
+    vec![
+      text("склад"),
+      text("total"),
+      Cell::Empty,
+      Cell::Empty,
+      num(0),
+      Cell::Empty,
+      num(70),
+      Cell::Empty,
+      num(20),
+      Cell::Empty,
+      num(50)
+    ]
+  );
+  assert!(rows[2].total);
+  assert_eq!(rows[3].cells[..3], [text("цех"), text("краска"), text("кг")]);
+  assert_eq!(rows[4].cells[1], text("total"));
+  assert_eq!(rows[5].cells[0], Cell::Empty);
+  assert_eq!(rows[5].cells[6], num(90));
+  assert_eq!(rows[5].cells[10], num(70));
+
+  let csv = String::from_utf8(to_csv(&rows).unwrap()).unwrap();
+  let mut lines = csv.lines();
+  assert_eq!(
+    lines.next(),
+    Some("storage,goods,uom,open qty,open cost,receive qty,receive cost,issue qty,issue cost,close qty,close cost")
+  );
+  assert_eq!(lines.next(), Some("склад,гвозди,кг,0,0,10,20,0,0,10,20"));
+
+  let xlsx = read_rows(Format::Xlsx, &to_xlsx(&rows).unwrap(), b',').unwrap();
+  assert_eq!(xlsx.len(), 7);
+  assert_eq!(xlsx[0][0], "storage");
+  assert_eq!(xlsx[2][1], "краска");
+  assert_eq!(xlsx[3][1], "total");
+  assert_eq!(xlsx[3][6], "70");
+  assert_eq!(xlsx[6][10], "70");
+}
@@ -0,0 +1,125 @@
+mod test_init;
+
+use json::{object, JsonValue};
+use rust_decimal::Decimal;
+use std::sync::Arc;
+
+use crate::test_init::{goods, init, store, uom, DocumentCreation};
+use nae_backend::commutator::Application;
+use nae_backend::memories::MemoriesInFiles;
+use nae_backend::storage::Workspaces;
+use service::utils::json::JsonParams;
+use service::{Context, Services};
+use store::qty::{Number, Qty};
+use uuid::Uuid;
+
+const WID: &str = "yjmgJUmDo_kn9uxVi8s9Mj9mgGRJISxRt63wT46NyTQ";
+
+#[actix_web::test]
+async fn check_lineage() {
+  let (tmp_dir, settings, db) = init();
+
+  let wss = Workspaces::new(tmp_dir.path().join("companies"));
+
+  let (mut app, _) = Application::new(Arc::new(settings), Arc::new(db), wss).await.unwrap();
+
+  app.register(MemoriesInFiles::new(app.clone(), "memories"));
+  app.register(nae_backend::inventory::service::Inventory::new(app.clone()));
+
+  let materials = store(&app, "склад сырья");
+  let products = store(&app, "склад продукции");
+  let granules = goods(&app, "гранулы");
+  let film = goods(&app, "пленка");
+  let kg = uom(&app, "кг");
+
+  let qty =
+    |n: u32| -> JsonValue { (&Qty::new(vec![Number::new(Decimal::from(n), kg, None)])).into() };
+
+  // raw material
+  let receive = vec!["warehouse", "receive", "document"]
+    .create(&app, object! { date: "2023-01-01", storage: materials.to_string(), number: "1" });
+  let raw = vec!["warehouse", "receive"].create(
+    &app,
+    object! {
+      document: receive["_id"].string(),
+      goods: granules.to_string(),
+      qty: qty(100),
+      cost: { number: "1000" },
+    },
+  );
+  let raw_batch = raw["_uuid"].uuid().unwrap();
+
+  // production
+  let area = vec!["production", "area"]
+    .create(&app, object! { name: "экструдер", storage: products.to_string() });
+  let product = vec!["product"].create(&app, object! { name: "пленка", goods: film.to_string() });
+  let order = vec!["production", "order"].create(
+    &app,
+    object! { date: "2023-01-05", area: area["_id"].string(), product: product["_id"].string() },
+  );
+  vec!["production", "material", "used"].create(
+    &app,
+    object! {
+      document: order["_id"].string(),
+      storage: materials.to_string(),
+      goods: granules.to_string(),
+      qty: qty(40),
+      batch: { id: raw_batch.to_string(), date: "2023-01-01" },
+    },
+  );
+  vec!["production", "produce"]
+    .create(&app, object! { document: order["_id"].string(), qty: qty(30) });
+  let produced_batch = order["_uuid"].uuid().unwrap();
+
+  // finished goods leave
+  let dispatch = vec!["warehouse", "dispatch", "document"]
+    .create(&app, object! { date: "2023-01-10", storage: products.to_string(), number: "1" });
+  vec!["warehouse", "dispatch"].create(
+    &app,
+    object! {
+      document: dispatch["_id"].string(),
+      goods: film.to_string(),
+      qty: qty(10),
+      batch: { id: produced_batch.to_string(), date: "2023-01-05" },
+    },
+  );
+
+  let find = |direction: &str, goods: Uuid, batch: Uuid, date: &str| {
+    let params = object! {
+      oid: WID,
+      filter: { lineage: direction, goods: goods.to_string(), batch_id: batch.to_string(), batch_date: date },
+    };
+    app.service("inventory").find(Context::local(), params).unwrap()["data"][0].clone()
+  };
+  let edge = |lineage: &JsonValue, from: String, into: String| -> Qty {
+    let edge = lineage["edges"].members().find(|e| e["from"] == from && e["into"] == into);
+    edge.unwrap()["qty"].clone().try_into().unwrap()
+  };
+  let q = |n: u32| Qty::new(vec![Number::new(Decimal::from(n), kg, None)]);
+
+  let raw_node = format!("{granules}/{raw_batch}");
+  let produced_node = format!("{film}/{produced_batch}");
+
+  // recall of raw material
+  let forward = find("forward", granules, raw_batch, "2023-01-01");
+  assert_eq!(forward["edges"].len(), 3);
+  assert_eq!(forward["nodes"].len(), 4);
+  assert_eq!(edge(&forward, raw_node.clone(), order["_id"].string()), q(40));
+  assert_eq!(edge(&forward, order["_id"].string(), produced_node.clone()), q(30));
+  assert_eq!(edge(&forward, produced_node.clone(), dispatch["_id"].string()), q(10));
+
+  // origin of finished goods
+  let backward = find("backward", film, produced_batch, "2023-01-05");
+  assert_eq!(backward["edges"].len(), 3);
+  assert_eq!(edge(&backward, order["_id"].string(), produced_node), q(30));
+  assert_eq!(edge(&backward, raw_node.clone(), order["_id"].string()), q(40));
+  assert_eq!(edge(&backward, receive["_id"].string(), raw_node), q(100));
+
+  let params = object! {
+    oid: WID,
+    filter: { lineage: "sideways", goods: film.to_string(), batch_id: produced_batch.to_string(), batch_date: "2023-01-05" },
+  };
+  assert!(app.service("inventory").find(Context::local(), params).is_err());
+
+  tmp_dir.close().unwrap();
+}
@@ -0,0 +1,99 @@
+mod test_init;
+
+use json::{object, JsonValue};
+use rust_decimal::Decimal;
+use std::sync::Arc;
+
+use crate::test_init::{goods, init, uom, DocumentCreation};
+use nae_backend::commutator::Application;
+use nae_backend::memories::MemoriesInFiles;
+use nae_backend::storage::Workspaces;
+use service::error::Error;
+use service::utils::json::JsonParams;
+use service::{Context, Services};
+use store::negative_stock::NegativeStockPolicy;
+use store::qty::{Number, Qty};
+use store::GetWarehouse;
+
+const WID: &str = "yjmgJUmDo_kn9uxVi8s9Mj9mgGRJISxRt63wT46NyTQ";
+
+#[actix_web::test]
+async fn check_negative_stock() {
+  let (tmp_dir, settings, db) = init();
+
+  let wss = Workspaces::new(tmp_dir.path().join("companies"));
+
+  let (mut app, _) = Application::new(Arc::new(settings), Arc::new(db), wss).await.unwrap();
+
+  app.register(MemoriesInFiles::new(app.clone(), "memories"));
+
+  let storage = vec!["warehouse", "storage"].create(&app, object! { name: "склад" });
+  let s1 = storage["_uuid"].uuid().unwrap();
+  let milk = goods(&app, "молоко");
+  let l = uom(&app, "л");
+
+  let qty =
+    |n: u32| -> JsonValue { (&Qty::new(vec![Number::new(Decimal::from(n), l, None)])).into() };
+
+  let receive = vec!["warehouse", "receive", "document"]
+    .create(&app, object! { date: "2023-01-10", storage: s1.to_string(), number: "1" });
+  let record = vec!["warehouse", "receive"].create(
+    &app,
+    object! {
+      document: receive["_id"].string(),
+      goods: milk.to_string(),
+      qty: qty(2),
+      cost: { number: "20" },
+    },
+  );
+
+  // policy is setting of storage document
+  let set_policy = |policy: &str| {
+    app
+      .service("memories")
+      .patch(
+        Context::local(),
+        storage["_id"].string(),
+        object! { negative_stock: policy },
+        object! { oid: WID, ctx: vec!["warehouse", "storage"] },
+      )
+      .unwrap();
+  };
+  set_policy("reject");
+  let policies = &app.warehouse().database.negative_stock;
+  assert_eq!(policies.get(s1).unwrap(), NegativeStockPolicy::Reject);
+
+  let dispatch = vec!["warehouse", "dispatch", "document"]
+    .create(&app, object! { date: "2023-01-12", storage: s1.to_string(), number: "2" });
+  let issue = || {
+    app.service("memories").create(
+      Context::local(),
+      object! {
+        document: dispatch["_id"].string(),
+        goods: milk.to_string(),
+        batch: { id: record["_uuid"].string(), date: "2023-01-10" },
+        qty: qty(5),
+        cost: { number: "50" },
+      },
+      object! { oid: WID, ctx: vec!["warehouse", "dispatch"] },
+    )
+  };
+  let err = issue().unwrap_err();
+
+  // shortage reach client as data of error
+  assert!(matches!(err, Error::Unprocessable(..)));
+  let json = err.to_json();
+  assert_eq!(json["code"], 422);
+  assert_eq!(json["data"]["store"], s1.to_string());
+  assert_eq!(json["data"]["goods"], milk.to_string());
+  assert_eq!(
+    json["data"]["shortfall"],
+    Qty::new(vec![Number::new(Decimal::from(3), l, None)]).to_json()
+  );
+
+  set_policy("warn");
+  assert_eq!(policies.get(s1).unwrap(), NegativeStockPolicy::Warn);
+  issue().unwrap();
+
+  tmp_dir.close().unwrap();
+}
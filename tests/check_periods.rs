@@ -0,0 +1,129 @@
+mod test_init;
+
+use json::{object, JsonValue};
+use rust_decimal::Decimal;
+use std::sync::Arc;
+
+use crate::test_init::{goods, init, store, uom, DocumentCreation};
+use nae_backend::commutator::Application;
+use nae_backend::inventory::periods::Periods;
+use nae_backend::memories::MemoriesInFiles;
+use nae_backend::storage::Workspaces;
+use service::error::Error;
+use service::utils::json::JsonParams;
+use service::{Context, Origin, Services};
+use store::qty::{Number, Qty};
+use values::ID;
+
+const WID: &str = "yjmgJUmDo_kn9uxVi8s9Mj9mgGRJISxRt63wT46NyTQ";
+const OTHER: &str = "Fs3mvEYC9gcnSwlsKOeCL0xVnYbPbjGPZtrSK6F_Fzk";
+
+#[actix_web::test]
+async fn check_periods() {
+  let (tmp_dir, settings, db) = init();
+
+  let companies = tmp_dir.path().join("companies");
+  let wss = Workspaces::new(&companies);
+
+  let (mut app, _) = Application::new(Arc::new(settings), Arc::new(db), wss).await.unwrap();
+
+  app.register(MemoriesInFiles::new(app.clone(), "memories"));
+  app.register(Periods::new(app.clone()));
+
+  let s1 = store(&app, "склад");
+  let milk = goods(&app, "молоко");
+  let l = uom(&app, "л");
+
+  let qty =
+    |n: u32| -> JsonValue { (&Qty::new(vec![Number::new(Decimal::from(n), l, None)])).into() };
+
+  let user = ID::random();
+  let by_user = || {
+    let context = Context::local();
+    context.account.write().unwrap().id = user;
+    context
+  };
+  let by_remote_user = || Context { origin: Origin::Websocket, ..by_user() };
+
+  let receive = vec!["warehouse", "receive", "document"]
+    .create(&app, object! { date: "2023-01-10", storage: s1.to_string(), number: "1" });
+
+  let record = app
+    .service("periods")
+    .create(by_user(), object! { action: "close", date: "2023-01-20" }, object! { oid: WID })
+    .unwrap();
+  assert_eq!(record["action"], "close");
+  assert_eq!(record["by"], user.to_base64());
+
+  let found = app.service("periods").find(Context::local(), object! { oid: WID }).unwrap();
+  assert_eq!(found["total"], 1);
+  assert_eq!(found["closed_till"], record["closed_till"]);
+
+  // other workspace is not closed
+  let found = app.service("periods").find(Context::local(), object! { oid: OTHER }).unwrap();
+  assert_eq!(found["total"], 0);
+  assert!(found["closed_till"].is_null());
+
+  // closing the same period again is a conflict
+  let err = app
+    .service("periods")
+    .create(by_user(), object! { action: "close", date: "2023-01-05" }, object! { oid: WID })
+    .unwrap_err();
+  assert!(matches!(err, Error::Conflict(..)));
+
+  let err = app
+    .service("memories")
+    .create(
+      Context::local(),
+      object! {
+        document: receive["_id"].string(),
+        goods: milk.to_string(),
+        qty: qty(2),
+        cost: { number: "20" },
+      },
+      object! { oid: WID, ctx: vec!["warehouse", "receive"] },
+    )
+    .unwrap_err();
+
+  // rejection reach client as forbidden
+  assert!(matches!(err, Error::Forbidden(..)));
+  assert_eq!(err.to_json()["code"], 403);
+
+  // remote account have to be accountant of company
+  let reopen = object! { action: "reopen", date: "2023-01-01" };
+  let err = app
+    .service("periods")
+    .create(by_remote_user(), reopen.clone(), object! { oid: WID })
+    .unwrap_err();
+  assert!(matches!(err, Error::Forbidden(..)));
+
+  std::fs::create_dir_all(companies.join(WID)).unwrap();
+  std::fs::write(
+    companies.join(WID).join("organization.json"),
+    object! { accountants: [user.to_base64()] }.dump(),
+  )
+  .unwrap();
+
+  // months before january stay closed
+  let record = app
+    .service("periods")
+    .create(by_remote_user(), reopen, object! { oid: WID })
+    .unwrap();
+
+  let found = app.service("periods").find(Context::local(), object! { oid: WID }).unwrap();
+  assert_eq!(found["total"], 2);
+  assert_eq!(found["data"][0]["action"], "reopen");
+  assert_eq!(found["closed_till"], record["closed_till"]);
+
+  vec!["warehouse", "receive"].create(
+    &app,
+    object! {
+      document: receive["_id"].string(),
+      goods: milk.to_string(),
+      qty: qty(2),
+      cost: { number: "20" },
+    },
+  );
+
+  tmp_dir.close().unwrap();
+}
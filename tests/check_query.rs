@@ -0,0 +1,92 @@
+mod test_init;
+
+use json::{object, JsonValue};
+use std::sync::Arc;
+
+use crate::test_init::{init, store, DocumentCreation};
+use nae_backend::commutator::Application;
+use nae_backend::memories::MemoriesInFiles;
+use nae_backend::storage::Workspaces;
+use service::utils::json::JsonParams;
+use service::{Context, Services};
+
+const WID: &str = "yjmgJUmDo_kn9uxVi8s9Mj9mgGRJISxRt63wT46NyTQ";
+
+#[actix_web::test]
+async fn check_query() {
+  let (tmp_dir, settings, db) = init();
+
+  let wss = Workspaces::new(tmp_dir.path().join("companies"));
+
+  let (mut app, _) = Application::new(Arc::new(settings), Arc::new(db), wss).await.unwrap();
+
+  app.register(MemoriesInFiles::new(app.clone(), "memories"));
+
+  let s1 = store(&app, "склад");
+  let s2 = store(&app, "магазин");
+
+  let ctx = vec!["warehouse", "receive", "document"];
+  for (date, storage, number, sum) in [
+    ("2023-01-10", s1, "1", 30),
+    ("2023-01-20", s2, "2", 10),
+    ("2023-02-01", s1, "3", 50),
+    ("2023-02-15", s1, "4", 20),
+    ("2023-03-01", s2, "5", 40),
+  ] {
+    ctx
+      .clone()
+      .create(&app, object! { date: date, storage: storage.to_string(), number: number, sum: sum });
+  }
+
+  let query = |params: JsonValue| -> JsonValue {
+    let mut params = params;
+    params["oid"] = WID.into();
+    params["ctx"] = ctx.clone().into();
+    params["reverse"] = true.into();
+    app.service("memories").find(Context::local(), params).unwrap()
+  };
+  let numbers = |result: &JsonValue| -> Vec<String> {
+    result["data"].members().map(|d| d["number"].string()).collect()
+  };
+  let find = |filter: JsonValue| numbers(&query(object! { filter: filter }));
+
+  assert_eq!(find(object! { number: { "$in": ["2", "4", "7"] } }), vec!["2", "4"]);
+  assert_eq!(find(object! { number: { "$nin": ["2", "4"] } }), vec!["1", "3", "5"]);
+  assert_eq!(find(object! { number: { "$ne": "1" }, storage: s1.to_string() }), vec!["3", "4"]);
+  assert_eq!(find(object! { sum: { "$gt": 20, "$lte": 40 } }), vec!["1", "5"]);
+  assert_eq!(find(object! { date: { "$gte": "2023-01-20", "$lt": "2023-02-15" } }), vec!["2", "3"]);
+  assert_eq!(find(object! { "$or": [{ number: "1" }, { sum: { "$gte": 50 } }] }), vec!["1", "3"]);
+
+  // nested fields of referenced documents
+  assert_eq!(find(object! { "storage.name": "магазин" }), vec!["2", "5"]);
+  assert_eq!(find(object! { "storage._uuid": s1.to_string(), sum: { "$lt": 30 } }), vec!["4"]);
+
+  // sorting with and without filter
+  assert_eq!(numbers(&query(object! { "$sort": { sum: -1 } })), vec!["3", "5", "1", "4", "2"]);
+  let result = query(object! {
+    filter: { storage: s1.to_string() },
+    "$sort": { sum: 1 },
+    "$limit": 2,
+  });
+  assert_eq!(numbers(&result), vec!["4", "1"]);
+
+  // projection keeps selected fields and `_id` only
+  let result = query(object! { filter: { number: "3" }, "$select": ["number", "storage.name"] });
+  let data = &result["data"][0];
+  assert_eq!(data["number"], "3");
+  assert_eq!(data["storage"]["name"], "склад");
+  assert!(data["_id"].is_string());
+  assert!(data["date"].is_null());
+  assert!(data["sum"].is_null());
+
+  // unknown operator is an error
+  assert!(app
+    .service("memories")
+    .find(
+      Context::local(),
+      object! { oid: WID, ctx: ctx.clone(), filter: { number: { "$like": "1" } } }
+    )
+    .is_err());
+
+  tmp_dir.close().unwrap();
+}
@@ -0,0 +1,114 @@
+mod test_init;
+
+use json::{object, JsonValue};
+use rust_decimal::Decimal;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::test_init::{goods, init, store, uom, DocumentCreation};
+use nae_backend::commutator::Application;
+use nae_backend::memories::MemoriesInFiles;
+use nae_backend::services::Event;
+use nae_backend::storage::Workspaces;
+use service::utils::json::JsonParams;
+use service::{Context, Services};
+use store::qty::{Number, Qty};
+use store::GetWarehouse;
+
+const WID: &str = "yjmgJUmDo_kn9uxVi8s9Mj9mgGRJISxRt63wT46NyTQ";
+
+#[actix_web::test]
+async fn check_reorder() {
+  let (tmp_dir, settings, db) = init();
+
+  let wss = Workspaces::new(tmp_dir.path().join("companies"));
+
+  let (mut app, events) = Application::new(Arc::new(settings), Arc::new(db), wss).await.unwrap();
+
+  app.register(MemoriesInFiles::new(app.clone(), "memories"));
+  app.register(nae_backend::inventory::service::Inventory::new(app.clone()));
+
+  let s1 = store(&app, "склад");
+  let paint = goods(&app, "краска");
+  let kg = uom(&app, "кг");
+
+  let qty =
+    |n: u32| -> JsonValue { (&Qty::new(vec![Number::new(Decimal::from(n), kg, None)])).into() };
+  let q = |data: &JsonValue| -> Qty { data.clone().try_into().unwrap() };
+
+  let level = vec!["warehouse", "level"].create(
+    &app,
+    object! { storage: s1.to_string(), goods: paint.to_string(), min: qty(10), max: qty(40) },
+  );
+  let stored = app.warehouse().database.levels.get(s1, paint).unwrap().unwrap();
+  assert_eq!(stored.min, q(&qty(10)));
+
+  let receive = vec!["warehouse", "receive", "document"]
+    .create(&app, object! { date: "2023-01-10", storage: s1.to_string(), number: "1" });
+  vec!["warehouse", "receive"].create(
+    &app,
+    object! {
+      document: receive["_id"].string(),
+      goods: paint.to_string(),
+      qty: qty(25),
+      cost: { number: "250" },
+    },
+  );
+  assert!(events.try_recv().is_err());
+
+  let dispatch = vec!["warehouse", "dispatch", "document"]
+    .create(&app, object! { date: "2023-01-12", storage: s1.to_string(), number: "1" });
+  vec!["warehouse", "dispatch"].create(
+    &app,
+    object! { document: dispatch["_id"].string(), goods: paint.to_string(), qty: qty(20) },
+  );
+
+  match events.recv_timeout(Duration::from_secs(5)).unwrap() {
+    Event::Custom(name, event, data) => {
+      assert_eq!((name.as_str(), event.as_str()), ("inventory", "reorder"));
+      assert_eq!(data["store"], s1.to_string());
+      assert_eq!(data["goods"], paint.to_string());
+      assert_eq!(q(&data["suggested"]), q(&qty(35)));
+    },
+    event => panic!("unexpected event {event:?}"),
+  }
+
+  let find = || {
+    app
+      .service("inventory")
+      .find(Context::local(), object! { oid: WID, filter: { reorder: true } })
+      .unwrap()
+  };
+
+  let result = find();
+  assert_eq!(result["total"], 1);
+  assert_eq!(result["data"][0]["store"]["name"], "склад");
+  assert_eq!(result["data"][0]["goods"]["name"], "краска");
+  assert_eq!(q(&result["data"][0]["qty"]), q(&qty(5)));
+  assert_eq!(q(&result["data"][0]["suggested"]), q(&qty(35)));
+
+  // minimum lowered under balance
+  let mut data = level.clone();
+  data["min"] = qty(5);
+  app
+    .service("memories")
+    .update(
+      Context::local(),
+      level["_id"].string(),
+      data,
+      object! { oid: WID, ctx: vec!["warehouse", "level"] },
+    )
+    .unwrap();
+  assert_eq!(find()["total"], 0);
+
+  // levels are indexed from memories at start
+  let levels = &app.warehouse().database.levels;
+  levels.set(s1, paint, None).unwrap();
+  assert_eq!(levels.list().unwrap().len(), 0);
+
+  app.index_levels().unwrap();
+  let stored = levels.get(s1, paint).unwrap().unwrap();
+  assert_eq!((stored.min, stored.max), (q(&qty(5)), q(&qty(40))));
+
+  tmp_dir.close().unwrap();
+}
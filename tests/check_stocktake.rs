@@ -0,0 +1,110 @@
+mod test_init;
+
+use json::{object, JsonValue};
+use rust_decimal::Decimal;
+use std::sync::Arc;
+
+use crate::test_init::{goods, init, store, uom, DocumentCreation};
+use nae_backend::commutator::Application;
+use nae_backend::inventory::stocktake::StockTakes;
+use nae_backend::memories::MemoriesInFiles;
+use nae_backend::storage::Workspaces;
+use service::error::Error;
+use service::utils::json::JsonParams;
+use service::{Context, Origin, Services};
+use store::elements::{dt, ToJson};
+use store::qty::{Number, Qty};
+use store::GetWarehouse;
+
+const WID: &str = "yjmgJUmDo_kn9uxVi8s9Mj9mgGRJISxRt63wT46NyTQ";
+const OTHER: &str = "Fs3mvEYC9gcnSwlsKOeCL0xVnYbPbjGPZtrSK6F_Fzk";
+
+#[actix_web::test]
+async fn check_stocktake() {
+  let (tmp_dir, settings, db) = init();
+
+  let wss = Workspaces::new(tmp_dir.path().join("companies"));
+
+  let (mut app, _) = Application::new(Arc::new(settings), Arc::new(db), wss).await.unwrap();
+
+  app.register(MemoriesInFiles::new(app.clone(), "memories"));
+  app.register(StockTakes::new(app.clone()));
+
+  let s1 = store(&app, "склад");
+  let paint = goods(&app, "краска");
+  let kg = uom(&app, "кг");
+
+  let qty =
+    |n: u32| -> JsonValue { (&Qty::new(vec![Number::new(Decimal::from(n), kg, None)])).into() };
+
+  let receive = vec!["warehouse", "receive", "document"]
+    .create(&app, object! { date: "2023-01-10", storage: s1.to_string(), number: "1" });
+  vec!["warehouse", "receive"].create(
+    &app,
+    object! {
+      document: receive["_id"].string(),
+      goods: paint.to_string(),
+      qty: qty(10),
+      cost: { number: "100" },
+    },
+  );
+
+  let service = app.service("stocktake");
+  let params = || object! { oid: WID };
+
+  let stocktake = service
+    .create(Context::local(), object! { storage: s1.to_string(), date: "2023-01-20" }, params())
+    .unwrap();
+  let id = stocktake["id"].string();
+  assert_eq!(stocktake["posted"], false);
+  assert_eq!(stocktake["lines"].len(), 1);
+
+  // stock-take is not seen from other workspace
+  let err = service.get(Context::local(), id.clone(), object! { oid: OTHER }).unwrap_err();
+  assert!(matches!(err, Error::NotFound(..)));
+
+  // remote account have to be accountant of company
+  let remote = Context { origin: Origin::Websocket, ..Context::local() };
+  let err = service.get(remote, id.clone(), params()).unwrap_err();
+  assert!(matches!(err, Error::Forbidden(..)));
+
+  let batch = stocktake["lines"][0]["batch"].clone();
+  service
+    .patch(
+      Context::local(),
+      id.clone(),
+      object! { goods: paint.to_string(), batch: batch, qty: qty(7) },
+      params(),
+    )
+    .unwrap();
+
+  let posted = service
+    .patch(Context::local(), id.clone(), object! { action: "post" }, params())
+    .unwrap();
+  assert_eq!(posted["posted"], true);
+  assert_eq!(posted["shortage"], store::balance::Cost::from(30).to_json());
+
+  // posted differences are recorded by warehouse
+  assert_eq!(service.get(Context::local(), id.clone(), params()).unwrap()["posted"], true);
+  let err = service
+    .patch(Context::local(), id.clone(), object! { action: "post" }, params())
+    .unwrap_err();
+  assert!(matches!(err, Error::Conflict(..)));
+
+  // differences are posted by inventory document
+  let document = app
+    .service("memories")
+    .get(
+      Context::local(),
+      posted["document"].string(),
+      object! { oid: WID, ctx: ["warehouse", "inventory", "document"] },
+    )
+    .unwrap();
+  assert_eq!(document["stocktake"], id.as_str());
+
+  let balances = app.warehouse().database.get_balance_for_all(dt("2023-01-31").unwrap()).unwrap();
+  let balance = balances[&s1][&paint].values().next().unwrap();
+  assert_eq!(balance.qty, Qty::new(vec![Number::new(Decimal::from(7), kg, None)]));
+
+  tmp_dir.close().unwrap();
+}
@@ -0,0 +1,76 @@
+mod test_init;
+
+use json::object;
+use rust_decimal::Decimal;
+use std::sync::Arc;
+
+use crate::test_init::{goods, init, receive, store, transfer, uom};
+use nae_backend::commutator::Application;
+use nae_backend::memories::MemoriesInFiles;
+use nae_backend::storage::Workspaces;
+use service::{Context, Services};
+use store::qty::{Number, Qty};
+
+const WID: &str = "yjmgJUmDo_kn9uxVi8s9Mj9mgGRJISxRt63wT46NyTQ";
+
+#[actix_web::test]
+async fn check_turnover() {
+  let (tmp_dir, settings, db) = init();
+
+  let wss = Workspaces::new(tmp_dir.path().join("companies"));
+
+  let (mut app, _) = Application::new(Arc::new(settings), Arc::new(db), wss).await.unwrap();
+
+  app.register(MemoriesInFiles::new(app.clone(), "memories"));
+  app.register(nae_backend::inventory::service::Inventory::new(app.clone()));
+
+  let s1 = store(&app, "склад");
+  let s2 = store(&app, "цех");
+  let paint = goods(&app, "краска");
+  let kg = uom(&app, "кг");
+
+  let qty = |n: u32| Qty::new(vec![Number::new(Decimal::from(n), kg, None)]);
+
+  receive(&app, "2023-01-10", s1, paint, qty(5), 50.into());
+  transfer(&app, "2023-02-15", s1, s2, paint, qty(2));
+
+  let find = |filter: json::JsonValue| {
+    app
+      .service("inventory")
+      .find(Context::local(), object! { oid: WID, filter: filter })
+  };
+
+  let result = find(object! {
+    dates: { from: "2023-01-01", till: "2023-03-31" },
+    goods: paint.to_string(),
+    storage: s1.to_string(),
+    turnover: "monthly",
+  })
+  .unwrap();
+
+  assert_eq!(result["total"], 3);
+  assert_eq!(result["data"][0]["date"], "2023-01-01");
+  assert_eq!(result["data"][0]["receive"]["cost"], "50");
+  assert_eq!(result["data"][1]["issue"]["cost"], "20");
+  assert_eq!(result["data"][1]["close_balance"]["cost"], "30");
+  assert_eq!(result["data"][2]["close_balance"]["cost"], "30");
+
+  // all stores, transfer doesn't change total balance
+  let result = find(object! {
+    dates: { from: "2023-01-01", till: "2023-03-31" },
+    goods: paint.to_string(),
+    turnover: "monthly",
+  })
+  .unwrap();
+
+  assert_eq!(result["data"][1]["receive"]["cost"], "20");
+  assert_eq!(result["data"][1]["issue"]["cost"], "20");
+  assert_eq!(result["data"][2]["close_balance"]["cost"], "50");
+
+  let result = find(object! {
+    dates: { from: "2023-01-01", till: "2023-03-31" },
+    goods: paint.to_string(),
+    turnover: "hourly",
+  });
+  assert!(result.is_err());
+}
@@ -62,6 +62,8 @@ pub fn test(folder: PathBuf) -> Settings {
       memory: folder.join("memory"),
       inventory: folder.join("inventory"),
       links: folder.join("links"),
+      fields: folder.join("fields"),
+      audit: folder.join("audit"),
       ftsearch: folder.join("ftsearch"),
     },
   }
@@ -131,6 +133,7 @@ pub fn receive(
     batch: Batch { id, date },
     before: None,
     after: Some((InternalOperation::Receive(qty, cost), false)),
+    document: None,
   });
 
   app.warehouse().mutate(&ops).unwrap();
@@ -160,6 +163,7 @@ pub fn delete(
     batch,
     before: Some((before, false)),
     after: None,
+    document: None,
   });
 
   app.warehouse().mutate(&ops).unwrap();
@@ -188,6 +192,7 @@ pub fn update(
     batch,
     before: Some((before, false)),
     after: Some((after, false)),
+    document: None,
   });
 
   app.warehouse().mutate(&ops).unwrap();
@@ -213,6 +218,7 @@ pub fn transfer(
     batch: Batch::no(),
     before: None,
     after: Some((InternalOperation::Issue(qty, Cost::ZERO, Mode::Auto), false)),
+    document: None,
   });
 
   app.warehouse().mutate(&ops).unwrap();
@@ -3,6 +3,7 @@ pub const DELETED: &str = "deleted";
 
 pub const ID: &str = "_id";
 pub const UUID: &str = "_uuid";
+pub const REVISION: &str = "_revision";
 pub const DOCUMENT: &str = "document";
 
 pub const P_PRODUCE: [&str; 2] = ["production", "produce"];